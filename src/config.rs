@@ -1,10 +1,17 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::net::{IpAddr, Ipv4Addr};
 use std::env;
-use crossterm::event::KeyCode;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crate::file_system::SortMode;
+use crate::file_system::checksum::ChecksumAlgorithm;
+use crate::file_system::open_with::OpenWithApp;
+use crate::search::{CaseMode, PatternMode, SearchSettings};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct KeyBindings {
     pub navigation: NavigationKeys,
     pub actions: ActionKeys,
@@ -21,30 +28,116 @@ pub struct NavigationKeys {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ActionKeys {
     pub quit: Vec<String>,
+    pub force_quit: Vec<String>,
     pub search: Vec<String>,
     pub open: Vec<String>,
     pub reveal: Vec<String>,
     pub share: Vec<String>,
+    /// Shares with a token or password even when `file_sharing.protect` is off
+    pub share_protected: Vec<String>,
+    /// Turns accepting files at the share server's `/upload` page on or off
+    pub toggle_uploads: Vec<String>,
     pub copy_path: Vec<String>,
     pub cut: Vec<String>,
     pub copy: Vec<String>,
     pub paste: Vec<String>,
+    pub delete: Vec<String>,
+    pub delete_permanently: Vec<String>,
+    pub rename: Vec<String>,
+    pub show_config: Vec<String>,
+    pub new_file: Vec<String>,
+    pub new_dir: Vec<String>,
+    pub copy_markdown_link: Vec<String>,
+    pub toggle_hidden: Vec<String>,
+    pub cycle_sort: Vec<String>,
+    pub reverse_sort: Vec<String>,
+    pub empty_trash: Vec<String>,
+    pub toggle_bookmarks_bar: Vec<String>,
+    pub toggle_executable: Vec<String>,
+    pub toggle_mark: Vec<String>,
+    pub mark_all: Vec<String>,
+    pub clear_marks: Vec<String>,
+    pub duplicate: Vec<String>,
+    pub cancel_transfer: Vec<String>,
+    pub checksum: Vec<String>,
+    pub extract: Vec<String>,
+    pub compress: Vec<String>,
+    pub go_home: Vec<String>,
+    pub add_bookmark: Vec<String>,
+    pub show_bookmarks: Vec<String>,
+    pub history_back: Vec<String>,
+    pub history_forward: Vec<String>,
+    pub open_share_list: Vec<String>,
+    /// Opens the popup listing active shares, to copy, open or revoke them
+    pub manage_shares: Vec<String>,
+    /// Shows the most recent share's link as a QR code
+    pub share_qr: Vec<String>,
+    pub goto: Vec<String>,
+    /// Opens the popup picking one of the most visited directories
+    pub jump: Vec<String>,
+    /// Lists the files under the current directory with identical content
+    pub find_duplicates: Vec<String>,
+    /// Lists the files modified within `search.recent_hours`, newest first
+    pub recent_files: Vec<String>,
+    pub edit: Vec<String>,
+    pub open_terminal: Vec<String>,
+    pub filter: Vec<String>,
+    pub toggle_read_only: Vec<String>,
+    pub toggle_ignored: Vec<String>,
+    pub open_with: Vec<String>,
+    pub drives: Vec<String>,
+    pub properties: Vec<String>,
+    pub toggle_tree: Vec<String>,
+    pub focus_tree: Vec<String>,
+    pub diff: Vec<String>,
+    /// Walks the indexed tree again from scratch
+    pub rebuild_index: Vec<String>,
+    /// Adds the current directory to the roots searches walk, or removes it
+    pub toggle_search_root: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SearchModeKeys {
     pub exit_search: Vec<String>,
     pub exit_to_results: Vec<String>,
     pub toggle_strategy: Vec<String>,
+    /// Cycles fuzzy, substring, regex and glob matching
+    pub toggle_pattern_mode: Vec<String>,
+    /// Cycles smart case, case sensitive and ignore case
+    pub toggle_case: Vec<String>,
+    /// Whether walking searches look at hidden files, skip gitignored ones
+    /// and follow symlinked directories
+    pub toggle_hidden: Vec<String>,
+    pub toggle_gitignore: Vec<String>,
+    pub toggle_follow_links: Vec<String>,
     pub navigate_tab: Vec<String>,
     pub backspace: Vec<String>,
+    pub strategy_fast: Vec<String>,
+    pub strategy_comprehensive: Vec<String>,
+    pub strategy_local: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SearchResultsKeys {
     pub back: Vec<String>,
+    pub cycle_type_filter: Vec<String>,
+    /// Lists directories ahead of files, or by score alone again
+    pub directories_first: Vec<String>,
+    /// Collapses the preview pane to give long paths the full width
+    pub toggle_preview: Vec<String>,
+    /// Narrows the shown results by another pattern
+    pub refine: Vec<String>,
+    /// Writes the shown results to a plain, CSV or JSON file
+    pub export: Vec<String>,
+    /// Marks every shown result, after a confirmation with their total size
+    pub select_all: Vec<String>,
+    /// Marks all but the oldest file of each group of duplicates
+    pub mark_copies: Vec<String>,
 }
 
 impl Default for KeyBindings {
@@ -56,68 +149,191 @@ impl Default for KeyBindings {
                 left: vec!["Left".to_string()],
                 enter: vec!["Right".to_string()],
             },
-            actions: ActionKeys {
-                quit: vec!["q".to_string()],
-                search: vec!["/".to_string()],
-                open: vec!["o".to_string(), "O".to_string()],
-                reveal: vec!["r".to_string(), "R".to_string()],
-                share: vec!["s".to_string(), "S".to_string()],
-                copy_path: vec!["p".to_string(), "P".to_string()],
-                cut: vec!["x".to_string(), "X".to_string()],
-                copy: vec!["c".to_string(), "C".to_string()],
-                paste: vec!["v".to_string(), "V".to_string()],
-            },
-            search_mode: SearchModeKeys {
-                exit_search: vec!["Esc".to_string()],
-                exit_to_results: vec!["Enter".to_string()],
-                toggle_strategy: vec!["F2".to_string()],
-                navigate_tab: vec!["Tab".to_string()],
-                backspace: vec!["Backspace".to_string()],
-            },
-            search_results: SearchResultsKeys {
-                back: vec!["Esc".to_string(), "Left".to_string()],
-            },
+            actions: ActionKeys::default(),
+            search_mode: SearchModeKeys::default(),
+            search_results: SearchResultsKeys::default(),
+        }
+    }
+}
+
+impl Default for SearchModeKeys {
+    fn default() -> Self {
+        Self {
+            exit_search: vec!["Esc".to_string()],
+            exit_to_results: vec!["Enter".to_string()],
+            toggle_strategy: vec!["F2".to_string()],
+            toggle_pattern_mode: vec!["F3".to_string()],
+            toggle_case: vec!["Alt+c".to_string()],
+            toggle_hidden: vec!["Alt+h".to_string()],
+            toggle_gitignore: vec!["Alt+g".to_string()],
+            toggle_follow_links: vec!["Alt+l".to_string()],
+            navigate_tab: vec!["Tab".to_string()],
+            backspace: vec!["Backspace".to_string()],
+            strategy_fast: vec!["F5".to_string()],
+            strategy_comprehensive: vec!["F6".to_string()],
+            strategy_local: vec!["F7".to_string()],
+        }
+    }
+}
+
+impl Default for SearchResultsKeys {
+    fn default() -> Self {
+        Self {
+            back: vec!["Esc".to_string(), "Left".to_string()],
+            cycle_type_filter: vec!["F4".to_string()],
+            directories_first: vec!["G".to_string()],
+            toggle_preview: vec!["|".to_string()],
+            refine: vec!["f".to_string()],
+            export: vec!["w".to_string()],
+            select_all: vec!["A".to_string()],
+            mark_copies: vec!["K".to_string()],
+        }
+    }
+}
+
+impl Default for ActionKeys {
+    fn default() -> Self {
+        Self {
+            quit: vec!["q".to_string()],
+            force_quit: vec!["Ctrl+q".to_string()],
+            search: vec!["/".to_string()],
+            open: vec!["o".to_string(), "O".to_string()],
+            reveal: vec!["r".to_string(), "R".to_string()],
+            share: vec!["s".to_string(), "S".to_string()],
+            share_protected: vec!["Alt+s".to_string()],
+            toggle_uploads: vec!["Alt+u".to_string()],
+            copy_path: vec!["p".to_string(), "P".to_string()],
+            cut: vec!["x".to_string(), "X".to_string()],
+            copy: vec!["c".to_string(), "C".to_string()],
+            paste: vec!["v".to_string(), "V".to_string()],
+            delete: vec!["d".to_string(), "Delete".to_string()],
+            delete_permanently: vec!["Shift+Delete".to_string(), "Alt+d".to_string()],
+            rename: vec!["m".to_string()],
+            show_config: vec!["F1".to_string()],
+            new_file: vec!["n".to_string()],
+            new_dir: vec!["N".to_string()],
+            copy_markdown_link: vec!["L".to_string()],
+            toggle_hidden: vec![".".to_string()],
+            cycle_sort: vec!["t".to_string()],
+            reverse_sort: vec!["T".to_string()],
+            empty_trash: vec!["F8".to_string()],
+            toggle_bookmarks_bar: vec!["F11".to_string()],
+            toggle_executable: vec!["Ctrl+x".to_string()],
+            toggle_mark: vec!["Space".to_string()],
+            mark_all: vec!["a".to_string()],
+            clear_marks: vec!["Esc".to_string()],
+            duplicate: vec!["D".to_string()],
+            cancel_transfer: vec!["Ctrl+c".to_string()],
+            checksum: vec!["#".to_string()],
+            extract: vec!["E".to_string()],
+            compress: vec!["Z".to_string()],
+            go_home: vec!["~".to_string()],
+            add_bookmark: vec!["b".to_string()],
+            show_bookmarks: vec!["B".to_string()],
+            history_back: vec!["Alt+Left".to_string(), "[".to_string()],
+            history_forward: vec!["Alt+Right".to_string(), "]".to_string()],
+            open_share_list: vec!["W".to_string()],
+            manage_shares: vec!["Y".to_string()],
+            share_qr: vec!["Q".to_string()],
+            goto: vec!["g".to_string()],
+            jump: vec!["z".to_string()],
+            find_duplicates: vec!["U".to_string()],
+            recent_files: vec!["H".to_string()],
+            edit: vec!["e".to_string()],
+            open_terminal: vec!["Ctrl+t".to_string()],
+            filter: vec!["f".to_string()],
+            toggle_read_only: vec!["Ctrl+r".to_string()],
+            toggle_ignored: vec!["i".to_string()],
+            open_with: vec!["Ctrl+o".to_string()],
+            drives: vec!["M".to_string()],
+            properties: vec!["I".to_string()],
+            toggle_tree: vec!["F9".to_string()],
+            focus_tree: vec!["Tab".to_string()],
+            diff: vec!["=".to_string()],
+            rebuild_index: vec!["Ctrl+b".to_string()],
+            toggle_search_root: vec!["Alt+r".to_string()],
         }
     }
 }
 
 impl KeyBindings {
     pub fn matches_key(&self, key_lists: &[String], key_code: &KeyCode) -> bool {
+        key_lists.iter().any(|key_str| Self::key_name_matches(key_str, key_code))
+    }
+
+    /// Like `matches_key`, but also understands modifier chords such as
+    /// "Ctrl+q" or "Alt+Left". Plain entries only match when neither Ctrl
+    /// nor Alt is held.
+    pub fn matches_chord(&self, key_lists: &[String], key: &KeyEvent) -> bool {
         key_lists.iter().any(|key_str| {
-            match key_str.as_str() {
-                "Up" => matches!(key_code, KeyCode::Up),
-                "Down" => matches!(key_code, KeyCode::Down),
-                "Left" => matches!(key_code, KeyCode::Left),
-                "Right" => matches!(key_code, KeyCode::Right),
-                "Enter" => matches!(key_code, KeyCode::Enter),
-                "Esc" => matches!(key_code, KeyCode::Esc),
-                "Tab" => matches!(key_code, KeyCode::Tab),
-                "Backspace" => matches!(key_code, KeyCode::Backspace),
-                "F2" => matches!(key_code, KeyCode::F(2)),
-                "F3" => matches!(key_code, KeyCode::F(3)),
-                "F4" => matches!(key_code, KeyCode::F(4)),
-                "F5" => matches!(key_code, KeyCode::F(5)),
-                "F6" => matches!(key_code, KeyCode::F(6)),
-                "F7" => matches!(key_code, KeyCode::F(7)),
-                "F8" => matches!(key_code, KeyCode::F(8)),
-                "F9" => matches!(key_code, KeyCode::F(9)),
-                "F10" => matches!(key_code, KeyCode::F(10)),
-                "F11" => matches!(key_code, KeyCode::F(11)),
-                "F12" => matches!(key_code, KeyCode::F(12)),
-                other => {
-                    // Handle single character keys
-                    if other.len() == 1 {
-                        if let Some(c) = other.chars().next() {
-                            matches!(key_code, KeyCode::Char(ch) if ch == &c)
-                        } else {
-                            false
-                        }
+            let mut required = KeyModifiers::NONE;
+            let mut name = key_str.as_str();
+            loop {
+                if let Some(rest) = name.strip_prefix("Ctrl+") {
+                    required |= KeyModifiers::CONTROL;
+                    name = rest;
+                } else if let Some(rest) = name.strip_prefix("Alt+") {
+                    required |= KeyModifiers::ALT;
+                    name = rest;
+                } else if let Some(rest) = name.strip_prefix("Shift+") {
+                    required |= KeyModifiers::SHIFT;
+                    name = rest;
+                } else {
+                    break;
+                }
+            }
+
+            // Shift is already reflected in the character itself, so only
+            // Ctrl and Alt have to line up exactly
+            let chord_mask = KeyModifiers::CONTROL | KeyModifiers::ALT;
+            if key.modifiers & chord_mask != required & chord_mask {
+                return false;
+            }
+            if required.contains(KeyModifiers::SHIFT) && !key.modifiers.contains(KeyModifiers::SHIFT) {
+                return false;
+            }
+
+            Self::key_name_matches(name, &key.code)
+        })
+    }
+
+    fn key_name_matches(key_str: &str, key_code: &KeyCode) -> bool {
+        match key_str {
+            "Up" => matches!(key_code, KeyCode::Up),
+            "Down" => matches!(key_code, KeyCode::Down),
+            "Left" => matches!(key_code, KeyCode::Left),
+            "Right" => matches!(key_code, KeyCode::Right),
+            "Enter" => matches!(key_code, KeyCode::Enter),
+            "Esc" => matches!(key_code, KeyCode::Esc),
+            "Tab" => matches!(key_code, KeyCode::Tab),
+            "Backspace" => matches!(key_code, KeyCode::Backspace),
+            "Delete" => matches!(key_code, KeyCode::Delete),
+            "Space" => matches!(key_code, KeyCode::Char(' ')),
+            "F1" => matches!(key_code, KeyCode::F(1)),
+            "F2" => matches!(key_code, KeyCode::F(2)),
+            "F3" => matches!(key_code, KeyCode::F(3)),
+            "F4" => matches!(key_code, KeyCode::F(4)),
+            "F5" => matches!(key_code, KeyCode::F(5)),
+            "F6" => matches!(key_code, KeyCode::F(6)),
+            "F7" => matches!(key_code, KeyCode::F(7)),
+            "F8" => matches!(key_code, KeyCode::F(8)),
+            "F9" => matches!(key_code, KeyCode::F(9)),
+            "F10" => matches!(key_code, KeyCode::F(10)),
+            "F11" => matches!(key_code, KeyCode::F(11)),
+            "F12" => matches!(key_code, KeyCode::F(12)),
+            other => {
+                // Handle single character keys
+                if other.len() == 1 {
+                    if let Some(c) = other.chars().next() {
+                        matches!(key_code, KeyCode::Char(ch) if ch == &c)
                     } else {
                         false
                     }
+                } else {
+                    false
                 }
             }
-        })
+        }
     }
 
     pub fn get_key_display(&self, key_lists: &[String]) -> String {
@@ -126,10 +342,103 @@ impl KeyBindings {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FileShareSettings {
     pub server_port: u16,
     pub port_range_start: u16,
     pub port_range_end: u16,
+    /// Always serve on this port so share URLs stay the same between runs.
+    /// Sharing fails instead of scanning the range when it is taken.
+    pub fixed_port: Option<u16>,
+    /// IP the share server listens on, such as `127.0.0.1` to keep shares
+    /// on this machine; `None` listens on every interface
+    pub bind_address: Option<String>,
+    /// PEM certificate to serve shares over HTTPS with, together with
+    /// `tls_key`
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key for `tls_cert`
+    pub tls_key: Option<PathBuf>,
+    /// File each request for a shared file is appended to
+    pub access_log: Option<PathBuf>,
+    /// Minutes a share link works for; `None` keeps it working until the
+    /// app quits
+    pub expiry_minutes: Option<u64>,
+    /// Downloads a share link allows before it stops working; `None` for no
+    /// limit
+    pub max_downloads: Option<u64>,
+    /// Protect every share: with a token in its link, or with `password`
+    /// when one is set
+    pub protect: bool,
+    /// Asked for by the pages of protected shares instead of a token
+    pub password: Option<String>,
+    /// Accept files sent to `/upload` from the start, instead of only after
+    /// turning uploads on
+    pub uploads_enabled: bool,
+    /// Where uploads are saved; `None` uses the directory being viewed when
+    /// uploads are turned on
+    pub upload_dir: Option<PathBuf>,
+    /// Largest upload accepted, in megabytes
+    pub max_upload_mb: u64,
+    /// Rows a page of a CSV preview shows, and an Excel sheet at most
+    pub preview_rows: usize,
+    /// Lines a page of a long text file's preview shows
+    pub preview_lines: usize,
+}
+
+impl FileShareSettings {
+    /// The configured `upload_dir`, with `~` expanded.
+    pub fn upload_dir(&self) -> Option<PathBuf> {
+        self.upload_dir.as_deref().map(expand_home)
+    }
+
+    /// The configured `tls_cert`, with `~` expanded.
+    pub fn tls_cert(&self) -> Option<PathBuf> {
+        self.tls_cert.as_deref().map(expand_home)
+    }
+
+    /// The configured `tls_key`, with `~` expanded.
+    pub fn tls_key(&self) -> Option<PathBuf> {
+        self.tls_key.as_deref().map(expand_home)
+    }
+
+    /// The configured `access_log`, with `~` expanded.
+    pub fn access_log(&self) -> Option<PathBuf> {
+        self.access_log.as_deref().map(expand_home)
+    }
+
+    /// The IP to listen on, from `bind_address`: every interface when it is
+    /// unset, and loopback for `localhost`.
+    pub fn bind_ip(&self) -> Result<IpAddr, String> {
+        match self.bind_address.as_deref().map(str::trim) {
+            None | Some("") => Ok(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            Some("localhost") => Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            Some(address) => address.parse().map_err(|_| format!(
+                "Invalid share bind address '{}': use an IP such as 127.0.0.1, or 0.0.0.0 for every interface",
+                address
+            )),
+        }
+    }
+
+    /// Applies a `--port` value: `PORT` serves on that port only, and
+    /// `START-END` scans the range, both ends included.
+    pub fn set_ports(&mut self, ports: &str) -> Result<(), String> {
+        let parse = |port: &str| port.trim().parse::<u16>()
+            .map_err(|_| format!("Invalid share port '{}': expected a number from 1 to 65535", port.trim()))
+            .and_then(|port| if port == 0 { Err("Share port must not be 0".to_string()) } else { Ok(port) });
+        match ports.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end || end == u16::MAX {
+                    return Err(format!("Invalid share port range '{}'", ports));
+                }
+                self.fixed_port = None;
+                self.port_range_start = start;
+                self.port_range_end = end + 1;
+            }
+            None => self.fixed_port = Some(parse(ports)?),
+        }
+        Ok(())
+    }
 }
 
 impl Default for FileShareSettings {
@@ -138,16 +447,132 @@ impl Default for FileShareSettings {
             server_port: 8080,
             port_range_start: 8080,
             port_range_end: 8090,
+            fixed_port: None,
+            bind_address: None,
+            tls_cert: None,
+            tls_key: None,
+            access_log: None,
+            expiry_minutes: Some(60),
+            max_downloads: None,
+            protect: false,
+            password: None,
+            uploads_enabled: false,
+            upload_dir: None,
+            max_upload_mb: 1024,
+            preview_rows: 1000,
+            preview_lines: 1000,
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub notification_endpoint: Option<String>,
     pub notification_enabled: bool,
     pub key_bindings: KeyBindings,
     pub file_sharing: FileShareSettings,
+    /// Markdown links use absolute `file://` URLs when true, otherwise paths
+    /// relative to the current directory
+    pub markdown_links_absolute: bool,
+    /// Whether dotfiles (or hidden-attribute files on Windows) are listed at startup
+    pub show_hidden: bool,
+    /// Initial ordering of the file list
+    pub default_sort: SortMode,
+    pub default_sort_reverse: bool,
+    /// How search text is matched until switched in search mode:
+    /// "fuzzy", "substring", "regex" or "glob"
+    pub default_pattern_mode: PatternMode,
+    /// "smart" (case matters only when the pattern has an uppercase
+    /// letter), "sensitive" or "insensitive"
+    pub search_case: CaseMode,
+    /// Search depth and result limits, e.g.
+    /// `{"max_depth_comprehensive": null, "max_results": 5000}`
+    pub search: SearchSettings,
+    /// Index file names below the start path in the background for the
+    /// indexed search strategy, in `index/` next to the config file
+    pub search_index: bool,
+    /// Gitignore-style globs for entries every search strategy skips, on
+    /// top of `.gitignore`; setting it replaces the defaults. `--exclude`
+    /// adds to it for one run.
+    pub search_exclude: Vec<String>,
+    /// Directories the walking search strategies cover together instead of
+    /// the current one, e.g. `["~/work", "~/oss"]`; `~` expands to $HOME.
+    /// Empty searches the current directory.
+    pub search_roots: Vec<PathBuf>,
+    /// Commands used to open files by extension, e.g. `"csv": "visidata {file}"`.
+    /// `{file}` is replaced with the path; without it the path is appended.
+    pub open_commands: HashMap<String, String>,
+    /// Applications listed first in the open-with menu, e.g.
+    /// `{"name": "GIMP", "command": "gimp {path}"}`. `{path}` is replaced
+    /// with the file; without it the path is appended.
+    pub open_with: Vec<OpenWithApp>,
+    /// Application last picked in the open-with menu per extension, listed
+    /// first next time. Written back to the config file by the menu.
+    pub open_with_recent: HashMap<String, String>,
+    /// Editor run in this terminal by the edit key, e.g. `"nvim {file}"`.
+    /// Unset uses $VISUAL or $EDITOR, then a platform default.
+    pub editor_command: Option<String>,
+    /// Terminal opened at the current directory, e.g.
+    /// `"alacritty --working-directory {dir}"`. Unset uses $TERMINAL, then a
+    /// platform default.
+    pub terminal_command: Option<String>,
+    /// Bookmarked directories; the first nine are reachable with keys 1-9
+    pub bookmarks: Vec<PathBuf>,
+    pub show_bookmarks_bar: bool,
+    /// Directory treated as "home" by the home key and the launch fallback;
+    /// `~` expands to $HOME. Unset means $HOME itself.
+    pub home_base: Option<PathBuf>,
+    /// Show the selected entry's absolute path on a line below the header
+    pub show_selected_path: bool,
+    /// Give each file list entry a second, dimmer line with its size,
+    /// modification time and path
+    pub two_line_rows: bool,
+    /// Share of the search results view's width, in percent, given to the
+    /// preview pane; 0 leaves it out
+    pub search_preview_width: u16,
+    /// Resolve symlinks when entering a directory so the header shows the
+    /// real path; turned off, the path that was clicked through is kept
+    pub resolve_symlinks_on_navigate: bool,
+    /// Algorithm used by the checksum key: "sha256", "md5" or "crc32"
+    pub checksum_algorithm: ChecksumAlgorithm,
+    /// Whether the delete action moves entries to the trash; the
+    /// permanent delete keys always bypass it
+    pub use_trash: bool,
+    /// Refresh the listing when the current directory changes on disk.
+    /// Worth turning off on slow network filesystems.
+    pub watch_directory: bool,
+    /// Start in read-only mode, where cut, paste, delete, rename and
+    /// creating entries are refused. Also set by `--read-only`.
+    pub read_only: bool,
+    /// Mark modified, staged, untracked and ignored entries inside git
+    /// repositories. Worth turning off where `git status` is slow.
+    pub git_status: bool,
+    /// Leave out entries the repository's `.gitignore` rules ignore when
+    /// listing directories inside git repositories.
+    pub hide_gitignored: bool,
+    /// List kernel pseudo filesystems such as proc, sysfs and cgroup in
+    /// the drives view
+    pub show_pseudo_filesystems: bool,
+    /// Keep each directory's sort order, hidden-files setting, filter and
+    /// selection between runs, in `view_state.json` next to the config file
+    pub remember_view_state: bool,
+    /// Show the directory tree pane left of the file list. Toggling the
+    /// pane writes this back to the config file.
+    pub show_tree_pane: bool,
+    /// Start where the last run quit, with its sort order and hidden-files
+    /// setting, unless a path is given with `-p`
+    pub restore_last_dir: bool,
+    /// Keep the last 100 submitted searches between runs, in
+    /// `search_history.json` next to the config file. Up in the search
+    /// input recalls them either way.
+    pub persist_search_history: bool,
+    /// Keep how often and how recently each directory was entered between
+    /// runs, in `frecency.json` next to the config file, for the jump popup
+    pub persist_frecency: bool,
+    /// Where this configuration was loaded from, if it came from a file
+    #[serde(skip)]
+    pub source_path: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -157,17 +582,99 @@ impl Default for Config {
             notification_enabled: false,
             key_bindings: KeyBindings::default(),
             file_sharing: FileShareSettings::default(),
+            markdown_links_absolute: true,
+            show_hidden: true,
+            default_sort: SortMode::Name,
+            default_sort_reverse: false,
+            default_pattern_mode: PatternMode::Fuzzy,
+            search_case: CaseMode::Smart,
+            search: SearchSettings::default(),
+            search_index: false,
+            search_roots: Vec::new(),
+            search_exclude: vec![
+                ".git".to_string(),
+                "node_modules".to_string(),
+                "target".to_string(),
+                ".cache".to_string(),
+            ],
+            open_commands: HashMap::new(),
+            open_with: Vec::new(),
+            open_with_recent: HashMap::new(),
+            editor_command: None,
+            terminal_command: None,
+            bookmarks: Vec::new(),
+            show_bookmarks_bar: true,
+            home_base: None,
+            show_selected_path: true,
+            two_line_rows: false,
+            search_preview_width: 40,
+            resolve_symlinks_on_navigate: true,
+            checksum_algorithm: ChecksumAlgorithm::Sha256,
+            use_trash: true,
+            watch_directory: true,
+            read_only: false,
+            git_status: true,
+            hide_gitignored: false,
+            show_pseudo_filesystems: false,
+            remember_view_state: false,
+            show_tree_pane: false,
+            restore_last_dir: false,
+            persist_search_history: true,
+            persist_frecency: true,
+            source_path: None,
         }
     }
 }
 
 impl Config {
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
         let content = fs::read_to_string(path)?;
-        let config: Config = serde_json::from_str(&content)?;
+        let mut config: Config = serde_json::from_str(&content)?;
+        config.source_path = Some(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
         Ok(config)
     }
 
+    /// The editor command template to use, see `editor_command`.
+    pub fn editor_command(&self) -> String {
+        let from_env = || {
+            ["VISUAL", "EDITOR"].iter()
+                .filter_map(|name| env::var(name).ok())
+                .find(|value| !value.trim().is_empty())
+        };
+        let default = if cfg!(windows) { "notepad" } else { "vi" };
+        self.editor_command.clone()
+            .or_else(from_env)
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    /// The terminal command template to use, see `terminal_command`.
+    pub fn terminal_command(&self) -> String {
+        let default = if cfg!(target_os = "macos") {
+            "open -a Terminal {dir}"
+        } else if cfg!(windows) {
+            "cmd /c start cmd"
+        } else {
+            "x-terminal-emulator"
+        };
+        self.terminal_command.clone()
+            .or_else(|| env::var("TERMINAL").ok().filter(|value| !value.trim().is_empty()))
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    /// The configured `home_base`, falling back to $HOME.
+    pub fn home_base(&self) -> Option<PathBuf> {
+        match &self.home_base {
+            Some(base) => Some(expand_home(base)),
+            None => env::var("HOME").ok().map(PathBuf::from),
+        }
+    }
+
+    /// The configured `search_roots`, with `~` expanded.
+    pub fn search_roots(&self) -> Vec<PathBuf> {
+        self.search_roots.iter().map(|root| expand_home(root)).collect()
+    }
+
     pub fn find_config_file() -> Option<PathBuf> {
         // List of potential config file locations in order of preference
         let mut candidates = Vec::new();
@@ -217,29 +724,89 @@ impl Config {
         Self::default()
     }
 
-    pub fn create_default_config_file() -> Result<PathBuf, Box<dyn std::error::Error>> {
-        let config = Self::default();
-        
+    fn default_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
         // Try to create config in user's home directory first
-        let config_path = if let Ok(home) = env::var("HOME") {
+        if let Ok(home) = env::var("HOME") {
             let config_dir = PathBuf::from(home).join(".filepilot");
             fs::create_dir_all(&config_dir)?;
-            config_dir.join("config.json")
+            Ok(config_dir.join("config.json"))
         } else {
             // Fallback to current directory
-            PathBuf::from("config.json")
-        };
+            Ok(PathBuf::from("config.json"))
+        }
+    }
+
+    pub fn create_default_config_file() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let config = Self::default();
+        let config_path = Self::default_config_path()?;
         
         let config_json = serde_json::to_string_pretty(&config)?;
         fs::write(&config_path, config_json)?;
         
         Ok(config_path)
     }
+
+    /// Where a state file called `file_name` lives: next to the loaded
+    /// config file, or in the default config directory.
+    pub fn state_file_path(&self, file_name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let config_path = match &self.source_path {
+            Some(path) => path.clone(),
+            None => Self::default_config_path()?,
+        };
+        Ok(config_path.with_file_name(file_name))
+    }
+
+    /// Writes `bookmarks` back to the loaded config file, or to a new one in
+    /// the default location. Only that key is touched so the rest of the
+    /// user's file stays as written.
+    pub fn save_bookmarks(&mut self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let value = serde_json::to_value(&self.bookmarks)?;
+        self.save_key("bookmarks", value)
+    }
+
+    /// Writes `open_with_recent` back the same way as `save_bookmarks`.
+    pub fn save_open_with_recent(&mut self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let value = serde_json::to_value(&self.open_with_recent)?;
+        self.save_key("open_with_recent", value)
+    }
+
+    /// Writes `show_tree_pane` back the same way as `save_bookmarks`.
+    pub fn save_show_tree_pane(&mut self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.save_key("show_tree_pane", serde_json::Value::Bool(self.show_tree_pane))
+    }
+
+    fn save_key(&mut self, key: &str, value: serde_json::Value) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let config_path = match &self.source_path {
+            Some(path) => path.clone(),
+            None => Self::default_config_path()?,
+        };
+
+        let mut document = match fs::read_to_string(&config_path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::Value::Object(Default::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let object = document.as_object_mut().ok_or("config file is not a JSON object")?;
+        object.insert(key.to_string(), value);
+        fs::write(&config_path, serde_json::to_string_pretty(&document)?)?;
+
+        self.source_path = Some(config_path.clone());
+        Ok(config_path)
+    }
+}
+
+/// `path` with a leading `~` replaced by $HOME, when it is set.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var("HOME")) {
+        (Ok(rest), Ok(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::TempDir;
 
     #[test]
     fn test_key_binding_matching() {
@@ -264,6 +831,22 @@ mod tests {
         assert!(!bindings.matches_key(&vec!["F2".to_string()], &KeyCode::F(3)));
     }
 
+    #[test]
+    fn test_chord_matching() {
+        let bindings = KeyBindings::default();
+        let ctrl_q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        let plain_q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+
+        assert!(bindings.matches_chord(&["Ctrl+q".to_string()], &ctrl_q));
+        assert!(!bindings.matches_chord(&["Ctrl+q".to_string()], &plain_q));
+        assert!(bindings.matches_chord(&["q".to_string()], &plain_q));
+        assert!(!bindings.matches_chord(&["q".to_string()], &ctrl_q));
+
+        let alt_left = KeyEvent::new(KeyCode::Left, KeyModifiers::ALT);
+        assert!(bindings.matches_chord(&["Alt+Left".to_string()], &alt_left));
+        assert!(!bindings.matches_chord(&["Ctrl+Left".to_string()], &alt_left));
+    }
+
     #[test]
     fn test_key_display() {
         let bindings = KeyBindings::default();
@@ -273,6 +856,60 @@ mod tests {
         assert_eq!(bindings.get_key_display(&vec!["o".to_string(), "O".to_string()]), "o/O");
     }
 
+    #[test]
+    fn test_home_base_expands_tilde() {
+        let home = PathBuf::from(env::var("HOME").unwrap());
+        let mut config = Config::default();
+        assert_eq!(config.home_base(), Some(home.clone()));
+
+        config.home_base = Some(PathBuf::from("~/projects"));
+        assert_eq!(config.home_base(), Some(home.join("projects")));
+
+        config.home_base = Some(PathBuf::from("/srv/work"));
+        assert_eq!(config.home_base(), Some(PathBuf::from("/srv/work")));
+
+        config.search_roots = vec![PathBuf::from("~/work"), PathBuf::from("/srv/oss")];
+        assert_eq!(config.search_roots(), [home.join("work"), PathBuf::from("/srv/oss")]);
+    }
+
+    #[test]
+    fn test_share_bind_address_and_ports() {
+        let mut settings = FileShareSettings::default();
+        assert_eq!(settings.bind_ip(), Ok(IpAddr::V4(Ipv4Addr::UNSPECIFIED)));
+        settings.bind_address = Some("localhost".to_string());
+        assert_eq!(settings.bind_ip(), Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        settings.bind_address = Some("192.168.1.20".to_string());
+        assert_eq!(settings.bind_ip(), Ok("192.168.1.20".parse().unwrap()));
+        settings.bind_address = Some("192.168.1".to_string());
+        assert!(settings.bind_ip().unwrap_err().contains("Invalid share bind address '192.168.1'"));
+
+        settings.set_ports("9000").unwrap();
+        assert_eq!(settings.fixed_port, Some(9000));
+        settings.set_ports("9100-9104").unwrap();
+        assert_eq!(settings.fixed_port, None);
+        assert_eq!((settings.port_range_start, settings.port_range_end), (9100, 9105));
+        for invalid in ["", "0", "70000", "9104-9100", "http", "9100-"] {
+            assert!(settings.set_ports(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!((settings.port_range_start, settings.port_range_end), (9100, 9105));
+    }
+
+    #[test]
+    fn test_save_bookmarks_keeps_other_settings() {
+        let dir = TempDir::new("bookmarks");
+        let path = dir.join("config.json");
+        fs::write(&path, r#"{ "show_hidden": false }"#).unwrap();
+
+        let mut config = Config::load_from_file(&path).unwrap();
+        config.bookmarks.push(PathBuf::from("/srv/projects"));
+        config.save_bookmarks().unwrap();
+
+        let reloaded = Config::load_from_file(&path).unwrap();
+        assert_eq!(reloaded.bookmarks, vec![PathBuf::from("/srv/projects")]);
+        assert!(!reloaded.show_hidden);
+
+    }
+
     #[test]
     fn test_default_config() {
         let config = Config::default();
@@ -286,6 +923,9 @@ mod tests {
         // Test default action keys
         assert!(config.key_bindings.matches_key(&config.key_bindings.actions.quit, &KeyCode::Char('q')));
         assert!(config.key_bindings.matches_key(&config.key_bindings.actions.search, &KeyCode::Char('/')));
+
+        // Entering a symlinked directory goes to its real location
+        assert!(config.resolve_symlinks_on_navigate);
     }
 
     #[test]
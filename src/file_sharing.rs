@@ -1,30 +1,55 @@
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use warp::Filter;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::task::JoinHandle;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
 use uuid::Uuid;
+use rand::{rngs::OsRng, RngCore};
 use arboard::Clipboard;
 use local_ip_address::local_ip;
 use csv::ReaderBuilder;
-use calamine::{Reader, Xlsx, Xls, open_workbook};
+use calamine::{Reader, open_workbook_auto};
 use serde::{Deserialize, Serialize};
-use crate::config::Config;
+use crate::config::{Config, FileShareSettings};
+use crate::file_system::{self, archive, FileKind};
 use qrcode::{QrCode, EcLevel};
-use image::{Luma};
-use base64::{Engine as _, engine::general_purpose};
+
+pub mod tls;
+
+use tls::TlsIdentity;
 
 // Size limits for different file types
 const MAX_JSON_CLIENT_SIZE: u64 = 5 * 1024 * 1024; // 5MB limit for client-side JSON processing
 const MAX_NOTEBOOK_SIZE: u64 = 50 * 1024 * 1024; // 50MB limit for notebooks
 const MAX_MARKDOWN_SIZE: u64 = 5 * 1024 * 1024; // 5MB limit for markdown
 const MAX_SPREADSHEET_SIZE: u64 = 10 * 1024 * 1024; // 10MB limit for spreadsheets
-const MAX_TEXT_PREVIEW_SIZE: u64 = 10 * 1024 * 1024; // 10MB limit for text file previews
 const MAX_CODE_PREVIEW_SIZE: u64 = 5 * 1024 * 1024; // 5MB limit for code file previews
 const MAX_FILE_PREVIEW_SIZE: u64 = 5 * 1024 * 1024; // 5MB global limit for any file preview
-const MAX_CSV_ROWS: usize = 1000; // Maximum rows to display for CSV
-const MAX_EXCEL_ROWS: usize = 1000; // Maximum rows to display for Excel
+const PREVIEW_COUNT_LIMIT: usize = 50_000; // Rows or lines counted for a paged preview's total
+const MAX_PREVIEW_LINE_BYTES: usize = 16 * 1024; // Bytes of each line a text page keeps
+
+// How often shares past their expiry are dropped from the map
+const PRUNE_INTERVAL: Duration = Duration::from_secs(30);
+
+// How long shutdown waits for open connections to finish before dropping them
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Room in an upload's body for the multipart headers around its files
+const UPLOAD_FORM_OVERHEAD: u64 = 64 * 1024;
+
+// Requests to shared files the access log keeps, oldest dropped first
+const ACCESS_LOG_CAPACITY: usize = 1000;
+
+// Views and downloads waiting for the UI to report them; more are dropped
+const ACCESS_EVENT_BACKLOG: usize = 64;
+
+// Files modified more recently than this may still be being written (e.g. a download in progress)
+const STILL_CHANGING_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct FileShareNotification {
@@ -42,40 +67,918 @@ struct FileInfo {
     id: String,
     name: String,
     path: String,
+    /// The workbook sheet to show, counting from 0
+    sheet: usize,
+    /// The page of a CSV or text file to show, counting from 0
+    page: usize,
+}
+
+/// What a share link asks for besides its ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareProtection {
+    /// A random token, added to the link as `?t=...`
+    Token,
+    /// A password typed into the viewer page
+    Password(String),
+}
+
+impl ShareProtection {
+    /// The protection the config asks for: its password, or else a token.
+    pub fn configured(settings: &FileShareSettings) -> Self {
+        match &settings.password {
+            Some(password) => ShareProtection::Password(password.clone()),
+            None => ShareProtection::Token,
+        }
+    }
+}
+
+/// How long a share link works for, and who it works for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareOptions {
+    /// `None` keeps the link working until the server shuts down
+    pub ttl: Option<Duration>,
+    /// Whole-file downloads allowed through the download link
+    pub max_downloads: Option<u64>,
+    pub protection: Option<ShareProtection>,
+}
+
+impl From<&FileShareSettings> for ShareOptions {
+    fn from(settings: &FileShareSettings) -> Self {
+        ShareOptions {
+            ttl: settings.expiry_minutes.map(|minutes| Duration::from_secs(minutes * 60)),
+            max_downloads: settings.max_downloads,
+            protection: settings.protect.then(|| ShareProtection::configured(settings)),
+        }
+    }
+}
+
+impl ShareOptions {
+    /// What limits the link, e.g. "password required, expires in 1h or
+    /// after 3 downloads", or `None` when nothing does.
+    pub fn describe(&self) -> Option<String> {
+        let downloads = self.max_downloads.map(|count| match count {
+            1 => "after 1 download".to_string(),
+            _ => format!("after {} downloads", count),
+        });
+        let expiry = match (self.ttl, downloads) {
+            (Some(ttl), Some(downloads)) => Some(format!("expires in {} or {}", file_system::span_label(ttl), downloads)),
+            (Some(ttl), None) => Some(format!("expires in {}", file_system::span_label(ttl))),
+            (None, Some(downloads)) => Some(format!("expires {}", downloads)),
+            (None, None) => None,
+        };
+        let protection = self.protection.as_ref().map(|protection| match protection {
+            ShareProtection::Token => "token in link".to_string(),
+            ShareProtection::Password(_) => "password required".to_string(),
+        });
+        let notes: Vec<String> = protection.into_iter().chain(expiry).collect();
+        (!notes.is_empty()).then(|| notes.join(", "))
+    }
+}
+
+/// What a protected share checks requests against.
+enum Lock {
+    Token(String),
+    Password(String),
+}
+
+/// A file being served under its share ID.
+struct ShareEntry {
+    path: PathBuf,
+    shared_at: SystemTime,
+    /// Viewer page loads and downloads; the raw route isn't counted, as the
+    /// viewer page itself fetches from it
+    hits: AtomicU64,
+    expires_at: Option<SystemTime>,
+    remaining_downloads: Option<AtomicU64>,
+    lock: Option<Lock>,
+    /// Cookie value given to browsers that passed the token or password,
+    /// so the viewer page's own requests for the file get through
+    session: String,
+}
+
+/// How a request uses a share.
+enum Access {
+    /// The viewer page, counted as a hit
+    View,
+    /// Content fetched by the viewer page
+    Embed,
+    /// The download link; `whole` when it starts from the first byte, which
+    /// uses up one of the allowed downloads
+    Download { whole: bool },
+}
+
+/// A share link past its expiry or download limit, answered with 410 Gone.
+#[derive(Debug)]
+struct ShareGone;
+
+impl warp::reject::Reject for ShareGone {}
+
+/// A request for a protected share without the token, password or cookie,
+/// answered with 403 Forbidden.
+#[derive(Debug)]
+struct ShareForbidden;
+
+impl warp::reject::Reject for ShareForbidden {}
+
+/// Why a protected share turned a request away.
+#[derive(Debug, PartialEq, Eq)]
+enum Locked {
+    /// The viewer page can ask for the password
+    Password,
+    /// Nothing but the link's token gets in
+    Token,
+}
+
+/// Compares secrets in time that depends only on their lengths, so timing
+/// answers doesn't reveal how much of a guess was right.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `bytes` random bytes from the operating system, hex-encoded, for
+/// secrets a visitor must not be able to guess.
+fn random_secret(bytes: usize) -> String {
+    let mut secret = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut secret);
+    secret.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The value of the cookie called `name` in a `Cookie` header.
+fn cookie_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn session_cookie_name(file_id: &str) -> String {
+    format!("filepilot-share-{}", file_id)
+}
+
+impl ShareEntry {
+    /// What goes after the share's path in its links: the token, if it has
+    /// one.
+    fn query(&self) -> String {
+        match &self.lock {
+            Some(Lock::Token(token)) => format!("?t={}", token),
+            _ => String::new(),
+        }
+    }
+
+    fn protection(&self) -> Option<ShareProtection> {
+        self.lock.as_ref().map(|lock| match lock {
+            Lock::Token(_) => ShareProtection::Token,
+            Lock::Password(password) => ShareProtection::Password(password.clone()),
+        })
+    }
+
+    /// The `Set-Cookie` value that lets a browser in from now on; `secure`
+    /// when shares are served over HTTPS, so it is never sent in the clear.
+    fn session_cookie(&self, file_id: &str, secure: bool) -> String {
+        let secure = if secure { "; Secure" } else { "" };
+        format!("{}={}; Path=/; HttpOnly; SameSite=Strict{}", session_cookie_name(file_id), self.session, secure)
+    }
+
+    /// Checks a request's `token` and `Cookie` header against the share's
+    /// lock. Passing the token earns the session cookie: true when it
+    /// should be set on the response.
+    fn authorize(&self, file_id: &str, token: Option<&str>, cookies: Option<&str>) -> Result<bool, Locked> {
+        let Some(lock) = &self.lock else {
+            return Ok(false);
+        };
+        let name = session_cookie_name(file_id);
+        if cookies.and_then(|header| cookie_value(header, &name)).is_some_and(|value| constant_time_eq(value, &self.session)) {
+            return Ok(false);
+        }
+        match lock {
+            Lock::Token(expected) if token.is_some_and(|token| constant_time_eq(token, expected)) => Ok(true),
+            Lock::Token(_) => Err(Locked::Token),
+            Lock::Password(_) => Err(Locked::Password),
+        }
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+            || self.remaining_downloads.as_ref().is_some_and(|remaining| remaining.load(Ordering::Relaxed) == 0)
+    }
+
+    /// The shared path for a request, counted as `access` says, or a
+    /// `ShareGone` rejection once the link has expired.
+    fn open(&self, access: Access) -> Result<&PathBuf, Rejection> {
+        if self.is_expired(SystemTime::now()) {
+            return Err(warp::reject::custom(ShareGone));
+        }
+        match access {
+            Access::View => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            Access::Embed => {}
+            Access::Download { whole } => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                if let Some(remaining) = self.remaining_downloads.as_ref().filter(|_| whole) {
+                    // Another download may have taken the last one meanwhile
+                    remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| left.checked_sub(1))
+                        .map_err(|_| warp::reject::custom(ShareGone))?;
+                }
+            }
+        }
+        Ok(&self.path)
+    }
+}
+
+/// Drops the shares in `files` that have expired by `now`, returning how
+/// many there were.
+fn prune_expired(files: &mut HashMap<String, ShareEntry>, now: SystemTime) -> usize {
+    let before = files.len();
+    files.retain(|_, shared| !shared.is_expired(now));
+    before - files.len()
+}
+
+/// A bare page with a heading and some body HTML, for the pages shown in
+/// place of a shared file.
+fn message_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title></head>\
+        <body style=\"font-family: sans-serif; background: #1e1e1e; color: #ddd; text-align: center; padding-top: 15%;\">\
+        <h1>{}</h1>{}</body></html>",
+        title, title, body
+    )
+}
+
+/// The form a password-protected share's viewer page shows instead, with
+/// a note when the last attempt was wrong.
+fn password_page(file_id: &str, wrong: bool) -> String {
+    let note = if wrong { "<p style=\"color: #f85149;\">Wrong password, try again.</p>" } else { "" };
+    let form = format!(
+        "{}<form method=\"post\" action=\"/file/{}\">\
+        <input type=\"password\" name=\"password\" autofocus style=\"padding: 8px;\"/> \
+        <button type=\"submit\" style=\"padding: 8px;\">Open</button></form>",
+        note, escape_html(file_id)
+    );
+    message_page("This file is password protected", &form)
+}
+
+/// Resolves the rest of a request's path under a share (e.g.
+/// `docs/a%20b.txt`) to a path inside `root`, which for folders is
+/// canonical. Every segment must decode to a single plain name, and the
+/// result is canonicalized and checked to still be under `root`, so
+/// neither `..` nor a symlink can lead out of the share.
+fn resolve_in_share(root: &Path, tail: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for segment in tail.split('/').filter(|segment| !segment.is_empty()) {
+        let name = decode_segment(segment)?;
+        match Path::new(&name).components().collect::<Vec<_>>().as_slice() {
+            [std::path::Component::Normal(_)] => path.push(&name),
+            _ => return None,
+        }
+    }
+    if path == root {
+        return Some(path);
+    }
+    let resolved = path.canonicalize().ok()?;
+    resolved.starts_with(root).then_some(resolved)
+}
+
+/// Percent-decodes one URL path segment into a file name.
+fn decode_segment(segment: &str) -> Option<std::ffi::OsString> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Some(std::ffi::OsString::from_vec(decoded))
+    }
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(decoded).ok().map(std::ffi::OsString::from)
+    }
+}
+
+/// `relative` as a URL path, each component percent-encoded byte by byte
+/// so that any file name comes back intact through `decode_segment`.
+fn link_path(relative: &Path) -> String {
+    relative.components()
+        .map(|component| {
+            #[cfg(unix)]
+            let bytes = std::os::unix::ffi::OsStrExt::as_bytes(component.as_os_str()).to_vec();
+            #[cfg(not(unix))]
+            let bytes = component.as_os_str().to_string_lossy().into_owned().into_bytes();
+            bytes.iter()
+                .map(|&byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+                    _ => format!("%{:02X}", byte),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The id `target` is served under: the share's own for the shared path,
+/// followed by the path under it for anything inside a shared folder.
+fn share_path_id(file_id: &str, root: &Path, target: &Path) -> String {
+    let relative = target.strip_prefix(root).unwrap_or(Path::new(""));
+    if relative.as_os_str().is_empty() {
+        file_id.to_string()
+    } else {
+        format!("{}/{}", file_id, link_path(relative))
+    }
+}
+
+/// How many files sharing `path` exposes: the regular files anywhere under
+/// it for a folder, counted the way its zip download walks it.
+pub fn exposed_file_count(path: &Path) -> usize {
+    walkdir::WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .count()
+}
+
+/// Modification time as e.g. "2024-03-09 14:05 UTC".
+fn modified_label(metadata: &std::fs::Metadata) -> String {
+    metadata.modified()
+        .map(|modified| {
+            let (year, month, day, hour, minute, _) = file_system::utc_date_time(modified);
+            format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, hour, minute)
+        })
+        .unwrap_or_default()
+}
+
+/// The page a folder share shows for `dir`, the shared `root` or a folder
+/// inside it: a row per entry with its size and modification time, links
+/// to view, fetch or download each file, and a button that downloads the
+/// whole folder as a zip.
+fn directory_page(file_id: &str, root: &Path, dir: &Path) -> String {
+    let relative = dir.strip_prefix(root).unwrap_or(Path::new(""));
+    let base = if relative.as_os_str().is_empty() {
+        file_id.to_string()
+    } else {
+        format!("{}/{}", file_id, link_path(relative))
+    };
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .map(|entries| entries.filter_map(Result::ok)
+            .filter_map(|entry| {
+                let path = entry.path();
+                let metadata = std::fs::metadata(&path).ok()?;
+                // Symlinks leading out of the share would only answer 404
+                if entry.file_type().ok()?.is_symlink() && !path.canonicalize().ok()?.starts_with(root) {
+                    return None;
+                }
+                Some((entry.file_name(), metadata))
+            })
+            .collect())
+        .unwrap_or_default();
+    entries.sort_by_cached_key(|(name, metadata)| (!metadata.is_dir(), name.to_string_lossy().to_lowercase()));
+
+    let folders = entries.iter().filter(|(_, metadata)| metadata.is_dir()).count();
+    let mut rows = String::new();
+    if let Some(parent) = relative.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            file_id.to_string()
+        } else {
+            format!("{}/{}", file_id, link_path(parent))
+        };
+        rows.push_str(&format!("<tr><td><a href=\"/file/{}/\">..</a></td><td></td><td></td><td></td></tr>", parent));
+    }
+    for (name, metadata) in &entries {
+        let link = format!("{}/{}", base, link_path(Path::new(name)));
+        let name = escape_html(&name.to_string_lossy());
+        if metadata.is_dir() {
+            rows.push_str(&format!(
+                "<tr><td><a href=\"/file/{}/\">📁 {}/</a></td><td></td><td>{}</td><td><a href=\"/zip/{}\">.zip</a></td></tr>",
+                link, name, modified_label(metadata), link
+            ));
+        } else {
+            rows.push_str(&format!(
+                "<tr><td><a href=\"/file/{}\">{}</a></td><td>{}</td><td>{}</td>\
+                <td><a href=\"/raw/{}\">raw</a> | <a href=\"/download/{}\">download</a></td></tr>",
+                link, name, file_system::format_size(metadata.len()), modified_label(metadata), link, link
+            ));
+        }
+    }
+
+    let title = escape_html(&Path::new(&file_system::display_name(root)).join(relative).to_string_lossy());
+    format!(
+        "<!DOCTYPE html>\
+        <html><head>\
+        <title>FilePilot - {}</title>\
+        <meta charset=\"UTF-8\">\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\
+        <style>\
+        body {{ font-family: Arial, sans-serif; margin: 20px; background-color: #1a1a1a; color: #e0e0e0; }}\
+        h1 {{ color: #ffffff; border-bottom: 2px solid #0d7377; padding-bottom: 10px; }}\
+        table {{ border-collapse: collapse; width: 100%; }}\
+        th, td {{ text-align: left; padding: 8px 12px; border-bottom: 1px solid #333; }}\
+        a {{ color: #58a6ff; text-decoration: none; }}\
+        a:hover {{ text-decoration: underline; }}\
+        .button {{ display: inline-block; background: #0d7377; color: #fff; padding: 10px 16px; border-radius: 6px; margin-bottom: 16px; }}\
+        </style>\
+        </head><body>\
+        <h1>📁 {}</h1>\
+        <p>{} folders, {} files</p>\
+        <a class=\"button\" href=\"/zip/{}\">Download all as .zip</a>\
+        <table><tr><th>Name</th><th>Size</th><th>Modified</th><th></th></tr>{}</table>\
+        </body></html>",
+        title, title, folders, entries.len() - folders, base, rows
+    )
+}
+
+/// Hands what a blocking writer produces to a streamed response body.
+struct BodySender {
+    sender: Option<warp::hyper::body::Sender>,
+    runtime: tokio::runtime::Handle,
+}
+
+impl std::io::Write for BodySender {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let sender = self.sender.as_mut().ok_or(std::io::ErrorKind::BrokenPipe)?;
+        // Fails once the client has gone away
+        self.runtime.block_on(sender.send_data(warp::hyper::body::Bytes::copy_from_slice(buf)))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Answers requests for expired shares with 410 Gone and ones turned away
+/// by a share's protection with 403 Forbidden, each with a short page, and
+/// leaves every other rejection to warp.
+async fn recover_share_errors(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    let (status, page) = if rejection.find::<ShareGone>().is_some() {
+        (StatusCode::GONE, message_page("This link has expired", "<p>Ask the sender to share the file again.</p>"))
+    } else if rejection.find::<ShareForbidden>().is_some() {
+        (StatusCode::FORBIDDEN, message_page("Access denied", "<p>This link is missing its access token.</p>"))
+    } else {
+        return Err(rejection);
+    };
+    Ok(warp::reply::with_status(warp::reply::html(page), status))
+}
+
+/// Where files sent to `/upload` are saved, while uploads are on.
+#[derive(Debug, Clone)]
+struct UploadTarget {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+/// A file another device sent through the upload page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// A request for a shared file, as the access log keeps it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRecord {
+    pub at: SystemTime,
+    pub client: Option<IpAddr>,
+    pub file_id: String,
+    /// The shared file or folder, followed by the path asked for inside a
+    /// folder share
+    pub name: String,
+    /// `file`, `raw`, `download` or `zip`
+    pub route: String,
+    /// The `Range` header of a partial request, such as a resumed download
+    pub range: Option<String>,
+    pub status: u16,
+}
+
+impl AccessRecord {
+    /// What the status bar says about the request: viewer pages and
+    /// downloads that were answered, but not the rest of a download already
+    /// reported or requests turned away.
+    pub fn summary(&self) -> Option<String> {
+        let whole = self.range.as_deref().is_none_or(|range| range.trim_start().starts_with("bytes=0-"));
+        let action = match (self.route.as_str(), self.status) {
+            ("file", 200) if !self.name.contains('/') => "opened",
+            ("download", 200) => "downloaded",
+            ("download", 206) if whole => "downloaded",
+            ("zip", 200) => "downloaded as a zip",
+            _ => return None,
+        };
+        let client = self.client.map(|ip| ip.to_string()).unwrap_or_else(|| "unknown client".to_string());
+        Some(format!("'{}' {} by {}", self.name, action, client))
+    }
+
+    /// The record as a line of the access log file.
+    fn log_line(&self) -> String {
+        let (year, month, day, hour, minute, second) = file_system::utc_date_time(self.at);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC {} {} {} {} {} {}",
+            year, month, day, hour, minute, second,
+            self.client.map(|ip| ip.to_string()).unwrap_or_else(|| "-".to_string()),
+            self.route, self.file_id, self.status, self.range.as_deref().unwrap_or("-"), self.name
+        )
+    }
+}
+
+/// Where the server records requests to shared files: the bounded list
+/// the UI can look through, the channel it reports views and downloads
+/// from, and the optional log file.
+#[derive(Clone)]
+struct AccessLog {
+    records: Arc<std::sync::Mutex<VecDeque<AccessRecord>>>,
+    events: mpsc::Sender<AccessRecord>,
+    file: Option<Arc<std::sync::Mutex<std::fs::File>>>,
+}
+
+impl AccessLog {
+    /// Records the answer `info` describes, when it was for a shared file.
+    fn record(&self, info: warp::log::Info, shared_files: &RwLock<HashMap<String, ShareEntry>>) {
+        if info.method() == warp::http::Method::POST {
+            return;
+        }
+        let mut segments = info.path().trim_start_matches('/').splitn(3, '/');
+        let (Some(route), Some(file_id)) = (segments.next(), segments.next()) else {
+            return;
+        };
+        if !matches!(route, "file" | "raw" | "download" | "zip") {
+            return;
+        }
+        let tail: Vec<String> = segments.next().unwrap_or("")
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| decode_segment(segment).map_or_else(|| segment.to_string(), |name| name.to_string_lossy().into_owned()))
+            .collect();
+        // Skipped rather than waited for while a share is being added
+        let shared_name = shared_files.try_read().ok()
+            .and_then(|files| files.get(file_id).map(|shared| file_system::display_name(&shared.path)));
+        let name = std::iter::once(shared_name.unwrap_or_else(|| file_id.to_string()))
+            .chain(tail)
+            .collect::<Vec<_>>()
+            .join("/");
+
+        self.push(AccessRecord {
+            at: SystemTime::now(),
+            client: info.remote_addr().map(|address| address.ip()),
+            file_id: file_id.to_string(),
+            name,
+            route: route.to_string(),
+            range: info.request_headers().get("range").and_then(|range| range.to_str().ok()).map(str::to_string),
+            status: info.status().as_u16(),
+        });
+    }
+
+    fn push(&self, record: AccessRecord) {
+        if let Some(file) = &self.file {
+            use std::io::Write;
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            let _ = writeln!(file, "{}", record.log_line());
+        }
+        if record.summary().is_some() {
+            // A UI that isn't keeping up misses some, rather than piling them up
+            let _ = self.events.try_send(record.clone());
+        }
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if records.len() == ACCESS_LOG_CAPACITY {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+}
+
+/// An upload that couldn't be saved, answered with `status` and a page
+/// saying why.
+#[derive(Debug)]
+struct UploadFailed {
+    status: StatusCode,
+    reason: String,
+}
+
+impl UploadFailed {
+    fn new(status: StatusCode, reason: impl Into<String>) -> Self {
+        Self { status, reason: reason.into() }
+    }
+}
+
+/// A name from an upload's form data that is safe to create in the drop
+/// directory: only the last path component, without control or reserved
+/// characters, trailing dots or spaces, and never `.` or `..`.
+fn sanitize_upload_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = name.chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*') { '_' } else { c })
+        .collect();
+    let mut cleaned = cleaned.trim().trim_end_matches(['.', ' ']).to_string();
+    // Names Windows keeps for devices, with or without an extension
+    let stem = cleaned.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4 && (stem.starts_with("COM") || stem.starts_with("LPT")) && stem.ends_with(|c: char| c.is_ascii_digit()));
+    if reserved {
+        cleaned.insert(0, '_');
+    }
+    while cleaned.len() > 255 {
+        cleaned.pop();
+    }
+    if cleaned.is_empty() {
+        "upload".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Creates `name` in `dir` for an upload, numbering it "name (1).ext" and
+/// so on when taken. Creating with `create_new` means two uploads racing
+/// for the same name never write into one file.
+async fn create_upload_file(dir: &Path, name: &str) -> std::io::Result<(PathBuf, tokio::fs::File)> {
+    let mut path = dir.join(name);
+    loop {
+        match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                path = file_system::numbered_target(&dir.join(name), false);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Writes one uploaded file into `target.dir`, giving up and removing what
+/// was written once the upload has gone past `budget` bytes.
+async fn save_upload(part: warp::multipart::Part, name: &str, target: &UploadTarget, budget: u64) -> Result<ReceivedFile, UploadFailed> {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+    use warp::hyper::body::Buf;
+
+    let (path, mut file) = create_upload_file(&target.dir, name).await
+        .map_err(|e| UploadFailed::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Couldn't save '{}': {}", name, e)))?;
+    let mut size = 0u64;
+    let mut stream = part.stream();
+    let result = async {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| UploadFailed::new(StatusCode::BAD_REQUEST, format!("Upload interrupted: {}", e)))?;
+            size += chunk.remaining() as u64;
+            if size > budget {
+                return Err(UploadFailed::new(StatusCode::PAYLOAD_TOO_LARGE, too_large(target.max_bytes)));
+            }
+            file.write_all(chunk.chunk()).await
+                .map_err(|e| UploadFailed::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Couldn't save '{}': {}", name, e)))?;
+        }
+        file.flush().await
+            .map_err(|e| UploadFailed::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Couldn't save '{}': {}", name, e)))
+    }.await;
+    if let Err(failed) = result {
+        drop(file);
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(failed);
+    }
+    Ok(ReceivedFile { path, size })
+}
+
+fn too_large(max_bytes: u64) -> String {
+    format!("Uploads can be at most {}.", file_system::format_size(max_bytes))
+}
+
+/// The form other devices send files from.
+fn upload_page(max_bytes: u64) -> String {
+    format!(
+        "<!DOCTYPE html>\
+        <html><head>\
+        <title>FilePilot - Send Files</title>\
+        <meta charset=\"UTF-8\">\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\
+        <style>\
+        body {{ font-family: Arial, sans-serif; margin: 20px; background-color: #1a1a1a; color: #e0e0e0; text-align: center; }}\
+        h1 {{ color: #ffffff; border-bottom: 2px solid #0d7377; padding-bottom: 10px; }}\
+        input, button {{ font-size: 1.1em; margin: 12px; }}\
+        button {{ background: #0d7377; color: #fff; border: none; padding: 10px 20px; border-radius: 6px; }}\
+        </style>\
+        </head><body>\
+        <h1>Send files to FilePilot</h1>\
+        <form method=\"post\" action=\"/upload\" enctype=\"multipart/form-data\">\
+        <input type=\"file\" name=\"file\" multiple required/><br/>\
+        <button type=\"submit\">Upload</button>\
+        </form>\
+        <p>Up to {} in total.</p>\
+        </body></html>",
+        file_system::format_size(max_bytes)
+    )
+}
+
+/// What the share manager shows about one share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareInfo {
+    pub file_id: String,
+    pub path: PathBuf,
+    pub url: String,
+    pub shared_at: SystemTime,
+    pub hits: u64,
+    pub expires_at: Option<SystemTime>,
+    pub remaining_downloads: Option<u64>,
+    pub protection: Option<ShareProtection>,
+    /// Who last opened or downloaded the share
+    pub last_client: Option<IpAddr>,
 }
 
 pub struct FileShareServer {
-    shared_files: Arc<RwLock<HashMap<String, PathBuf>>>,
+    shared_files: Arc<RwLock<HashMap<String, ShareEntry>>>,
     server_port: u16,
     is_running: Arc<RwLock<bool>>,
+    /// Tells the running server to stop accepting connections
+    shutdown_signal: Option<oneshot::Sender<()>>,
+    /// The running server, finished once its socket is closed
+    server_task: Option<JoinHandle<()>>,
+    /// Where `/upload` saves files, or `None` while it answers 404
+    uploads: Arc<RwLock<Option<UploadTarget>>>,
+    /// Files uploaded since the UI last asked
+    received: Arc<std::sync::Mutex<Vec<ReceivedFile>>>,
+    /// The most recent requests to shared files, oldest first
+    access_records: Arc<std::sync::Mutex<VecDeque<AccessRecord>>>,
+    access_sender: mpsc::Sender<AccessRecord>,
+    /// Views and downloads the UI hasn't reported yet
+    access_events: mpsc::Receiver<AccessRecord>,
+    /// The address the running server listens on
+    bind_ip: IpAddr,
+    /// SHA-256 of the certificate while serving HTTPS
+    tls_fingerprint: Option<String>,
+    /// Why the running server uses plain HTTP although TLS is configured
+    tls_warning: Option<String>,
     config: Config,
 }
 
 impl FileShareServer {
-    pub fn new() -> Self {
-        let config = Config::load_default();
+    pub fn new(config: Config) -> Self {
+        let (access_sender, access_events) = mpsc::channel(ACCESS_EVENT_BACKLOG);
         Self {
             shared_files: Arc::new(RwLock::new(HashMap::new())),
             server_port: config.file_sharing.server_port,
             is_running: Arc::new(RwLock::new(false)),
+            shutdown_signal: None,
+            server_task: None,
+            uploads: Arc::new(RwLock::new(None)),
+            received: Arc::new(std::sync::Mutex::new(Vec::new())),
+            access_records: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            access_sender,
+            access_events,
+            bind_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            tls_fingerprint: None,
+            tls_warning: None,
             config,
         }
     }
 
+    /// Stops the server and forgets every share. Requests already being
+    /// answered get `SHUTDOWN_GRACE` to finish; once this returns the port
+    /// is free, and sharing again starts a new server.
     pub async fn shutdown(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut is_running = self.is_running.write().await;
-        *is_running = false;
-        
-        // Clear shared files
-        let mut shared_files = self.shared_files.write().await;
-        shared_files.clear();
-        
-        // Give the server a moment to shut down gracefully
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        
+        *self.is_running.write().await = false;
+        self.shared_files.write().await.clear();
+        *self.uploads.write().await = None;
+
+        if let Some(signal) = self.shutdown_signal.take() {
+            // The server may have stopped on its own already
+            let _ = signal.send(());
+        }
+        if let Some(mut task) = self.server_task.take() {
+            if tokio::time::timeout(SHUTDOWN_GRACE, &mut task).await.is_err() {
+                // Connections still open after the grace period are dropped
+                task.abort();
+                let _ = task.await;
+            }
+        }
         Ok(())
     }
 
+    /// Starts accepting files at `/upload` into `dir`, at most `max_bytes`
+    /// per request, starting the server if needed. Returns the page's URL.
+    pub async fn enable_uploads(&mut self, dir: PathBuf, max_bytes: u64) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if !dir.is_dir() {
+            return Err(format!("'{}' is not a directory", dir.display()).into());
+        }
+        self.start_server().await?;
+        *self.uploads.write().await = Some(UploadTarget { dir, max_bytes });
+        let origin = self.origin().await.ok_or("Share server is not running")?;
+        Ok(format!("{}/upload", origin))
+    }
+
+    /// Stops accepting uploads; the page answers 404 again.
+    pub async fn disable_uploads(&self) {
+        *self.uploads.write().await = None;
+    }
+
+    /// The directory uploads go to, while they are on.
+    pub async fn upload_dir(&self) -> Option<PathBuf> {
+        self.uploads.read().await.as_ref().map(|target| target.dir.clone())
+    }
+
+    /// Files received since the last call, oldest first.
+    pub fn take_received(&self) -> Vec<ReceivedFile> {
+        std::mem::take(&mut *self.received.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// The most recent requests to shared files, oldest first.
+    pub fn access_log(&self) -> Vec<AccessRecord> {
+        self.access_records.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    /// Views and downloads since the last call, oldest first.
+    pub fn take_access_events(&mut self) -> Vec<AccessRecord> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.access_events.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    pub async fn shared_file_count(&self) -> usize {
+        self.shared_files.read().await.len()
+    }
+
+    /// Where share links point: the bound port on this machine's LAN IP,
+    /// or on `bind_address` when one is set, over HTTPS when the server has
+    /// a certificate. `None` while the server isn't running.
+    pub async fn origin(&self) -> Option<String> {
+        if !*self.is_running.read().await {
+            return None;
+        }
+        let address = SocketAddr::new(url_host(self.bind_ip), self.server_port);
+        Some(format!("{}://{}", self.scheme(), address))
+    }
+
+    fn scheme(&self) -> &'static str {
+        if self.tls_fingerprint.is_some() { "https" } else { "http" }
+    }
+
+    /// SHA-256 of the certificate the running server uses, for recipients
+    /// to check against what their browser shows.
+    pub fn tls_fingerprint(&self) -> Option<&str> {
+        self.tls_fingerprint.as_deref()
+    }
+
+    /// Why the running server fell back to plain HTTP, when TLS is
+    /// configured but its files couldn't be used.
+    pub fn tls_warning(&self) -> Option<&str> {
+        self.tls_warning.as_deref()
+    }
+
+    /// URL of the page listing every file shared this session.
+    pub async fn list_url(&self) -> Option<String> {
+        self.origin().await.map(|origin| format!("{}/list", origin))
+    }
+
+    /// Every file being shared whose link still works, most recently
+    /// shared first.
+    pub async fn list_shares(&self) -> Vec<ShareInfo> {
+        let Some(origin) = self.origin().await else {
+            return Vec::new();
+        };
+        let now = SystemTime::now();
+        let last_clients: HashMap<String, Option<IpAddr>> = self.access_log().into_iter()
+            .filter(|record| record.summary().is_some())
+            .map(|record| (record.file_id, record.client))
+            .collect();
+        let files = self.shared_files.read().await;
+        let mut shares: Vec<ShareInfo> = files.iter()
+            .filter(|(_, shared)| !shared.is_expired(now))
+            .map(|(file_id, shared)| ShareInfo {
+                file_id: file_id.clone(),
+                path: shared.path.clone(),
+                url: format!("{}/file/{}{}", origin, file_id, shared.query()),
+                shared_at: shared.shared_at,
+                hits: shared.hits.load(Ordering::Relaxed),
+                expires_at: shared.expires_at,
+                remaining_downloads: shared.remaining_downloads.as_ref().map(|remaining| remaining.load(Ordering::Relaxed)),
+                protection: shared.protection(),
+                last_client: last_clients.get(file_id).copied().flatten(),
+            })
+            .collect();
+        shares.sort_by(|a, b| b.shared_at.cmp(&a.shared_at).then_with(|| a.path.cmp(&b.path)));
+        shares
+    }
+
+    /// Stops sharing `file_id`, so its links answer 404 from now on.
+    /// Returns the path it served, or `None` if it wasn't shared.
+    pub async fn revoke(&self, file_id: &str) -> Option<PathBuf> {
+        self.shared_files.write().await.remove(file_id).map(|shared| shared.path)
+    }
+
+    /// Stops sharing everything, returning how many shares there were. The
+    /// server keeps running for files shared later.
+    pub async fn revoke_all(&self) -> usize {
+        let mut files = self.shared_files.write().await;
+        let count = files.len();
+        files.clear();
+        count
+    }
+
     async fn send_notification(&self, notification: FileShareNotification) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if !self.config.notification_enabled {
             return Ok(());
@@ -117,65 +1020,191 @@ impl FileShareServer {
         let shared_files_for_download = self.shared_files.clone();
         let is_running_clone = self.is_running.clone();
 
-        // Find an available port
-        let port = self.find_available_port().await?;
+        // Find an available port on the configured address
+        let bind_ip = self.config.file_sharing.bind_ip()?;
+        let port = self.find_available_port(bind_ip).await?;
+        self.bind_ip = bind_ip;
+
+        // Serve HTTPS when a usable certificate is configured, and plain
+        // HTTP with a warning when it isn't
+        let tls = TlsIdentity::from_settings(&self.config.file_sharing).unwrap_or_else(|e| {
+            self.tls_warning = Some(format!("HTTPS is off, {}", e));
+            None
+        });
+        if tls.is_some() {
+            self.tls_warning = None;
+        }
+        self.tls_fingerprint = tls.as_ref().map(|identity| identity.fingerprint.clone());
+        let scheme = self.scheme();
+        let secure = self.tls_fingerprint.is_some();
         
         // Main file route - serves HTML viewer pages
+        // The token in a share's link, and the cookie passing it (or the
+        // password) earns
+        let credentials = warp::query::<HashMap<String, String>>()
+            .map(|mut query: HashMap<String, String>| query.remove("t"))
+            .and(warp::header::optional::<String>("cookie"));
+
+        // Unlike `warp::post()`, which would turn the 404 for a GET of an
+        // unknown share into a 405
+        let post_only = warp::method()
+            .and_then(|method: warp::http::Method| async move {
+                if method == warp::http::Method::POST {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            })
+            .untuple_one();
+
+        let shared_files_for_password = self.shared_files.clone();
+        let password_route = post_only
+            .and(warp::path("file"))
+            .and(warp::path::param::<String>())
+            .and(warp::path::end())
+            .and(warp::body::content_length_limit(4 * 1024))
+            .and(warp::body::form::<HashMap<String, String>>())
+            .and_then(move |file_id: String, form: HashMap<String, String>| {
+                let shared_files = shared_files_for_password.clone();
+                async move {
+                    let files = shared_files.read().await;
+                    let shared = files.get(&file_id).ok_or_else(warp::reject::not_found)?;
+                    if shared.is_expired(SystemTime::now()) {
+                        return Err(warp::reject::custom(ShareGone));
+                    }
+                    let typed = form.get("password").map(String::as_str).unwrap_or_default();
+                    let response = match &shared.lock {
+                        Some(Lock::Password(password)) if !constant_time_eq(typed, password) => {
+                            warp::reply::with_status(warp::reply::html(password_page(&file_id, true)), StatusCode::FORBIDDEN).into_response()
+                        }
+                        Some(Lock::Password(_)) => warp::http::Response::builder()
+                            .status(StatusCode::SEE_OTHER)
+                            .header("Location", format!("/file/{}", file_id))
+                            .header("Set-Cookie", shared.session_cookie(&file_id, secure))
+                            .body(warp::hyper::Body::empty())
+                            .map_err(|_| warp::reject::not_found())?,
+                        _ => return Err(warp::reject::not_found()),
+                    };
+                    Ok(response)
+                }
+            });
+
+        let settings_for_files = self.config.file_sharing.clone();
         let files_route = warp::path("file")
             .and(warp::path::param::<String>())
-            .and_then(move |file_id: String| {
+            .and(warp::path::tail())
+            .and(warp::query::<HashMap<String, String>>().map(|mut query: HashMap<String, String>| (query.remove("sheet"), query.remove("page"))))
+            .and(credentials)
+            .and_then(move |file_id: String, tail: warp::path::Tail, (sheet, page): (Option<String>, Option<String>), token: Option<String>, cookies: Option<String>| {
                 let shared_files = shared_files.clone();
-                let server_port = port;
+                let settings = settings_for_files.clone();
                 async move {
                     let files = shared_files.read().await;
-                    if let Some(file_path) = files.get(&file_id) {
-                        if file_path.exists() && file_path.is_file() {
+                    let Some(shared) = files.get(&file_id) else {
+                        return Err(warp::reject::not_found());
+                    };
+                    let set_cookie = match shared.authorize(&file_id, token.as_deref(), cookies.as_deref()) {
+                        Ok(earned) => earned.then(|| shared.session_cookie(&file_id, secure)),
+                        Err(Locked::Password) => {
+                            let page = warp::reply::html(password_page(&file_id, false));
+                            return Ok(warp::reply::with_status(page, StatusCode::FORBIDDEN).into_response());
+                        }
+                        Err(Locked::Token) => return Err(warp::reject::custom(ShareForbidden)),
+                    };
+                    let root = &shared.path;
+                    let target = resolve_in_share(root, tail.as_str()).ok_or_else(warp::reject::not_found)?;
+                    // Sheets of a workbook are numbered from 1 in links
+                    let sheet = match sheet.as_deref().map(str::parse::<usize>) {
+                        None => 0,
+                        Some(Ok(sheet)) if sheet > 0 && excel_sheet_names(&target).is_ok_and(|names| sheet <= names.len()) => sheet - 1,
+                        Some(_) => {
+                            let page = message_page("No such sheet", "<p>This workbook has no sheet by that number.</p>");
+                            return Ok(warp::reply::with_status(warp::reply::html(page), StatusCode::NOT_FOUND).into_response());
+                        }
+                    };
+                    // Pages of long files are numbered from 1 in links too
+                    let page = match page.as_deref().map(str::parse::<usize>) {
+                        None => 0,
+                        Some(Ok(page)) if page > 0 => page - 1,
+                        Some(_) => {
+                            let page = message_page("No such page", "<p>Pages are numbered from 1.</p>");
+                            return Ok(warp::reply::with_status(warp::reply::html(page), StatusCode::NOT_FOUND).into_response());
+                        }
+                    };
+                    // Pages further inside a shared folder, other sheets of a
+                    // workbook or later pages of a file are browsing, not new
+                    // visits
+                    let browsing = !tail.as_str().is_empty() || sheet > 0 || page > 0;
+                    shared.open(if browsing { Access::Embed } else { Access::View })?;
+                    let root = root.clone();
+                    // Pages of long files take a while to read, so they are
+                    // rendered off the async workers without holding the shares
+                    drop(files);
+                    let html = tokio::task::spawn_blocking(move || {
+                        if target.is_dir() {
+                            Some(directory_page(&file_id, &root, &target))
+                        } else if target.is_file() {
                             // Create FileInfo for the viewer
                             let file_info = FileInfo {
-                                id: file_id.clone(),
-                                name: file_path.file_name()
-                                    .and_then(|n| n.to_str())
-                                    .unwrap_or("unknown")
-                                    .to_string(),
-                                path: file_path.to_string_lossy().to_string(),
+                                id: share_path_id(&file_id, &root, &target),
+                                name: file_system::display_name(&target),
+                                path: target.to_string_lossy().to_string(),
+                                sheet,
+                                page,
                             };
                             // Generate HTML viewer page for this file
-                            let local_ip = local_ip().unwrap_or_else(|_| "127.0.0.1".parse().unwrap());
-                            let share_url = format!("http://{}:{}/file/{}", local_ip, server_port, file_id);
-                            let html = create_file_viewer_page(&file_info, &share_url);
-                            Ok(warp::reply::html(html))
+                            Some(create_file_viewer_page(&file_info, &settings))
                         } else {
-                            Err(warp::reject::not_found())
+                            None
                         }
-                    } else {
-                        Err(warp::reject::not_found())
+                    }).await.ok().flatten().ok_or_else(warp::reject::not_found)?;
+                    let mut response = warp::reply::html(html).into_response();
+                    if let Some(cookie) = set_cookie.and_then(|cookie| cookie.parse().ok()) {
+                        response.headers_mut().insert("Set-Cookie", cookie);
                     }
+                    Ok(response)
                 }
             });
 
         // Raw file route - serves actual file content for embedding/downloading
         let raw_route = warp::path("raw")
             .and(warp::path::param::<String>())
+            .and(warp::path::tail())
             .and(warp::header::optional::<String>("range"))
-            .and_then(move |file_id: String, range_header: Option<String>| {
+            .and(credentials)
+            .and_then(move |file_id: String, tail: warp::path::Tail, range_header: Option<String>, token: Option<String>, cookies: Option<String>| {
                 let shared_files = shared_files_for_raw.clone();
                 async move {
                     let files = shared_files.read().await;
-                    if let Some(file_path) = files.get(&file_id) {
+                    let file_path = files.get(&file_id)
+                        .map(|shared| {
+                            shared.authorize(&file_id, token.as_deref(), cookies.as_deref())
+                                .map_err(|_| warp::reject::custom(ShareForbidden))?;
+                            let file_path = resolve_in_share(&shared.path, tail.as_str()).ok_or_else(warp::reject::not_found)?;
+                            shared.open(Access::Embed)?;
+                            Ok::<_, Rejection>(file_path)
+                        })
+                        .transpose()?;
+                    if let Some(file_path) = file_path.as_deref() {
                         if file_path.exists() && file_path.is_file() {
-                            let mime_type = get_mime_type(file_path);
+                            let mime_type = content_mime_type(file_path);
                             
-                            // Get file metadata
-                            let metadata = tokio::fs::metadata(file_path).await
+                            // Stat the open handle right before streaming so Content-Length
+                            // matches what we send, even if the file is still being written
+                            let mut file = tokio::fs::File::open(file_path).await
+                                .map_err(|_| warp::reject::not_found())?;
+                            let metadata = file.metadata().await
                                 .map_err(|_| warp::reject::not_found())?;
                             let file_size = metadata.len();
+                            let cache_control = if is_still_changing(&metadata) {
+                                "no-store"
+                            } else {
+                                "public, max-age=3600"
+                            };
                             
                             // Handle range requests for all file types
                             if let Some(range) = range_header {
                                 if let Some((start, end)) = parse_range(&range, file_size) {
-                                    let mut file = tokio::fs::File::open(file_path).await
-                                        .map_err(|_| warp::reject::not_found())?;
-                                    
                                     // Seek to start position
                                     use tokio::io::AsyncSeekExt;
                                     file.seek(std::io::SeekFrom::Start(start)).await
@@ -193,7 +1222,7 @@ impl FileShareServer {
                                         .header("Content-Length", content_length.to_string())
                                         .header("Content-Range", format!("bytes {}-{}/{}", start, end, file_size))
                                         .header("Accept-Ranges", "bytes")
-                                        .header("Cache-Control", "public, max-age=3600")
+                                        .header("Cache-Control", cache_control)
                                         .header("Access-Control-Allow-Origin", "*")
                                         .body(body)
                                         .map_err(|_| warp::reject::not_found())?;
@@ -202,17 +1231,16 @@ impl FileShareServer {
                                 }
                             }
                             
-                            // Serve full file if no range request
-                            let file = tokio::fs::File::open(file_path).await
-                                .map_err(|_| warp::reject::not_found())?;
-                            
-                            let stream = tokio_util::io::ReaderStream::new(file);
+                            // Serve full file if no range request. Bytes appended after
+                            // the stat are cut off so the body never exceeds Content-Length.
+                            let limited_file = tokio::io::AsyncReadExt::take(file, file_size);
+                            let stream = tokio_util::io::ReaderStream::new(limited_file);
                             let body = warp::hyper::Body::wrap_stream(stream);
                             
                             let response = warp::http::Response::builder()
                                 .header("Content-Type", mime_type)
                                 .header("Content-Length", file_size.to_string())
-                                .header("Cache-Control", "public, max-age=3600")
+                                .header("Cache-Control", cache_control)
                                 .header("Accept-Ranges", "bytes")
                                 .header("Access-Control-Allow-Origin", "*")
                                 .body(body)
@@ -231,98 +1259,281 @@ impl FileShareServer {
         // Download route - forces file download with proper filename and range request support
         let download_route = warp::path("download")
             .and(warp::path::param::<String>())
+            .and(warp::path::tail())
             .and(warp::header::optional::<String>("range"))
-            .and_then(move |file_id: String, range_header: Option<String>| {
+            .and(credentials)
+            .and_then(move |file_id: String, tail: warp::path::Tail, range_header: Option<String>, token: Option<String>, cookies: Option<String>| {
                 let shared_files = shared_files_for_download.clone();
                 async move {
                     let files = shared_files.read().await;
-                    if let Some(file_path) = files.get(&file_id) {
-                        if file_path.exists() && file_path.is_file() {
-                            let mime_type = get_mime_type(file_path);
-                            
-                            // Get file metadata
-                            let metadata = tokio::fs::metadata(file_path).await
-                                .map_err(|_| warp::reject::not_found())?;
-                            let file_size = metadata.len();
-                            
-                            let filename = file_path.file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("download");
-                            
-                            // Handle range requests for ALL file types
-                            if let Some(range) = range_header {
-                                if let Some((start, end)) = parse_range(&range, file_size) {
-                                    let mut file = tokio::fs::File::open(file_path).await
-                                        .map_err(|_| warp::reject::not_found())?;
-                                    
-                                    // Seek to start position
-                                    use tokio::io::AsyncSeekExt;
-                                    file.seek(std::io::SeekFrom::Start(start)).await
-                                        .map_err(|_| warp::reject::not_found())?;
-                                    
-                                    // Take only the requested range
-                                    let content_length = end - start + 1;
-                                    let limited_file = tokio::io::AsyncReadExt::take(file, content_length);
-                                    let stream = tokio_util::io::ReaderStream::new(limited_file);
-                                    let body = warp::hyper::Body::wrap_stream(stream);
-                                    
-                                    let response = warp::http::Response::builder()
-                                        .status(206) // Partial Content
-                                        .header("Content-Type", mime_type)
-                                        .header("Content-Length", content_length.to_string())
-                                        .header("Content-Range", format!("bytes {}-{}/{}", start, end, file_size))
-                                        .header("Accept-Ranges", "bytes")
-                                        .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
-                                        .header("Cache-Control", "public, max-age=3600")
-                                        .header("Access-Control-Allow-Origin", "*")
-                                        .body(body)
-                                        .map_err(|_| warp::reject::not_found())?;
-                                    
-                                    return Ok(response);
-                                }
+                    let whole = range_header.as_deref().is_none_or(|range| range.trim().starts_with("bytes=0-"));
+                    let file_path = match files.get(&file_id) {
+                        Some(shared) => {
+                            shared.authorize(&file_id, token.as_deref(), cookies.as_deref())
+                                .map_err(|_| warp::reject::custom(ShareForbidden))?;
+                            let file_path = resolve_in_share(&shared.path, tail.as_str())
+                                .ok_or_else(warp::reject::not_found)?;
+                            if !tokio::fs::metadata(&file_path).await.is_ok_and(|metadata| metadata.is_file()) {
+                                return Err(warp::reject::not_found());
                             }
-                            
-                            // Serve full file if no range request
-                            let file = tokio::fs::File::open(file_path).await
-                                .map_err(|_| warp::reject::not_found())?;
-                            
-                            let stream = tokio_util::io::ReaderStream::new(file);
-                            let body = warp::hyper::Body::wrap_stream(stream);
-                            
-                            // Force download with proper filename
-                            let response = warp::http::Response::builder()
-                                .header("Content-Type", mime_type)
-                                .header("Content-Length", file_size.to_string())
-                                .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
-                                .header("Accept-Ranges", "bytes")
-                                .header("Cache-Control", "public, max-age=3600")
-                                .header("Access-Control-Allow-Origin", "*")
-                                .body(body)
-                                .map_err(|_| warp::reject::not_found())?;
-                            
-                            Ok(response)
+                            shared.open(Access::Download { whole })?;
+                            Some(file_path)
+                        }
+                        None => None,
+                    };
+                    if let Some(file_path) = file_path.as_deref() {
+                        let mime_type = content_mime_type(file_path);
+                        
+                        // Stat the open handle right before streaming so Content-Length
+                        // matches what we send, even if the file is still being written
+                        let mut file = tokio::fs::File::open(file_path).await
+                            .map_err(|_| warp::reject::not_found())?;
+                        let metadata = file.metadata().await
+                            .map_err(|_| warp::reject::not_found())?;
+                        let file_size = metadata.len();
+                        let cache_control = if is_still_changing(&metadata) {
+                            "no-store"
                         } else {
-                            Err(warp::reject::not_found())
+                            "public, max-age=3600"
+                        };
+                        
+                        let filename = file_system::display_name(file_path);
+                        
+                        // Handle range requests for ALL file types
+                        if let Some(range) = range_header {
+                            if let Some((start, end)) = parse_range(&range, file_size) {
+                                // Seek to start position
+                                use tokio::io::AsyncSeekExt;
+                                file.seek(std::io::SeekFrom::Start(start)).await
+                                    .map_err(|_| warp::reject::not_found())?;
+                                
+                                // Take only the requested range
+                                let content_length = end - start + 1;
+                                let limited_file = tokio::io::AsyncReadExt::take(file, content_length);
+                                let stream = tokio_util::io::ReaderStream::new(limited_file);
+                                let body = warp::hyper::Body::wrap_stream(stream);
+                                
+                                let response = warp::http::Response::builder()
+                                    .status(206) // Partial Content
+                                    .header("Content-Type", mime_type)
+                                    .header("Content-Length", content_length.to_string())
+                                    .header("Content-Range", format!("bytes {}-{}/{}", start, end, file_size))
+                                    .header("Accept-Ranges", "bytes")
+                                    .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
+                                    .header("Cache-Control", cache_control)
+                                    .header("Access-Control-Allow-Origin", "*")
+                                    .body(body)
+                                    .map_err(|_| warp::reject::not_found())?;
+                                
+                                return Ok(response);
+                            }
                         }
+                        
+                        // Serve full file if no range request. Bytes appended after
+                        // the stat are cut off so the body never exceeds Content-Length.
+                        let limited_file = tokio::io::AsyncReadExt::take(file, file_size);
+                        let stream = tokio_util::io::ReaderStream::new(limited_file);
+                        let body = warp::hyper::Body::wrap_stream(stream);
+                        
+                        // Force download with proper filename
+                        let response = warp::http::Response::builder()
+                            .header("Content-Type", mime_type)
+                            .header("Content-Length", file_size.to_string())
+                            .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
+                            .header("Accept-Ranges", "bytes")
+                            .header("Cache-Control", cache_control)
+                            .header("Access-Control-Allow-Origin", "*")
+                            .body(body)
+                            .map_err(|_| warp::reject::not_found())?;
+                        
+                        Ok(response)
                     } else {
                         Err(warp::reject::not_found())
                     }
                 }
             });
 
+        // QR route - the link to a shared file as a QR code to scan with a
+        // phone. It carries the share's token, so it's guarded like the link.
+        let shared_files_for_qr = self.shared_files.clone();
+        let qr_route = warp::path("qr")
+            .and(warp::path::param::<String>())
+            .and(warp::path::tail())
+            .and(credentials)
+            .and_then(move |file_id: String, tail: warp::path::Tail, token: Option<String>, cookies: Option<String>| {
+                let shared_files = shared_files_for_qr.clone();
+                let server_port = port;
+                async move {
+                    let files = shared_files.read().await;
+                    let Some(shared) = files.get(&file_id) else {
+                        return Err(warp::reject::not_found());
+                    };
+                    shared.authorize(&file_id, token.as_deref(), cookies.as_deref())
+                        .map_err(|_| warp::reject::custom(ShareForbidden))?;
+                    let target = resolve_in_share(&shared.path, tail.as_str()).ok_or_else(warp::reject::not_found)?;
+                    shared.open(Access::Embed)?;
+                    let url = format!(
+                        "{}://{}/file/{}{}",
+                        scheme, SocketAddr::new(url_host(bind_ip), server_port), share_path_id(&file_id, &shared.path, &target), shared.query()
+                    );
+                    let svg = qr_code_svg(&url).ok_or_else(warp::reject::not_found)?;
+                    warp::http::Response::builder()
+                        .header("Content-Type", "image/svg+xml")
+                        .header("Cache-Control", "no-store")
+                        .body(svg)
+                        .map_err(|_| warp::reject::not_found())
+                }
+            });
+
+        // Zip route - streams a shared folder, or a folder inside it, as a
+        // zip made on the fly, so nothing is staged on disk
+        let shared_files_for_zip = self.shared_files.clone();
+        let zip_route = warp::path("zip")
+            .and(warp::path::param::<String>())
+            .and(warp::path::tail())
+            .and(credentials)
+            .and_then(move |file_id: String, tail: warp::path::Tail, token: Option<String>, cookies: Option<String>| {
+                let shared_files = shared_files_for_zip.clone();
+                async move {
+                    let files = shared_files.read().await;
+                    let Some(shared) = files.get(&file_id) else {
+                        return Err(warp::reject::not_found());
+                    };
+                    shared.authorize(&file_id, token.as_deref(), cookies.as_deref())
+                        .map_err(|_| warp::reject::custom(ShareForbidden))?;
+                    let dir = resolve_in_share(&shared.path, tail.as_str())
+                        .filter(|dir| dir.is_dir())
+                        .ok_or_else(warp::reject::not_found)?;
+                    shared.open(Access::Download { whole: true })?;
+                    drop(files);
+
+                    let filename = format!("{}.zip", file_system::display_name(&dir));
+                    let (sender, body) = warp::hyper::Body::channel();
+                    let runtime = tokio::runtime::Handle::current();
+                    tokio::task::spawn_blocking(move || {
+                        let mut out = std::io::BufWriter::with_capacity(256 * 1024, BodySender { sender: Some(sender), runtime });
+                        if archive::write_zip_stream(&dir, &mut out).is_err() {
+                            // Fail the download rather than end it with a cut-off zip
+                            if let Some(sender) = out.get_mut().sender.take() {
+                                sender.abort();
+                            }
+                        }
+                    });
+
+                    warp::http::Response::builder()
+                        .header("Content-Type", "application/zip")
+                        .header("Content-Disposition", format!("attachment; filename=\"{}\"", filename))
+                        .header("Cache-Control", "no-store")
+                        .header("Access-Control-Allow-Origin", "*")
+                        .body(body)
+                        .map_err(|_| warp::reject::not_found())
+                }
+            });
+
+        // Upload routes - a form other devices send files from, saving them
+        // into the drop directory while uploads are on
+        let uploads = self.uploads.clone();
+        let upload_target = warp::any()
+            .and_then(move || {
+                let uploads = uploads.clone();
+                async move { uploads.read().await.clone().ok_or_else(warp::reject::not_found) }
+            });
+        let upload_page_route = warp::path("upload")
+            .and(warp::path::end())
+            .and(upload_target.clone())
+            .map(|target: UploadTarget| warp::reply::html(upload_page(target.max_bytes)));
+
+        let received = self.received.clone();
+        let upload_route = post_only
+            .and(warp::path("upload"))
+            .and(warp::path::end())
+            .and(upload_target)
+            .and(warp::header::optional::<u64>("content-length"))
+            // The size limit is checked while saving, as it can change
+            .and(warp::multipart::form().max_length(None))
+            .then(move |target: UploadTarget, content_length: Option<u64>, mut form: warp::multipart::FormData| {
+                let received = received.clone();
+                async move {
+                    use futures_util::StreamExt;
+
+                    let mut saved = Vec::new();
+                    // Turned away before anything is read when the body can't fit
+                    let mut result = if content_length.is_some_and(|length| length > target.max_bytes.saturating_add(UPLOAD_FORM_OVERHEAD)) {
+                        Err(UploadFailed::new(StatusCode::PAYLOAD_TOO_LARGE, too_large(target.max_bytes)))
+                    } else {
+                        Ok(())
+                    };
+                    while result.is_ok() {
+                        let part = match form.next().await {
+                            Some(Ok(part)) => part,
+                            Some(Err(e)) => {
+                                result = Err(UploadFailed::new(StatusCode::BAD_REQUEST, format!("Upload interrupted: {}", e)));
+                                break;
+                            }
+                            None => break,
+                        };
+                        // Other form fields carry no file
+                        let Some(name) = part.filename().map(sanitize_upload_name) else {
+                            continue;
+                        };
+                        let budget = target.max_bytes - saved.iter().map(|file: &ReceivedFile| file.size).sum::<u64>();
+                        match save_upload(part, &name, &target, budget).await {
+                            Ok(file) => saved.push(file),
+                            Err(failed) => result = Err(failed),
+                        }
+                    }
+                    if result.is_ok() && saved.is_empty() {
+                        result = Err(UploadFailed::new(StatusCode::BAD_REQUEST, "No file was attached."));
+                    }
+
+                    // Files saved before a failure are kept and reported
+                    received.lock().unwrap_or_else(|e| e.into_inner()).extend(saved.iter().cloned());
+                    let (status, page) = match result {
+                        Ok(()) => {
+                            let names: Vec<String> = saved.iter()
+                                .map(|file| format!("<p>{}</p>", escape_html(&file_system::display_name(&file.path))))
+                                .collect();
+                            (StatusCode::OK, message_page(
+                                &format!("Received {} file{}", saved.len(), if saved.len() == 1 { "" } else { "s" }),
+                                &format!("{}<p><a href=\"/upload\" style=\"color: #58a6ff;\">Send more</a></p>", names.join("")),
+                            ))
+                        }
+                        Err(failed) => (failed.status, message_page(
+                            "Upload failed",
+                            &format!("<p>{}</p><p><a href=\"/upload\" style=\"color: #58a6ff;\">Try again</a></p>", escape_html(&failed.reason)),
+                        )),
+                    };
+                    warp::reply::with_status(warp::reply::html(page), status)
+                }
+            });
+
         let list_route = warp::path("list")
             .and_then(move || {
                 let shared_files = shared_files_for_list.clone();
                 async move {
                     let files = shared_files.read().await;
-                    let file_list: Vec<_> = files.iter()
+                    let now = SystemTime::now();
+                    let listed: Vec<(String, PathBuf)> = files.iter()
+                        // Protected shares stay off the public list
+                        .filter(|(_, shared)| !shared.is_expired(now) && shared.lock.is_none())
+                        .map(|(id, shared)| (id.clone(), shared.path.clone()))
+                        .collect();
+                    drop(files);
+
+                    // Each entry is stat-ed, which can block on slow disks
+                    let file_list = tokio::task::spawn_blocking(move || listed.iter()
                         .map(|(id, path)| {
-                            let name = path.file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("unknown");
+                            let name = file_system::display_name(path);
                             
                             // Create different display based on file type
-                            if should_display_inline(path) {
+                            let item = if path.is_dir() {
+                                format!(
+                                    "<li><strong>📁 {}/</strong> - <em>Folder</em><br/>\
+                                    <a href=\"/file/{}\" target=\"_blank\">Browse</a> | \
+                                    <a href=\"/zip/{}\">Download as .zip</a></li>",
+                                    name, id, id
+                                )
+                            } else if should_display_inline(path) {
                                 let extension = path.extension()
                                     .and_then(|ext| ext.to_str())
                                     .unwrap_or("")
@@ -346,7 +1557,7 @@ impl FileShareServer {
                                             Your browser does not support the video tag.\
                                             </video><br/>\
                                             <a href=\"/file/{}\" target=\"_blank\">View Full</a></li>", 
-                                            name, id, get_mime_type(path), id
+                                            name, id, content_mime_type(path), id
                                         )
                                     },
                                     "mp3" | "wav" | "m4a" | "aac" | "oga" | "ogg" | "flac" => {
@@ -357,7 +1568,7 @@ impl FileShareServer {
                                             Your browser does not support the audio tag.\
                                             </audio><br/>\
                                             <a href=\"/file/{}\" target=\"_blank\">View Full</a></li>", 
-                                            name, id, get_mime_type(path), id
+                                            name, id, content_mime_type(path), id
                                         )
                                     },
                                     "json" | "geojson" | "xml" | "ipynb" => {
@@ -421,9 +1632,21 @@ impl FileShareServer {
                                 }
                             } else {
                                 format!("<li><a href=\"/file/{}\" download=\"{}\">{} (download)</a></li>", id, name, name)
+                            };
+
+                            let still_changing = std::fs::metadata(path)
+                                .is_ok_and(|metadata| is_still_changing(&metadata));
+                            match item.strip_suffix("</li>") {
+                                Some(body) if still_changing => format!(
+                                    "{}<br/><em class=\"changing\">⚠ File may still be changing - downloads could be incomplete</em></li>",
+                                    body
+                                ),
+                                _ => item,
                             }
                         })
-                        .collect();
+                        .collect::<Vec<_>>())
+                        .await
+                        .unwrap_or_default();
                     
                     let html = format!(
                         "<!DOCTYPE html>\
@@ -440,6 +1663,7 @@ impl FileShareServer {
                         a:hover {{ text-decoration: underline; }}\
                         img {{ border-radius: 4px; }}\
                         video, audio {{ border-radius: 4px; }}\
+                        .changing {{ color: #f0b429; }}\
                         </style>\
                         </head><body>\
                         <h1>FilePilot - Shared Files</h1>\
@@ -453,16 +1677,52 @@ impl FileShareServer {
                 }
             });
 
-        let routes = files_route.or(raw_route).or(download_route).or(list_route);
+        let access_log = AccessLog {
+            records: self.access_records.clone(),
+            events: self.access_sender.clone(),
+            file: match self.config.file_sharing.access_log() {
+                Some(path) => Some(Arc::new(std::sync::Mutex::new(
+                    std::fs::OpenOptions::new().create(true).append(true).open(&path)
+                        .map_err(|e| format!("Can't open access log '{}': {}", path.display(), e))?,
+                ))),
+                None => None,
+            },
+        };
+        let shared_files_for_log = self.shared_files.clone();
+        let log = warp::log::custom(move |info| access_log.record(info, &shared_files_for_log));
 
-        let addr: SocketAddr = ([0, 0, 0, 0], port).into();
-        
-        // Start server in background
-        tokio::spawn(async move {
-            warp::serve(routes).run(addr).await;
+        let routes = password_route.or(files_route).or(raw_route).or(download_route).or(qr_route).or(zip_route).or(upload_route).or(upload_page_route).or(list_route).recover(recover_share_errors).with(log);
+
+        let addr = SocketAddr::new(bind_ip, port);
+        let (signal, stopped) = oneshot::channel();
+        let stopped = async {
+            let _ = stopped.await;
+        };
+        let cannot_listen = |e: warp::Error| format!("Can't listen on {}: {}", addr, e);
+        let server: std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> = match tls {
+            Some(identity) => Box::pin(warp::serve(routes).tls().cert(identity.cert).key(identity.key)
+                .try_bind_with_graceful_shutdown(addr, stopped).map_err(cannot_listen)?.1),
+            None => Box::pin(warp::serve(routes).try_bind_with_graceful_shutdown(addr, stopped).map_err(cannot_listen)?.1),
+        };
+
+        // Start server in background, dropping expired shares while it runs
+        let shared_files = self.shared_files.clone();
+        self.server_task = Some(tokio::spawn(async move {
+            let prune = async {
+                let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + PRUNE_INTERVAL, PRUNE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    prune_expired(&mut *shared_files.write().await, SystemTime::now());
+                }
+            };
+            tokio::select! {
+                _ = server => {}
+                _ = prune => {}
+            }
             let mut running = is_running_clone.write().await;
             *running = false;
-        });
+        }));
+        self.shutdown_signal = Some(signal);
 
         {
             let mut is_running = self.is_running.write().await;
@@ -471,14 +1731,16 @@ impl FileShareServer {
         Ok(())
     }
 
-    pub async fn share_file(&mut self, file_path: &Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    /// Shares `file_path` until `options` says the link expires, starting
+    /// the server if needed, and returns the link.
+    pub async fn share_file(&mut self, file_path: &Path, options: ShareOptions) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         if !file_path.exists() {
             return Err("File does not exist".into());
         }
 
-        if file_path.is_dir() {
-            return Err("Cannot share directories (yet)".into());
-        }
+        // Folders are served from their canonical path, which everything
+        // requested inside them is checked against
+        let file_path = &if file_path.is_dir() { file_path.canonicalize()? } else { file_path.to_path_buf() };
 
         // Start server if not running
         self.start_server().await?;
@@ -488,14 +1750,25 @@ impl FileShareServer {
         
         // Add file to shared files
         let mut shared_files = self.shared_files.write().await;
-        shared_files.insert(file_id.clone(), file_path.to_path_buf());
+        let shared_at = SystemTime::now();
+        shared_files.insert(file_id.clone(), ShareEntry {
+            path: file_path.to_path_buf(),
+            shared_at,
+            hits: AtomicU64::new(0),
+            expires_at: options.ttl.and_then(|ttl| shared_at.checked_add(ttl)),
+            remaining_downloads: options.max_downloads.map(AtomicU64::new),
+            lock: options.protection.map(|protection| match protection {
+                ShareProtection::Token => Lock::Token(random_secret(16)),
+                ShareProtection::Password(password) => Lock::Password(password),
+            }),
+            session: random_secret(32),
+        });
+        let query = shared_files[&file_id].query();
         drop(shared_files); // Release the lock early
 
-        // Get local IP
-        let local_ip = local_ip().unwrap_or_else(|_| "127.0.0.1".parse().unwrap());
-        
-        // Create shareable URL
-        let url = format!("http://{}:{}/file/{}", local_ip, self.server_port, file_id);
+        // Create shareable URL, with the token that opens it
+        let origin = self.origin().await.ok_or("Share server is not running")?;
+        let url = format!("{}/file/{}{}", origin, file_id, query);
 
         // Copy to clipboard
         if let Ok(mut clipboard) = Clipboard::new() {
@@ -503,12 +1776,9 @@ impl FileShareServer {
         }
 
         // Get file metadata for notification
-        let file_size = std::fs::metadata(file_path).ok().map(|m| m.len());
-        let file_name = file_path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
-        let mime_type = get_mime_type(file_path).to_string();
+        let file_size = std::fs::metadata(file_path).ok().filter(|m| m.is_file()).map(|m| m.len());
+        let file_name = file_system::display_name(file_path);
+        let mime_type = if file_path.is_dir() { "inode/directory" } else { content_mime_type(file_path) }.to_string();
 
         // Create and send notification
         let notification = FileShareNotification {
@@ -537,13 +1807,24 @@ impl FileShareServer {
         }
     }
 
-    async fn find_available_port(&mut self) -> Result<u16, Box<dyn std::error::Error + Send + Sync>> {
-        // Try ports starting from configured port range
+    async fn find_available_port(&mut self, ip: IpAddr) -> Result<u16, Box<dyn std::error::Error + Send + Sync>> {
         let start_port = self.config.file_sharing.port_range_start;
         let end_port = self.config.file_sharing.port_range_end;
-        
+
+        if let Some(port) = self.config.file_sharing.fixed_port {
+            if self.is_port_available(ip, port).await? {
+                self.server_port = port;
+                return Ok(port);
+            }
+            return Err(format!(
+                "Fixed share port {} is already in use. Free it, or remove `fixed_port` from the config to pick a port from {}-{}",
+                port, start_port, end_port.saturating_sub(1)
+            ).into());
+        }
+
+        // Try ports starting from configured port range
         for port in start_port..end_port {
-            if self.is_port_available(port).await {
+            if self.is_port_available(ip, port).await? {
                 self.server_port = port;
                 return Ok(port);
             }
@@ -553,21 +1834,24 @@ impl FileShareServer {
         tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
         
         for port in start_port..end_port {
-            if self.is_port_available(port).await {
+            if self.is_port_available(ip, port).await? {
                 self.server_port = port;
                 return Ok(port);
             }
         }
         
-        Err("No available ports found (8080-8089)".into())
+        Err(format!("No available ports found ({}-{})", start_port, end_port.saturating_sub(1)).into())
     }
 
-    async fn is_port_available(&self, port: u16) -> bool {
-        use std::net::{TcpListener, SocketAddr};
+    /// Whether `port` on `ip` is free. Only a port in use counts as taken;
+    /// anything else, such as an address this machine doesn't have, is an
+    /// error no other port would get around.
+    async fn is_port_available(&self, ip: IpAddr, port: u16) -> Result<bool, String> {
+        use std::net::TcpListener;
         use std::time::Duration;
         
-        // Try to bind to the port with SO_REUSEADDR equivalent behavior
-        let addr: SocketAddr = ([127, 0, 0, 1], port).into();
+        let addr = SocketAddr::new(ip, port);
+        let cannot_listen = |e: std::io::Error| format!("Can't listen on {}: {}", addr, e);
         
         match TcpListener::bind(addr) {
             Ok(listener) => {
@@ -576,9 +1860,9 @@ impl FileShareServer {
                 
                 // Give a small delay to ensure the port is fully released
                 tokio::time::sleep(Duration::from_millis(10)).await;
-                true
+                Ok(true)
             }
-            Err(_) => {
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
                 // Port is in use, try to see if it's a recent binding that might be released soon
                 tokio::time::sleep(Duration::from_millis(100)).await;
                 
@@ -586,15 +1870,28 @@ impl FileShareServer {
                 match TcpListener::bind(addr) {
                     Ok(listener) => {
                         drop(listener);
-                        true
+                        Ok(true)
                     }
-                    Err(_) => false
+                    Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => Ok(false),
+                    Err(e) => Err(cannot_listen(e)),
                 }
             }
+            Err(e) => Err(cannot_listen(e)),
         }
     }
 }
 
+/// The host share links use for a server listening on `bind_ip`: that
+/// address itself when it is a specific one, such as loopback, and this
+/// machine's LAN IP when listening on every interface.
+fn url_host(bind_ip: IpAddr) -> IpAddr {
+    if bind_ip.is_unspecified() {
+        local_ip().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    } else {
+        bind_ip
+    }
+}
+
 fn should_display_inline(path: &Path) -> bool {
     let extension = path.extension()
         .and_then(|ext| ext.to_str())
@@ -625,34 +1922,41 @@ fn should_display_inline(path: &Path) -> bool {
     }
 }
 
-fn generate_qr_code_base64(url: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // Generate QR code
-    let code = QrCode::with_error_correction_level(url, EcLevel::M)?;
-    
-    // Convert to image with some padding
-    let image = code.render::<Luma<u8>>()
+/// `url` as an SVG QR code, for the viewer page to show and link to.
+fn qr_code_svg(url: &str) -> Option<String> {
+    let code = QrCode::with_error_correction_level(url, EcLevel::M).ok()?;
+    Some(code.render::<qrcode::render::svg::Color>()
         .min_dimensions(200, 200) // Minimum size for readability
-        .max_dimensions(400, 400) // Maximum size to keep it reasonable
-        .build();
-    
-    // Convert to PNG bytes
-    let mut png_bytes = Vec::new();
-    {
-        use image::codecs::png::PngEncoder;
-        use image::ImageEncoder;
-        
-        let encoder = PngEncoder::new(&mut png_bytes);
-        encoder.write_image(
-            image.as_raw(),
-            image.width(),
-            image.height(),
-            image::ExtendedColorType::L8,
-        )?;
+        .build())
+}
+
+/// `url` as a QR code drawn with half-block characters, two modules to a
+/// character cell, for showing in a terminal; dark modules are the filled
+/// parts, so it needs dark text on a light background to scan.
+pub fn qr_code_text(url: &str) -> Option<Vec<String>> {
+    let code = QrCode::with_error_correction_level(url, EcLevel::M).ok()?;
+    let text = code.render::<qrcode::render::unicode::Dense1x2>().quiet_zone(true).build();
+    Some(text.lines().map(str::to_string).collect())
+}
+
+fn is_still_changing(metadata: &std::fs::Metadata) -> bool {
+    metadata.modified().ok()
+        .and_then(|modified| std::time::SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < STILL_CHANGING_WINDOW)
+}
+
+/// The MIME type from the extension unless the file's content says
+/// otherwise, e.g. a PNG saved as `.txt` or a text file without extension.
+pub(crate) fn content_mime_type(path: &Path) -> &'static str {
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match file_system::detect_type(path) {
+        Ok(kind) if !kind.agrees_with_extension(&extension) => kind.mime_type(),
+        // Text the extension table doesn't know is still text
+        Ok(FileKind::Text) if get_mime_type(path) == "application/octet-stream" => FileKind::Text.mime_type(),
+        _ => get_mime_type(path),
     }
-    
-    // Encode to base64
-    let base64_string = general_purpose::STANDARD.encode(&png_bytes);
-    Ok(base64_string)
 }
 
 fn get_mime_type(path: &Path) -> &'static str {
@@ -725,14 +2029,99 @@ fn get_mime_type(path: &Path) -> &'static str {
     }
 }
 
-fn parse_csv_to_html(file_path: &Path, max_rows: usize) -> Result<String, Box<dyn std::error::Error>> {
-    let file = std::fs::File::open(file_path)?;
+/// The delimiter the CSV preview splits on: whichever of comma, semicolon
+/// and tab `first_line` uses most outside quotes, comma on a tie.
+fn detect_csv_delimiter(first_line: &[u8]) -> u8 {
+    const DELIMITERS: [u8; 3] = [b',', b';', b'\t'];
+    let mut counts = [0; DELIMITERS.len()];
+    let mut quoted = false;
+    for &byte in first_line {
+        if byte == b'"' {
+            quoted = !quoted;
+        } else if let Some(i) = DELIMITERS.iter().position(|&delimiter| delimiter == byte).filter(|_| !quoted) {
+            counts[i] += 1;
+        }
+    }
+    let mut best = 0;
+    for i in 1..DELIMITERS.len() {
+        if counts[i] > counts[best] {
+            best = i;
+        }
+    }
+    DELIMITERS[best]
+}
+
+/// Whether the first row of a CSV names its columns rather than holding
+/// data: every field filled in, none of them a number, and no name used
+/// twice.
+fn looks_like_csv_header(first: &csv::StringRecord) -> bool {
+    let mut seen = std::collections::HashSet::new();
+    first.iter().all(|field| {
+        let field = field.trim();
+        !field.is_empty() && field.parse::<f64>().is_err() && seen.insert(field)
+    })
+}
+
+/// Page `page` (counting from 0) of the CSV at `file_path` as an HTML
+/// table of up to `rows_per_page` rows, between links to the neighbouring
+/// pages of the viewer for `file_id`. The file is read as a stream up to
+/// the page and counted no further than `PREVIEW_COUNT_LIMIT` rows, so its
+/// size doesn't matter. Rows may have any number of fields; columns
+/// without a header are numbered.
+fn parse_csv_to_html(file_path: &Path, file_id: &str, page: usize, rows_per_page: usize) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::BufRead;
+    let mut file = std::io::BufReader::new(std::fs::File::open(file_path)?);
+    let buffered = file.fill_buf()?;
+    let first_line = buffered.split(|&byte| byte == b'\n').next().unwrap_or_default();
+    let delimiter = detect_csv_delimiter(first_line);
     let mut reader = ReaderBuilder::new()
-        .has_headers(true)
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
         .from_reader(file);
-    
-    let headers = reader.headers()?.clone();
+
+    let mut records = reader.records();
+    let Some(first) = records.next().transpose()? else {
+        return Ok("<p>This CSV file is empty.</p>".to_string());
+    };
+    let headers = looks_like_csv_header(&first).then(|| first.clone());
+    let mut leading = if headers.is_some() { None } else { Some(first) };
+    drop(records);
+
+    // Earlier pages are skipped, and later ones only counted, without
+    // checking they are valid UTF-8
+    let skip = page.saturating_mul(rows_per_page);
+    let mut skipped = usize::from(leading.is_some() && skip > 0);
+    if skipped == 1 {
+        leading = None;
+    }
+    let mut record = csv::ByteRecord::new();
+    while skipped < skip && reader.read_byte_record(&mut record)? {
+        skipped += 1;
+    }
+    let mut rows: Vec<csv::StringRecord> = leading.into_iter().collect();
+    while rows.len() < rows_per_page {
+        match reader.records().next() {
+            Some(row) => rows.push(row?),
+            None => break,
+        }
+    }
+    let mut total_rows = skipped + rows.len();
+    while total_rows < PREVIEW_COUNT_LIMIT && reader.read_byte_record(&mut record)? {
+        total_rows += 1;
+    }
+    let more = total_rows >= PREVIEW_COUNT_LIMIT && reader.read_byte_record(&mut record)?;
+
     let mut html = String::new();
+    let pager = pager_html(file_id, page, rows_per_page, LineCount { seen: total_rows, more }, "Rows");
+    if rows.is_empty() {
+        html.push_str(&format!("<p>Page {} is past the end of this file.</p>", page + 1));
+        html.push_str(&pager);
+        return Ok(html);
+    }
+    html.push_str(&pager);
+
+    let width = rows.iter().chain(&headers).map(csv::StringRecord::len).max().unwrap_or(0);
     
     // Table start with styling
     html.push_str(r#"<div class="table-container">
@@ -740,142 +2129,217 @@ fn parse_csv_to_html(file_path: &Path, max_rows: usize) -> Result<String, Box<dy
             <thead>
                 <tr>"#);
     
-    // Add headers
-    for header in headers.iter() {
-        html.push_str(&format!("<th>{}</th>", escape_html(header)));
+    // Add headers, numbering the columns that have none
+    for column in 0..width {
+        let header = headers.as_ref()
+            .and_then(|headers| headers.get(column))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Column {}", column + 1));
+        html.push_str(&format!("<th>{}</th>", escape_html(&header)));
     }
     html.push_str("</tr></thead><tbody>");
     
-    // Add data rows (limited)
-    let mut row_count = 0;
-    for result in reader.records() {
-        if row_count >= max_rows {
-            html.push_str(&format!(
-                r#"<tr><td colspan="{}" style="text-align: center; font-style: italic; color: #ffeb3b;">
-                ... and {} more rows (showing first {} rows)
-                </td></tr>"#, 
-                headers.len(), 
-                reader.records().count(), 
-                max_rows
-            ));
-            break;
-        }
-        
-        let record = result?;
+    // Add this page's rows, short ones padded to the full width
+    for record in &rows {
         html.push_str("<tr>");
-        for field in record.iter() {
-            html.push_str(&format!("<td>{}</td>", escape_html(field)));
+        for column in 0..width {
+            html.push_str(&format!("<td>{}</td>", escape_html(record.get(column).unwrap_or(""))));
         }
         html.push_str("</tr>");
-        row_count += 1;
     }
     
     html.push_str("</tbody></table></div>");
+    html.push_str(&pager);
     Ok(html)
 }
 
-fn parse_excel_to_html(file_path: &Path, max_rows: usize) -> Result<String, Box<dyn std::error::Error>> {
-    let extension = file_path.extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("")
-        .to_lowercase();
+/// How many rows or lines a file has, as far as a paged preview counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineCount {
+    seen: usize,
+    /// Counting stopped at `PREVIEW_COUNT_LIMIT` with more still to come
+    more: bool,
+}
+
+/// One page of a text file: its lines from `first` on, and how many lines
+/// the file has.
+struct LinePage {
+    first: usize,
+    lines: Vec<String>,
+    total: LineCount,
+}
+
+/// Reads page `page` (counting from 0) of `per_page` lines from the text
+/// file at `path`, streaming past the lines before it and counting those
+/// after it up to `PREVIEW_COUNT_LIMIT`. Only `MAX_PREVIEW_LINE_BYTES` of
+/// each line are kept, so a file without line breaks isn't loaded whole.
+fn read_line_page(path: &Path, page: usize, per_page: usize) -> std::io::Result<LinePage> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let first = page.saturating_mul(per_page);
+    let mut lines = Vec::new();
+    let mut seen = 0;
+    let mut line = Vec::new();
+    loop {
+        let on_page = seen >= first && lines.len() < per_page;
+        if !on_page && seen >= first.max(PREVIEW_COUNT_LIMIT) {
+            break;
+        }
+        let Some(cut) = read_capped_line(&mut reader, &mut line, on_page)? else {
+            return Ok(LinePage { first, lines, total: LineCount { seen, more: false } });
+        };
+        if on_page {
+            let text = String::from_utf8_lossy(&line);
+            let mut text = text.trim_end_matches(['\n', '\r']).to_string();
+            if cut {
+                text.push_str(" …");
+            }
+            lines.push(text);
+        }
+        seen += 1;
+    }
+    let more = read_capped_line(&mut reader, &mut line, false)?.is_some();
+    Ok(LinePage { first, lines, total: LineCount { seen, more } })
+}
+
+/// Reads the next line from `reader`, putting up to
+/// `MAX_PREVIEW_LINE_BYTES` of it in `line` when `keep` is set. `None` at
+/// the end of the file, otherwise whether the line was cut short.
+fn read_capped_line(reader: &mut impl std::io::BufRead, line: &mut Vec<u8>, keep: bool) -> std::io::Result<Option<bool>> {
+    line.clear();
+    let mut started = false;
+    let mut cut = false;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(started.then_some(cut));
+        }
+        started = true;
+        let (chunk, ends_line) = match buffer.iter().position(|&byte| byte == b'\n') {
+            Some(end) => (&buffer[..=end], true),
+            None => (buffer, false),
+        };
+        if keep {
+            let room = MAX_PREVIEW_LINE_BYTES.saturating_sub(line.len());
+            cut |= chunk.len() > room;
+            line.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        }
+        let used = chunk.len();
+        reader.consume(used);
+        if ends_line {
+            return Ok(Some(cut));
+        }
+    }
+}
+
+/// Links to the previous and next pages of the viewer for `file_id`
+/// around a note of which `unit` page `page` shows, e.g. "Rows 1001-2000 of
+/// 53214". Nothing when everything fits on one page.
+fn pager_html(file_id: &str, page: usize, per_page: usize, total: LineCount, unit: &str) -> String {
+    let pages = total.seen.div_ceil(per_page.max(1));
+    if pages <= 1 && page == 0 && !total.more {
+        return String::new();
+    }
+    let of = if total.more { format!("{}+", total.seen) } else { total.seen.to_string() };
+    let first = page.saturating_mul(per_page) + 1;
+    let last = (first + per_page - 1).min(total.seen);
+    let position = if first <= total.seen {
+        format!("{} {}&ndash;{} of {}", unit, first, last, of)
+    } else {
+        format!("{} in all: {}", unit, of)
+    };
+    let previous = match page {
+        0 => "<span>&larr; Previous</span>".to_string(),
+        // Past the end, the way back is to the last page
+        _ => format!(r#"<a href="/file/{}?page={}">&larr; Previous</a>"#, file_id, page.min(pages)),
+    };
+    let next = if page + 1 < pages || (total.more && first <= total.seen) {
+        format!(r#"<a href="/file/{}?page={}">Next &rarr;</a>"#, file_id, page + 2)
+    } else {
+        "<span>Next &rarr;</span>".to_string()
+    };
+    format!(r#"<nav class="pager">{} <span class="pager-position">{}</span> {}</nav>"#, previous, position, next)
+}
+
+/// The names of the sheets in the workbook at `file_path`, in order.
+fn excel_sheet_names(file_path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Ok(open_workbook_auto(file_path)?.sheet_names())
+}
+
+/// Sheet `sheet` (counting from 0) of the workbook at `file_path` as a
+/// table of its first `max_rows` rows, below links to every sheet of the
+/// viewer page for `file_id` when there are several.
+fn parse_excel_to_html(file_path: &Path, file_id: &str, sheet: usize, max_rows: usize) -> Result<String, Box<dyn std::error::Error>> {
+    let mut workbook = open_workbook_auto(file_path)?;
+    let sheet_names = workbook.sheet_names();
+    if sheet_names.is_empty() {
+        return Ok("<p>No sheets found in workbook</p>".to_string());
+    }
+    let sheet_name = sheet_names.get(sheet).ok_or_else(|| format!("No sheet {} in workbook", sheet + 1))?;
     
     let mut html = String::new();
+    if sheet_names.len() > 1 {
+        html.push_str(r#"<nav class="sheet-tabs">"#);
+        for (index, name) in sheet_names.iter().enumerate() {
+            let class = if index == sheet { r#" class="active""# } else { "" };
+            html.push_str(&format!(
+                r#"<a href="/file/{}?sheet={}"{}>{}</a>"#,
+                file_id, index + 1, class, escape_html(name)
+            ));
+        }
+        html.push_str("</nav>");
+    }
+    html.push_str(&format!("<h3>Sheet: {}</h3>", escape_html(sheet_name)));
     
-    match extension.as_str() {
-        "xlsx" => {
-            let mut workbook: Xlsx<_> = open_workbook(file_path)?;
-            let sheet_names = workbook.sheet_names().to_owned();
-            
-            if sheet_names.is_empty() {
-                return Ok("<p>No sheets found in workbook</p>".to_string());
-            }
-            
-            // Process first sheet
-            let sheet_name = &sheet_names[0];
-            if let Ok(range) = workbook.worksheet_range(sheet_name) {
-                html.push_str(&format!("<h3>Sheet: {}</h3>", escape_html(sheet_name)));
-                html.push_str(r#"<div class="table-container">
-                    <table class="data-table">
-                        <tbody>"#);
-                
-                let mut row_count = 0;
-                for row in range.rows() {
-                    if row_count >= max_rows {
-                        html.push_str(&format!(
-                            r#"<tr><td colspan="{}" style="text-align: center; font-style: italic; color: #ffeb3b;">
-                            ... and more rows (showing first {} rows)
-                            </td></tr>"#, 
-                            row.len(), 
-                            max_rows
-                        ));
-                        break;
-                    }
-                    
-                    html.push_str("<tr>");
-                    for cell in row {
-                        let cell_value = format!("{}", cell);
-                        html.push_str(&format!("<td>{}</td>", escape_html(&cell_value)));
-                    }
-                    html.push_str("</tr>");
-                    row_count += 1;
-                }
-                
-                html.push_str("</tbody></table></div>");
-            }
-        },
-        "xls" => {
-            let mut workbook: Xls<_> = open_workbook(file_path)?;
-            let sheet_names = workbook.sheet_names().to_owned();
-            
-            if sheet_names.is_empty() {
-                return Ok("<p>No sheets found in workbook</p>".to_string());
-            }
-            
-            // Process first sheet
-            let sheet_name = &sheet_names[0];
-            if let Ok(range) = workbook.worksheet_range(sheet_name) {
-                html.push_str(&format!("<h3>Sheet: {}</h3>", escape_html(sheet_name)));
-                html.push_str(r#"<div class="table-container">
-                    <table class="data-table">
-                        <tbody>"#);
-                
-                let mut row_count = 0;
-                for row in range.rows() {
-                    if row_count >= max_rows {
-                        html.push_str(&format!(
-                            r#"<tr><td colspan="{}" style="text-align: center; font-style: italic; color: #ffeb3b;">
-                            ... and more rows (showing first {} rows)
-                            </td></tr>"#, 
-                            row.len(), 
-                            max_rows
-                        ));
-                        break;
-                    }
-                    
-                    html.push_str("<tr>");
-                    for cell in row {
-                        let cell_value = format!("{}", cell);
-                        html.push_str(&format!("<td>{}</td>", escape_html(&cell_value)));
-                    }
-                    html.push_str("</tr>");
-                    row_count += 1;
-                }
-                
-                html.push_str("</tbody></table></div>");
-            }
-        },
-        _ => return Err("Unsupported Excel format".into()),
+    let range = workbook.worksheet_range(sheet_name)?;
+    if range.is_empty() {
+        html.push_str("<p>This sheet is empty.</p>");
+        return Ok(html);
+    }
+    html.push_str(r#"<div class="table-container">
+        <table class="data-table">
+            <tbody>"#);
+    
+    for row in range.rows().take(max_rows) {
+        html.push_str("<tr>");
+        for cell in row {
+            let cell_value = format!("{}", cell);
+            html.push_str(&format!("<td>{}</td>", escape_html(&cell_value)));
+        }
+        html.push_str("</tr>");
+    }
+    if range.height() > max_rows {
+        html.push_str(&format!(
+            r#"<tr><td colspan="{}" style="text-align: center; font-style: italic; color: #ffeb3b;">
+            Showing first {} of {} rows
+            </td></tr>"#,
+            range.width(),
+            max_rows,
+            range.height()
+        ));
     }
     
+    html.push_str("</tbody></table></div>");
     Ok(html)
 }
 
-fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
+/// Whether files with `extension` are previewed a page at a time, so they
+/// can be any size.
+fn previews_in_pages(extension: &str) -> bool {
+    matches!(extension, "csv" | "txt" | "rst" | "log" | "ini" | "cfg" | "conf")
+}
+
+fn create_file_viewer_page(file_info: &FileInfo, settings: &FileShareSettings) -> String {
+    let extension = Path::new(&file_info.name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
     // Global file size check - prevent displaying any file larger than 5MB
+    // that isn't shown a page at a time
     let file_path = Path::new(&file_info.path);
     if let Ok(metadata) = std::fs::metadata(file_path) {
-        if metadata.len() > MAX_FILE_PREVIEW_SIZE {
+        if metadata.len() > MAX_FILE_PREVIEW_SIZE && !previews_in_pages(&extension) {
             let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
             let viewer_content = format!(
                 r#"<div class="file-info">
@@ -963,7 +2427,7 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
         <div class="qr-section">
             <div class="qr-code">
                 <div>
-                    <img src="data:image/png;base64,{}" alt="QR Code" style="display: block;" />
+                    <a href="/qr/{}" target="_blank" title="Open the QR code"><img src="/qr/{}" alt="QR Code" width="250" height="250" style="display: block;" /></a>
                 </div>
             </div>
             <p><a href="/download/{}" class="download-btn">Download {}</a></p>
@@ -976,7 +2440,8 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
 </html>"#,
                 file_info.name, 
                 file_info.name, 
-                generate_qr_code_base64(share_url).unwrap_or_else(|_| "".to_string()),
+                file_info.id,
+                file_info.id,
                 file_info.id,
                 file_info.name,
                 viewer_content
@@ -984,11 +2449,8 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
         }
     }
 
-    let extension = Path::new(&file_info.name)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("")
-        .to_lowercase();
+    // A page always has something on it, whatever the config says
+    let (preview_rows, preview_lines) = (settings.preview_rows.max(1), settings.preview_lines.max(1));
 
     let viewer_content = match extension.as_str() {
         // Video files
@@ -1726,89 +3188,55 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
                 )
             }
         },
-        // Other text files
+        // Other text files, in pages when they are long
         "txt" | "rst" | "log" | "ini" | "cfg" | "conf" => {
-            // Check file size first
-            let file_path = Path::new(&file_info.path);
-            if let Ok(metadata) = std::fs::metadata(file_path) {
-                if metadata.len() > MAX_TEXT_PREVIEW_SIZE {
-                    format!(
-                        r#"<div class="file-info">
-                            <h3>Text File: {}</h3>
-                            <p>File too large for preview ({:.1} MB)</p>
-                            <p>Files larger than {:.1} MB cannot be previewed.</p>
-                            <p><a href="/raw/{}" target="_blank">View Raw Content</a></p>
-                        </div>"#,
-                        file_info.name, 
-                        metadata.len() as f64 / (1024.0 * 1024.0),
-                        MAX_TEXT_PREVIEW_SIZE as f64 / (1024.0 * 1024.0),
-                        file_info.id
-                    )
-                } else {
-                    format!(
-                        r#"<div class="text-viewer">
-                            <iframe src="/raw/{}" style="width: 100%; height: 600px; border: 1px solid #ddd; border-radius: 5px;"></iframe>
-                        </div>"#,
-                        file_info.id
-                    )
+            match read_line_page(Path::new(&file_info.path), file_info.page, preview_lines) {
+                Ok(page) if page.total.seen <= preview_lines && !page.total.more && file_info.page == 0 => format!(
+                    r#"<div class="text-viewer">
+                        <iframe src="/raw/{}" style="width: 100%; height: 600px; border: 1px solid #ddd; border-radius: 5px;"></iframe>
+                    </div>"#,
+                    file_info.id
+                ),
+                Ok(page) => {
+                    let pager = pager_html(&file_info.id, file_info.page, preview_lines, page.total, "Lines");
+                    let text = if page.lines.is_empty() {
+                        format!("<p>Page {} is past the end of this file.</p>", file_info.page + 1)
+                    } else {
+                        let numbered: Vec<String> = page.lines.iter().enumerate()
+                            .map(|(i, line)| format!(r#"<span class="line-number">{}</span>{}"#, page.first + i + 1, escape_html(line)))
+                            .collect();
+                        format!(r#"<pre class="text-page">{}</pre>"#, numbered.join("\n"))
+                    };
+                    format!(r#"<div class="text-viewer">{}{}{}</div>"#, pager, text, pager)
                 }
-            } else {
-                format!(
+                Err(_) => format!(
                     r#"<div class="file-info">
                         <h3>Error reading text file: {}</h3>
                     </div>"#,
                     file_info.name
-                )
+                ),
             }
         },
-        // CSV files - display as table
+        // CSV files - display as table, a page at a time
         "csv" => {
-            let file_path = Path::new(&file_info.path);
-            if let Ok(metadata) = std::fs::metadata(file_path) {
-                if metadata.len() > MAX_SPREADSHEET_SIZE {
-                    format!(
-                        r#"<div class="file-info">
-                            <h3>Large CSV File: {}</h3>
-                            <p>CSV file too large for preview ({:.1} MB)</p>
-                            <p>Files over {} MB are not displayed to prevent browser issues.</p>
-                            <p><a href="/download/{}" class="download-btn">Download CSV</a></p>
-                            <p><a href="/raw/{}" target="_blank">View Raw Content</a></p>
-                        </div>"#,
-                        file_info.name, 
-                        metadata.len() as f64 / (1024.0 * 1024.0),
-                        MAX_SPREADSHEET_SIZE / (1024 * 1024),
-                        file_info.id,
-                        file_info.id
-                    )
-                } else {
-                    match parse_csv_to_html(file_path, MAX_CSV_ROWS) {
-                        Ok(table_html) => format!(
-                            r#"<div class="spreadsheet-viewer">
-                                <h3>📊 CSV File: {}</h3>
-                                {}
-                                <br>
-                                <p><a href="/download/{}" class="download-btn">Download CSV</a></p>
-                            </div>"#,
-                            file_info.name, table_html, file_info.id
-                        ),
-                        Err(_) => format!(
-                            r#"<div class="file-info">
-                                <h3>Error reading CSV file: {}</h3>
-                                <p>Unable to parse CSV content. The file may be corrupted or use an unsupported format.</p>
-                                <p><a href="/download/{}" class="download-btn">Download CSV</a></p>
-                                <p><a href="/raw/{}" target="_blank">View Raw Content</a></p>
-                            </div>"#,
-                            file_info.name, file_info.id, file_info.id
-                        )
-                    }
-                }
-            } else {
-                format!(
+            match parse_csv_to_html(Path::new(&file_info.path), &file_info.id, file_info.page, preview_rows) {
+                Ok(table_html) => format!(
+                    r#"<div class="spreadsheet-viewer">
+                        <h3>📊 CSV File: {}</h3>
+                        {}
+                        <br>
+                        <p><a href="/download/{}" class="download-btn">Download CSV</a></p>
+                    </div>"#,
+                    file_info.name, table_html, file_info.id
+                ),
+                Err(_) => format!(
                     r#"<div class="file-info">
                         <h3>Error reading CSV file: {}</h3>
-                        <p><a href="/download/{}" class="download-btn">Download File</a></p>
+                        <p>Unable to parse CSV content. The file may be corrupted or use an unsupported format.</p>
+                        <p><a href="/download/{}" class="download-btn">Download CSV</a></p>
+                        <p><a href="/raw/{}" target="_blank">View Raw Content</a></p>
                     </div>"#,
-                    file_info.name, file_info.id
+                    file_info.name, file_info.id, file_info.id
                 )
             }
         },
@@ -1830,7 +3258,7 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
                         file_info.id
                     )
                 } else {
-                    match parse_excel_to_html(file_path, MAX_EXCEL_ROWS) {
+                    match parse_excel_to_html(file_path, &file_info.id, file_info.sheet, preview_rows) {
                         Ok(table_html) => format!(
                             r#"<div class="spreadsheet-viewer">
                                 <h3>Excel File: {}</h3>
@@ -2070,6 +3498,55 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
         .data-table tr:hover {{
             background-color: #404040;
         }}
+        .pager {{
+            display: flex;
+            gap: 16px;
+            justify-content: center;
+            align-items: center;
+            margin: 10px 0;
+        }}
+        .pager a {{
+            color: #58a6ff;
+            text-decoration: none;
+        }}
+        .pager span {{
+            color: #8b949e;
+        }}
+        .text-page {{
+            text-align: left;
+            background-color: #1e1e1e;
+            border: 1px solid #444;
+            border-radius: 5px;
+            padding: 12px;
+            overflow: auto;
+            white-space: pre;
+        }}
+        .text-page .line-number {{
+            display: inline-block;
+            min-width: 4em;
+            margin-right: 1em;
+            text-align: right;
+            color: #6e7681;
+            user-select: none;
+        }}
+        .sheet-tabs {{
+            display: flex;
+            flex-wrap: wrap;
+            gap: 6px;
+            justify-content: center;
+            margin: 10px 0;
+        }}
+        .sheet-tabs a {{
+            padding: 6px 14px;
+            border-radius: 5px;
+            background-color: #3a3a3a;
+            color: #e0e0e0;
+            text-decoration: none;
+        }}
+        .sheet-tabs a.active {{
+            background-color: #0d7377;
+            color: #ffffff;
+        }}
         .data-table td:empty::after {{
             content: "—";
             color: #8b949e;
@@ -2331,7 +3808,7 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
         <div class="qr-section">
             <div class="qr-code">
                 <div>
-                    <img src="data:image/png;base64,{}" alt="QR Code" style="display: block;" />
+                    <a href="/qr/{}" target="_blank" title="Open the QR code"><img src="/qr/{}" alt="QR Code" width="250" height="250" style="display: block;" /></a>
                 </div>
             </div>
             <p><a href="/download/{}" class="download-btn">Download {}</a></p>
@@ -2347,7 +3824,8 @@ fn create_file_viewer_page(file_info: &FileInfo, share_url: &str) -> String {
 </html>"#,
         file_info.name, 
         file_info.name, 
-        generate_qr_code_base64(share_url).unwrap_or_else(|_| "".to_string()),
+        file_info.id,
+        file_info.id,
         file_info.id,
         file_info.name,
         viewer_content
@@ -2655,7 +4133,7 @@ fn parse_range(range_header: &str, file_size: u64) -> Option<(u64, u64)> {
     let range_part = &range_header[6..]; // Remove "bytes="
     let parts: Vec<&str> = range_part.split('-').collect();
     
-    if parts.len() != 2 {
+    if parts.len() != 2 || file_size == 0 {
         return None;
     }
     
@@ -2676,8 +4154,8 @@ fn parse_range(range_header: &str, file_size: u64) -> Option<(u64, u64)> {
         return None;
     };
     
-    let end = if parts[1].is_empty() {
-        // Range like "bytes=1024-" (from 1024 to end)
+    let end = if parts[1].is_empty() || parts[0].is_empty() {
+        // Range like "bytes=1024-" (from 1024 to end), or a suffix range
         file_size - 1
     } else if let Ok(end_pos) = parts[1].parse::<u64>() {
         std::cmp::min(end_pos, file_size - 1)
@@ -2691,3 +4169,819 @@ fn parse_range(range_header: &str, file_size: u64) -> Option<(u64, u64)> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const UNLIMITED: ShareOptions = ShareOptions { ttl: None, max_downloads: None, protection: None };
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html(r#"<a href="x">Tom & Jerry's</a>"#), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#x27;s&lt;/a&gt;");
+        // Existing entities are escaped again rather than passed through
+        assert_eq!(escape_html("&lt;"), "&amp;lt;");
+        assert_eq!(escape_html("plain"), "plain");
+    }
+
+    #[test]
+    fn test_markdown_headings_and_rules() {
+        assert_eq!(simple_markdown_to_html("# Title"), "<h1>Title</h1>\n");
+        assert_eq!(simple_markdown_to_html("### Third"), "<h3>Third</h3>\n");
+        assert_eq!(simple_markdown_to_html("###### Sixth"), "<h6>Sixth</h6>\n");
+        assert_eq!(simple_markdown_to_html("## <b>"), "<h2>&lt;b&gt;</h2>\n");
+        assert_eq!(simple_markdown_to_html("---"), "<hr>\n");
+        assert_eq!(simple_markdown_to_html("> quoted"), "<blockquote><p>quoted</p></blockquote>\n");
+    }
+
+    #[test]
+    fn test_markdown_lists() {
+        assert_eq!(
+            simple_markdown_to_html("- one\n* two\n\nafter"),
+            "<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n<p>after</p>\n"
+        );
+        assert_eq!(
+            simple_markdown_to_html("1. first\n2. second"),
+            "<ol>\n<li>first</li>\n<li>second</li>\n</ol>\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown_code_blocks_are_escaped_verbatim() {
+        assert_eq!(
+            simple_markdown_to_html("```rust\nlet x = a < b && *c*;\n```"),
+            "<pre><code class=\"language-rust\">let x = a &lt; b &amp;&amp; *c*;\n</code></pre>\n"
+        );
+        assert_eq!(simple_markdown_to_html("```\n# not a heading\n```"), "<pre><code># not a heading\n</code></pre>\n");
+    }
+
+    #[test]
+    fn test_inline_formatting() {
+        assert_eq!(process_inline_formatting("**bold** and *em*"), "<strong>bold</strong> and <em>em</em>");
+        assert_eq!(process_inline_formatting("run `cargo test`"), "run <code>cargo test</code>");
+        assert_eq!(process_inline_formatting("[docs](https://example.com)"), "<a href=\"https://example.com\">docs</a>");
+        assert_eq!(process_inline_formatting("<script>"), "&lt;script&gt;");
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+        // Suffix ranges ask for the last N bytes
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-5000", 1000), Some((0, 999)));
+        // The end is clamped to the file, but a start past it is unsatisfiable
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=50-10", 1000), None);
+        assert_eq!(parse_range("bytes=0-10", 0), None);
+        assert_eq!(parse_range("items=0-10", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+    }
+
+    #[test]
+    fn test_content_mime_type_prefers_content_on_mismatch() {
+        let dir = TempDir::new("content-mime");
+        let files = [
+            ("photo.txt", &b"\x89PNG\r\n\x1a\n\0"[..], "image/png"),
+            ("Makefile", &b"all:\n\tcargo build\n"[..], "text/plain"),
+            ("main.rs", &b"fn main() {}\n"[..], "text/x-rust"),
+            ("clip.mp4", &b"\0\0\0\x18ftypmp42"[..], "video/mp4"),
+            ("blob", &b"\0\x01\x02"[..], "application/octet-stream"),
+        ];
+        for (name, content, expected) in files {
+            std::fs::write(dir.join(name), content).unwrap();
+            assert_eq!(content_mime_type(&dir.join(name)), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_mime_types() {
+        assert_eq!(get_mime_type(Path::new("index.HTML")), "text/html");
+        assert_eq!(get_mime_type(Path::new("notes.md")), "text/plain");
+        assert_eq!(get_mime_type(Path::new("main.rs")), "text/x-rust");
+        assert_eq!(get_mime_type(Path::new("photo.jpeg")), "image/jpeg");
+        assert_eq!(get_mime_type(Path::new("clip.mp4")), "video/mp4");
+        assert_eq!(get_mime_type(Path::new("song.flac")), "audio/flac");
+        assert_eq!(get_mime_type(Path::new("report.pdf")), "application/pdf");
+        assert_eq!(get_mime_type(Path::new("data.csv")), "text/csv");
+        assert_eq!(get_mime_type(Path::new("bundle.zip")), "application/zip");
+        assert_eq!(get_mime_type(Path::new("Makefile")), "application/octet-stream");
+        assert_eq!(get_mime_type(Path::new("program.exe")), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_inline_display_classification() {
+        for name in ["a.PNG", "b.mp4", "c.mp3", "d.txt", "e.py", "f.sh", "g.toml", "h.csv", "i.pdf"] {
+            assert!(should_display_inline(Path::new(name)), "{} should display inline", name);
+        }
+        for name in ["a.zip", "b.exe", "c.docx", "Makefile"] {
+            assert!(!should_display_inline(Path::new(name)), "{} should download", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_url_needs_running_server() {
+        let mut server = FileShareServer::new(Config::default());
+        assert_eq!(server.list_url().await, None);
+
+        *server.is_running.write().await = true;
+        server.server_port = 8123;
+        let url = server.list_url().await.unwrap();
+        assert!(url.starts_with("http://") && url.ends_with(":8123/list"), "{}", url);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_releases_port() {
+        let bound = |port: u16| std::net::TcpListener::bind(("0.0.0.0", port)).is_err();
+        let answers = |port: u16| async move {
+            reqwest::get(format!("http://127.0.0.1:{}/list", port)).await.is_ok_and(|response| response.status().is_success())
+        };
+
+        let mut server = FileShareServer::new(Config::default());
+        server.start_server().await.unwrap();
+        let port = server.server_port;
+        assert!(answers(port).await);
+        assert!(bound(port));
+
+        server.shutdown().await.unwrap();
+        assert!(!bound(port));
+        assert_eq!(server.list_url().await, None);
+        assert!(reqwest::get(format!("http://127.0.0.1:{}/list", port)).await.is_err());
+
+        // Starts again, on whichever port is free now
+        server.start_server().await.unwrap();
+        assert!(server.list_url().await.is_some());
+        assert!(answers(server.server_port).await);
+        server.shutdown().await.unwrap();
+        assert!(!bound(server.server_port));
+    }
+
+    #[tokio::test]
+    async fn test_list_and_revoke_shares() {
+        let dir = TempDir::new("share-revoke");
+        std::fs::write(dir.join("a.txt"), "first").unwrap();
+        std::fs::write(dir.join("b.txt"), "second").unwrap();
+
+        let mut server = FileShareServer::new(Config::default());
+        server.config.notification_enabled = false;
+        let first = server.share_file(&dir.join("a.txt"), UNLIMITED).await.unwrap();
+        let second = server.share_file(&dir.join("b.txt"), UNLIMITED).await.unwrap();
+        let file_id = first.rsplit('/').next().unwrap().to_string();
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+        let status = |route: &str| {
+            let url = format!("{}/{}/{}", base, route, file_id);
+            async move { reqwest::get(&url).await.unwrap().status().as_u16() }
+        };
+        assert_eq!(status("file").await, 200);
+        assert_eq!(status("download").await, 200);
+        // Fetched by the viewer page, so not a hit of its own
+        assert_eq!(status("raw").await, 200);
+
+        let shares = server.list_shares().await;
+        let urls: Vec<&str> = shares.iter().map(|share| share.url.as_str()).collect();
+        assert_eq!(urls, [second.as_str(), first.as_str()]);
+        assert_eq!((shares[0].hits, shares[1].hits), (0, 2));
+        assert_eq!(shares[1].path, dir.join("a.txt"));
+
+        assert_eq!(server.revoke(&file_id).await, Some(dir.join("a.txt")));
+        assert_eq!(server.revoke(&file_id).await, None);
+        assert_eq!(status("file").await, 404);
+        assert_eq!(status("download").await, 404);
+        assert_eq!(server.list_shares().await.len(), 1);
+
+        assert_eq!(server.revoke_all().await, 1);
+        assert!(server.list_shares().await.is_empty());
+        // Still running, for whatever is shared next
+        assert!(server.list_url().await.is_some());
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_shares_are_gone() {
+        let dir = TempDir::new("share-expiry");
+        std::fs::write(dir.join("report.txt"), "quarterly").unwrap();
+
+        let mut server = FileShareServer::new(Config::default());
+        server.config.notification_enabled = false;
+        let twice = ShareOptions { max_downloads: Some(2), ..UNLIMITED };
+        let limited = server.share_file(&dir.join("report.txt"), twice.clone()).await.unwrap();
+        let timed = server.share_file(&dir.join("report.txt"), ShareOptions { ttl: Some(Duration::ZERO), ..UNLIMITED }).await.unwrap();
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+        let client = reqwest::Client::new();
+        let get = |route: &str, url: &str, range: Option<&str>| {
+            let mut request = client.get(format!("{}/{}/{}", base, route, url.rsplit('/').next().unwrap()));
+            if let Some(range) = range {
+                request = request.header("Range", range);
+            }
+            async move {
+                let response = request.send().await.unwrap();
+                (response.status().as_u16(), response.text().await.unwrap())
+            }
+        };
+
+        // Viewing and resuming a download don't use up a download
+        assert_eq!(get("file", &limited, None).await.0, 200);
+        assert_eq!(get("download", &limited, None).await.0, 200);
+        assert_eq!(get("download", &limited, Some("bytes=3-")).await.0, 206);
+        assert_eq!(server.list_shares().await[0].remaining_downloads, Some(1));
+        assert_eq!(get("download", &limited, None).await.0, 200);
+        let (status, page) = get("file", &limited, None).await;
+        assert_eq!(status, 410);
+        assert!(page.contains("expired"));
+        assert_eq!(get("raw", &limited, None).await.0, 410);
+        assert_eq!(get("download", &timed, None).await.0, 410);
+        assert!(server.list_shares().await.is_empty());
+
+        // Pruned shares are forgotten altogether
+        assert_eq!(prune_expired(&mut *server.shared_files.write().await, SystemTime::now()), 2);
+        assert_eq!(get("file", &limited, None).await.0, 404);
+
+        assert_eq!(twice.describe().unwrap(), "expires after 2 downloads");
+        let hour = ShareOptions::from(&FileShareSettings::default());
+        assert_eq!(hour.describe().unwrap(), "expires in 1h");
+        assert_eq!(ShareOptions { max_downloads: Some(1), ..hour }.describe().unwrap(), "expires in 1h or after 1 download");
+        assert_eq!(UNLIMITED.describe(), None);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_protected_shares() {
+        let dir = TempDir::new("share-protected");
+        std::fs::write(dir.join("secret.txt"), "classified").unwrap();
+
+        let mut server = FileShareServer::new(Config::default());
+        server.config.notification_enabled = false;
+        let token_options = ShareOptions { protection: Some(ShareProtection::Token), ..UNLIMITED };
+        let url = server.share_file(&dir.join("secret.txt"), token_options).await.unwrap();
+        let (link, token) = url.split_once("?t=").unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(token.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        let file_id = link.rsplit('/').next().unwrap().to_string();
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+        let client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build().unwrap();
+        let send = |request: reqwest::RequestBuilder| async move { request.send().await.unwrap() };
+        let status = |route: &str, query: &str, cookie: Option<&str>| {
+            let mut request = client.get(format!("{}/{}/{}{}", base, route, file_id, query));
+            if let Some(cookie) = cookie {
+                request = request.header("Cookie", cookie);
+            }
+            let request = send(request);
+            async move { request.await.status().as_u16() }
+        };
+
+        for route in ["file", "raw", "download"] {
+            assert_eq!(status(route, "", None).await, 403);
+            assert_eq!(status(route, "?t=0000000000000000", None).await, 403);
+        }
+        assert_eq!(status("download", &format!("?t={}", token), None).await, 200);
+        // The viewer page hands out a cookie for its own requests
+        let page = send(client.get(&url)).await;
+        assert_eq!(page.status(), 200);
+        // Plain HTTP can't carry a Secure cookie back
+        assert!(!page.headers()["set-cookie"].to_str().unwrap().contains("Secure"));
+        let cookie = page.headers()["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+        assert_eq!(status("raw", "", Some(&cookie)).await, 200);
+        assert_eq!(status("raw", "", Some("filepilot-share-x=y")).await, 403);
+        assert_eq!(server.list_shares().await[0].url, url);
+        // Turned away requests aren't hits
+        assert_eq!(server.list_shares().await[0].hits, 2);
+        let list = send(client.get(format!("{}/list", base))).await.text().await.unwrap();
+        assert!(!list.contains(&file_id));
+
+        let password_options = ShareOptions { protection: Some(ShareProtection::Password("hunter2".to_string())), ..UNLIMITED };
+        let url = server.share_file(&dir.join("secret.txt"), password_options).await.unwrap();
+        assert!(!url.contains('?'));
+        let file_id = url.rsplit('/').next().unwrap().to_string();
+        let page = send(client.get(&url)).await;
+        assert_eq!(page.status(), 403);
+        assert!(page.text().await.unwrap().contains("type=\"password\""));
+        let wrong = send(client.post(&url).form(&[("password", "hunter3")])).await;
+        assert_eq!(wrong.status(), 403);
+        assert!(wrong.text().await.unwrap().contains("Wrong password"));
+        let right = send(client.post(&url).form(&[("password", "hunter2")])).await;
+        assert_eq!(right.status(), 303);
+        assert_eq!(right.headers()["location"], format!("/file/{}", file_id).as_str());
+        let cookie = right.headers()["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+        let unlocked = send(client.get(&url).header("Cookie", format!("theme=dark; {}", cookie))).await;
+        assert_eq!(unlocked.status(), 200);
+        let raw = send(client.get(format!("{}/raw/{}", base, file_id)).header("Cookie", &cookie)).await;
+        assert_eq!(raw.text().await.unwrap(), "classified");
+        assert_eq!(send(client.get(format!("{}/raw/{}", base, file_id))).await.status(), 403);
+
+        assert!(constant_time_eq("abc", "abc"));
+        assert!(!constant_time_eq("abc", "abd") && !constant_time_eq("abc", "abcd"));
+        assert_eq!(cookie_value("a=1; b=2", "b"), Some("2"));
+        assert_eq!(cookie_value("a=1", "b"), None);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_directory_shares() {
+        let dir = TempDir::new("share-folder");
+        std::fs::create_dir_all(dir.join("shared/sub dir")).unwrap();
+        std::fs::write(dir.join("shared/top.txt"), "top").unwrap();
+        std::fs::write(dir.join("shared/sub dir/inner.txt"), "inner").unwrap();
+        std::fs::write(dir.join("outside.txt"), "private").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("outside.txt"), dir.join("shared/escape.txt")).unwrap();
+        assert_eq!(exposed_file_count(&dir.join("shared")), 2);
+
+        let root = dir.join("shared").canonicalize().unwrap();
+        assert_eq!(resolve_in_share(&root, "sub%20dir/inner.txt"), Some(root.join("sub dir/inner.txt")));
+        assert_eq!(resolve_in_share(&root, "sub%20dir/"), Some(root.join("sub dir")));
+        for escape in ["..", "%2E%2E/outside.txt", "sub%20dir/../../outside.txt", "..%2Foutside.txt", "escape.txt", "%zz"] {
+            assert_eq!(resolve_in_share(&root, escape), None, "{}", escape);
+        }
+
+        let mut server = FileShareServer::new(Config::default());
+        server.config.notification_enabled = false;
+        let url = server.share_file(&dir.join("shared"), UNLIMITED).await.unwrap();
+        let file_id = url.rsplit('/').next().unwrap().to_string();
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+        let get = |path: String| {
+            let url = format!("{}/{}", base, path);
+            async move { reqwest::get(&url).await.unwrap() }
+        };
+
+        let listing = get(format!("file/{}", file_id)).await.text().await.unwrap();
+        assert!(listing.contains(&format!("href=\"/file/{}/sub%20dir/\"", file_id)));
+        assert!(listing.contains(&format!("href=\"/download/{}/top.txt\"", file_id)));
+        assert!(listing.contains(&format!("href=\"/zip/{}\"", file_id)));
+        assert!(!listing.contains("escape.txt"));
+        let nested = get(format!("file/{}/sub%20dir/", file_id)).await.text().await.unwrap();
+        assert!(nested.contains(&format!("href=\"/raw/{}/sub%20dir/inner.txt\"", file_id)));
+        let viewer = get(format!("file/{}/sub%20dir/inner.txt", file_id)).await;
+        assert_eq!(viewer.status(), 200);
+        assert!(viewer.text().await.unwrap().contains(&format!("/download/{}/sub%20dir/inner.txt", file_id)));
+        assert_eq!(get(format!("raw/{}/sub%20dir/inner.txt", file_id)).await.text().await.unwrap(), "inner");
+        assert_eq!(get(format!("download/{}/top.txt", file_id)).await.text().await.unwrap(), "top");
+        for escape in ["%2E%2E/outside.txt", "..%2Foutside.txt", "escape.txt", ""] {
+            assert_eq!(get(format!("raw/{}/{}", file_id, escape)).await.status(), 404, "{}", escape);
+        }
+
+        let zip = get(format!("zip/{}", file_id)).await;
+        assert_eq!(zip.headers()["content-disposition"], "attachment; filename=\"shared.zip\"");
+        let zip = zip::ZipArchive::new(std::io::Cursor::new(zip.bytes().await.unwrap().to_vec())).unwrap();
+        let mut names: Vec<_> = zip.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, ["shared/", "shared/sub dir/", "shared/sub dir/inner.txt", "shared/top.txt"]);
+        let inner = get(format!("zip/{}/sub%20dir", file_id)).await.bytes().await.unwrap();
+        assert_eq!(zip::ZipArchive::new(std::io::Cursor::new(inner.to_vec())).unwrap().len(), 2);
+        assert_eq!(get(format!("zip/{}/top.txt", file_id)).await.status(), 404);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_uploads() {
+        let dir = TempDir::new("share-upload");
+        std::fs::write(dir.join("photo.jpg"), "already here").unwrap();
+
+        assert_eq!(sanitize_upload_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_upload_name("C:\\Users\\me\\a<b>.txt"), "a_b_.txt");
+        assert_eq!(sanitize_upload_name(".."), "upload");
+        assert_eq!(sanitize_upload_name("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_upload_name("notes.txt. "), "notes.txt");
+
+        let mut server = FileShareServer::new(Config::default());
+        server.config.notification_enabled = false;
+        server.share_file(&dir.join("photo.jpg"), UNLIMITED).await.unwrap();
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+        let client = reqwest::Client::new();
+        let upload = |files: &[(&str, &str)]| {
+            let mut body = String::new();
+            for (name, content) in files {
+                body.push_str(&format!(
+                    "--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n{}\r\n",
+                    name, content
+                ));
+            }
+            body.push_str("--XyZ--\r\n");
+            let request = client.post(format!("{}/upload", base))
+                .header("Content-Type", "multipart/form-data; boundary=XyZ")
+                .body(body)
+                .send();
+            async move { request.await.unwrap().status().as_u16() }
+        };
+
+        // Off until turned on
+        assert_eq!(upload(&[("a.txt", "a")]).await, 404);
+        assert_eq!(reqwest::get(format!("{}/upload", base)).await.unwrap().status(), 404);
+
+        server.enable_uploads(dir.to_path_buf(), 10).await.unwrap();
+        assert_eq!(server.upload_dir().await, Some(dir.to_path_buf()));
+        let page = reqwest::get(format!("{}/upload", base)).await.unwrap().text().await.unwrap();
+        assert!(page.contains("multipart/form-data"));
+        assert_eq!(upload(&[("photo.jpg", "new"), ("../photo.jpg", "newer")]).await, 200);
+        assert_eq!(std::fs::read_to_string(dir.join("photo.jpg")).unwrap(), "already here");
+        assert_eq!(std::fs::read_to_string(dir.join("photo (1).jpg")).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(dir.join("photo (2).jpg")).unwrap(), "newer");
+        let received = server.take_received();
+        assert_eq!(received, [
+            ReceivedFile { path: dir.join("photo (1).jpg"), size: 3 },
+            ReceivedFile { path: dir.join("photo (2).jpg"), size: 5 },
+        ]);
+        assert!(server.take_received().is_empty());
+
+        // Over the limit, and nothing half-written is left behind
+        assert_eq!(upload(&[("big.bin", "0123456789abc")]).await, 413);
+        assert!(!dir.join("big.bin").exists());
+        assert_eq!(upload(&[]).await, 400);
+
+        server.disable_uploads().await;
+        assert_eq!(upload(&[("a.txt", "a")]).await, 404);
+        assert!(!dir.join("a.txt").exists());
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_qr_codes() {
+        let dir = TempDir::new("share-qr");
+        std::fs::write(dir.join("photo.jpg"), "jpeg").unwrap();
+
+        let mut server = FileShareServer::new(Config::default());
+        server.config.notification_enabled = false;
+        let options = ShareOptions { protection: Some(ShareProtection::Token), ..UNLIMITED };
+        let url = server.share_file(&dir.join("photo.jpg"), options).await.unwrap();
+        let (link, token) = url.split_once("?t=").unwrap();
+        let file_id = link.rsplit('/').next().unwrap();
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+
+        // The code gives the token away, so it needs the token too
+        let locked = reqwest::get(format!("{}/qr/{}", base, file_id)).await.unwrap();
+        assert_eq!(locked.status(), 403);
+        let qr = reqwest::get(format!("{}/qr/{}?t={}", base, file_id, token)).await.unwrap();
+        assert_eq!(qr.headers()["content-type"], "image/svg+xml");
+        assert_eq!(qr.text().await.unwrap(), qr_code_svg(&url).unwrap());
+        let page = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(page.contains(&format!("<img src=\"/qr/{}\"", file_id)));
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn test_csv_preview() {
+        let dir = TempDir::new("share-csv");
+        let preview = |name: &str, content: &str, max_rows: usize| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            parse_csv_to_html(&path, "abc", 0, max_rows).unwrap()
+        };
+
+        // Rows past the first page are counted, not dropped from the total
+        let long: String = std::iter::once("id,name\n".to_string())
+            .chain((1..=25).map(|n| format!("{},row {}\n", n, n)))
+            .collect();
+        let html = preview("long.csv", &long, 10);
+        assert!(html.contains("<th>id</th><th>name</th>"));
+        assert!(html.contains("<td>10</td><td>row 10</td>") && !html.contains("<td>11</td>"));
+        assert!(html.contains("Rows 1&ndash;10 of 25"));
+        assert!(html.contains(r#"<a href="/file/abc?page=2">"#));
+        let html = preview("short.csv", "id,name\n1,one\n", 10);
+        assert!(!html.contains("class=\"pager\""));
+
+        // Later pages keep the header row, and the last one may be short
+        let page = |page: usize| parse_csv_to_html(&dir.join("long.csv"), "abc", page, 10).unwrap();
+        let html = page(1);
+        assert!(html.contains("<th>id</th><th>name</th>") && html.contains("Rows 11&ndash;20 of 25"));
+        assert!(html.contains("<td>11</td>") && !html.contains("<td>10</td>") && !html.contains("<td>21</td>"));
+        assert!(html.contains(r#"<a href="/file/abc?page=1">"#) && html.contains(r#"<a href="/file/abc?page=3">"#));
+        let html = page(2);
+        assert!(html.contains("Rows 21&ndash;25 of 25") && html.contains("<td>25</td>"));
+        assert!(!html.contains("?page=4"));
+        assert!(page(5).contains("past the end"));
+        // Without a header, the first row is data on the first page only
+        std::fs::write(dir.join("numbers.csv"), "1\n2\n3\n").unwrap();
+        let html = parse_csv_to_html(&dir.join("numbers.csv"), "abc", 1, 2).unwrap();
+        assert!(html.contains("<td>3</td>") && !html.contains("<td>2</td>") && html.contains("Rows 3&ndash;3 of 3"));
+
+        // A first row of numbers is data, under numbered columns
+        let html = preview("numbers.csv", "1,2,3\n4,5,6\n", 10);
+        assert!(html.contains("<th>Column 1</th><th>Column 2</th><th>Column 3</th>"));
+        assert!(html.contains("<td>1</td><td>2</td><td>3</td>"));
+        assert!(preview("blank-header.csv", "name,,city\nAnn,1,Oslo\n", 10).contains("<th>Column 2</th>"));
+
+        // Short rows are padded, and extra fields get their own column
+        let html = preview("ragged.csv", "name,age\nalice\nbob,30,extra\n", 10);
+        assert!(html.contains("<th>name</th><th>age</th><th>Column 3</th>"));
+        assert!(html.contains("<td>alice</td><td></td><td></td>"));
+        assert!(html.contains("<td>bob</td><td>30</td><td>extra</td>"));
+
+        // Semicolons and tabs are picked up from the first line
+        let html = preview("semicolon.csv", "name;city\n\"Smith, Ann\";Oslo\n", 10);
+        assert!(html.contains("<td>Smith, Ann</td><td>Oslo</td>"));
+        assert!(preview("tabs.tsv", "name\tcity\nAnn\tOslo\n", 10).contains("<td>Ann</td><td>Oslo</td>"));
+        assert_eq!(detect_csv_delimiter(b"\"a;b;c\",d"), b',');
+        assert_eq!(detect_csv_delimiter(b"single"), b',');
+
+        assert!(preview("empty.csv", "", 10).contains("empty"));
+    }
+
+    #[test]
+    fn test_read_line_page() {
+        let dir = TempDir::new("share-lines");
+        let path = dir.join("server.log");
+        let text: String = (1..=25).map(|n| format!("line {}\r\n", n)).collect();
+        std::fs::write(&path, format!("{}no newline", text)).unwrap();
+
+        let page = read_line_page(&path, 1, 10).unwrap();
+        assert_eq!((page.first, page.total), (10, LineCount { seen: 26, more: false }));
+        assert_eq!(page.lines.first().map(String::as_str), Some("line 11"));
+        assert_eq!(page.lines.len(), 10);
+        assert_eq!(read_line_page(&path, 2, 10).unwrap().lines.last().map(String::as_str), Some("no newline"));
+        assert!(read_line_page(&path, 3, 10).unwrap().lines.is_empty());
+
+        let exact = |seen| LineCount { seen, more: false };
+        let pager = pager_html("abc", 1, 10, exact(26), "Lines");
+        assert!(pager.contains("Lines 11&ndash;20 of 26"));
+        assert!(pager_html("abc", 0, 10, exact(10), "Lines").is_empty());
+
+        // Counting stops at the limit, and a line without breaks is cut short
+        let many = "x\n".repeat(PREVIEW_COUNT_LIMIT + 5);
+        std::fs::write(&path, format!("{}\n{}", "y".repeat(MAX_PREVIEW_LINE_BYTES * 3), many)).unwrap();
+        let page = read_line_page(&path, 0, 10).unwrap();
+        assert_eq!(page.total, LineCount { seen: PREVIEW_COUNT_LIMIT, more: true });
+        assert_eq!(page.lines[0].len(), MAX_PREVIEW_LINE_BYTES + " …".len());
+        assert_eq!(page.lines[1], "x");
+        let pager = pager_html("abc", 0, 10, page.total, "Lines");
+        assert!(pager.contains(&format!("of {}+", PREVIEW_COUNT_LIMIT)) && pager.contains("?page=2"));
+        // Pages past the limit are still read, and know whether more follow
+        let last = read_line_page(&path, (PREVIEW_COUNT_LIMIT + 5) / 10, 10).unwrap();
+        assert_eq!((last.lines.len(), last.total.more), (6, false));
+    }
+
+    #[tokio::test]
+    async fn test_paged_text_preview() {
+        let dir = TempDir::new("share-paged");
+        let path = dir.join("server.log");
+        std::fs::write(&path, (1..=25).map(|n| format!("<entry {}>\n", n)).collect::<String>()).unwrap();
+
+        let config = Config {
+            notification_enabled: false,
+            file_sharing: FileShareSettings { preview_lines: 10, ..FileShareSettings::default() },
+            ..Config::default()
+        };
+        let mut server = FileShareServer::new(config);
+        let url = server.share_file(&path, UNLIMITED).await.unwrap();
+
+        // A long file is shown a page of numbered lines at a time
+        let html = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(html.contains("Lines 1&ndash;10 of 25") && html.contains("&lt;entry 10&gt;"));
+        assert!(!html.contains("&lt;entry 11&gt;") && !html.contains("<iframe"));
+        let html = reqwest::get(format!("{}?page=3", url)).await.unwrap().text().await.unwrap();
+        assert!(html.contains("Lines 21&ndash;25 of 25") && html.contains(r#"<span class="line-number">25</span>"#));
+        let response = reqwest::get(format!("{}?page=0", url)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // Paging is browsing, not another visit
+        assert_eq!(server.list_shares().await[0].hits, 1);
+
+        server.shutdown().await.unwrap();
+    }
+
+    /// Writes a workbook with a sheet per `(name, rows)`, numbers stored as
+    /// numbers and the rest as inline strings.
+    fn write_xlsx(path: &Path, sheets: &[(&str, Vec<Vec<&str>>)]) {
+        use std::io::Write;
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let mut add = |name: &str, content: String| {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        };
+        let entries: String = sheets.iter().enumerate()
+            .map(|(i, (name, _))| format!(r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#, escape_html(name), i + 1, i + 1))
+            .collect();
+        add("xl/workbook.xml", format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>{}</sheets></workbook>"#,
+            entries
+        ));
+        let relationships: String = (1..=sheets.len())
+            .map(|i| format!(r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#, i, i))
+            .collect();
+        add("xl/_rels/workbook.xml.rels", format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">{}</Relationships>"#,
+            relationships
+        ));
+        for (i, (_, rows)) in sheets.iter().enumerate() {
+            let data: String = rows.iter().enumerate().map(|(row, cells)| {
+                let cells: String = cells.iter().enumerate().map(|(column, value)| {
+                    let reference = format!("{}{}", (b'A' + column as u8) as char, row + 1);
+                    if value.parse::<f64>().is_ok() {
+                        format!(r#"<c r="{}"><v>{}</v></c>"#, reference, value)
+                    } else {
+                        format!(r#"<c r="{}" t="inlineStr"><is><t>{}</t></is></c>"#, reference, escape_html(value))
+                    }
+                }).collect();
+                format!(r#"<row r="{}">{}</row>"#, row + 1, cells)
+            }).collect();
+            add(&format!("xl/worksheets/sheet{}.xml", i + 1), format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?><worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>{}</sheetData></worksheet>"#,
+                data
+            ));
+        }
+        zip.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_excel_sheets() {
+        let dir = TempDir::new("share-xlsx");
+        let path = dir.join("budget.xlsx");
+        write_xlsx(&path, &[
+            ("Budget", vec![vec!["Item", "Cost"], vec!["Rent", "1200"], vec!["Food", "300"], vec!["Travel", "150"]]),
+            ("Q&A <2>", vec![vec!["Question", "Answer"], vec!["Why", "Because"]]),
+            ("Notes", vec![]),
+        ]);
+
+        // Each sheet links to the others, with only its own rows limited
+        let html = parse_excel_to_html(&path, "abc", 0, 2).unwrap();
+        assert!(html.contains(r#"<a href="/file/abc?sheet=1" class="active">Budget</a>"#));
+        assert!(html.contains(r#"<a href="/file/abc?sheet=2">Q&amp;A &lt;2&gt;</a>"#));
+        assert!(html.contains("<td>Rent</td><td>1200</td>") && !html.contains("<td>Food</td>"));
+        assert!(html.contains("Showing first 2 of 4 rows"));
+        let html = parse_excel_to_html(&path, "abc", 1, 2).unwrap();
+        assert!(html.contains("<h3>Sheet: Q&amp;A &lt;2&gt;</h3>"));
+        assert!(html.contains("<td>Why</td><td>Because</td>") && !html.contains("Showing first"));
+        assert!(parse_excel_to_html(&path, "abc", 2, 2).unwrap().contains("This sheet is empty."));
+        assert!(parse_excel_to_html(&path, "abc", 3, 2).is_err());
+
+        let mut server = FileShareServer::new(Config { notification_enabled: false, ..Config::default() });
+        let url = server.share_file(&path, UNLIMITED).await.unwrap();
+        assert_eq!(reqwest::get(&url).await.unwrap().status(), 200);
+        let page = reqwest::get(format!("{}?sheet=2", url)).await.unwrap();
+        assert_eq!(page.status(), 200);
+        assert!(page.text().await.unwrap().contains("<td>Why</td>"));
+        for sheet in ["0", "4", "first"] {
+            let page = reqwest::get(format!("{}?sheet={}", url, sheet)).await.unwrap();
+            assert_eq!(page.status(), 404, "{}", sheet);
+            assert!(page.text().await.unwrap().contains("No such sheet"));
+        }
+        // Switching sheets isn't another visit
+        assert_eq!(server.list_shares().await[0].hits, 1);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        let dir = TempDir::new("share-access");
+        std::fs::write(dir.join("photo.jpg"), "jpeg bytes").unwrap();
+        let mut config = Config { notification_enabled: false, ..Config::default() };
+        config.file_sharing.access_log = Some(dir.join("access.log"));
+
+        let mut server = FileShareServer::new(config);
+        let url = server.share_file(&dir.join("photo.jpg"), UNLIMITED).await.unwrap();
+        let file_id = url.rsplit('/').next().unwrap().to_string();
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+        let client = reqwest::Client::new();
+        assert_eq!(client.get(format!("{}/file/{}", base, file_id)).send().await.unwrap().status(), 200);
+        assert_eq!(client.get(format!("{}/raw/{}", base, file_id)).send().await.unwrap().status(), 200);
+        let resumed = client.get(format!("{}/download/{}", base, file_id)).header("Range", "bytes=4-").send().await.unwrap();
+        assert_eq!(resumed.status(), 206);
+        assert_eq!(client.get(format!("{}/download/{}", base, file_id)).send().await.unwrap().status(), 200);
+        assert_eq!(client.get(format!("{}/download/missing", base)).send().await.unwrap().status(), 404);
+
+        let log = server.access_log();
+        let seen: Vec<_> = log.iter().map(|record| (record.route.as_str(), record.status, record.range.as_deref())).collect();
+        assert_eq!(seen, [("file", 200, None), ("raw", 200, None), ("download", 206, Some("bytes=4-")), ("download", 200, None), ("download", 404, None)]);
+        assert!(log.iter().all(|record| record.client == Some(IpAddr::V4(Ipv4Addr::LOCALHOST))));
+        assert_eq!((log[0].name.as_str(), log[4].name.as_str()), ("photo.jpg", "missing"));
+
+        // Only the page view and the whole download are worth reporting
+        let events: Vec<_> = server.take_access_events().iter().filter_map(AccessRecord::summary).collect();
+        assert_eq!(events, ["'photo.jpg' opened by 127.0.0.1", "'photo.jpg' downloaded by 127.0.0.1"]);
+        assert!(server.take_access_events().is_empty());
+        assert_eq!(server.list_shares().await[0].last_client, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+
+        let lines = std::fs::read_to_string(dir.join("access.log")).unwrap();
+        assert_eq!(lines.lines().count(), 5);
+        assert!(lines.lines().nth(2).unwrap().ends_with(&format!(" UTC 127.0.0.1 download {} 206 bytes=4- photo.jpg", file_id)));
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn test_access_log_is_bounded() {
+        let (events, mut receiver) = mpsc::channel(ACCESS_EVENT_BACKLOG);
+        let log = AccessLog { records: Default::default(), events, file: None };
+        for n in 0..ACCESS_LOG_CAPACITY + 10 {
+            log.push(AccessRecord {
+                at: SystemTime::now(),
+                client: None,
+                file_id: n.to_string(),
+                name: "notes.txt".to_string(),
+                route: "download".to_string(),
+                range: None,
+                status: 200,
+            });
+        }
+        let records = log.records.lock().unwrap();
+        assert_eq!(records.len(), ACCESS_LOG_CAPACITY);
+        assert_eq!(records.front().unwrap().file_id, "10");
+        let mut reported = 0;
+        while receiver.try_recv().is_ok() {
+            reported += 1;
+        }
+        assert_eq!(reported, ACCESS_EVENT_BACKLOG);
+    }
+
+    #[tokio::test]
+    async fn test_bind_address() {
+        let dir = TempDir::new("share-bind");
+        std::fs::write(dir.join("notes.txt"), "notes").unwrap();
+        let mut config = Config { notification_enabled: false, ..Config::default() };
+
+        // Links to a server only this machine can reach use loopback
+        config.file_sharing.bind_address = Some("127.0.0.1".to_string());
+        let mut server = FileShareServer::new(config.clone());
+        let url = server.share_file(&dir.join("notes.txt"), UNLIMITED).await.unwrap();
+        assert!(url.starts_with(&format!("http://127.0.0.1:{}/file/", server.server_port)));
+        assert_eq!(reqwest::get(&url).await.unwrap().status(), 200);
+        server.shutdown().await.unwrap();
+
+        // Bad addresses say so instead of blaming the ports
+        config.file_sharing.bind_address = Some("localhost:8080".to_string());
+        let mut server = FileShareServer::new(config.clone());
+        let error = server.share_file(&dir.join("notes.txt"), UNLIMITED).await.unwrap_err().to_string();
+        assert!(error.starts_with("Invalid share bind address 'localhost:8080'"), "{}", error);
+        // 192.0.2.0/24 is reserved for documentation, so no machine has it
+        config.file_sharing.bind_address = Some("192.0.2.1".to_string());
+        let mut server = FileShareServer::new(config);
+        let error = server.share_file(&dir.join("notes.txt"), UNLIMITED).await.unwrap_err().to_string();
+        assert!(error.starts_with("Can't listen on 192.0.2.1:"), "{}", error);
+        assert_eq!(server.list_url().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_https_shares() {
+        let dir = TempDir::new("share-tls");
+        std::fs::write(dir.join("notes.txt"), "notes").unwrap();
+        let (cert, key) = (dir.join(tls::CERT_FILE), dir.join(tls::KEY_FILE));
+        let fingerprint = tls::generate_self_signed(&cert, &key, vec!["127.0.0.1".to_string()]).unwrap();
+
+        let mut config = Config { notification_enabled: false, ..Config::default() };
+        config.file_sharing.bind_address = Some("127.0.0.1".to_string());
+        config.file_sharing.tls_cert = Some(cert);
+        config.file_sharing.tls_key = Some(key);
+        let mut server = FileShareServer::new(config.clone());
+        let url = server.share_file(&dir.join("notes.txt"), UNLIMITED).await.unwrap();
+        assert!(url.starts_with(&format!("https://127.0.0.1:{}/file/", server.server_port)), "{}", url);
+        assert_eq!(server.tls_fingerprint(), Some(fingerprint.as_str()));
+        let client = reqwest::Client::builder().danger_accept_invalid_certs(true).build().unwrap();
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 200);
+        assert!(reqwest::get(url.replacen("https", "http", 1)).await.is_err());
+        // The session a protected share hands out is kept to HTTPS
+        let protected = ShareOptions { protection: Some(ShareProtection::Token), ..UNLIMITED };
+        let url = server.share_file(&dir.join("notes.txt"), protected).await.unwrap();
+        let page = client.get(&url).send().await.unwrap();
+        assert!(page.headers()["set-cookie"].to_str().unwrap().ends_with("; SameSite=Strict; Secure"));
+        server.shutdown().await.unwrap();
+
+        // Unreadable certificates leave sharing working over plain HTTP
+        config.file_sharing.tls_cert = Some(dir.join("missing.pem"));
+        let mut server = FileShareServer::new(config);
+        let url = server.share_file(&dir.join("notes.txt"), UNLIMITED).await.unwrap();
+        assert!(url.starts_with("http://127.0.0.1:"), "{}", url);
+        assert_eq!(server.tls_fingerprint(), None);
+        assert!(server.tls_warning().unwrap().starts_with("HTTPS is off, can't read"));
+        assert_eq!(reqwest::get(&url).await.unwrap().status(), 200);
+        server.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_share_file_with_non_utf8_name() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = TempDir::new("share-latin1");
+        let path = dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9.txt"));
+        // Filesystems that insist on UTF-8 (like APFS) refuse the name outright
+        if std::fs::write(&path, "menu").is_err() {
+            return;
+        }
+
+        let mut server = FileShareServer::new(Config::default());
+        server.config.notification_enabled = false;
+        let url = server.share_file(&path, UNLIMITED).await.unwrap();
+        let file_id = url.rsplit('/').next().unwrap().to_string();
+        assert_eq!(server.shared_files.read().await.get(&file_id).map(|shared| &shared.path), Some(&path));
+
+        let base = format!("http://127.0.0.1:{}", server.server_port);
+        let download = reqwest::get(format!("{}/download/{}", base, file_id)).await.unwrap();
+        assert!(download.status().is_success());
+        let disposition = download.headers()["content-disposition"].as_bytes().to_vec();
+        assert!(String::from_utf8_lossy(&disposition).contains("caf\u{FFFD}.txt"));
+        assert_eq!(download.text().await.unwrap(), "menu");
+
+        let page = reqwest::get(format!("{}/file/{}", base, file_id)).await.unwrap().text().await.unwrap();
+        assert!(page.contains("caf\u{FFFD}.txt"));
+
+        server.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! HTTPS for the share server: loading the configured certificate and key,
+//! and making a self-signed pair for `--generate-cert`.
+
+use crate::config::FileShareSettings;
+use rcgen::{CertificateParams, DnType, KeyPair};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Files `--generate-cert` writes, in `~/.filepilot`
+pub const CERT_FILE: &str = "share-cert.pem";
+pub const KEY_FILE: &str = "share-key.pem";
+
+/// A certificate and private key to serve shares over HTTPS with.
+pub struct TlsIdentity {
+    /// PEM certificate chain, the server's own certificate first
+    pub cert: Vec<u8>,
+    /// PEM private key
+    pub key: Vec<u8>,
+    /// SHA-256 of the server's certificate, written the way browsers show it
+    pub fingerprint: String,
+}
+
+impl TlsIdentity {
+    /// Reads the PEM files at `cert_path` and `key_path`, checking each has
+    /// what it should.
+    pub fn load(cert_path: &Path, key_path: &Path) -> Result<Self, String> {
+        let read = |path: &Path| fs::read(path).map_err(|e| format!("can't read '{}': {}", path.display(), e));
+        let cert = read(cert_path)?;
+        let key = read(key_path)?;
+
+        let leaf = rustls_pemfile::certs(&mut cert.as_slice())
+            .next()
+            .and_then(Result::ok)
+            .ok_or_else(|| format!("no certificate in '{}'", cert_path.display()))?;
+        if !matches!(rustls_pemfile::private_key(&mut key.as_slice()), Ok(Some(_))) {
+            return Err(format!("no private key in '{}'", key_path.display()));
+        }
+
+        Ok(Self { fingerprint: fingerprint(&leaf), cert, key })
+    }
+
+    /// The identity `settings` asks for: `None` when neither `tls_cert` nor
+    /// `tls_key` is set, and an error saying why HTTPS is off when they
+    /// can't be used.
+    pub fn from_settings(settings: &FileShareSettings) -> Result<Option<Self>, String> {
+        match (settings.tls_cert(), settings.tls_key()) {
+            (None, None) => Ok(None),
+            (Some(cert), Some(key)) => Self::load(&cert, &key).map(Some),
+            _ => Err("tls_cert and tls_key must both be set".to_string()),
+        }
+    }
+}
+
+/// `der` hashed with SHA-256, as colon-separated uppercase hex.
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Writes a new self-signed certificate valid for `hosts` (names or IPs)
+/// to `cert_path` and its key to `key_path`, replacing any from before. The
+/// key file is readable by its owner only. Returns the certificate's
+/// fingerprint.
+pub fn generate_self_signed(cert_path: &Path, key_path: &Path, hosts: Vec<String>) -> Result<String, Box<dyn std::error::Error>> {
+    let mut params = CertificateParams::new(hosts)?;
+    params.distinguished_name.push(DnType::CommonName, "FilePilot share server");
+    let key = KeyPair::generate()?;
+    let cert = params.self_signed(&key)?;
+
+    fs::write(cert_path, cert.pem())?;
+    write_private(key_path, key.serialize_pem().as_bytes())?;
+    Ok(fingerprint(cert.der()))
+}
+
+/// The names a generated certificate covers: this machine by name and
+/// loopback, its LAN IP, and `bind_address` when it names another.
+pub fn default_hosts(settings: &FileShareSettings) -> Vec<String> {
+    let mut hosts = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+    let addresses = [local_ip_address::local_ip().ok(), settings.bind_ip().ok()];
+    for address in addresses.into_iter().flatten().filter(|ip| !ip.is_unspecified()) {
+        if !hosts.contains(&address.to_string()) {
+            hosts.push(address.to_string());
+        }
+    }
+    hosts
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // An existing file keeps its mode when opened
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_generate_and_load() {
+        let dir = TempDir::new("tls");
+        let (cert_path, key_path) = (dir.join(CERT_FILE), dir.join(KEY_FILE));
+
+        let hosts = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        let generated = generate_self_signed(&cert_path, &key_path, hosts).unwrap();
+        let identity = TlsIdentity::load(&cert_path, &key_path).unwrap();
+        assert_eq!(identity.fingerprint, generated);
+        assert_eq!(identity.fingerprint.len(), 32 * 3 - 1);
+        let der = rustls_pemfile::certs(&mut identity.cert.as_slice()).next().unwrap().unwrap();
+        assert_eq!(identity.fingerprint, fingerprint(&der));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&key_path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // The files swapped, or missing, are reported by name
+        let error = TlsIdentity::load(&key_path, &cert_path).err().unwrap();
+        assert_eq!(error, format!("no certificate in '{}'", key_path.display()));
+        let missing = dir.join("missing.pem");
+        assert!(TlsIdentity::load(&missing, &key_path).err().unwrap().starts_with("can't read"));
+
+        let mut settings = FileShareSettings::default();
+        assert!(TlsIdentity::from_settings(&settings).unwrap().is_none());
+        settings.tls_cert = Some(cert_path.clone());
+        assert!(TlsIdentity::from_settings(&settings).is_err());
+        settings.tls_key = Some(key_path.clone());
+        assert!(TlsIdentity::from_settings(&settings).unwrap().is_some());
+    }
+}
@@ -1,6 +1,28 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
+
+pub mod archive;
+pub mod batch_rename;
+pub mod checksum;
+pub mod compare;
+pub mod completion;
+pub mod file_type;
+pub mod frecency;
+pub mod git_status;
+pub mod gitignore;
+pub mod mounts;
+pub mod open_with;
+pub mod transfer;
+pub mod trash;
+pub mod watcher;
+
+pub use file_type::{detect_type, FileKind};
+use frecency::Frecency;
+use gitignore::IgnoreRules;
 
 #[derive(Debug, Clone)]
 pub struct FileInfo {
@@ -9,35 +31,590 @@ pub struct FileInfo {
     pub is_directory: bool,
     pub size: u64,
     pub modified: Option<SystemTime>,
+    pub is_hidden: bool,
+    pub is_symlink: bool,
+    pub link_target: Option<PathBuf>,
+    pub is_broken_link: bool,
+    pub permissions: FilePermissions,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    /// Only the name and type are known yet; size, times, permissions and
+    /// owner are placeholders until the entry is read with `from_path`
+    pub metadata_pending: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilePermissions {
+    /// Unix mode bits
+    Mode(u32),
+    /// Windows only exposes the read-only attribute
+    #[cfg_attr(unix, allow(dead_code))]
+    ReadOnly(bool),
+}
+
+impl FilePermissions {
+    fn from_metadata(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            FilePermissions::Mode(metadata.permissions().mode())
+        }
+        #[cfg(not(unix))]
+        {
+            FilePermissions::ReadOnly(metadata.permissions().readonly())
+        }
+    }
+
+    pub fn is_writable(&self) -> bool {
+        match self {
+            FilePermissions::Mode(mode) => mode & 0o222 != 0,
+            FilePermissions::ReadOnly(readonly) => !readonly,
+        }
+    }
+
+    /// `ls -l` style string such as `-rw-r--r--`, or "read-only"/"writable" on Windows.
+    pub fn display(&self, is_directory: bool, is_symlink: bool) -> String {
+        let mode = match self {
+            FilePermissions::Mode(mode) => *mode,
+            FilePermissions::ReadOnly(true) => return "read-only".to_string(),
+            FilePermissions::ReadOnly(false) => return "writable".to_string(),
+        };
+
+        let mut text = String::with_capacity(10);
+        text.push(if is_symlink { 'l' } else if is_directory { 'd' } else { '-' });
+        // (read bit, write bit, execute bit, special bit, special char)
+        let triples = [
+            (0o400, 0o200, 0o100, 0o4000, 's'),
+            (0o040, 0o020, 0o010, 0o2000, 's'),
+            (0o004, 0o002, 0o001, 0o1000, 't'),
+        ];
+        for (read, write, execute, special, special_char) in triples {
+            text.push(if mode & read != 0 { 'r' } else { '-' });
+            text.push(if mode & write != 0 { 'w' } else { '-' });
+            text.push(match (mode & execute != 0, mode & special != 0) {
+                (true, true) => special_char,
+                (false, true) => special_char.to_ascii_uppercase(),
+                (true, false) => 'x',
+                (false, false) => '-',
+            });
+        }
+        text
+    }
 }
 
 impl FileInfo {
     pub fn from_path(path: &Path) -> Result<Self, std::io::Error> {
-        let metadata = fs::metadata(path)?;
+        // Look at the entry itself first so symlinks (even dangling ones)
+        // are never dropped from the listing
+        let link_metadata = fs::symlink_metadata(path)?;
+        let is_symlink = link_metadata.file_type().is_symlink();
+        let link_target = if is_symlink { fs::read_link(path).ok() } else { None };
+
+        let (metadata, is_broken_link) = if is_symlink {
+            match fs::metadata(path) {
+                Ok(target_metadata) => (target_metadata, false),
+                Err(_) => (link_metadata, true),
+            }
+        } else {
+            (link_metadata, false)
+        };
+
+        let name = display_name(path);
+        let is_hidden = is_hidden_entry(&name, &metadata);
+        let (owner, group) = owner_and_group(&metadata);
         
         Ok(FileInfo {
             path: path.to_path_buf(),
-            name: path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("")
-                .to_string(),
+            name,
             is_directory: metadata.is_dir(),
             size: metadata.len(),
             modified: metadata.modified().ok(),
+            is_hidden,
+            is_symlink,
+            link_target,
+            is_broken_link,
+            permissions: FilePermissions::from_metadata(&metadata),
+            owner,
+            group,
+            metadata_pending: false,
         })
     }
+
+    /// What the directory entry itself tells, without a `stat` call on
+    /// most filesystems. Symlinks still need their target read to know
+    /// whether they lead to a directory, and Windows keeps the hidden flag
+    /// in the metadata, so those are read in full.
+    fn from_dir_entry(entry: &fs::DirEntry) -> Result<Self, std::io::Error> {
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_symlink() || cfg!(windows) {
+            return Self::from_path(&path);
+        }
+
+        let name = display_name(&path);
+        Ok(FileInfo {
+            is_hidden: name.starts_with('.'),
+            path,
+            name,
+            is_directory: file_type.is_dir(),
+            size: 0,
+            modified: None,
+            is_symlink: false,
+            link_target: None,
+            is_broken_link: false,
+            permissions: FilePermissions::Mode(0),
+            owner: None,
+            group: None,
+            metadata_pending: true,
+        })
+    }
+}
+
+/// The file name of `path` for showing to the user. Bytes that aren't
+/// valid UTF-8 become `\u{FFFD}` (�), so such names stay visible and
+/// recognisable; operations always go through the path itself.
+pub fn display_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+/// Size in binary units, e.g. "512B" or "1.5MB".
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = size as f64;
+    let mut unit_index = 0;
+    
+    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit_index += 1;
+    }
+    
+    if unit_index == 0 {
+        format!("{:.0}{}", size, UNITS[unit_index])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit_index])
+    }
+}
+
+#[cfg(windows)]
+fn is_hidden_entry(_name: &str, metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+#[cfg(not(windows))]
+fn is_hidden_entry(name: &str, _metadata: &fs::Metadata) -> bool {
+    name.starts_with('.')
+}
+
+#[cfg(unix)]
+fn owner_and_group(metadata: &fs::Metadata) -> (Option<String>, Option<String>) {
+    use std::os::unix::fs::MetadataExt;
+    (Some(unix_names::user_name(metadata.uid())), Some(unix_names::group_name(metadata.gid())))
+}
+
+#[cfg(not(unix))]
+fn owner_and_group(_metadata: &fs::Metadata) -> (Option<String>, Option<String>) {
+    (None, None)
+}
+
+/// Cached uid/gid to name lookups, falling back to the numeric id
+#[cfg(unix)]
+mod unix_names {
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+    use std::sync::{Mutex, OnceLock};
+
+    static USERS: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+    static GROUPS: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+
+    pub fn user_name(uid: u32) -> String {
+        let cache = USERS.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.entry(uid).or_insert_with(|| lookup_user(uid).unwrap_or_else(|| uid.to_string())).clone()
+    }
+
+    pub fn group_name(gid: u32) -> String {
+        let cache = GROUPS.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.entry(gid).or_insert_with(|| lookup_group(gid).unwrap_or_else(|| gid.to_string())).clone()
+    }
+
+    fn lookup_user(uid: u32) -> Option<String> {
+        let mut buffer = vec![0 as libc::c_char; 4096];
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        // SAFETY: every pointer refers to a live local and the buffer length is accurate
+        let status = unsafe {
+            libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+        if status != 0 || result.is_null() {
+            return None;
+        }
+        // SAFETY: on success pw_name points into `buffer` as a NUL-terminated string
+        Some(unsafe { CStr::from_ptr(passwd.pw_name) }.to_string_lossy().into_owned())
+    }
+
+    fn lookup_group(gid: u32) -> Option<String> {
+        let mut buffer = vec![0 as libc::c_char; 4096];
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::group = std::ptr::null_mut();
+        // SAFETY: every pointer refers to a live local and the buffer length is accurate
+        let status = unsafe {
+            libc::getgrgid_r(gid, &mut group, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+        if status != 0 || result.is_null() {
+            return None;
+        }
+        // SAFETY: on success gr_name points into `buffer` as a NUL-terminated string
+        Some(unsafe { CStr::from_ptr(group.gr_name) }.to_string_lossy().into_owned())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMode {
+    Name,      // A to Z
+    Size,      // Largest first
+    Modified,  // Newest first
+    Extension, // A to Z by extension, then name
+}
+
+impl SortMode {
+    pub fn next(&self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Size,
+            SortMode::Size => SortMode::Modified,
+            SortMode::Modified => SortMode::Extension,
+            SortMode::Extension => SortMode::Name,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Size => "size",
+            SortMode::Modified => "modified",
+            SortMode::Extension => "extension",
+        }
+    }
+
+    /// Whether the natural order of this mode puts the biggest value first
+    fn descending(&self) -> bool {
+        matches!(self, SortMode::Size | SortMode::Modified)
+    }
+
+    fn compare(&self, a: &FileInfo, b: &FileInfo) -> Ordering {
+        match self {
+            SortMode::Name => a.name.cmp(&b.name),
+            SortMode::Size => b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)),
+            SortMode::Modified => b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)),
+            SortMode::Extension => {
+                let ext_a = a.path.extension().map(|e| e.to_ascii_lowercase());
+                let ext_b = b.path.extension().map(|e| e.to_ascii_lowercase());
+                ext_a.cmp(&ext_b).then_with(|| a.name.cmp(&b.name))
+            }
+        }
+    }
+}
+
+/// First free sibling name for a copy of `path`: `name (copy).ext`, then
+/// `name (copy 2).ext` and so on.
+pub fn duplicate_target(path: &Path, is_directory: bool) -> PathBuf {
+    free_sibling_name(path, is_directory, |counter| {
+        if counter == 1 { " (copy)".to_string() } else { format!(" (copy {})", counter) }
+    })
+}
+
+/// First free "name (1).ext", "name (2).ext", ... next to `path`, used when a
+/// paste keeps both the existing entry and the incoming one.
+pub fn numbered_target(path: &Path, is_directory: bool) -> PathBuf {
+    free_sibling_name(path, is_directory, |counter| format!(" ({})", counter))
+}
+
+fn free_sibling_name(path: &Path, is_directory: bool, suffix: impl Fn(u32) -> String) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    // Kept as OsStr so names that aren't valid UTF-8 are copied byte for byte
+    let (stem, extension) = if is_directory {
+        (path.file_name().unwrap_or_default(), None)
+    } else {
+        (path.file_stem().unwrap_or_default(), path.extension())
+    };
+
+    (1u32..)
+        .map(|counter| {
+            let mut name = stem.to_os_string();
+            name.push(suffix(counter));
+            if let Some(ext) = extension {
+                name.push(".");
+                name.push(ext);
+            }
+            parent.join(name)
+        })
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("ran out of free names")
+}
+
+/// Size and free space of the filesystem holding a path, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub total: u64,
+    /// Space available to this user, which excludes blocks reserved for root
+    pub available: u64,
+}
+
+/// Space on the filesystem containing `path`, or `None` when the platform
+/// or the filesystem (some FUSE mounts, for one) can't tell.
+#[cfg(unix)]
+pub fn disk_usage(path: &Path) -> Option<DiskUsage> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    let block_size = stats.f_frsize as u64;
+    let total = (stats.f_blocks as u64).checked_mul(block_size)?;
+    if total == 0 {
+        // Pseudo filesystems like /proc report no size at all
+        return None;
+    }
+    Some(DiskUsage { total, available: (stats.f_bavail as u64).saturating_mul(block_size) })
+}
+
+#[cfg(windows)]
+pub fn disk_usage(path: &Path) -> Option<DiskUsage> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let (mut available, mut total, mut total_free) = (0u64, 0u64, 0u64);
+    let ok = unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, &mut total, &mut total_free) };
+    (ok != 0 && total > 0).then_some(DiskUsage { total, available })
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn disk_usage(_path: &Path) -> Option<DiskUsage> {
+    None
+}
+
+/// Builds a command from a whitespace-separated template such as
+/// `"code --goto {file}"`, substituting `path` for `placeholder`. Without a
+/// placeholder the path is appended when `append_path` is set. Returns
+/// `None` for an empty template.
+pub fn template_command(template: &str, placeholder: &str, path: &Path, append_path: bool) -> Option<std::process::Command> {
+    let mut parts = template.split_whitespace();
+    let mut command = std::process::Command::new(parts.next()?);
+    let mut has_placeholder = false;
+    for part in parts {
+        if part == placeholder {
+            command.arg(path);
+            has_placeholder = true;
+        } else if part.contains(placeholder) {
+            command.arg(part.replace(placeholder, &path.to_string_lossy()));
+            has_placeholder = true;
+        } else {
+            command.arg(part);
+        }
+    }
+    if !has_placeholder && append_path {
+        command.arg(path);
+    }
+    Some(command)
+}
+
+/// Starts `command` without waiting for it, detached from the terminal.
+/// A thread waits for it in the background so it doesn't linger as a
+/// zombie once it exits.
+pub fn spawn_detached(command: &mut std::process::Command) -> std::io::Result<()> {
+    let mut child = command.stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// UTC calendar date and time of `time` as (year, month, day, hour, minute, second).
+pub(crate) fn utc_date_time(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let seconds_of_day = (secs % 86_400) as u32;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month as u32,
+        day as u32,
+        seconds_of_day / 3600,
+        (seconds_of_day / 60) % 60,
+        seconds_of_day % 60,
+    )
+}
+
+/// Local calendar date and time of `time`, in the form of `utc_date_time`.
+/// UTC where the local timezone isn't known.
+pub(crate) fn local_date_time(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    #[cfg(unix)]
+    {
+        let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if !unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+            return (
+                tm.tm_year as i64 + 1900,
+                tm.tm_mon as u32 + 1,
+                tm.tm_mday as u32,
+                tm.tm_hour as u32,
+                tm.tm_min as u32,
+                // A leap second
+                tm.tm_sec.min(59) as u32,
+            );
+        }
+    }
+    utc_date_time(time)
+}
+
+/// How long before `now` `time` was, e.g. "just now", "5m ago", "3h ago",
+/// "2d ago" or "6w ago".
+pub fn age_label(time: SystemTime, now: SystemTime) -> String {
+    let age = now.duration_since(time).unwrap_or_default();
+    if age.as_secs() < 60 {
+        return "just now".to_string();
+    }
+    format!("{} ago", span_label(age))
+}
+
+/// `span` in its largest whole unit, e.g. "45s", "5m", "3h", "2d" or "6w".
+pub fn span_label(span: Duration) -> String {
+    let secs = span.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3_600 => format!("{}m", secs / 60),
+        3_600..86_400 => format!("{}h", secs / 3_600),
+        86_400..604_800 => format!("{}d", secs / 86_400),
+        _ => format!("{}w", secs / 604_800),
+    }
+}
+
+/// Makes `path` absolute and resolves `.` and `..` lexically, without
+/// following symlinks, the way a shell's `cd` keeps the logical path.
+fn logical_path(path: &Path) -> Result<PathBuf, std::io::Error> {
+    let absolute = std::path::absolute(path)?;
+    let mut resolved = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other.as_os_str()),
+        }
+    }
+    Ok(resolved)
+}
+
+/// Expresses `target` relative to the directory `base`, walking up with `..`
+/// where the two paths diverge.
+pub fn relative_path(base: &Path, target: &Path) -> PathBuf {
+    let base_components: Vec<_> = base.components().collect();
+    let target_components: Vec<_> = target.components().collect();
+
+    let common = base_components.iter()
+        .zip(target_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &target_components[common..] {
+        relative.push(component.as_os_str());
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+// Directories kept in each direction of the navigation history
+const HISTORY_LIMIT: usize = 100;
+
+// Directories with more entries than this are listed from their names
+// first; `fill_metadata` completes the entries afterwards
+pub const LAZY_METADATA_THRESHOLD: usize = 2_000;
+
+/// A directory left through navigation, with the entry that was selected
+/// in it so going back can land on it again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    pub selected: Option<PathBuf>,
 }
 
 pub struct FileExplorer {
     current_path: PathBuf,
+    back_history: VecDeque<HistoryEntry>,
+    forward_history: Vec<HistoryEntry>,
+    /// Every listed entry of the current directory, sorted
+    entries: Vec<FileInfo>,
+    /// The entries matching `filter`, which is what `files` shows
     files: Vec<FileInfo>,
+    filter: String,
+    show_hidden: bool,
+    sort_mode: SortMode,
+    sort_reverse: bool,
+    open_commands: HashMap<String, String>,
+    resolve_symlinks: bool,
+    hide_ignored: bool,
+    /// The git ignore rules for `current_path`, kept so toggling is instant
+    ignore_rules: Option<IgnoreRules>,
+    /// Entries left out of `files` because git ignores them
+    ignored_count: usize,
+    /// Entries still waiting for `fill_metadata`
+    pending_metadata: usize,
+    /// Directories entered with `navigate_to`, for the jump popup
+    frecency: Frecency,
 }
 
 impl FileExplorer {
     pub fn new(path: PathBuf) -> Result<Self, std::io::Error> {
         let mut explorer = FileExplorer {
             current_path: path.canonicalize()?,
+            back_history: VecDeque::new(),
+            forward_history: Vec::new(),
+            entries: Vec::new(),
             files: Vec::new(),
+            filter: String::new(),
+            show_hidden: true,
+            sort_mode: SortMode::Name,
+            sort_reverse: false,
+            open_commands: HashMap::new(),
+            resolve_symlinks: true,
+            hide_ignored: false,
+            ignore_rules: None,
+            ignored_count: 0,
+            pending_metadata: 0,
+            frecency: Frecency::default(),
         };
         explorer.refresh()?;
         Ok(explorer)
@@ -51,53 +628,337 @@ impl FileExplorer {
         &self.files
     }
 
-    pub fn navigate_to(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
+    pub fn show_hidden(&self) -> bool {
+        self.show_hidden
+    }
+
+    pub fn set_show_hidden(&mut self, show_hidden: bool) -> Result<(), std::io::Error> {
+        self.show_hidden = show_hidden;
+        self.refresh().map(|_| ())
+    }
+
+    pub fn toggle_hidden(&mut self) -> Result<(), std::io::Error> {
+        self.set_show_hidden(!self.show_hidden)
+    }
+
+    pub fn hide_ignored(&self) -> bool {
+        self.hide_ignored
+    }
+
+    /// Hides entries the repository's `.gitignore` rules ignore. Outside a
+    /// repository nothing is hidden. The rules are read once per directory,
+    /// so switching back and forth doesn't touch the disk.
+    pub fn set_hide_ignored(&mut self, hide_ignored: bool) {
+        self.hide_ignored = hide_ignored;
+        if hide_ignored && self.ignore_rules.as_ref().is_none_or(|rules| rules.dir() != self.current_path) {
+            self.ignore_rules = IgnoreRules::load(&self.current_path);
+        }
+        self.apply_filter();
+    }
+
+    /// Whether the current directory is inside a git repository, as far as
+    /// the cached ignore rules know.
+    pub fn in_git_repository(&self) -> bool {
+        self.ignore_rules.as_ref().is_some_and(|rules| rules.dir() == self.current_path)
+    }
+
+    /// Number of entries hidden because git ignores them.
+    pub fn ignored_count(&self) -> usize {
+        self.ignored_count
+    }
+
+    pub fn sort_mode(&self) -> SortMode {
+        self.sort_mode
+    }
+
+    pub fn sort_reverse(&self) -> bool {
+        self.sort_reverse
+    }
+
+    pub fn set_sort(&mut self, mode: SortMode, reverse: bool) {
+        self.sort_mode = mode;
+        self.sort_reverse = reverse;
+        self.sort_files();
+    }
+
+    /// Title suffix describing the active ordering, e.g. "by size ↓"
+    pub fn sort_description(&self) -> String {
+        let descending = self.sort_mode.descending() != self.sort_reverse;
+        format!("by {} {}", self.sort_mode.label(), if descending { "↓" } else { "↑" })
+    }
+
+    /// Whether entering a directory resolves symlinks to the real path
+    /// (like `cd -P`) instead of keeping the path that was clicked through.
+    pub fn set_resolve_symlinks(&mut self, resolve_symlinks: bool) {
+        self.resolve_symlinks = resolve_symlinks;
+    }
+
+    /// Sets the per-extension commands `open_file` tries before the system default.
+    /// Extensions are matched case-insensitively and without the leading dot.
+    pub fn set_open_commands(&mut self, open_commands: HashMap<String, String>) {
+        self.open_commands = open_commands.into_iter()
+            .map(|(ext, command)| (ext.trim_start_matches('.').to_lowercase(), command))
+            .collect();
+    }
+
+    /// The configured command for this file's extension, ready to run.
+    pub fn custom_open_command(&self, file_info: &FileInfo) -> Option<std::process::Command> {
+        if file_info.is_directory {
+            return None;
+        }
+        let ext = file_info.path.extension()?.to_str()?.to_lowercase();
+        let template = self.open_commands.get(&ext)?;
+        template_command(template, "{file}", &file_info.path, true)
+    }
+
+    /// Enters `path`, recording `selected` as the entry to land on when the
+    /// history leads back to the directory being left, and counting the
+    /// visit in the frecency table.
+    pub fn navigate_to(&mut self, path: PathBuf, selected: Option<PathBuf>) -> Result<(), std::io::Error> {
         if path.is_dir() {
-            self.current_path = path.canonicalize()?;
-            self.refresh()?;
+            // The logical path makes going up from a symlinked directory
+            // return to where the link lives, not the target's parent
+            let target = if self.resolve_symlinks {
+                path.canonicalize()?
+            } else {
+                logical_path(&path)?
+            };
+            let moving = target != self.current_path;
+            self.change_directory(target, selected)?;
+            if moving {
+                self.frecency.visit(&self.current_path, SystemTime::now());
+            }
         }
         Ok(())
     }
 
-    pub fn go_up(&mut self) -> Result<(), std::io::Error> {
+    pub fn frecency(&self) -> &Frecency {
+        &self.frecency
+    }
+
+    pub fn frecency_mut(&mut self) -> &mut Frecency {
+        &mut self.frecency
+    }
+
+    pub fn go_up(&mut self, selected: Option<PathBuf>) -> Result<(), std::io::Error> {
         if let Some(parent) = self.current_path.parent() {
-            self.current_path = parent.to_path_buf();
+            self.change_directory(parent.to_path_buf(), selected)?;
+        }
+        Ok(())
+    }
+
+    fn change_directory(&mut self, target: PathBuf, selected: Option<PathBuf>) -> Result<(), std::io::Error> {
+        if target == self.current_path {
             self.refresh()?;
+            return Ok(());
         }
+        let previous = self.enter(target)?;
+        self.back_history.push_back(HistoryEntry { path: previous, selected });
+        if self.back_history.len() > HISTORY_LIMIT {
+            self.back_history.pop_front();
+        }
+        self.forward_history.clear();
         Ok(())
     }
 
-    pub fn refresh(&mut self) -> Result<(), std::io::Error> {
-        self.files.clear();
+    /// Returns to the most recent directory in the history that still
+    /// exists, remembering `selected` for the directory being left. Gives
+    /// the entry that was returned to, or `None` when there is nowhere to go.
+    pub fn back(&mut self, selected: Option<PathBuf>) -> Result<Option<HistoryEntry>, std::io::Error> {
+        while let Some(entry) = self.back_history.pop_back() {
+            if !entry.path.is_dir() {
+                continue;
+            }
+            match self.enter(entry.path.clone()) {
+                Ok(path) => {
+                    self.forward_history.push(HistoryEntry { path, selected });
+                    return Ok(Some(entry));
+                }
+                Err(e) => {
+                    self.back_history.push_back(entry);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The counterpart of `back`, walking towards the newest directory again.
+    pub fn forward(&mut self, selected: Option<PathBuf>) -> Result<Option<HistoryEntry>, std::io::Error> {
+        while let Some(entry) = self.forward_history.pop() {
+            if !entry.path.is_dir() {
+                continue;
+            }
+            match self.enter(entry.path.clone()) {
+                Ok(path) => {
+                    self.back_history.push_back(HistoryEntry { path, selected });
+                    return Ok(Some(entry));
+                }
+                Err(e) => {
+                    self.forward_history.push(entry);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Switches to `target` and returns the directory that was left. If the
+    /// new directory can't be listed, stays where it was.
+    fn enter(&mut self, target: PathBuf) -> Result<PathBuf, std::io::Error> {
+        // A filter only applies to the directory it was typed in
+        self.filter.clear();
+        let previous = std::mem::replace(&mut self.current_path, target);
+        if let Err(e) = self.refresh() {
+            self.current_path = previous;
+            let _ = self.refresh();
+            return Err(e);
+        }
+        Ok(previous)
+    }
+
+    /// How many directories `back` and `forward` can still step through.
+    pub fn history_depth(&self) -> (usize, usize) {
+        (self.back_history.len(), self.forward_history.len())
+    }
+
+    /// Re-reads the current directory and returns the new listing. Large
+    /// directories come back with `metadata_pending` entries, see
+    /// `pending_metadata`.
+    pub fn refresh(&mut self) -> Result<&[FileInfo], std::io::Error> {
+        self.entries.clear();
         
-        for entry in fs::read_dir(&self.current_path)? {
-            let entry = entry?;
-            if let Ok(file_info) = FileInfo::from_path(&entry.path()) {
-                self.files.push(file_info);
+        let dir_entries = fs::read_dir(&self.current_path)?.collect::<Result<Vec<_>, _>>()?;
+        let lazy = dir_entries.len() > LAZY_METADATA_THRESHOLD;
+        for entry in dir_entries {
+            let file_info = if lazy { FileInfo::from_dir_entry(&entry) } else { FileInfo::from_path(&entry.path()) };
+            if let Ok(file_info) = file_info {
+                if file_info.is_hidden && !self.show_hidden {
+                    continue;
+                }
+                self.entries.push(file_info);
             }
         }
+        self.pending_metadata = self.entries.iter().filter(|file| file.metadata_pending).count();
 
-        // Sort: directories first, then by name
-        self.files.sort_by(|a, b| {
-            match (a.is_directory, b.is_directory) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.name.cmp(&b.name),
+        // Re-read so edits to .gitignore show up with the next refresh
+        if self.hide_ignored {
+            self.ignore_rules = IgnoreRules::load(&self.current_path);
+        }
+        self.sort_files();
+
+        Ok(&self.files)
+    }
+
+    /// Paths of the listed entries whose metadata hasn't been read yet.
+    pub fn pending_metadata(&self) -> Vec<PathBuf> {
+        if self.pending_metadata == 0 {
+            return Vec::new();
+        }
+        self.entries.iter()
+            .filter(|file| file.metadata_pending)
+            .map(|file| file.path.clone())
+            .collect()
+    }
+
+    /// Completes pending entries with metadata read elsewhere, typically a
+    /// batch from a background task. `None` means the entry disappeared.
+    /// Entries that are no longer listed are skipped, so a batch for a
+    /// directory that was left changes nothing.
+    pub fn fill_metadata(&mut self, loaded: Vec<(PathBuf, Option<FileInfo>)>) {
+        let mut loaded: HashMap<PathBuf, Option<FileInfo>> = loaded.into_iter().collect();
+        let mut filled = 0;
+        self.entries.retain_mut(|file| {
+            if !file.metadata_pending {
+                return true;
+            }
+            match loaded.remove(&file.path) {
+                Some(Some(info)) => {
+                    *file = info;
+                    filled += 1;
+                    true
+                }
+                Some(None) => {
+                    filled += 1;
+                    false
+                }
+                None => true,
             }
         });
+        if filled > 0 {
+            self.pending_metadata -= filled;
+            self.sort_files();
+        }
+    }
 
-        Ok(())
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// Narrows `files` to entries whose name contains `filter`, ignoring
+    /// case, without re-reading the directory. Empty shows everything.
+    pub fn set_filter(&mut self, filter: &str) {
+        self.filter = filter.to_string();
+        self.apply_filter();
+    }
+
+    /// Number of entries in the directory listing before filtering,
+    /// not counting git-ignored ones while those are hidden.
+    pub fn unfiltered_len(&self) -> usize {
+        self.entries.len() - self.ignored_count
+    }
+
+    fn apply_filter(&mut self) {
+        let needle = self.filter.to_lowercase();
+        let rules = self.ignore_rules.as_ref()
+            .filter(|rules| self.hide_ignored && rules.dir() == self.current_path);
+        let mut ignored_count = 0;
+        self.files = self.entries.iter()
+            .filter(|file| {
+                let ignored = rules.is_some_and(|rules| rules.is_ignored(&file.path, file.is_directory));
+                ignored_count += ignored as usize;
+                !ignored && (needle.is_empty() || file.name.to_lowercase().contains(&needle))
+            })
+            .cloned()
+            .collect();
+        self.ignored_count = ignored_count;
+    }
+
+    fn sort_files(&mut self) {
+        let mode = self.sort_mode;
+        let reverse = self.sort_reverse;
+
+        // Directories always come first; the sort mode orders within each group
+        self.entries.sort_by(|a, b| {
+            match (a.is_directory, b.is_directory) {
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                _ => {
+                    let ordering = mode.compare(a, b);
+                    if reverse { ordering.reverse() } else { ordering }
+                }
+            }
+        });
+        self.apply_filter();
     }
 
     pub fn open_file(&self, file_info: &FileInfo) -> Result<(), std::io::Error> {
-        if file_info.is_directory {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "Cannot open directory as file. Use navigate_to instead.",
-            ));
+        if let Some(mut command) = self.custom_open_command(file_info) {
+            match command.status() {
+                Ok(status) if status.success() => return Ok(()),
+                Ok(status) => {
+                    return Err(std::io::Error::other(
+                        format!("{} exited with {}", command.get_program().to_string_lossy(), status),
+                    ));
+                }
+                // Command not installed: fall through to the system default
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
         }
 
-        // Use the system's default application to open the file
+        // Use the system's default application to open the file. For
+        // directories this is the GUI file manager (Finder, Explorer, ...).
         match open::that(&file_info.path) {
             Ok(_) => Ok(()),
             Err(e) => Err(std::io::Error::new(
@@ -107,6 +968,133 @@ impl FileExplorer {
         }
     }
 
+    pub fn delete(&self, file_info: &FileInfo) -> Result<(), std::io::Error> {
+        if file_info.is_directory {
+            fs::remove_dir_all(&file_info.path)
+        } else {
+            fs::remove_file(&file_info.path)
+        }
+    }
+
+    /// Moves a file or directory to the platform trash instead of removing it.
+    pub fn move_to_trash(&self, file_info: &FileInfo) -> Result<(), std::io::Error> {
+        trash::move_to_trash(&file_info.path)
+    }
+
+    /// Flips the executable bits of a file, like `chmod +x`/`chmod -x`.
+    /// Execute is granted to every class that can read. Returns whether the
+    /// file is now executable.
+    #[cfg(unix)]
+    pub fn toggle_executable(&self, file_info: &FileInfo) -> Result<bool, std::io::Error> {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = fs::metadata(&file_info.path)?.permissions();
+        let mode = permissions.mode();
+        let executable = mode & 0o111 == 0;
+        let new_mode = if executable {
+            mode | ((mode & 0o444) >> 2)
+        } else {
+            mode & !0o111
+        };
+        permissions.set_mode(new_mode);
+        fs::set_permissions(&file_info.path, permissions)?;
+        Ok(executable)
+    }
+
+    #[cfg(not(unix))]
+    pub fn toggle_executable(&self, _file_info: &FileInfo) -> Result<bool, std::io::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Executable bits are not supported on this platform",
+        ))
+    }
+
+    /// Renames a file or directory in place and returns its new path.
+    /// Refuses empty names, names containing path separators and names
+    /// that are already taken.
+    pub fn rename_entry(&self, file_info: &FileInfo, new_name: &str) -> Result<PathBuf, std::io::Error> {
+        let new_name = new_name.trim();
+        if new_name.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Name cannot be empty",
+            ));
+        }
+        if new_name == "." || new_name == ".." || new_name.contains('/') || new_name.contains('\\') {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Name cannot be '.', '..' or contain path separators",
+            ));
+        }
+
+        // An untouched display name leaves the real one alone, even when it
+        // had bytes that couldn't be shown
+        if new_name == file_info.name {
+            return Ok(file_info.path.clone());
+        }
+        let parent = file_info.path.parent().unwrap_or(&self.current_path);
+        let new_path = parent.join(new_name);
+        if new_path == file_info.path {
+            return Ok(new_path);
+        }
+        // On a case-insensitive filesystem `Foo` is found when renaming `foo`
+        if new_path.exists() && !transfer::is_same_entry(&file_info.path, &new_path) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("'{}' already exists", new_name),
+            ));
+        }
+
+        fs::rename(&file_info.path, &new_path)?;
+        Ok(new_path)
+    }
+
+    /// Creates an empty file relative to the current directory. Nested names
+    /// like `a/b/c.txt` create the intermediate directories as needed.
+    pub fn create_file(&self, name: &str) -> Result<PathBuf, std::io::Error> {
+        let path = self.new_entry_path(name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok(path)
+    }
+
+    /// Creates a directory (and any missing parents) relative to the current directory.
+    pub fn create_dir(&self, name: &str) -> Result<PathBuf, std::io::Error> {
+        let path = self.new_entry_path(name)?;
+        fs::create_dir_all(&path)?;
+        Ok(path)
+    }
+
+    fn new_entry_path(&self, name: &str) -> Result<PathBuf, std::io::Error> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Name cannot be empty",
+            ));
+        }
+
+        // Only plain relative names are allowed so nothing escapes the current directory
+        let relative = Path::new(name);
+        let is_plain = relative.components().all(|c| matches!(c, std::path::Component::Normal(_)));
+        if !is_plain {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid name '{}'", name),
+            ));
+        }
+
+        let path = self.current_path.join(relative);
+        if path.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("'{}' already exists", name),
+            ));
+        }
+        Ok(path)
+    }
+
     pub fn reveal_in_file_manager(&self, file_info: &FileInfo) -> Result<(), std::io::Error> {
         // On most systems, this will open the file manager and highlight the file
         let path_to_reveal = if file_info.is_directory {
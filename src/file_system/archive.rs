@@ -0,0 +1,783 @@
+//! Reading and extracting `.zip` and `.tar` (optionally gzipped) archives.
+
+use flate2::read::GzDecoder;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Archives with more entries than this are summarized from the first ones only
+const MAX_SCANNED_ENTRIES: usize = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// Recognizes archives by file name, e.g. `.zip`, `.tar`, `.tar.gz` or `.tgz`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Directory an archive extracts into by default: its name without the
+/// archive extension, next to the archive.
+pub fn extraction_dir(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let lower = name.to_lowercase();
+    let stem_len = [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find(|ext| lower.ends_with(*ext))
+        .map(|ext| name.len() - ext.len())
+        .unwrap_or(name.len());
+    let stem = if stem_len == 0 { name.as_str() } else { &name[..stem_len] };
+    path.with_file_name(stem)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    /// Uncompressed size; for directories the total of everything inside
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveSummary {
+    /// First entries at the root of the archive, directories first
+    pub top_level: Vec<ArchiveEntry>,
+    pub top_level_count: usize,
+    /// Number of entries (files and directories) at any depth
+    pub entry_count: usize,
+    pub total_size: u64,
+    /// True when the archive was too large to scan completely
+    pub truncated: bool,
+}
+
+/// Lists the root of an archive, keeping at most `limit` top-level entries.
+pub fn summarize(path: &Path, limit: usize) -> io::Result<ArchiveSummary> {
+    let kind = ArchiveKind::from_path(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a supported archive"))?;
+    let raw_entries = match kind {
+        ArchiveKind::Zip => zip_entries(path)?,
+        ArchiveKind::Tar => tar_entries(tar::Archive::new(File::open(path)?))?,
+        ArchiveKind::TarGz => tar_entries(tar::Archive::new(GzDecoder::new(File::open(path)?)))?,
+    };
+
+    let truncated = raw_entries.len() >= MAX_SCANNED_ENTRIES;
+    let mut roots: BTreeMap<String, ArchiveEntry> = BTreeMap::new();
+    let mut total_size = 0;
+    for (entry_path, size, is_dir) in &raw_entries {
+        let components: Vec<_> = Path::new(entry_path)
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let Some(first) = components.first() else {
+            continue;
+        };
+
+        total_size += size;
+        let root = roots.entry(first.clone()).or_insert_with(|| ArchiveEntry {
+            name: first.clone(),
+            size: 0,
+            is_dir: false,
+        });
+        root.size += size;
+        root.is_dir |= *is_dir || components.len() > 1;
+    }
+
+    let top_level_count = roots.len();
+    let mut top_level: Vec<ArchiveEntry> = roots.into_values().collect();
+    top_level.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    top_level.truncate(limit);
+
+    Ok(ArchiveSummary {
+        top_level,
+        top_level_count,
+        entry_count: raw_entries.len(),
+        total_size,
+        truncated,
+    })
+}
+
+/// (path inside the archive, uncompressed size, is directory)
+type RawEntry = (String, u64, bool);
+
+fn zip_entries(path: &Path) -> io::Result<Vec<RawEntry>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let mut entries = Vec::new();
+    for index in 0..archive.len().min(MAX_SCANNED_ENTRIES) {
+        // Only the central directory is read here, not the file data
+        let file = archive.by_index_raw(index).map_err(io::Error::other)?;
+        entries.push((file.name().to_string(), file.size(), file.is_dir()));
+    }
+    Ok(entries)
+}
+
+fn tar_entries<R: io::Read>(mut archive: tar::Archive<R>) -> io::Result<Vec<RawEntry>> {
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        if entries.len() >= MAX_SCANNED_ENTRIES {
+            break;
+        }
+        let entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let is_dir = entry.header().entry_type().is_dir();
+        entries.push((path, entry.header().size().unwrap_or(0), is_dir));
+    }
+    Ok(entries)
+}
+
+/// Unpacks the archive at `path` into `destination`, creating it if needed
+/// and replacing files that already exist there. An entry that would land
+/// outside `destination` (an absolute path or one climbing out with `..`)
+/// aborts the extraction. `progress` gets the entries done and, for zips,
+/// the entry count. Returns the number of entries extracted.
+pub fn extract(
+    path: &Path,
+    destination: &Path,
+    cancel: &AtomicBool,
+    progress: impl FnMut(usize, Option<usize>),
+) -> io::Result<usize> {
+    let kind = ArchiveKind::from_path(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a supported archive"))?;
+    fs::create_dir_all(destination)?;
+    match kind {
+        ArchiveKind::Zip => extract_zip(path, destination, cancel, progress),
+        ArchiveKind::Tar => extract_tar(tar::Archive::new(File::open(path)?), destination, cancel, progress),
+        ArchiveKind::TarGz => {
+            extract_tar(tar::Archive::new(GzDecoder::new(File::open(path)?)), destination, cancel, progress)
+        }
+    }
+}
+
+/// The entry path with `.` components dropped, or `None` if it is absolute
+/// or climbs out of the extraction directory.
+fn contained_path(entry_path: &Path) -> Option<PathBuf> {
+    let mut contained = PathBuf::new();
+    for component in entry_path.components() {
+        match component {
+            Component::Normal(name) => contained.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(contained)
+}
+
+fn escaping_entry(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("archive entry '{}' points outside the extraction directory", name),
+    )
+}
+
+fn check_cancelled(cancel: &AtomicBool) -> io::Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+    } else {
+        Ok(())
+    }
+}
+
+fn extract_zip(
+    path: &Path,
+    destination: &Path,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(usize, Option<usize>),
+) -> io::Result<usize> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let total = archive.len();
+    for index in 0..total {
+        check_cancelled(cancel)?;
+        let mut file = archive.by_index(index).map_err(io::Error::other)?;
+        let relative = contained_path(Path::new(file.name())).ok_or_else(|| escaping_entry(file.name()))?;
+        let target = destination.join(relative);
+
+        if file.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = File::create(&target)?;
+            io::copy(&mut file, &mut output)?;
+            #[cfg(unix)]
+            if let Some(mode) = file.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o777))?;
+            }
+        }
+        progress(index + 1, Some(total));
+    }
+    Ok(total)
+}
+
+fn extract_tar<R: io::Read>(
+    mut archive: tar::Archive<R>,
+    destination: &Path,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(usize, Option<usize>),
+) -> io::Result<usize> {
+    archive.set_overwrite(true);
+    let mut done = 0;
+    for entry in archive.entries()? {
+        check_cancelled(cancel)?;
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_path_buf();
+        if contained_path(&entry_path).is_none() {
+            return Err(escaping_entry(&entry_path.to_string_lossy()));
+        }
+        // unpack_in also refuses to write through symlinks leading outside
+        if !entry.unpack_in(destination)? {
+            return Err(escaping_entry(&entry_path.to_string_lossy()));
+        }
+        done += 1;
+        progress(done, None);
+    }
+    Ok(done)
+}
+
+/// Writes `sources` into a new zip at `output`, streaming file contents so
+/// memory stays bounded. Directories are added recursively with paths
+/// relative to their parent, and `output` itself is never included.
+/// `progress` gets the bytes written and the total. An existing `output`
+/// is never touched, and a name with nothing before `.zip` is refused; on a
+/// later failure or cancel the partial zip is removed. Returns the number
+/// of files added.
+pub fn create_zip(
+    output: &Path,
+    sources: &[PathBuf],
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(u64, u64),
+) -> io::Result<usize> {
+    let name = output.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    if name.strip_suffix(".zip").unwrap_or(&name).trim().is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The archive needs a name before '.zip'"));
+    }
+    let file = File::create_new(output)?;
+    let result = write_zip(file, output, sources, cancel, &mut progress);
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+fn write_zip(
+    file: File,
+    output: &Path,
+    sources: &[PathBuf],
+    cancel: &AtomicBool,
+    progress: &mut impl FnMut(u64, u64),
+) -> io::Result<usize> {
+    // (absolute path, name inside the zip, metadata) for everything to add
+    let mut entries = Vec::new();
+    for source in sources {
+        let base = source.parent().unwrap_or(Path::new(""));
+        for entry in walkdir::WalkDir::new(source).follow_links(false) {
+            let entry = entry.map_err(io::Error::other)?;
+            if entry.path() == output {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(base).unwrap_or(entry.path());
+            let name = relative.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            entries.push((entry.path().to_path_buf(), name, entry.metadata().map_err(io::Error::other)?));
+        }
+    }
+    let total: u64 = entries.iter().filter(|(_, _, metadata)| metadata.is_file()).map(|(_, _, metadata)| metadata.len()).sum();
+
+    let mut writer = zip::ZipWriter::new(file);
+    let mut written = 0;
+    let mut files = 0;
+    for (path, name, metadata) in entries {
+        check_cancelled(cancel)?;
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .large_file(metadata.len() >= u32::MAX as u64);
+        if let Some(time) = metadata.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(time);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(metadata.permissions().mode() & 0o777);
+        }
+
+        if metadata.is_dir() {
+            writer.add_directory(format!("{}/", name), options).map_err(io::Error::other)?;
+        } else if metadata.is_file() {
+            writer.start_file(name, options).map_err(io::Error::other)?;
+            let mut input = File::open(&path)?;
+            let mut buffer = vec![0u8; 256 * 1024];
+            loop {
+                check_cancelled(cancel)?;
+                let read = match io::Read::read(&mut input, &mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                io::Write::write_all(&mut writer, &buffer[..read])?;
+                written += read as u64;
+                progress(written, total);
+            }
+            files += 1;
+        }
+        // Symlinks and other special files are left out
+    }
+    writer.finish().map_err(io::Error::other)?;
+    Ok(files)
+}
+
+/// Zip timestamps only cover 1980-2107, so anything outside is left unset.
+fn zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let (year, month, day, hour, minute, second) = super::utc_date_time(time);
+    let year = u16::try_from(year).ok()?;
+    zip::DateTime::from_date_and_time(year, month as u8, day as u8, hour as u8, minute as u8, second as u8).ok()
+}
+
+/// Entries at least this large are written with Zip64 sizes from the start,
+/// leaving room for deflate output that comes out slightly bigger than its input.
+const ZIP64_THRESHOLD: u64 = 0xF000_0000;
+
+/// Writes a `.zip` archive front to back without seeking, so it can be sent
+/// while it's being made. Each file's CRC and compressed size follow its data
+/// in a data descriptor, and Zip64 records are used wherever sizes, offsets or
+/// the entry count don't fit the classic format.
+pub struct ZipStream<W: Write> {
+    out: Counted<W>,
+    entries: Vec<CentralEntry>,
+}
+
+/// A writer that knows how many bytes went through it, for entry offsets.
+struct Counted<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// What the central directory records about an entry already written.
+struct CentralEntry {
+    name: Vec<u8>,
+    method: u16,
+    flags: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+    external_attributes: u32,
+    zip64: bool,
+}
+
+impl<W: Write> ZipStream<W> {
+    pub fn new(out: W) -> Self {
+        Self { out: Counted { inner: out, written: 0 }, entries: Vec::new() }
+    }
+
+    /// Adds an empty directory entry; `name` gets its trailing slash here.
+    pub fn add_directory(&mut self, name: &str, metadata: &fs::Metadata) -> io::Result<()> {
+        let mut entry = self.entry(format!("{}/", name.trim_end_matches('/')), metadata, false);
+        entry.method = 0;
+        entry.flags &= !DATA_DESCRIPTOR_FLAG;
+        self.write_local_header(&entry)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Deflates up to `metadata.len()` bytes of `content` into a file entry;
+    /// anything the file grew by since it was stat-ed is left out.
+    pub fn add_file(&mut self, name: &str, metadata: &fs::Metadata, content: impl io::Read) -> io::Result<()> {
+        let mut entry = self.entry(name.to_string(), metadata, metadata.len() >= ZIP64_THRESHOLD);
+        self.write_local_header(&entry)?;
+
+        let start = self.out.written;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut encoder = flate2::write::DeflateEncoder::new(&mut self.out, flate2::Compression::default());
+        let mut content = content.take(metadata.len());
+        let mut buffer = vec![0u8; 256 * 1024];
+        loop {
+            let read = match content.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            hasher.update(&buffer[..read]);
+            encoder.write_all(&buffer[..read])?;
+            entry.size += read as u64;
+        }
+        encoder.finish()?;
+        entry.crc = hasher.finalize();
+        entry.compressed = self.out.written - start;
+
+        let mut descriptor = Vec::with_capacity(24);
+        put_u32(&mut descriptor, 0x0807_4b50);
+        put_u32(&mut descriptor, entry.crc);
+        if entry.zip64 {
+            put_u64(&mut descriptor, entry.compressed);
+            put_u64(&mut descriptor, entry.size);
+        } else {
+            put_u32(&mut descriptor, entry.compressed as u32);
+            put_u32(&mut descriptor, entry.size as u32);
+        }
+        self.out.write_all(&descriptor)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and hands back the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let directory_start = self.out.written;
+        for entry in &self.entries {
+            let size_overflows = entry.zip64 || entry.size >= u32::MAX as u64;
+            let compressed_overflows = entry.zip64 || entry.compressed >= u32::MAX as u64;
+            let offset_overflows = entry.offset >= u32::MAX as u64;
+            let mut extra = Vec::new();
+            if size_overflows {
+                put_u64(&mut extra, entry.size);
+            }
+            if compressed_overflows {
+                put_u64(&mut extra, entry.compressed);
+            }
+            if offset_overflows {
+                put_u64(&mut extra, entry.offset);
+            }
+            if !extra.is_empty() {
+                let mut field = Vec::with_capacity(extra.len() + 4);
+                put_u16(&mut field, 0x0001);
+                put_u16(&mut field, extra.len() as u16);
+                field.extend_from_slice(&extra);
+                extra = field;
+            }
+
+            let mut header = Vec::with_capacity(46 + entry.name.len() + extra.len());
+            put_u32(&mut header, 0x0201_4b50);
+            put_u16(&mut header, VERSION_MADE_BY);
+            put_u16(&mut header, if extra.is_empty() { 20 } else { 45 });
+            put_u16(&mut header, entry.flags);
+            put_u16(&mut header, entry.method);
+            put_u16(&mut header, entry.time);
+            put_u16(&mut header, entry.date);
+            put_u32(&mut header, entry.crc);
+            put_u32(&mut header, if compressed_overflows { u32::MAX } else { entry.compressed as u32 });
+            put_u32(&mut header, if size_overflows { u32::MAX } else { entry.size as u32 });
+            put_u16(&mut header, entry.name.len() as u16);
+            put_u16(&mut header, extra.len() as u16);
+            put_u16(&mut header, 0); // comment length
+            put_u16(&mut header, 0); // disk number
+            put_u16(&mut header, 0); // internal attributes
+            put_u32(&mut header, entry.external_attributes);
+            put_u32(&mut header, if offset_overflows { u32::MAX } else { entry.offset as u32 });
+            header.extend_from_slice(&entry.name);
+            header.extend_from_slice(&extra);
+            self.out.write_all(&header)?;
+        }
+        let directory_size = self.out.written - directory_start;
+        let count = self.entries.len() as u64;
+
+        let mut end = Vec::with_capacity(98);
+        if count >= u16::MAX as u64 || directory_start >= u32::MAX as u64 || directory_size >= u32::MAX as u64 {
+            let record_start = self.out.written;
+            put_u32(&mut end, 0x0606_4b50);
+            put_u64(&mut end, 44); // size of the rest of the record
+            put_u16(&mut end, VERSION_MADE_BY);
+            put_u16(&mut end, 45);
+            put_u32(&mut end, 0); // this disk
+            put_u32(&mut end, 0); // disk with the central directory
+            put_u64(&mut end, count);
+            put_u64(&mut end, count);
+            put_u64(&mut end, directory_size);
+            put_u64(&mut end, directory_start);
+            // Zip64 end of central directory locator
+            put_u32(&mut end, 0x0706_4b50);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, record_start);
+            put_u32(&mut end, 1); // total disks
+        }
+        put_u32(&mut end, 0x0605_4b50);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, count.min(u16::MAX as u64) as u16);
+        put_u16(&mut end, count.min(u16::MAX as u64) as u16);
+        put_u32(&mut end, directory_size.min(u32::MAX as u64) as u32);
+        put_u32(&mut end, directory_start.min(u32::MAX as u64) as u32);
+        put_u16(&mut end, 0); // comment length
+        self.out.write_all(&end)?;
+        self.out.flush()?;
+        Ok(self.out.inner)
+    }
+
+    fn entry(&self, name: String, metadata: &fs::Metadata, zip64: bool) -> CentralEntry {
+        let (time, date) = metadata.modified().ok()
+            .and_then(zip_time)
+            .map(|time| (time.timepart(), time.datepart()))
+            // 1980-01-01, the earliest a zip can say
+            .unwrap_or((0, 0x21));
+        CentralEntry {
+            name: name.into_bytes(),
+            method: 8,
+            flags: DATA_DESCRIPTOR_FLAG | UTF8_NAME_FLAG,
+            time,
+            date,
+            crc: 0,
+            compressed: 0,
+            size: 0,
+            offset: self.out.written,
+            external_attributes: external_attributes(metadata),
+            zip64,
+        }
+    }
+
+    fn write_local_header(&mut self, entry: &CentralEntry) -> io::Result<()> {
+        let mut extra = Vec::new();
+        if entry.zip64 {
+            // Sizes follow in the data descriptor
+            put_u16(&mut extra, 0x0001);
+            put_u16(&mut extra, 16);
+            put_u64(&mut extra, 0);
+            put_u64(&mut extra, 0);
+        }
+        let mut header = Vec::with_capacity(30 + entry.name.len() + extra.len());
+        put_u32(&mut header, 0x0403_4b50);
+        put_u16(&mut header, if entry.zip64 { 45 } else { 20 });
+        put_u16(&mut header, entry.flags);
+        put_u16(&mut header, entry.method);
+        put_u16(&mut header, entry.time);
+        put_u16(&mut header, entry.date);
+        put_u32(&mut header, 0); // CRC
+        put_u32(&mut header, if entry.zip64 { u32::MAX } else { 0 });
+        put_u32(&mut header, if entry.zip64 { u32::MAX } else { 0 });
+        put_u16(&mut header, entry.name.len() as u16);
+        put_u16(&mut header, extra.len() as u16);
+        header.extend_from_slice(&entry.name);
+        header.extend_from_slice(&extra);
+        self.out.write_all(&header)
+    }
+}
+
+/// Sizes and CRC come after the data instead of in the local header
+const DATA_DESCRIPTOR_FLAG: u16 = 1 << 3;
+/// Names are UTF-8 rather than code page 437
+const UTF8_NAME_FLAG: u16 = 1 << 11;
+/// Zip 4.5 on Unix, so readers pick up permissions from the high bits of
+/// the external attributes; plain zip 4.5 elsewhere
+const VERSION_MADE_BY: u16 = if cfg!(unix) { (3 << 8) | 45 } else { 45 };
+
+#[cfg(unix)]
+fn external_attributes(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    let kind = if metadata.is_dir() { 0o040000 } else { 0o100000 };
+    let dos = if metadata.is_dir() { 0x10 } else { 0 };
+    ((kind | (metadata.permissions().mode() & 0o777)) << 16) | dos
+}
+
+#[cfg(not(unix))]
+fn external_attributes(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() { 0x10 } else { 0 }
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Streams `root` and everything under it into `out` as a zip whose entries
+/// sit in a folder named after `root`, returning how many files went in.
+/// Symlinks are left out, so nothing outside `root` ends up in the archive.
+pub fn write_zip_stream(root: &Path, out: impl Write) -> io::Result<usize> {
+    let base = root.parent().unwrap_or(Path::new(""));
+    let mut zip = ZipStream::new(out);
+    let mut files = 0;
+    for entry in walkdir::WalkDir::new(root).follow_links(false).sort_by_file_name() {
+        // Whatever vanished or can't be read is skipped rather than cutting
+        // off the whole download
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let relative = entry.path().strip_prefix(base).unwrap_or(entry.path());
+        let name = relative.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if metadata.is_dir() {
+            zip.add_directory(&name, &metadata)?;
+        } else if metadata.is_file() {
+            let Ok(file) = File::open(entry.path()) else {
+                continue;
+            };
+            zip.add_file(&name, &metadata, file)?;
+            files += 1;
+        }
+    }
+    zip.finish()?;
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_summarize_tar_groups_top_level_entries() {
+        let dir = TempDir::new("archive");
+        let archive_path = dir.join("sample.tar");
+
+        let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+        for (name, contents) in [("docs/a.txt", "hello"), ("docs/b.txt", "world!"), ("readme.md", "hi")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+
+        let summary = summarize(&archive_path, 10).unwrap();
+
+        assert_eq!(summary.entry_count, 3);
+        assert_eq!(summary.total_size, 13);
+        assert_eq!(summary.top_level_count, 2);
+        assert_eq!(summary.top_level[0], ArchiveEntry { name: "docs".to_string(), size: 11, is_dir: true });
+        assert_eq!(summary.top_level[1], ArchiveEntry { name: "readme.md".to_string(), size: 2, is_dir: false });
+        assert!(!summary.truncated);
+    }
+
+    fn write_tar(path: &Path, entries: &[(&str, &str)]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            // set_path refuses "..", so write the raw name like a hostile archive would
+            let raw = &mut header.as_old_mut().name;
+            raw[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_tar_and_refuse_traversal() {
+        let dir = TempDir::new("extract");
+        let cancel = AtomicBool::new(false);
+
+        let plain = dir.join("bundle.tar");
+        write_tar(&plain, &[("docs/a.txt", "hello"), ("./b.txt", "world")]);
+        let raw = std::fs::read(&plain).unwrap();
+        let archive_path = dir.join("bundle.tar.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&archive_path).unwrap(), flate2::Compression::fast());
+        io::Write::write_all(&mut encoder, &raw).unwrap();
+        encoder.finish().unwrap();
+
+        let destination = extraction_dir(&archive_path);
+        assert_eq!(destination, dir.join("bundle"));
+        let count = extract(&archive_path, &destination, &cancel, |_, _| {}).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(std::fs::read_to_string(destination.join("docs/a.txt")).unwrap(), "hello");
+        assert_eq!(std::fs::read_to_string(destination.join("b.txt")).unwrap(), "world");
+
+        let evil = dir.join("evil.tar");
+        write_tar(&evil, &[("../escaped.txt", "gotcha")]);
+        let err = extract(&evil, &dir.join("evil"), &cancel, |_, _| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.join("escaped.txt").exists());
+    }
+
+    #[test]
+    fn test_create_zip_round_trips_and_skips_itself() {
+        let dir = TempDir::new("compress");
+        std::fs::create_dir_all(dir.join("project/src")).unwrap();
+        std::fs::write(dir.join("project/src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.join("notes.txt"), "remember").unwrap();
+        let cancel = AtomicBool::new(false);
+
+        // The output lives inside a source directory and must not be added to itself
+        let output = dir.join("project/selection.zip");
+        let sources = [dir.join("project"), dir.join("notes.txt")];
+        let files = create_zip(&output, &sources, &cancel, |_, _| {}).unwrap();
+        assert_eq!(files, 2);
+
+        let destination = dir.join("unpacked");
+        extract(&output, &destination, &cancel, |_, _| {}).unwrap();
+        assert_eq!(std::fs::read_to_string(destination.join("project/src/main.rs")).unwrap(), "fn main() {}");
+        assert_eq!(std::fs::read_to_string(destination.join("notes.txt")).unwrap(), "remember");
+        assert!(!destination.join("project/selection.zip").exists());
+
+        // A zip that is already there is refused and left alone
+        std::fs::write(dir.join("taken.zip"), "mine").unwrap();
+        let err = create_zip(&dir.join("taken.zip"), &sources, &cancel, |_, _| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(dir.join("taken.zip")).unwrap(), "mine");
+
+        // So is a bare extension
+        let err = create_zip(&dir.join(".zip"), &sources, &cancel, |_, _| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!dir.join(".zip").exists());
+    }
+
+    #[test]
+    fn test_zip_stream_reads_back() {
+        let dir = TempDir::new("zip-stream");
+        std::fs::create_dir_all(dir.join("photos/empty")).unwrap();
+        std::fs::write(dir.join("photos/caf\u{e9}.txt"), "x".repeat(100_000)).unwrap();
+        std::fs::write(dir.join("photos/blank.txt"), "").unwrap();
+
+        // Written to something that can't seek, as a response body can't
+        let mut streamed = Vec::new();
+        let files = write_zip_stream(&dir.join("photos"), &mut streamed).unwrap();
+        assert_eq!(files, 2);
+
+        let mut zip = zip::ZipArchive::new(io::Cursor::new(streamed)).unwrap();
+        let mut names: Vec<_> = zip.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, ["photos/", "photos/blank.txt", "photos/caf\u{e9}.txt", "photos/empty/"]);
+        let mut content = String::new();
+        zip.by_name("photos/caf\u{e9}.txt").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content.len(), 100_000);
+        assert!(zip.by_name("photos/empty/").unwrap().is_dir());
+    }
+}
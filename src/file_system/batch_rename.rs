@@ -0,0 +1,347 @@
+//! Renaming many entries at once from a pattern. A pattern is either a
+//! template such as `vacation_{n:03}.jpg` or a find/replace such as
+//! `IMG_->photo_`, applied to each entry's name in order.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameStep {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl RenameStep {
+    pub fn old_name(&self) -> String {
+        file_name(&self.from)
+    }
+
+    pub fn new_name(&self) -> String {
+        file_name(&self.to)
+    }
+
+    /// Whether the new name finds the entry itself, as when only the case
+    /// changes on a case-insensitive filesystem.
+    fn renames_in_place(&self) -> bool {
+        super::transfer::is_same_entry(&self.from, &self.to)
+    }
+}
+
+/// The renames a pattern produces, in the order the sources were given.
+/// Sources whose name doesn't change are left out. The plan can only be
+/// applied while `conflicts` is empty.
+#[derive(Debug, Clone, Default)]
+pub struct RenamePlan {
+    pub renames: Vec<RenameStep>,
+    pub conflicts: Vec<String>,
+    // Indices into `renames` so no step lands on a name still in use
+    order: Vec<usize>,
+}
+
+/// A batch that stopped partway: `renamed` were applied, `failed` was not.
+/// `failed` is empty when the plan was refused before anything ran.
+#[derive(Debug)]
+pub struct BatchRenameError {
+    pub renamed: Vec<RenameStep>,
+    pub failed: Option<RenameStep>,
+    pub error: io::Error,
+}
+
+impl fmt::Display for BatchRenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failed {
+            Some(step) => write!(f, "'{}' -> '{}': {}", step.old_name(), step.new_name(), self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+enum Pattern<'a> {
+    Template(Vec<Part<'a>>),
+    Replace { find: &'a str, replace: &'a str },
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Counter { width: usize },
+    Name,
+    Extension,
+}
+
+/// Works out the new name of every source. Fails only when the pattern
+/// itself can't be understood; problems with the resulting names are
+/// reported in `RenamePlan::conflicts`.
+pub fn plan(sources: &[PathBuf], pattern: &str) -> Result<RenamePlan, String> {
+    let pattern = parse(pattern)?;
+
+    let mut renames = Vec::new();
+    let mut conflicts = Vec::new();
+    for (index, source) in sources.iter().enumerate() {
+        let name = file_name(source);
+        let new_name = match &pattern {
+            Pattern::Template(parts) => render(parts, &name, index + 1),
+            Pattern::Replace { find, replace } => name.replace(find, replace),
+        };
+        if new_name == name {
+            continue;
+        }
+        // Patterns work on text, and the replacement marker shown for bytes
+        // that aren't UTF-8 would end up in the new name
+        if source.file_name().and_then(|name| name.to_str()).is_none() {
+            conflicts.push(format!("'{}' can't be renamed by pattern: its name isn't valid UTF-8", name));
+            continue;
+        }
+        if let Err(reason) = check_name(&new_name) {
+            conflicts.push(format!("'{}' -> '{}': {}", name, new_name, reason));
+            continue;
+        }
+        let parent = source.parent().unwrap_or(Path::new(""));
+        renames.push(RenameStep { from: source.clone(), to: parent.join(new_name) });
+    }
+
+    // Names held by an entry that is itself renamed away are free to take
+    let moving: HashSet<&PathBuf> = renames.iter().map(|step| &step.from).collect();
+    let mut targets: HashMap<&PathBuf, &RenameStep> = HashMap::new();
+    for step in &renames {
+        if let Some(other) = targets.insert(&step.to, step) {
+            conflicts.push(format!(
+                "'{}' and '{}' would both become '{}'",
+                other.old_name(), step.old_name(), step.new_name()
+            ));
+        } else if step.to.symlink_metadata().is_ok() && !moving.contains(&step.to) && !step.renames_in_place() {
+            conflicts.push(format!("'{}' already exists", step.new_name()));
+        }
+    }
+
+    // A step has to wait while its target is still the name of a source
+    // that hasn't moved yet; what can never go is a cycle like a swap
+    let mut order = Vec::new();
+    let mut pending: Vec<usize> = (0..renames.len()).collect();
+    while !pending.is_empty() {
+        let waiting: HashSet<&PathBuf> = pending.iter().map(|&i| &renames[i].from).collect();
+        let (ready, blocked): (Vec<usize>, Vec<usize>) =
+            pending.iter().partition(|&&i| !waiting.contains(&renames[i].to));
+        if ready.is_empty() {
+            let names: Vec<String> = blocked.iter().map(|&i| format!("'{}'", renames[i].old_name())).collect();
+            conflicts.push(format!("{} would swap names", names.join(", ")));
+            break;
+        }
+        order.extend(ready);
+        pending = blocked;
+    }
+
+    Ok(RenamePlan { renames, conflicts, order })
+}
+
+/// Performs the renames one at a time, stopping at the first failure.
+/// Nothing is renamed while the plan has conflicts.
+pub fn apply(plan: &RenamePlan) -> Result<Vec<RenameStep>, BatchRenameError> {
+    if let Some(conflict) = plan.conflicts.first() {
+        return Err(BatchRenameError {
+            renamed: Vec::new(),
+            failed: None,
+            error: io::Error::new(io::ErrorKind::InvalidInput, conflict.clone()),
+        });
+    }
+
+    let mut renamed = Vec::new();
+    for &index in &plan.order {
+        let step = &plan.renames[index];
+        let result = if step.to.symlink_metadata().is_ok() && !step.renames_in_place() {
+            // Appeared since the plan was made
+            Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("'{}' already exists", step.new_name())))
+        } else {
+            fs::rename(&step.from, &step.to)
+        };
+        if let Err(error) = result {
+            return Err(BatchRenameError { renamed, failed: Some(step.clone()), error });
+        }
+        renamed.push(step.clone());
+    }
+    Ok(renamed)
+}
+
+fn parse(pattern: &str) -> Result<Pattern<'_>, String> {
+    if pattern.trim().is_empty() {
+        return Err("Type a pattern like photo_{n:03}{ext} or IMG_->photo_".to_string());
+    }
+    if let Some((find, replace)) = pattern.split_once("->").or_else(|| pattern.split_once('→')) {
+        if find.is_empty() {
+            return Err("Nothing to find before '->'".to_string());
+        }
+        return Ok(Pattern::Replace { find, replace });
+    }
+
+    let mut parts = Vec::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+        let end = rest[start..].find('}').ok_or("Unclosed '{' in pattern")? + start;
+        parts.push(match &rest[start + 1..end] {
+            "n" => Part::Counter { width: 0 },
+            "name" => Part::Name,
+            "ext" => Part::Extension,
+            spec => match spec.strip_prefix("n:").and_then(|width| width.parse().ok()) {
+                Some(width) => Part::Counter { width },
+                None => return Err(format!("Unknown placeholder {{{}}} - use {{n}}, {{n:03}}, {{name}} or {{ext}}", spec)),
+            },
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(Pattern::Template(parts))
+}
+
+fn render(parts: &[Part], name: &str, counter: usize) -> String {
+    // `{name}` is the name without its extension and `{ext}` includes the
+    // dot, so `{name}{ext}` gives back the original name
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    parts.iter()
+        .map(|part| match part {
+            Part::Text(text) => text.to_string(),
+            Part::Counter { width } => format!("{:0width$}", counter, width = *width),
+            Part::Name => stem.to_string(),
+            Part::Extension => extension.to_string(),
+        })
+        .collect()
+}
+
+fn check_name(name: &str) -> Result<(), &'static str> {
+    if name.trim().is_empty() {
+        Err("name would be empty")
+    } else if name == "." || name == ".." || name.contains('/') || name.contains('\\') {
+        Err("name cannot be '.', '..' or contain path separators")
+    } else {
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn names(plan: &RenamePlan) -> Vec<(String, String)> {
+        plan.renames.iter().map(|step| (step.old_name(), step.new_name())).collect()
+    }
+
+    #[test]
+    fn test_template_and_replace_patterns() {
+        let sources: Vec<PathBuf> = ["IMG_1.jpg", "IMG_2.jpg", "notes"].iter().map(|name| PathBuf::from("/p").join(name)).collect();
+
+        let plan = plan(&sources, "vacation_{n:03}{ext}").unwrap();
+        assert_eq!(names(&plan), [
+            ("IMG_1.jpg".to_string(), "vacation_001.jpg".to_string()),
+            ("IMG_2.jpg".to_string(), "vacation_002.jpg".to_string()),
+            ("notes".to_string(), "vacation_003".to_string()),
+        ]);
+        assert!(plan.conflicts.is_empty());
+
+        let plan = super::plan(&sources, "IMG_->photo_").unwrap();
+        assert_eq!(names(&plan).len(), 2);
+        assert_eq!(plan.renames[1].to, PathBuf::from("/p/photo_2.jpg"));
+
+        assert_eq!(names(&super::plan(&sources, "{name}-{n}{ext}").unwrap())[0].1, "IMG_1-1.jpg");
+        assert!(super::plan(&sources, "{count}").is_err());
+        assert!(super::plan(&sources, "a{n").is_err());
+    }
+
+    #[test]
+    fn test_conflicts_block_apply() {
+        let dir = TempDir::new("conflicts");
+        for name in ["a.txt", "b.txt", "ab.txt", "taken.txt"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        let sources = vec![dir.join("a.txt"), dir.join("b.txt")];
+        let plan = plan(&sources, "same.txt").unwrap();
+        assert_eq!(plan.conflicts, ["'a.txt' and 'b.txt' would both become 'same.txt'"]);
+        assert!(apply(&plan).unwrap_err().renamed.is_empty());
+        assert!(dir.join("a.txt").exists() && dir.join("b.txt").exists());
+
+        let plan = super::plan(&sources[..1], "taken.txt").unwrap();
+        assert_eq!(plan.conflicts, ["'taken.txt' already exists"]);
+
+        // ab.txt -> b.txt lands on a batch member that keeps its name
+        let plan = super::plan(&[dir.join("ab.txt"), dir.join("b.txt")], "a->").unwrap();
+        assert_eq!(plan.conflicts, ["'b.txt' already exists"]);
+
+        let plan = super::plan(&[dir.join("a.txt")], "{name}/x").unwrap();
+        assert_eq!(plan.conflicts, ["'a.txt' -> 'a/x': name cannot be '.', '..' or contain path separators"]);
+        assert!(apply(&plan).unwrap_err().failed.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_are_refused() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = TempDir::new("non-utf8");
+        let odd = dir.join(std::ffi::OsStr::from_bytes(b"IMG_\xe9.jpg"));
+        // Some filesystems only take UTF-8 names
+        if fs::write(&odd, "").is_err() {
+            return;
+        }
+
+        let plan = plan(std::slice::from_ref(&odd), "IMG_->photo_").unwrap();
+        assert!(plan.renames.is_empty());
+        assert_eq!(plan.conflicts, ["'IMG_\u{FFFD}.jpg' can't be renamed by pattern: its name isn't valid UTF-8"]);
+        assert!(apply(&plan).is_err());
+        assert!(odd.exists());
+        // A pattern that leaves the name alone isn't a problem
+        assert!(super::plan(&[odd], "x->y").unwrap().conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_apply_orders_chains_and_detects_swaps() {
+        let dir = TempDir::new("chain");
+        for name in ["a", "ab"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        // a -> ab only works once ab -> abb is out of the way
+        let plan = plan(&[dir.join("a"), dir.join("ab")], "a->ab").unwrap();
+        assert!(plan.conflicts.is_empty());
+        let renamed = apply(&plan).unwrap();
+        assert_eq!(renamed.iter().map(RenameStep::new_name).collect::<Vec<_>>(), ["abb", "ab"]);
+        assert_eq!(fs::read_to_string(dir.join("ab")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.join("abb")).unwrap(), "ab");
+
+        let swap = super::plan(&[dir.join("abb"), dir.join("ab")], "{name}").unwrap();
+        assert!(swap.renames.is_empty());
+        fs::rename(dir.join("abb"), dir.join("1")).unwrap();
+        fs::rename(dir.join("ab"), dir.join("2")).unwrap();
+        let swap = super::plan(&[dir.join("2"), dir.join("1")], "{n}").unwrap();
+        assert_eq!(swap.conflicts, ["'2', '1' would swap names"]);
+    }
+
+    #[test]
+    fn test_apply_reports_partial_failure() {
+        let dir = TempDir::new("partial");
+        for name in ["1", "2"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        let plan = plan(&[dir.join("1"), dir.join("2")], "file_{n}").unwrap();
+        fs::write(dir.join("file_2"), "late").unwrap();
+        let err = apply(&plan).unwrap_err();
+        assert_eq!(err.renamed.len(), 1);
+        assert_eq!(err.renamed[0].new_name(), "file_1");
+        assert_eq!(err.failed.unwrap().old_name(), "2");
+        assert_eq!(err.error.kind(), io::ErrorKind::AlreadyExists);
+        assert!(dir.join("file_1").exists() && dir.join("2").exists());
+    }
+}
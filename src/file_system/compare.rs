@@ -0,0 +1,150 @@
+//! Comparing two files: a unified diff when both are text, otherwise their
+//! sizes and SHA-256 checksums. Also the fallback when a diff takes too long.
+
+use super::checksum::{self, ChecksumAlgorithm};
+use super::file_type::looks_like_text;
+use similar::TextDiff;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// Text files larger than this are compared like binary ones
+const TEXT_DIFF_LIMIT: u64 = 8 * 1024 * 1024;
+
+// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+// Time a text diff may take before the files are compared by checksum
+const DIFF_DEADLINE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// A unified diff for text files, a size and checksum summary otherwise
+    pub report: String,
+    pub identical: bool,
+}
+
+/// Compares `left` with `right`. `progress` gets the bytes hashed and the
+/// total when checksums are needed, and `cancel` stops the comparison with
+/// an `Interrupted` error.
+pub fn compare_files(left: &Path, right: &Path, cancel: &AtomicBool, progress: impl FnMut(u64, u64)) -> io::Result<Comparison> {
+    compare_within(left, right, DIFF_DEADLINE, cancel, progress)
+}
+
+fn compare_within(
+    left: &Path,
+    right: &Path,
+    deadline: Duration,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(u64, u64),
+) -> io::Result<Comparison> {
+    let check_cancelled = || {
+        if cancel.load(Ordering::Relaxed) {
+            Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+        } else {
+            Ok(())
+        }
+    };
+    let sizes = (fs::metadata(left)?.len(), fs::metadata(right)?.len());
+    if sizes.0 <= TEXT_DIFF_LIMIT && sizes.1 <= TEXT_DIFF_LIMIT {
+        let left_bytes = fs::read(left)?;
+        check_cancelled()?;
+        let right_bytes = fs::read(right)?;
+        check_cancelled()?;
+        let is_text = |bytes: &[u8]| bytes.is_empty() || looks_like_text(bytes);
+        if is_text(&left_bytes) && is_text(&right_bytes) {
+            if let Some(comparison) = text_comparison(left, right, &left_bytes, &right_bytes, deadline) {
+                return Ok(comparison);
+            }
+        }
+    }
+    check_cancelled()?;
+
+    let total = sizes.0 + sizes.1;
+    let left_checksum = checksum::checksum(left, ChecksumAlgorithm::Sha256, cancel, |done, _| progress(done, total))?;
+    let right_checksum = checksum::checksum(right, ChecksumAlgorithm::Sha256, cancel, |done, _| progress(sizes.0 + done, total))?;
+    let checksums = (left_checksum, right_checksum);
+    let identical = sizes.0 == sizes.1 && checksums.0 == checksums.1;
+    let verdict = if identical { "Files are identical" } else { "Files differ" };
+    let report = format!(
+        "{}\n\n{}\n  {} bytes\n  SHA-256 {}\n\n{}\n  {} bytes\n  SHA-256 {}",
+        verdict, left.display(), sizes.0, checksums.0, right.display(), sizes.1, checksums.1,
+    );
+    Ok(Comparison { report, identical })
+}
+
+/// A unified diff of two texts, or `None` when it isn't done by `deadline`;
+/// past it the diff would no longer be minimal.
+fn text_comparison(left: &Path, right: &Path, left_bytes: &[u8], right_bytes: &[u8], deadline: Duration) -> Option<Comparison> {
+    if left_bytes == right_bytes {
+        return Some(Comparison { report: "Files are identical".to_string(), identical: true });
+    }
+    let (old, new) = (String::from_utf8_lossy(left_bytes), String::from_utf8_lossy(right_bytes));
+    let (old_name, new_name) = (left.display().to_string(), right.display().to_string());
+    let deadline = Instant::now() + deadline;
+    let diff = TextDiff::configure().deadline(deadline).diff_lines(old.as_ref(), new.as_ref());
+    if Instant::now() >= deadline {
+        return None;
+    }
+    let report = diff.unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(&old_name, &new_name)
+        .to_string();
+    Some(Comparison { report, identical: false })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_text_and_binary_comparisons() {
+        let dir = TempDir::new("compare");
+        fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        fs::write(dir.join("b.txt"), "one\n2\nthree\n").unwrap();
+        fs::write(dir.join("a.bin"), [0u8, 1, 2, 3]).unwrap();
+        fs::write(dir.join("b.bin"), [0u8, 1, 2, 4]).unwrap();
+        let cancel = AtomicBool::new(false);
+        let compare = |left: &str, right: &str| compare_files(&dir.join(left), &dir.join(right), &cancel, |_, _| {}).unwrap();
+
+        let diff = compare("a.txt", "b.txt");
+        assert!(!diff.identical);
+        let lines: Vec<&str> = diff.report.lines().collect();
+        assert!(lines[0].starts_with("--- ") && lines[0].ends_with("a.txt"));
+        assert_eq!(&lines[2..], ["@@ -1,3 +1,3 @@", " one", "-two", "+2", " three"]);
+
+        let same = compare("a.txt", "a.txt");
+        assert_eq!(same, Comparison { report: "Files are identical".to_string(), identical: true });
+
+        let binary = compare("a.bin", "b.bin");
+        assert!(!binary.identical);
+        assert!(binary.report.starts_with("Files differ\n"));
+        assert_eq!(binary.report.matches("4 bytes").count(), 2);
+        assert!(compare("a.bin", "a.bin").identical);
+    }
+
+    #[test]
+    fn test_slow_diff_falls_back_to_checksums() {
+        let dir = TempDir::new("compare-deadline");
+        fs::write(dir.join("a.txt"), "one\ntwo\n").unwrap();
+        fs::write(dir.join("b.txt"), "one\n2\n").unwrap();
+        let cancel = AtomicBool::new(false);
+
+        let mut hashed = 0;
+        let comparison = compare_within(&dir.join("a.txt"), &dir.join("b.txt"), Duration::ZERO, &cancel, |done, total| {
+            assert_eq!(total, 14);
+            hashed = done;
+        })
+        .unwrap();
+        assert!(!comparison.identical);
+        assert!(comparison.report.starts_with("Files differ\n"));
+        assert_eq!(hashed, 14);
+
+        cancel.store(true, Ordering::Relaxed);
+        let err = compare_files(&dir.join("a.txt"), &dir.join("b.txt"), &cancel, |_, _| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
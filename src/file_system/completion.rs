@@ -0,0 +1,133 @@
+//! Typed path input: shell-style `~` and `$VAR` expansion and Tab
+//! completion of the last path component.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Expands a leading `~` and `$VAR` / `${VAR}` references, then resolves
+/// relative paths against `base`. Unknown variables are left as typed.
+pub fn expand_path(input: &str, base: &Path) -> PathBuf {
+    let home = std::env::var("HOME").ok();
+    let input = match (&home, input.strip_prefix('~')) {
+        (Some(home), Some(rest)) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", home, rest),
+        _ => input.to_string(),
+    };
+
+    let path = PathBuf::from(expand_variables(&input));
+    if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    }
+}
+
+fn expand_variables(input: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, consumed) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match std::env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[start..start + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Completes the last component of `input` against the filesystem. Returns
+/// the input extended as far as all matches agree (a unique directory gets a
+/// trailing `/`) and, when several entries match, their names for display.
+pub fn complete_path(input: &str, base: &Path) -> (String, Vec<String>) {
+    let (dir_part, prefix) = match input.rfind('/') {
+        Some(index) => input.split_at(index + 1),
+        None if input == "~" => return ("~/".to_string(), Vec::new()),
+        None => ("", input),
+    };
+    let dir = expand_path(if dir_part.is_empty() { "." } else { dir_part }, base);
+
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return (input.to_string(), Vec::new());
+    };
+    let mut matches: Vec<(String, bool)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Dotfiles only show up once a dot has been typed, like in a shell
+            let visible = !name.starts_with('.') || prefix.starts_with('.');
+            (visible && name.starts_with(prefix)).then(|| (name, entry.path().is_dir()))
+        })
+        .collect();
+    matches.sort();
+
+    match matches.as_slice() {
+        [] => (input.to_string(), Vec::new()),
+        [(name, is_dir)] => {
+            let slash = if *is_dir { "/" } else { "" };
+            (format!("{}{}{}", dir_part, name, slash), Vec::new())
+        }
+        _ => {
+            let first = &matches[0].0;
+            let common = matches.iter()
+                .map(|(name, _)| name.chars().zip(first.chars()).take_while(|(a, b)| a == b).count())
+                .min()
+                .unwrap_or(0);
+            let common: String = first.chars().take(common).collect();
+            let candidates = matches.into_iter()
+                .map(|(name, is_dir)| if is_dir { format!("{}/", name) } else { name })
+                .collect();
+            (format!("{}{}", dir_part, common), candidates)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_expand_path() {
+        let home = PathBuf::from(std::env::var("HOME").unwrap());
+        let base = Path::new("/srv");
+        assert_eq!(expand_path("~", base), home);
+        assert_eq!(expand_path("~/projects", base), home.join("projects"));
+        assert_eq!(expand_path("$HOME/a", base), home.join("a"));
+        assert_eq!(expand_path("${HOME}/a", base), home.join("a"));
+        assert_eq!(expand_path("logs", base), PathBuf::from("/srv/logs"));
+        assert_eq!(expand_path("/x/$FILEPILOT_UNSET_VAR", base), PathBuf::from("/x/$FILEPILOT_UNSET_VAR"));
+        assert_eq!(expand_path("~user/x", base), PathBuf::from("/srv/~user/x"));
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let dir = TempDir::new("complete");
+        fs::create_dir_all(dir.join("projects/filepilot")).unwrap();
+        fs::create_dir_all(dir.join("projects/film")).unwrap();
+        fs::write(dir.join("private.txt"), "").unwrap();
+        fs::write(dir.join(".profile"), "").unwrap();
+
+        assert_eq!(complete_path("pro", &dir), ("projects/".to_string(), Vec::new()));
+        assert_eq!(
+            complete_path("projects/fi", &dir),
+            ("projects/fil".to_string(), vec!["filepilot/".to_string(), "film/".to_string()])
+        );
+        let (completed, candidates) = complete_path("p", &dir);
+        assert_eq!(completed, "pr");
+        assert_eq!(candidates, ["private.txt", "projects/"]);
+        assert_eq!(complete_path(".pro", &dir).0, ".profile");
+        assert_eq!(complete_path("missing/x", &dir), ("missing/x".to_string(), Vec::new()));
+    }
+}
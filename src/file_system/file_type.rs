@@ -0,0 +1,170 @@
+//! Content sniffing: what a file is from its first bytes, for when the
+//! extension is missing or wrong.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Bytes read from the start of a file to decide its type
+const SNIFF_LEN: usize = 512;
+
+// Extensions that name a specific binary format. Text and executables found
+// behind one of these disagree with the name; behind any other they don't.
+const BINARY_FORMAT_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "webp",
+    "mp4", "m4v", "avi", "mov", "wmv", "flv", "webm", "mkv", "ogv",
+    "mp3", "wav", "flac", "ogg", "oga", "m4a", "aac",
+    "pdf", "zip", "tar", "tgz", "gz", "rar", "7z", "xlsx", "xls",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Png,
+    Jpeg,
+    Gif,
+    Pdf,
+    Zip,
+    Gzip,
+    Elf,
+    Text,
+    /// Binary content that isn't one of the formats above, or an empty file
+    Unknown,
+}
+
+impl FileKind {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            FileKind::Png => "image/png",
+            FileKind::Jpeg => "image/jpeg",
+            FileKind::Gif => "image/gif",
+            FileKind::Pdf => "application/pdf",
+            FileKind::Zip => "application/zip",
+            FileKind::Gzip => "application/gzip",
+            FileKind::Elf => "application/x-executable",
+            FileKind::Text => "text/plain",
+            FileKind::Unknown => "application/octet-stream",
+        }
+    }
+
+    /// The usual extension for this content, if it has one.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            FileKind::Png => Some("png"),
+            FileKind::Jpeg => Some("jpg"),
+            FileKind::Gif => Some("gif"),
+            FileKind::Pdf => Some("pdf"),
+            FileKind::Zip => Some("zip"),
+            FileKind::Gzip => Some("gz"),
+            FileKind::Elf | FileKind::Text | FileKind::Unknown => None,
+        }
+    }
+
+    /// Whether a file named with `extension` (lowercase, without the dot)
+    /// could hold this content. Unrecognized content agrees with any name.
+    pub fn agrees_with_extension(self, extension: &str) -> bool {
+        let own: &[&str] = match self {
+            FileKind::Png => &["png"],
+            FileKind::Jpeg => &["jpg", "jpeg"],
+            FileKind::Gif => &["gif"],
+            FileKind::Pdf => &["pdf"],
+            // Office documents are zip files too
+            FileKind::Zip => &["zip", "xlsx"],
+            FileKind::Gzip => &["gz", "tgz"],
+            FileKind::Elf | FileKind::Text => return !BINARY_FORMAT_EXTENSIONS.contains(&extension),
+            FileKind::Unknown => return true,
+        };
+        own.contains(&extension)
+    }
+}
+
+/// Sniffs the first few hundred bytes of `path`: magic numbers for common
+/// binary formats, otherwise a check for UTF-8 text.
+pub fn detect_type(path: &Path) -> io::Result<FileKind> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?.take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+    Ok(detect_bytes(&head))
+}
+
+fn detect_bytes(head: &[u8]) -> FileKind {
+    const MAGIC: &[(&[u8], FileKind)] = &[
+        (b"\x89PNG\r\n\x1a\n", FileKind::Png),
+        (b"\xff\xd8\xff", FileKind::Jpeg),
+        (b"GIF87a", FileKind::Gif),
+        (b"GIF89a", FileKind::Gif),
+        (b"%PDF-", FileKind::Pdf),
+        (b"PK\x03\x04", FileKind::Zip),
+        // An empty zip has only the end of central directory record
+        (b"PK\x05\x06", FileKind::Zip),
+        (b"\x1f\x8b", FileKind::Gzip),
+        (b"\x7fELF", FileKind::Elf),
+    ];
+
+    if let Some((_, kind)) = MAGIC.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return *kind;
+    }
+    if !head.is_empty() && looks_like_text(head) {
+        FileKind::Text
+    } else {
+        FileKind::Unknown
+    }
+}
+
+pub(super) fn looks_like_text(head: &[u8]) -> bool {
+    let utf8 = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The sample may end partway through a multi-byte character
+        Err(e) => e.error_len().is_none(),
+    };
+    // Tabs, newlines, form feeds and escape sequences are normal in text;
+    // a few stray control characters are tolerated, NULs never
+    let control = head.iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || b == 0x7f)
+        .count();
+    utf8 && !head.contains(&0) && control * 20 < head.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_detect_bytes() {
+        assert_eq!(detect_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), FileKind::Png);
+        assert_eq!(detect_bytes(b"\xff\xd8\xff\xe0\0\x10JFIF"), FileKind::Jpeg);
+        assert_eq!(detect_bytes(b"%PDF-1.7\n"), FileKind::Pdf);
+        assert_eq!(detect_bytes(b"PK\x03\x04\x14\0"), FileKind::Zip);
+        assert_eq!(detect_bytes(b"\x7fELF\x02\x01\x01"), FileKind::Elf);
+        assert_eq!(detect_bytes("# Notes\n\tcafé ☕\n".as_bytes()), FileKind::Text);
+        assert_eq!(detect_bytes(b"\x1b[31mred\x1b[0m\n"), FileKind::Text);
+        // Cut in the middle of a two-byte character
+        assert_eq!(detect_bytes(&"é".repeat(3).as_bytes()[..5]), FileKind::Text);
+        assert_eq!(detect_bytes(b"text\0with nul"), FileKind::Unknown);
+        assert_eq!(detect_bytes(b"\xfe\xff\x00h\x00i"), FileKind::Unknown);
+        assert_eq!(detect_bytes(b""), FileKind::Unknown);
+    }
+
+    #[test]
+    fn test_extension_agreement() {
+        assert!(FileKind::Png.agrees_with_extension("png"));
+        assert!(!FileKind::Png.agrees_with_extension("txt"));
+        assert!(FileKind::Text.agrees_with_extension("rs"));
+        assert!(FileKind::Text.agrees_with_extension(""));
+        assert!(!FileKind::Text.agrees_with_extension("png"));
+        assert!(FileKind::Elf.agrees_with_extension("so"));
+        assert!(FileKind::Unknown.agrees_with_extension("mp4"));
+    }
+
+    #[test]
+    fn test_detect_type_reads_file_head() {
+        let dir = TempDir::new("detect");
+        let path = dir.join("image.txt");
+        let mut content = b"\x89PNG\r\n\x1a\n".to_vec();
+        content.resize(4096, 0);
+        fs::write(&path, content).unwrap();
+        assert_eq!(detect_type(&path).unwrap(), FileKind::Png);
+        fs::remove_file(&path).unwrap();
+        assert!(detect_type(&path).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! How often and how recently each directory was entered, so the jump
+//! popup can offer the likely ones first, the way `zoxide` does.
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Once the ranks add up to more than this they are all scaled down by
+// `AGING_FACTOR`, so directories no longer visited fade out
+const MAX_TOTAL_RANK: f64 = 1000.0;
+const AGING_FACTOR: f64 = 0.9;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Visits {
+    path: PathBuf,
+    /// One per visit, less once aged
+    rank: f64,
+    /// Seconds since the Unix epoch
+    last_visit: u64,
+}
+
+impl Visits {
+    /// `rank` weighted by how long ago the last visit was.
+    fn frecency(&self, now: u64) -> f64 {
+        let weight = match now.saturating_sub(self.last_visit) {
+            age if age < HOUR => 4.0,
+            age if age < DAY => 2.0,
+            age if age < WEEK => 0.5,
+            _ => 0.25,
+        };
+        self.rank * weight
+    }
+}
+
+/// Visited directories, in no particular order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Frecency {
+    entries: Vec<Visits>,
+}
+
+impl Frecency {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Counts a visit to `dir` at `now`.
+    pub fn visit(&mut self, dir: &Path, now: SystemTime) {
+        let now = seconds(now);
+        match self.entries.iter_mut().find(|entry| entry.path == dir) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.last_visit = now;
+            }
+            None => self.entries.push(Visits { path: dir.to_path_buf(), rank: 1.0, last_visit: now }),
+        }
+
+        if self.entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+            for entry in &mut self.entries {
+                entry.rank *= AGING_FACTOR;
+            }
+            self.entries.retain(|entry| entry.rank >= 1.0);
+        }
+    }
+
+    /// Forgets directories that no longer exist; returns how many.
+    pub fn prune_missing(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.path.is_dir());
+        before - self.entries.len()
+    }
+
+    /// The directories whose path fuzzily matches `query`, best first:
+    /// the match score weighted by frecency, or frecency alone for an
+    /// empty query.
+    pub fn matches(&self, query: &str, now: SystemTime) -> Vec<&Path> {
+        let now = seconds(now);
+        let matcher = SkimMatcherV2::default();
+        let mut found: Vec<(f64, &Path)> = self.entries.iter()
+            .filter_map(|entry| {
+                let score = if query.is_empty() {
+                    1
+                } else {
+                    matcher.fuzzy_match(&entry.path.to_string_lossy(), query)?
+                };
+                Some((score as f64 * entry.frecency(now), entry.path.as_path()))
+            })
+            .collect();
+        found.sort_by(|a, b| b.0.total_cmp(&a.0));
+        found.into_iter().map(|(_, path)| path).collect()
+    }
+
+    /// Reads the table written by `save`; a missing file is an empty one.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_ranking_and_aging() {
+        let now = SystemTime::now();
+        let mut frecency = Frecency::default();
+        let projects = Path::new("/home/me/projects");
+        let photos = Path::new("/home/me/photos");
+        for _ in 0..3 {
+            frecency.visit(photos, now - Duration::from_secs(2 * WEEK));
+        }
+        frecency.visit(projects, now);
+
+        // One recent visit outweighs three old ones
+        assert_eq!(frecency.matches("", now), [projects, photos]);
+        assert_eq!(frecency.matches("pho", now), [photos]);
+        assert!(frecency.matches("xyz", now).is_empty());
+
+        for _ in 0..3 * MAX_TOTAL_RANK as usize {
+            frecency.visit(projects, now);
+        }
+        // Aging scaled photos' three visits down to less than one, so it went
+        assert_eq!(frecency.entries.len(), 1);
+
+        let dir = TempDir::new("frecency");
+        let path = dir.join("frecency.json");
+        frecency.save(&path).unwrap();
+        assert_eq!(Frecency::load(&path).unwrap().entries, frecency.entries);
+        // Directories that don't exist are dropped when asked
+        assert_eq!(frecency.prune_missing(), 1);
+        assert!(frecency.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+//! Git status of the entries in a directory, read by running
+//! `git status --porcelain -z` limited to that directory.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitStatus {
+    // Ordered by how much they matter when a directory sums up its contents
+    Ignored,
+    Untracked,
+    Staged,
+    Modified,
+}
+
+/// Statuses for one directory and everything below it, keyed by absolute
+/// path under the directory as it was given (symlinks are not resolved).
+#[derive(Debug, Clone, Default)]
+pub struct RepoStatus {
+    dir: PathBuf,
+    entries: HashMap<PathBuf, GitStatus>,
+    // Directories containing changed entries, with the strongest status inside
+    dirs: HashMap<PathBuf, GitStatus>,
+}
+
+impl RepoStatus {
+    /// The status shown for `path`: its own, the strongest change inside it
+    /// for directories, or that of an untracked or ignored directory it is in.
+    pub fn status_of(&self, path: &Path) -> Option<GitStatus> {
+        if let Some(status) = self.entries.get(path).or_else(|| self.dirs.get(path)) {
+            return Some(*status);
+        }
+        path.ancestors()
+            .skip(1)
+            .take_while(|ancestor| ancestor.starts_with(&self.dir))
+            .find_map(|ancestor| self.entries.get(ancestor))
+            .copied()
+    }
+}
+
+/// Runs git in `dir`. Outside a repository, or without git installed,
+/// there is nothing to show and the result is `None`.
+pub fn read_status(dir: &Path) -> io::Result<Option<RepoStatus>> {
+    // Where `dir` is inside the repository, e.g. "src/" or "" at the root
+    let prefix = match Command::new("git").arg("-C").arg(dir).args(["rev-parse", "--show-prefix"]).output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim_end().to_string(),
+        Ok(_) => return Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain=v1", "-z", "--ignored", "--", "."])
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("git status failed: {}", stderr.trim())));
+    }
+    Ok(Some(parse_porcelain(dir, &prefix, &output.stdout)))
+}
+
+/// Parses `git status --porcelain=v1 -z` output, whose paths are relative
+/// to the repository root; `prefix` is `dir` relative to that root.
+fn parse_porcelain(dir: &Path, prefix: &str, output: &[u8]) -> RepoStatus {
+    let mut status = RepoStatus { dir: dir.to_path_buf(), ..Default::default() };
+    let mut records = output.split(|&b| b == 0).filter(|record| !record.is_empty());
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let (x, y) = (record[0], record[1]);
+        let path = String::from_utf8_lossy(&record[3..]);
+        if matches!(x, b'R' | b'C') {
+            // Renames and copies are followed by the original path
+            records.next();
+        }
+
+        let kind = match (x, y) {
+            (b'?', b'?') => GitStatus::Untracked,
+            (b'!', b'!') => GitStatus::Ignored,
+            (_, b' ') => GitStatus::Staged,
+            _ => GitStatus::Modified,
+        };
+        let relative = path.strip_prefix(prefix).unwrap_or(&path).trim_end_matches('/');
+        let path = dir.join(relative);
+
+        if kind != GitStatus::Ignored {
+            for ancestor in path.ancestors().skip(1).take_while(|ancestor| *ancestor != dir && ancestor.starts_with(dir)) {
+                let strongest = status.dirs.entry(ancestor.to_path_buf()).or_insert(kind);
+                *strongest = (*strongest).max(kind);
+            }
+        }
+        status.entries.insert(path, kind);
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_parse_porcelain() {
+        let dir = Path::new("/work/repo/sub");
+        let output = b" M sub/b\0?? sub/deep/\0M  sub/staged\0MM sub/both\0R  sub/new\0sub/old\0!! sub/ign/\0M  sub/lib/x/y.rs\0 M sub/lib/z.rs\0";
+        let status = parse_porcelain(dir, "sub/", output);
+
+        assert_eq!(status.status_of(&dir.join("b")), Some(GitStatus::Modified));
+        assert_eq!(status.status_of(&dir.join("staged")), Some(GitStatus::Staged));
+        assert_eq!(status.status_of(&dir.join("both")), Some(GitStatus::Modified));
+        assert_eq!(status.status_of(&dir.join("new")), Some(GitStatus::Staged));
+        assert_eq!(status.status_of(&dir.join("old")), None);
+        assert_eq!(status.status_of(&dir.join("deep")), Some(GitStatus::Untracked));
+        assert_eq!(status.status_of(&dir.join("deep/inner.txt")), Some(GitStatus::Untracked));
+        assert_eq!(status.status_of(&dir.join("ign/cache")), Some(GitStatus::Ignored));
+        // Directories show the strongest change inside them
+        assert_eq!(status.status_of(&dir.join("lib")), Some(GitStatus::Modified));
+        assert_eq!(status.status_of(&dir.join("lib/x")), Some(GitStatus::Staged));
+        assert_eq!(status.status_of(&dir.join("clean.txt")), None);
+        assert_eq!(status.status_of(dir), None);
+    }
+
+    #[test]
+    fn test_read_status_in_and_out_of_repository() {
+        let dir = TempDir::new("git-status");
+        fs::create_dir_all(dir.join("repo/src")).unwrap();
+        fs::create_dir_all(dir.join("plain")).unwrap();
+        let repo = dir.join("repo");
+
+        let git = |args: &[&str]| Command::new("git").arg("-C").arg(&repo).args(args).output();
+        if !git(&["init", "-q"]).is_ok_and(|output| output.status.success()) {
+            // git isn't installed here
+            return;
+        }
+        fs::write(repo.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(repo.join("src/lib.rs"), "").unwrap();
+        fs::write(repo.join(".gitignore"), "target\n").unwrap();
+        fs::create_dir(repo.join("target")).unwrap();
+        fs::write(repo.join("target/build.log"), "").unwrap();
+        git(&["add", "src/main.rs"]).unwrap();
+
+        let status = read_status(&repo).unwrap().unwrap();
+        assert_eq!(status.status_of(&repo.join("src/main.rs")), Some(GitStatus::Staged));
+        assert_eq!(status.status_of(&repo.join("src/lib.rs")), Some(GitStatus::Untracked));
+        assert_eq!(status.status_of(&repo.join("src")), Some(GitStatus::Staged));
+        assert_eq!(status.status_of(&repo.join("target")), Some(GitStatus::Ignored));
+
+        let status = read_status(&repo.join("src")).unwrap().unwrap();
+        assert_eq!(status.status_of(&repo.join("src/lib.rs")), Some(GitStatus::Untracked));
+
+        assert!(read_status(&dir.join("plain")).unwrap().is_none());
+    }
+}
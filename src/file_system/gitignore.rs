@@ -0,0 +1,104 @@
+//! The `.gitignore` rules in effect for one directory, used to hide
+//! ignored entries from the listing.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+
+/// Matchers from the directory's own `.gitignore` up to the repository
+/// root, then `.git/info/exclude` and the global excludes file. The first
+/// one with an opinion on a path decides, like git does.
+pub struct IgnoreRules {
+    dir: PathBuf,
+    root: PathBuf,
+    matchers: Vec<Gitignore>,
+    global: Gitignore,
+}
+
+impl IgnoreRules {
+    /// Reads the rules for `dir`, or `None` when it isn't inside a git
+    /// repository.
+    pub fn load(dir: &Path) -> Option<Self> {
+        let mut matchers = Vec::new();
+        let root = dir.ancestors().find(|ancestor| {
+            matchers.push(matcher(ancestor, &ancestor.join(".gitignore")));
+            ancestor.join(".git").exists()
+        })?;
+        matchers.push(matcher(root, &root.join(".git").join("info").join("exclude")));
+
+        Some(IgnoreRules {
+            dir: dir.to_path_buf(),
+            root: root.to_path_buf(),
+            matchers,
+            global: Gitignore::global().0,
+        })
+    }
+
+    /// The directory these rules were read for.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether `path`, somewhere under the directory, is ignored itself or
+    /// lies inside an ignored directory.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if !path.starts_with(&self.root) {
+            return false;
+        }
+        for matcher in &self.matchers {
+            let found = matcher.matched_path_or_any_parents(path, is_dir);
+            if !found.is_none() {
+                return found.is_ignore();
+            }
+        }
+        // The global file's patterns aren't tied to a directory
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.global.matched_path_or_any_parents(relative, is_dir).is_ignore()
+    }
+}
+
+fn matcher(dir: &Path, file: &Path) -> Gitignore {
+    if !file.is_file() {
+        return Gitignore::empty();
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    builder.add(file);
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_nested_rules_and_repository_boundary() {
+        let dir = TempDir::new("gitignore");
+        let repo = dir.join("repo");
+        fs::create_dir_all(repo.join(".git/info")).unwrap();
+        fs::create_dir_all(repo.join("web/node_modules")).unwrap();
+        fs::create_dir_all(repo.join("target/debug")).unwrap();
+        fs::write(repo.join(".gitignore"), "target/\n*.log\n/top-only.txt\n").unwrap();
+        fs::write(repo.join("web/.gitignore"), "node_modules\n!keep.log\n").unwrap();
+        fs::write(repo.join(".git/info/exclude"), "scratch\n").unwrap();
+
+        let rules = IgnoreRules::load(&repo).unwrap();
+        assert!(rules.is_ignored(&repo.join("target"), true));
+        assert!(!rules.is_ignored(&repo.join("target"), false));
+        assert!(rules.is_ignored(&repo.join("target/debug"), true));
+        assert!(rules.is_ignored(&repo.join("build.log"), false));
+        assert!(rules.is_ignored(&repo.join("scratch"), false));
+        assert!(rules.is_ignored(&repo.join("top-only.txt"), false));
+        assert!(!rules.is_ignored(&repo.join("src"), true));
+
+        let rules = IgnoreRules::load(&repo.join("web")).unwrap();
+        assert_eq!(rules.dir(), repo.join("web"));
+        assert!(rules.is_ignored(&repo.join("web/node_modules"), true));
+        assert!(rules.is_ignored(&repo.join("web/debug.log"), false));
+        assert!(!rules.is_ignored(&repo.join("web/keep.log"), false));
+        assert!(!rules.is_ignored(&repo.join("web/top-only.txt"), false));
+
+        fs::create_dir_all(dir.join("plain")).unwrap();
+        assert!(IgnoreRules::load(&dir.join("plain")).is_none());
+    }
+}
@@ -0,0 +1,204 @@
+//! Mounted filesystems for the drives view: `/proc/mounts` on Linux, the
+//! `mount` command on other Unixes and the drive letters on Windows.
+
+use super::{disk_usage, DiskUsage};
+use std::path::PathBuf;
+
+// Kernel interfaces mounted like filesystems, with nothing to browse for files
+const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "proc", "sysfs", "cgroup", "cgroup2", "devpts", "devtmpfs", "securityfs",
+    "debugfs", "tracefs", "pstore", "bpf", "mqueue", "hugetlbfs", "configfs",
+    "fusectl", "autofs", "binfmt_misc", "rpc_pipefs", "nsfs", "efivarfs",
+    "selinuxfs", "devfs",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub mount_point: PathBuf,
+    /// The device or source it is mounted from, e.g. `/dev/sdb1`
+    pub device: String,
+    /// Filesystem type such as `ext4` or `apfs`, empty when unknown
+    pub fs_type: String,
+    pub usage: Option<DiskUsage>,
+}
+
+impl Volume {
+    pub fn is_pseudo(&self) -> bool {
+        PSEUDO_FILESYSTEMS.contains(&self.fs_type.as_str())
+    }
+}
+
+/// Mounted volumes sorted by mount point, leaving out pseudo filesystems
+/// like proc and sysfs unless `include_pseudo` is set.
+pub fn list_volumes(include_pseudo: bool) -> Vec<Volume> {
+    let mut volumes: Vec<Volume> = mount_table().into_iter()
+        .map(|(device, mount_point, fs_type)| Volume { mount_point, device, fs_type, usage: None })
+        .filter(|volume| include_pseudo || !volume.is_pseudo())
+        .collect();
+
+    // A later mount on the same point hides the earlier one
+    let mut seen = std::collections::HashSet::new();
+    volumes.reverse();
+    volumes.retain(|volume| seen.insert(volume.mount_point.clone()));
+    volumes.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+
+    for volume in &mut volumes {
+        volume.usage = disk_usage(&volume.mount_point);
+    }
+    volumes
+}
+
+/// (device, mount point, filesystem type) for every mount, in mount order.
+#[cfg(target_os = "linux")]
+fn mount_table() -> Vec<(String, PathBuf, String)> {
+    std::fs::read_to_string("/proc/mounts")
+        .map(|content| parse_proc_mounts(&content))
+        .unwrap_or_default()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn mount_table() -> Vec<(String, PathBuf, String)> {
+    std::process::Command::new("mount")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| parse_mount_output(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+}
+
+#[cfg(windows)]
+fn mount_table() -> Vec<(String, PathBuf, String)> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetLogicalDrives() -> u32;
+        fn GetVolumeInformationW(
+            root: *const u16,
+            volume_name: *mut u16,
+            volume_name_len: u32,
+            serial_number: *mut u32,
+            max_component_len: *mut u32,
+            flags: *mut u32,
+            fs_name: *mut u16,
+            fs_name_len: u32,
+        ) -> i32;
+    }
+
+    let drives = unsafe { GetLogicalDrives() };
+    (0..26u8)
+        .filter(|letter| drives & (1 << letter) != 0)
+        .map(|letter| {
+            let root = format!("{}:\\", (b'A' + letter) as char);
+            let wide: Vec<u16> = std::ffi::OsStr::new(&root).encode_wide().chain(std::iter::once(0)).collect();
+            let mut fs_name = [0u16; 64];
+            let ok = unsafe {
+                GetVolumeInformationW(
+                    wide.as_ptr(),
+                    std::ptr::null_mut(),
+                    0,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    fs_name.as_mut_ptr(),
+                    fs_name.len() as u32,
+                )
+            };
+            let fs_type = if ok != 0 {
+                let len = fs_name.iter().position(|&c| c == 0).unwrap_or(fs_name.len());
+                String::from_utf16_lossy(&fs_name[..len])
+            } else {
+                String::new()
+            };
+            (root.clone(), PathBuf::from(root), fs_type)
+        })
+        .collect()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn mount_table() -> Vec<(String, PathBuf, String)> {
+    Vec::new()
+}
+
+/// Parses `/proc/mounts`: space-separated fields with spaces and other
+/// special characters in paths written as octal escapes like `\040`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_mounts(content: &str) -> Vec<(String, PathBuf, String)> {
+    content.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = unescape_octal(fields.next()?);
+            let mount_point = PathBuf::from(unescape_octal(fields.next()?));
+            let fs_type = fields.next()?.to_string();
+            Some((device, mount_point, fs_type))
+        })
+        .collect()
+}
+
+fn unescape_octal(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4)
+            .filter(|digits| bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .map(|digits| digits.iter().fold(0u32, |value, d| value * 8 + (d - b'0') as u32));
+        match escape {
+            Some(value) if value <= 0xff => {
+                out.push(value as u8);
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Parses BSD-style `mount` output: `/dev/disk3s1 on /Volumes/USB (apfs, local, nodev)`.
+#[cfg_attr(not(all(unix, not(target_os = "linux"))), allow(dead_code))]
+fn parse_mount_output(output: &str) -> Vec<(String, PathBuf, String)> {
+    output.lines()
+        .filter_map(|line| {
+            let (device, rest) = line.split_once(" on ")?;
+            // Mount points may contain spaces, so split at the last " ("
+            let (mount_point, options) = rest.rsplit_once(" (")?;
+            let fs_type = options.trim_end_matches(')').split(',').next()?.trim().to_string();
+            Some((device.to_string(), PathBuf::from(mount_point), fs_type))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_mounts() {
+        let content = "proc /proc proc rw,nosuid 0 0\n/dev/sdb1 /media/me/USB\\040Stick vfat rw 0 0\n/dev/nvme0n1p2 / ext4 rw 0 0\n";
+        let mounts = parse_proc_mounts(content);
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(mounts[1], ("/dev/sdb1".to_string(), PathBuf::from("/media/me/USB Stick"), "vfat".to_string()));
+        assert_eq!(unescape_octal("a\\134b\\12"), "a\\b\\12");
+    }
+
+    #[test]
+    fn test_parse_mount_output() {
+        let output = "/dev/disk3s1s1 on / (apfs, sealed, local, read-only, journaled)\n/dev/disk5s1 on /Volumes/My Drive (msdos, local, nodev, nosuid, noowners)\nmap auto_home on /System/Volumes/Data/home (autofs, automounted, nobrowse)\n";
+        let mounts = parse_mount_output(output);
+        assert_eq!(mounts[1], ("/dev/disk5s1".to_string(), PathBuf::from("/Volumes/My Drive"), "msdos".to_string()));
+        assert_eq!(mounts[2].2, "autofs");
+    }
+
+    #[test]
+    fn test_list_volumes_filters_pseudo_filesystems() {
+        let volumes = list_volumes(false);
+        assert!(volumes.iter().all(|volume| !volume.is_pseudo()));
+        assert!(volumes.windows(2).all(|pair| pair[0].mount_point < pair[1].mount_point));
+        if cfg!(target_os = "linux") {
+            assert!(list_volumes(true).iter().any(|volume| volume.fs_type == "proc"));
+        }
+    }
+}
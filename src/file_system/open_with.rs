@@ -0,0 +1,183 @@
+//! Applications offered by the open-with menu: the user's configured ones
+//! and, on Linux and the BSDs, those installed with a `.desktop` file.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An application that can open a file. `{path}` in `command` is replaced
+/// with the file; without it the path is appended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenWithApp {
+    pub name: String,
+    pub command: String,
+}
+
+impl OpenWithApp {
+    /// Starts the application on `path` without waiting for it or giving
+    /// it the terminal.
+    pub fn spawn(&self, path: &Path) -> io::Result<()> {
+        let mut command = super::template_command(&self.command, "{path}", path, true)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("'{}' has no command", self.name)))?;
+        if let Some(dir) = path.parent() {
+            command.current_dir(dir);
+        }
+        super::spawn_detached(&mut command)
+    }
+}
+
+/// The menu for a file: `configured` apps in their order, then installed
+/// ones not already listed, with the app last used for this kind of file
+/// moved to the top.
+pub fn menu_entries(configured: &[OpenWithApp], installed: Vec<OpenWithApp>, last_used: Option<&str>) -> Vec<OpenWithApp> {
+    let mut entries = configured.to_vec();
+    for app in installed {
+        if !entries.iter().any(|listed| listed.name == app.name) {
+            entries.push(app);
+        }
+    }
+    if let Some(index) = last_used.and_then(|name| entries.iter().position(|app| app.name == name)) {
+        let app = entries.remove(index);
+        entries.insert(0, app);
+    }
+    entries
+}
+
+/// Installed applications that declare they handle `mime_type`, sorted by
+/// name. Apps for `text/plain` are offered for every `text/*` type, since
+/// source files are text whatever their exact type. Empty on platforms
+/// without `.desktop` files.
+pub fn installed_apps(mime_type: &str) -> Vec<OpenWithApp> {
+    if cfg!(any(windows, target_os = "macos")) {
+        return Vec::new();
+    }
+
+    let mut apps: Vec<OpenWithApp> = Vec::new();
+    for dir in application_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "desktop") {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Some(app) = parse_desktop_entry(&content, mime_type) {
+                // Earlier directories take precedence, like the XDG lookup
+                if !apps.iter().any(|listed| listed.name == app.name) {
+                    apps.push(app);
+                }
+            }
+        }
+    }
+    apps.sort_by_key(|app| app.name.to_lowercase());
+    apps
+}
+
+/// `$XDG_DATA_HOME/applications` followed by the `applications` directory
+/// of each `$XDG_DATA_DIRS` entry, with the spec's defaults.
+fn application_dirs() -> Vec<PathBuf> {
+    let non_empty = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let data_home = non_empty("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var("HOME").ok().map(|home| PathBuf::from(home).join(".local").join("share")));
+    let data_dirs = non_empty("XDG_DATA_DIRS").unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+    data_home.into_iter()
+        .chain(data_dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// The app described by a `.desktop` file, if it is a visible application
+/// that handles `mime_type`.
+fn parse_desktop_entry(content: &str, mime_type: &str) -> Option<OpenWithApp> {
+    let mut in_entry = false;
+    let (mut name, mut exec, mut mime_types) = (None, None, "");
+    let mut application = false;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry {
+            continue;
+        }
+        // Localized keys such as Name[de] are skipped by the exact match
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match (key.trim(), value.trim()) {
+            ("Type", value) => application = value == "Application",
+            ("Name", value) => name = Some(value),
+            ("Exec", value) => exec = Some(value),
+            ("MimeType", value) => mime_types = value,
+            ("NoDisplay" | "Hidden", "true") => return None,
+            _ => {}
+        }
+    }
+
+    let text_fallback = mime_type.starts_with("text/");
+    let handles = mime_types.split(';')
+        .any(|declared| declared == mime_type || (text_fallback && declared == "text/plain"));
+    if !application || !handles {
+        return None;
+    }
+    Some(OpenWithApp {
+        name: name?.to_string(),
+        command: exec_to_template(exec?),
+    })
+}
+
+/// Turns an `Exec` value into a command template: the file and URL field
+/// codes become `{path}`, the others are dropped.
+fn exec_to_template(exec: &str) -> String {
+    exec.split_whitespace()
+        .filter_map(|part| match part {
+            "%f" | "%F" | "%u" | "%U" => Some("{path}".to_string()),
+            "%i" | "%c" | "%k" => None,
+            part => Some(part.replace("%%", "%")),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str) -> OpenWithApp {
+        OpenWithApp { name: name.to_string(), command: name.to_lowercase() }
+    }
+
+    #[test]
+    fn test_parse_desktop_entry() {
+        let gimp = "[Desktop Entry]\nType=Application\nName=GIMP\nName[de]=GIMP-Bildbearbeitung\nExec=gimp-2.10 %U\nMimeType=image/png;image/jpeg;\n\n[Desktop Action new]\nName=New Window\nExec=gimp --new\n";
+        assert_eq!(parse_desktop_entry(gimp, "image/png"), Some(OpenWithApp { name: "GIMP".to_string(), command: "gimp-2.10 {path}".to_string() }));
+        assert_eq!(parse_desktop_entry(gimp, "image/gif"), None);
+
+        let editor = "[Desktop Entry]\nType=Application\nName=Editor\nExec=editor --icon %i %F\nMimeType=text/plain;\n";
+        assert_eq!(parse_desktop_entry(editor, "text/x-rust").unwrap().command, "editor --icon {path}");
+
+        let hidden = "[Desktop Entry]\nType=Application\nName=Helper\nExec=helper %f\nMimeType=image/png;\nNoDisplay=true\n";
+        assert_eq!(parse_desktop_entry(hidden, "image/png"), None);
+        let link = "[Desktop Entry]\nType=Link\nName=Site\nURL=https://example.com\nMimeType=image/png;\n";
+        assert_eq!(parse_desktop_entry(link, "image/png"), None);
+    }
+
+    #[test]
+    fn test_menu_entries_order() {
+        let configured = vec![app("GIMP"), app("Krita")];
+        let installed = vec![app("Eye of GNOME"), app("GIMP"), app("Shotwell")];
+        let names = |entries: Vec<OpenWithApp>| entries.into_iter().map(|app| app.name).collect::<Vec<_>>();
+
+        assert_eq!(names(menu_entries(&configured, installed.clone(), None)), ["GIMP", "Krita", "Eye of GNOME", "Shotwell"]);
+        assert_eq!(names(menu_entries(&configured, installed.clone(), Some("Shotwell"))), ["Shotwell", "GIMP", "Krita", "Eye of GNOME"]);
+        // An app that is gone changes nothing
+        assert_eq!(names(menu_entries(&configured, installed, Some("Paint"))), ["GIMP", "Krita", "Eye of GNOME", "Shotwell"]);
+    }
+}
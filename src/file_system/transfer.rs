@@ -0,0 +1,681 @@
+//! Copying and moving entries, including across filesystems where `rename`
+//! can't, and resolving clashes with entries already at the destination.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How a paste resolves an entry that already exists at the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Overwrite,
+    KeepBoth,
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferMode {
+    Copy,
+    Move,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferProgress {
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current_file: PathBuf,
+}
+
+/// What a transfer running on another thread sends back to its starter.
+#[derive(Debug)]
+pub enum TransferEvent {
+    Progress(TransferProgress),
+    Finished(io::Result<Option<PathBuf>>),
+}
+
+/// Carries progress out of a transfer and a cancel request into it. The
+/// default reporter sends nothing and is never cancelled.
+#[derive(Debug, Default)]
+pub struct Reporter {
+    cancel: Arc<AtomicBool>,
+    events: Option<mpsc::Sender<TransferEvent>>,
+    bytes_done: u64,
+    bytes_total: u64,
+    last_sent: Option<Instant>,
+}
+
+impl Reporter {
+    pub fn new(bytes_total: u64, cancel: Arc<AtomicBool>, events: mpsc::Sender<TransferEvent>) -> Self {
+        Reporter { cancel, events: Some(events), bytes_done: 0, bytes_total, last_sent: None }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    fn check_cancelled(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn advance(&mut self, bytes: u64, current_file: &Path) {
+        self.bytes_done += bytes;
+        let Some(events) = &self.events else {
+            return;
+        };
+        // Throttled so a fast copy doesn't flood the channel
+        if self.last_sent.is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL) {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+        let _ = events.send(TransferEvent::Progress(TransferProgress {
+            bytes_done: self.bytes_done,
+            bytes_total: self.bytes_total,
+            current_file: current_file.to_path_buf(),
+        }));
+    }
+}
+
+/// Total size in bytes of the files under `path`, used as a progress total.
+pub fn total_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Whether `a` and `b` are the same entry on disk, however they are
+/// reached, e.g. through a symlinked directory. A symlink as the last
+/// component is compared as the link itself, not what it points to.
+pub fn is_same_entry(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+    }
+}
+
+/// Whether `path` lies inside the directory `ancestor` on disk, however
+/// either is reached. A symlink as the last component is taken as the link
+/// itself, as in [`is_same_entry`].
+fn is_inside(path: &Path, ancestor: &Path) -> bool {
+    let resolve = |path: &Path| {
+        let name = path.file_name()?;
+        let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        fs::canonicalize(parent).ok().map(|parent| parent.join(name))
+    };
+    matches!((resolve(path), resolve(ancestor)), (Some(path), Some(ancestor)) if path != ancestor && path.starts_with(&ancestor))
+}
+
+/// Copies or moves `source` to `destination`, resolving an existing
+/// destination with `policy`. Overwriting a directory with a directory merges
+/// into it rather than discarding its other contents; with `merge` set, a
+/// directory clash is merged for every policy and `policy` then applies to
+/// each clash inside. Returns where the entry ended up, or `None` if skipped.
+pub fn transfer(
+    source: &Path,
+    destination: &Path,
+    mode: TransferMode,
+    policy: ConflictPolicy,
+    merge: bool,
+    reporter: &mut Reporter,
+) -> io::Result<Option<PathBuf>> {
+    reporter.check_cancelled()?;
+    let existing = match fs::symlink_metadata(destination) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            transfer_new(source, destination, mode, reporter)?;
+            return Ok(Some(destination.to_path_buf()));
+        }
+        Err(e) => return Err(e),
+    };
+    // Overwriting an entry with itself would truncate it while reading it
+    if (policy == ConflictPolicy::Overwrite || merge) && is_same_entry(source, destination) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "source and destination are the same entry"));
+    }
+    let source_is_dir = fs::symlink_metadata(source)?.is_dir();
+    let both_directories = source_is_dir && existing.is_dir();
+
+    if both_directories && (merge || policy == ConflictPolicy::Overwrite) {
+        merge_directories(source, destination, mode, policy, reporter)?;
+        return Ok(Some(destination.to_path_buf()));
+    }
+
+    match policy {
+        ConflictPolicy::Skip => Ok(None),
+        ConflictPolicy::KeepBoth => {
+            let target = super::numbered_target(destination, source_is_dir);
+            transfer_new(source, &target, mode, reporter)?;
+            Ok(Some(target))
+        }
+        ConflictPolicy::Overwrite => {
+            replace_entry(source, destination, source_is_dir || existing.is_dir(), mode, reporter)?;
+            Ok(Some(destination.to_path_buf()))
+        }
+    }
+}
+
+/// Puts `source` in place of the entry at `destination`. The new entry is
+/// assembled beside the old one under a temporary name and only swapped in
+/// once complete, so a cancelled or failed paste leaves the old entry as it
+/// was, and a symlink is replaced rather than written through.
+fn replace_entry(source: &Path, destination: &Path, directories: bool, mode: TransferMode, reporter: &mut Reporter) -> io::Result<()> {
+    // The old entry is deleted once replaced, and the source with it
+    if is_inside(source, destination) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "destination contains the source"));
+    }
+    let staging = sibling_path(destination, "partial");
+    // Left behind by a paste that was killed
+    let _ = remove_entry(&staging);
+    if let Err(e) = transfer_new(source, &staging, mode, reporter) {
+        // A failed move has already cleaned up after itself
+        if mode == TransferMode::Copy {
+            let _ = remove_entry(&staging);
+        }
+        return Err(e);
+    }
+
+    let swapped = if directories {
+        // Only files can be renamed over, so the old entry is moved aside
+        // until the new one is in place
+        let aside = sibling_path(destination, "replaced");
+        let _ = remove_entry(&aside);
+        fs::rename(destination, &aside).and_then(|()| match fs::rename(&staging, destination) {
+            Ok(()) => remove_entry(&aside),
+            Err(e) => {
+                let _ = fs::rename(&aside, destination);
+                Err(e)
+            }
+        })
+    } else {
+        fs::rename(&staging, destination)
+    };
+    if swapped.is_err() {
+        let _ = match mode {
+            TransferMode::Copy => remove_entry(&staging),
+            TransferMode::Move => fs::rename(&staging, source),
+        };
+    }
+    swapped
+}
+
+/// A hidden name next to `path` for an entry that is on its way in or out.
+fn sibling_path(path: &Path, purpose: &str) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".filepilot-{}", purpose));
+    path.with_file_name(name)
+}
+
+fn transfer_new(source: &Path, destination: &Path, mode: TransferMode, reporter: &mut Reporter) -> io::Result<()> {
+    match mode {
+        TransferMode::Copy => {
+            let result = copy_path(source, destination, reporter);
+            if reporter.is_cancelled() {
+                // Nothing was here before, so a cancelled copy leaves no trace
+                let _ = remove_entry(destination);
+            }
+            result
+        }
+        TransferMode::Move => move_path_with(source, destination, |from, to| fs::rename(from, to), reporter),
+    }
+}
+
+fn merge_directories(
+    source: &Path,
+    destination: &Path,
+    mode: TransferMode,
+    policy: ConflictPolicy,
+    reporter: &mut Reporter,
+) -> io::Result<()> {
+    let mut failures = Vec::new();
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let path = entry.path();
+        if let Err(e) = transfer(&path, &destination.join(entry.file_name()), mode, policy, true, reporter) {
+            reporter.check_cancelled()?;
+            failures.push((path, e));
+        }
+    }
+    if !failures.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} of the entries could not be pasted: {}",
+            failures.len(),
+            describe_failures(&failures)
+        )));
+    }
+
+    if mode == TransferMode::Move {
+        // Skipped entries stay behind, so only a source that was fully moved goes away
+        let _ = fs::remove_dir(source);
+    }
+    Ok(())
+}
+
+/// Recursively copies `from` to `to`, keeping modification times. Every
+/// entry is attempted; failures are reported together at the end.
+pub fn copy_path(from: &Path, to: &Path, reporter: &mut Reporter) -> io::Result<()> {
+    let mut failures = Vec::new();
+    copy_preserving_times(from, to, &mut failures, reporter);
+    reporter.check_cancelled()?;
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} of the entries could not be copied: {}",
+            failures.len(),
+            describe_failures(&failures)
+        )))
+    }
+}
+
+/// Moves `from` to `to`. When they are on different filesystems the entry
+/// is copied (keeping modification times) and the source is removed only
+/// after everything was copied; on failure the source is left untouched.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    move_path_with(from, to, |from, to| fs::rename(from, to), &mut Reporter::default())
+}
+
+fn move_path_with(
+    from: &Path,
+    to: &Path,
+    rename: impl Fn(&Path, &Path) -> io::Result<()>,
+    reporter: &mut Reporter,
+) -> io::Result<()> {
+    match rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => copy_then_remove(from, to, reporter),
+        result => result,
+    }
+}
+
+fn copy_then_remove(from: &Path, to: &Path, reporter: &mut Reporter) -> io::Result<()> {
+    // Moves aren't sized up front since they are usually a cheap rename
+    if reporter.bytes_total == 0 {
+        reporter.bytes_total = total_size(from);
+    }
+    let mut failures = Vec::new();
+    copy_preserving_times(from, to, &mut failures, reporter);
+
+    if reporter.is_cancelled() || !failures.is_empty() {
+        // Don't leave a half-copied tree next to the intact source
+        let _ = remove_entry(to);
+        reporter.check_cancelled()?;
+        return Err(io::Error::other(format!(
+            "{} of the entries could not be copied, source left in place: {}",
+            failures.len(),
+            describe_failures(&failures)
+        )));
+    }
+
+    remove_entry(from)
+}
+
+fn describe_failures(failures: &[(PathBuf, io::Error)]) -> String {
+    failures.iter()
+        .map(|(path, e)| format!("{} ({})", path.display(), e))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Copies `from` to `to`, recording every entry that fails instead of
+/// stopping. Only a cancel request stops the walk early.
+fn copy_preserving_times(
+    from: &Path,
+    to: &Path,
+    failures: &mut Vec<(PathBuf, io::Error)>,
+    reporter: &mut Reporter,
+) {
+    if reporter.is_cancelled() {
+        return;
+    }
+    let metadata = match fs::symlink_metadata(from) {
+        Ok(metadata) => metadata,
+        Err(e) => return failures.push((from.to_path_buf(), e)),
+    };
+
+    if metadata.file_type().is_symlink() {
+        if let Err(e) = copy_symlink(from, to) {
+            failures.push((from.to_path_buf(), e));
+        }
+        return;
+    }
+
+    if metadata.is_dir() {
+        if let Err(e) = fs::create_dir_all(to) {
+            return failures.push((from.to_path_buf(), e));
+        }
+        match fs::read_dir(from) {
+            Ok(entries) => {
+                for entry in entries {
+                    match entry {
+                        Ok(entry) => copy_preserving_times(&entry.path(), &to.join(entry.file_name()), failures, reporter),
+                        Err(e) => failures.push((from.to_path_buf(), e)),
+                    }
+                }
+            }
+            Err(e) => failures.push((from.to_path_buf(), e)),
+        }
+    } else if let Err(e) = copy_file(from, to, reporter) {
+        if !reporter.is_cancelled() {
+            failures.push((from.to_path_buf(), e));
+        }
+        return;
+    }
+
+    // Best effort: directories can't be opened for this on every platform
+    if let Ok(modified) = metadata.modified() {
+        if let Ok(file) = fs::File::options().write(!metadata.is_dir()).read(metadata.is_dir()).open(to) {
+            let _ = file.set_modified(modified);
+        }
+    }
+}
+
+/// Copies a single file in chunks so progress can be reported and a cancel
+/// request noticed mid-file. A partially written destination is removed.
+fn copy_file(from: &Path, to: &Path, reporter: &mut Reporter) -> io::Result<()> {
+    let mut reader = fs::File::open(from)?;
+    let permissions = reader.metadata()?.permissions();
+    let mut writer = fs::File::create(to)?;
+
+    let result = (|| {
+        let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+        loop {
+            reporter.check_cancelled()?;
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            writer.write_all(&buffer[..read])?;
+            reporter.advance(read as u64, from);
+        }
+        writer.set_permissions(permissions)
+    })();
+
+    if result.is_err() {
+        drop(writer);
+        let _ = fs::remove_file(to);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+}
+
+fn remove_entry(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use crate::test_support::TempDir;
+
+    fn cross_device(_: &Path, _: &Path) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::CrossesDevices))
+    }
+
+    #[test]
+    fn test_cross_device_move_copies_and_removes_source() {
+        let source_root = TempDir::new("src");
+        let destination_root = TempDir::new("dst");
+        let source = source_root.join("project");
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::write(source.join("nested/file.txt"), "contents").unwrap();
+
+        let old_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(source.join("nested/file.txt")).unwrap()
+            .set_modified(old_time).unwrap();
+
+        let destination = destination_root.join("project");
+        move_path_with(&source, &destination, cross_device, &mut Reporter::default()).unwrap();
+
+        assert!(!source.exists());
+        let moved = destination.join("nested/file.txt");
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "contents");
+        assert_eq!(fs::metadata(&moved).unwrap().modified().unwrap(), old_time);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_partial_copy_keeps_source() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let source_root = TempDir::new("partial-src");
+        let destination_root = TempDir::new("partial-dst");
+        let source = source_root.join("data");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("readable.txt"), "ok").unwrap();
+        fs::write(source.join("secret.txt"), "no").unwrap();
+        fs::set_permissions(source.join("secret.txt"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Root can read anything, so the failure can't be provoked there
+        if fs::read(source.join("secret.txt")).is_ok() {
+            return;
+        }
+
+        let destination = destination_root.join("data");
+        let err = move_path_with(&source, &destination, cross_device, &mut Reporter::default()).unwrap_err();
+
+        assert!(err.to_string().contains("secret.txt"));
+        assert!(source.join("readable.txt").exists());
+        assert!(source.join("secret.txt").exists());
+        assert!(!destination.exists());
+
+        fs::set_permissions(source.join("secret.txt"), fs::Permissions::from_mode(0o644)).unwrap();
+    }
+
+    #[test]
+    fn test_conflict_policies_for_files() {
+        let source_root = TempDir::new("policy-src");
+        let destination_root = TempDir::new("policy-dst");
+        let source = source_root.join("notes.txt");
+        let destination = destination_root.join("notes.txt");
+        fs::write(&source, "new").unwrap();
+        fs::write(&destination, "old").unwrap();
+
+        let skipped = transfer(&source, &destination, TransferMode::Copy, ConflictPolicy::Skip, false, &mut Reporter::default()).unwrap();
+        assert_eq!(skipped, None);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+
+        let kept = transfer(&source, &destination, TransferMode::Copy, ConflictPolicy::KeepBoth, false, &mut Reporter::default()).unwrap();
+        assert_eq!(kept, Some(destination_root.join("notes (1).txt")));
+        assert_eq!(fs::read_to_string(destination_root.join("notes (1).txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+
+        transfer(&source, &destination, TransferMode::Move, ConflictPolicy::Overwrite, false, &mut Reporter::default()).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+        assert!(!source.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_overwrite_replaces_symlink_and_refuses_same_file() {
+        let source_root = TempDir::new("link-src");
+        let destination_root = TempDir::new("link-dst");
+        let source = source_root.join("notes.txt");
+        fs::write(&source, "new").unwrap();
+        let target = destination_root.join("target.txt");
+        fs::write(&target, "untouched").unwrap();
+        let link = destination_root.join("notes.txt");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // The link is replaced, not written through
+        transfer(&source, &link, TransferMode::Copy, ConflictPolicy::Overwrite, false, &mut Reporter::default()).unwrap();
+        assert!(!fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&link).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "untouched");
+
+        // Reached through a symlinked directory, the source is its own destination
+        let shortcut = destination_root.join("shortcut");
+        std::os::unix::fs::symlink(&source_root, &shortcut).unwrap();
+        assert!(is_same_entry(&source, &shortcut.join("notes.txt")));
+        let err = transfer(&source, &shortcut.join("notes.txt"), TransferMode::Copy, ConflictPolicy::Overwrite, false, &mut Reporter::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&source).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_overwrite_refuses_directory_containing_source() {
+        let dir = TempDir::new("transfer-ancestor");
+        let destination = dir.join("notes");
+        fs::create_dir(&destination).unwrap();
+        let source = destination.join("notes");
+        fs::write(&source, "keep me").unwrap();
+
+        // Replacing the directory would delete the file being copied
+        let err = transfer(&source, &destination, TransferMode::Copy, ConflictPolicy::Overwrite, false, &mut Reporter::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&source).unwrap(), "keep me");
+
+        // Also when the directory is reached through a symlink
+        let shortcut = dir.join("shortcut");
+        std::os::unix::fs::symlink(&dir, &shortcut).unwrap();
+        let err = transfer(&source, &shortcut.join("notes"), TransferMode::Copy, ConflictPolicy::Overwrite, false, &mut Reporter::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&source).unwrap(), "keep me");
+    }
+
+    #[test]
+    fn test_directory_merge_applies_policy_inside() {
+        let source_root = TempDir::new("merge-src");
+        let destination_root = TempDir::new("merge-dst");
+        let source = source_root.join("site");
+        let destination = destination_root.join("site");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&destination).unwrap();
+        fs::write(source.join("index.html"), "new").unwrap();
+        fs::write(source.join("style.css"), "added").unwrap();
+        fs::write(destination.join("index.html"), "old").unwrap();
+
+        // Without merging, keeping both renames the whole directory
+        let kept = transfer(&source, &destination, TransferMode::Copy, ConflictPolicy::KeepBoth, false, &mut Reporter::default()).unwrap();
+        assert_eq!(kept, Some(destination_root.join("site (1)")));
+
+        transfer(&source, &destination, TransferMode::Move, ConflictPolicy::Skip, true, &mut Reporter::default()).unwrap();
+        assert_eq!(fs::read_to_string(destination.join("index.html")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(destination.join("style.css")).unwrap(), "added");
+        // The skipped file keeps the source directory alive
+        assert!(source.join("index.html").exists());
+        assert!(!source.join("style.css").exists());
+    }
+
+    #[test]
+    fn test_cancelled_copy_removes_partial_destination() {
+        let source_root = TempDir::new("cancel-src");
+        let destination_root = TempDir::new("cancel-dst");
+        let source = source_root.join("big.bin");
+        fs::write(&source, vec![7u8; COPY_CHUNK_SIZE * 3]).unwrap();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (events, updates) = mpsc::channel();
+        let mut reporter = Reporter::new(total_size(&source), cancel.clone(), events);
+        cancel.store(true, Ordering::Relaxed);
+
+        let destination = destination_root.join("big.bin");
+        let err = transfer(&source, &destination, TransferMode::Copy, ConflictPolicy::Skip, false, &mut reporter)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(!destination.exists());
+        assert!(source.exists());
+        drop(reporter);
+        assert!(updates.try_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_cancelled_overwrite_keeps_existing_file() {
+        let source_root = TempDir::new("cancel-overwrite-src");
+        let destination_root = TempDir::new("cancel-overwrite-dst");
+        let source = source_root.join("big.bin");
+        fs::write(&source, vec![7u8; COPY_CHUNK_SIZE * 3]).unwrap();
+        let destination = destination_root.join("big.bin");
+        fs::write(&destination, "old").unwrap();
+
+        let cancel = Arc::new(AtomicBool::new(true));
+        let (events, _updates) = mpsc::channel();
+        let mut reporter = Reporter::new(total_size(&source), cancel, events);
+        let err = transfer(&source, &destination, TransferMode::Copy, ConflictPolicy::Overwrite, false, &mut reporter)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+        // Nothing half-copied is left next to it
+        assert_eq!(fs::read_dir(&destination_root).unwrap().count(), 1);
+
+        // A directory replacing a file is swapped in whole
+        let folder = source_root.join("folder");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("inner.txt"), "inside").unwrap();
+        fs::write(destination_root.join("folder"), "a file").unwrap();
+        transfer(&folder, &destination_root.join("folder"), TransferMode::Copy, ConflictPolicy::Overwrite, false, &mut Reporter::default())
+            .unwrap();
+        assert_eq!(fs::read_to_string(destination_root.join("folder/inner.txt")).unwrap(), "inside");
+        assert_eq!(fs::read_dir(&destination_root).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_copy_reports_progress() {
+        let source_root = TempDir::new("progress-src");
+        let destination_root = TempDir::new("progress-dst");
+        let source = source_root.join("data.bin");
+        fs::write(&source, vec![1u8; COPY_CHUNK_SIZE + 10]).unwrap();
+
+        let (events, updates) = mpsc::channel();
+        let mut reporter = Reporter::new(total_size(&source), Arc::default(), events);
+        transfer(&source, &destination_root.join("data.bin"), TransferMode::Copy, ConflictPolicy::Skip, false, &mut reporter)
+            .unwrap();
+        drop(reporter);
+
+        // Throttling may drop later updates, but the first chunk is always reported
+        match updates.recv().unwrap() {
+            TransferEvent::Progress(progress) => {
+                assert_eq!(progress.bytes_done, COPY_CHUNK_SIZE as u64);
+                assert_eq!(progress.bytes_total, COPY_CHUNK_SIZE as u64 + 10);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_other_rename_errors_are_returned() {
+        let dir = TempDir::new("other-error");
+        let result = move_path_with(&dir.join("a"), &dir.join("b"), |_, _| {
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        }, &mut Reporter::default());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+}
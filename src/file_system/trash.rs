@@ -0,0 +1,251 @@
+//! Platform trash (recycle bin) support.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Location of the current user's trash, where it is a plain directory.
+/// Windows keeps its recycle bin per drive behind a shell API, so `None` there.
+pub fn trash_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "macos") {
+        std::env::var("HOME").ok().map(|home| PathBuf::from(home).join(".Trash"))
+    } else if cfg!(windows) {
+        None
+    } else {
+        // XDG trash spec: $XDG_DATA_HOME/Trash, defaulting to ~/.local/share/Trash
+        let data_home = std::env::var("XDG_DATA_HOME").ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var("HOME").ok().map(|home| PathBuf::from(home).join(".local").join("share")))?;
+        Some(data_home.join("Trash"))
+    }
+}
+
+/// Total size in bytes of everything in the trash, if it can be determined.
+pub fn trash_size() -> Option<u64> {
+    let dir = trash_dir()?;
+    let files_dir = if cfg!(target_os = "macos") { dir } else { dir.join("files") };
+    if !files_dir.exists() {
+        return Some(0);
+    }
+
+    let total = WalkDir::new(&files_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    Some(total)
+}
+
+/// Permanently removes everything in the trash.
+pub fn empty_trash() -> io::Result<()> {
+    if cfg!(windows) {
+        return run_command(
+            "powershell",
+            &["-NoProfile", "-Command", "Clear-RecycleBin -Force -ErrorAction Stop"],
+        );
+    }
+
+    let dir = trash_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No trash directory found"))?;
+
+    if cfg!(target_os = "macos") {
+        // ~/.Trash is often protected by privacy controls; let Finder do it then
+        return match clear_directory(&dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                run_command("osascript", &["-e", "tell application \"Finder\" to empty trash"])
+            }
+            Err(e) => Err(e),
+        };
+    }
+
+    for sub_dir in ["files", "info", "expunged"] {
+        clear_directory(&dir.join(sub_dir))?;
+    }
+    let directory_sizes = dir.join("directorysizes");
+    if directory_sizes.exists() {
+        fs::remove_file(directory_sizes)?;
+    }
+    Ok(())
+}
+
+/// Moves `path` into the platform trash. The native mechanism is tried
+/// first; if it fails the entry is moved into the trash directory directly.
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    let path = std::path::absolute(path)?;
+
+    if cfg!(windows) {
+        // The recycle bin is only reachable through the shell, so there is no
+        // file-level fallback on Windows
+        let method = if path.is_dir() { "DeleteDirectory" } else { "DeleteFile" };
+        let script = format!(
+            "Add-Type -AssemblyName Microsoft.VisualBasic; \
+             [Microsoft.VisualBasic.FileIO.FileSystem]::{}('{}', 'OnlyErrorDialogs', 'SendToRecycleBin')",
+            method,
+            path.to_string_lossy().replace('\'', "''"),
+        );
+        return run_command("powershell", &["-NoProfile", "-Command", &script]);
+    }
+
+    if cfg!(target_os = "macos") {
+        // Finder records the original location so "Put Back" works
+        let script = format!(
+            "tell application \"Finder\" to delete POSIX file \"{}\"",
+            path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\""),
+        );
+        if run_command("osascript", &["-e", &script]).is_ok() {
+            return Ok(());
+        }
+        let dir = trash_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No trash directory found"))?;
+        fs::create_dir_all(&dir)?;
+        let (destination, _) = unique_trash_name(&dir, None, &path)?;
+        return super::transfer::move_path(&path, &destination);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        move_to_xdg_trash(&path)
+    }
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Moving to the trash isn't supported on this platform"))
+    }
+}
+
+/// Implements the home trash of the freedesktop.org trash specification:
+/// the entry goes to `Trash/files` and a matching `.trashinfo` file in
+/// `Trash/info` remembers where it came from.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn move_to_xdg_trash(path: &Path) -> io::Result<()> {
+    let dir = trash_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No trash directory found"))?;
+    let files_dir = dir.join("files");
+    let info_dir = dir.join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    let (destination, info_path) = unique_trash_name(&files_dir, Some(&info_dir), path)?;
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(path),
+        deletion_date(super::local_date_time(std::time::SystemTime::now())),
+    );
+    let info_path = info_path.expect("info directory was given");
+    fs::write(&info_path, info)?;
+
+    if let Err(e) = super::transfer::move_path(path, &destination) {
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Picks `name`, `name.2`, `name.3`, ... so nothing already in the trash is
+/// overwritten. With an info directory the `.trashinfo` file is created
+/// right away to reserve the name.
+fn unique_trash_name(files_dir: &Path, info_dir: Option<&Path>, path: &Path) -> io::Result<(PathBuf, Option<PathBuf>)> {
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Cannot trash this path"))?;
+
+    for counter in 1u32.. {
+        let mut candidate = name.to_os_string();
+        if counter > 1 {
+            candidate.push(format!(".{}", counter));
+        }
+        let destination = files_dir.join(&candidate);
+        if destination.symlink_metadata().is_ok() {
+            continue;
+        }
+
+        let Some(info_dir) = info_dir else {
+            return Ok((destination, None));
+        };
+        let mut info_name = candidate;
+        info_name.push(".trashinfo");
+        let info_path = info_dir.join(info_name);
+        match fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(_) => return Ok((destination, Some(info_path))),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of trash names")
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn percent_encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    // The spec stores raw bytes, so names that aren't UTF-8 survive a restore
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// `YYYY-MM-DDThh:mm:ss` as the trash spec expects, from a local
+/// calendar date and time.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn deletion_date((year, month, day, hour, minute, second): (i64, u32, u32, u32, u32, u32)) -> String {
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, hour, minute, second)
+}
+
+fn clear_directory(dir: &PathBuf) -> io::Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_command(program: &str, args: &[&str]) -> io::Result<()> {
+    // Capture output so nothing is printed over the TUI
+    let output = std::process::Command::new(program).args(args).output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(io::Error::other(format!("{} exited with {}: {}", program, output.status, stderr.trim())))
+    }
+}
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use super::*;
+    use crate::file_system::{local_date_time, utc_date_time};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_deletion_date() {
+        assert_eq!(deletion_date(utc_date_time(SystemTime::UNIX_EPOCH)), "1970-01-01T00:00:00");
+        let leap_day = SystemTime::UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(deletion_date(utc_date_time(leap_day)), "2000-02-29T12:34:56");
+
+        // Local time is UTC shifted by whole quarter hours, whatever the zone
+        let (_, _, _, utc_hour, utc_minute, utc_second) = utc_date_time(leap_day);
+        let (_, _, _, hour, minute, second) = local_date_time(leap_day);
+        assert_eq!(second, utc_second);
+        assert_eq!((hour * 60 + minute) % 15, (utc_hour * 60 + utc_minute) % 15);
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(percent_encode(Path::new("/home/me/a file#1.txt")), "/home/me/a%20file%231.txt");
+        use std::os::unix::ffi::OsStrExt;
+        let latin1 = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/caf\xe9.txt"));
+        assert_eq!(percent_encode(latin1), "/tmp/caf%E9.txt");
+    }
+}
@@ -0,0 +1,86 @@
+//! Change notifications for the directory being browsed.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// Bursts of events (e.g. an extracting archive) cause at most one refresh per this interval
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches a single directory (non-recursively) and reports when its
+/// listing may have changed.
+pub struct DirectoryWatcher {
+    watcher: Option<RecommendedWatcher>,
+    events: Option<Receiver<notify::Result<Event>>>,
+    watched_path: Option<PathBuf>,
+    pending: bool,
+    last_change: Option<Instant>,
+}
+
+impl DirectoryWatcher {
+    pub fn new() -> Self {
+        DirectoryWatcher {
+            watcher: None,
+            events: None,
+            watched_path: None,
+            pending: false,
+            last_change: None,
+        }
+    }
+
+    /// Points the watcher at `path`, tearing down the previous watch. Does
+    /// nothing when `path` is already being watched.
+    pub fn watch(&mut self, path: &Path) -> notify::Result<()> {
+        if self.watched_path.as_deref() == Some(path) {
+            return Ok(());
+        }
+
+        // Drop the old watcher first so its thread stops sending
+        self.watcher = None;
+        self.events = None;
+        self.pending = false;
+        self.watched_path = Some(path.to_path_buf());
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(path, RecursiveMode::NonRecursive)?;
+
+        self.watcher = Some(watcher);
+        self.events = Some(receiver);
+        Ok(())
+    }
+
+    /// Drains queued events and returns true when the listing should be
+    /// refreshed, no more often than every `DEBOUNCE_INTERVAL`.
+    pub fn poll_changed(&mut self) -> bool {
+        if let Some(events) = &self.events {
+            while let Ok(event) = events.try_recv() {
+                match event {
+                    Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+                    // Errors usually mean events were lost, so refresh to be safe
+                    _ => self.pending = true,
+                }
+            }
+        }
+
+        if !self.pending {
+            return false;
+        }
+        let now = Instant::now();
+        if self.last_change.is_some_and(|last| now.duration_since(last) < DEBOUNCE_INTERVAL) {
+            return false;
+        }
+        self.pending = false;
+        self.last_change = Some(now);
+        true
+    }
+}
+
+impl Default for DirectoryWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -6,11 +6,17 @@ mod search;
 mod ui;
 mod file_sharing;
 mod config;
+mod session;
+#[cfg(test)]
+mod test_support;
 
 use file_system::FileExplorer;
 use search::SearchEngine;
+use search::export::{self, ExportFormat};
 use ui::run_ui;
 use config::Config;
+use file_sharing::tls;
+use session::{LastSession, SESSION_FILE};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -33,6 +39,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("PATTERN")
                 .help("Search pattern"),
         )
+        .arg(
+            Arg::new("recent")
+                .long("recent")
+                .value_name("HOURS")
+                .num_args(0..=1)
+                .value_parser(clap::value_parser!(u64))
+                .help("List the files modified in the last HOURS, newest first (search.recent_hours by default)"),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .action(clap::ArgAction::Append)
+                .help("Skip entries matching GLOB when searching, on top of search_exclude (repeatable)"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(clap::ArgAction::SetTrue)
+                .help("With --search, report each entry search_exclude skips on stderr"),
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .value_name("FORMAT")
+                .value_parser(["plain", "csv", "json"])
+                .default_value("plain")
+                .help("With --search, print results as plain paths, CSV or JSON"),
+        )
         .arg(
             Arg::new("config")
                 .short('c')
@@ -40,6 +76,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("CONFIG_FILE")
                 .help("Path to configuration file"),
         )
+        .arg(
+            Arg::new("read-only")
+                .long("read-only")
+                .action(clap::ArgAction::SetTrue)
+                .help("Start in read-only mode: no cut, paste, delete, rename or new entries"),
+        )
+        .arg(
+            Arg::new("bind")
+                .long("bind")
+                .value_name("ADDR")
+                .help("Serve shares on this IP only, e.g. 127.0.0.1 (overrides file_sharing.bind_address)"),
+        )
+        .arg(
+            Arg::new("port")
+                .long("port")
+                .value_name("PORT|START-END")
+                .help("Serve shares on this port, or the first free one in the range"),
+        )
+        .arg(
+            Arg::new("generate-cert")
+                .long("generate-cert")
+                .action(clap::ArgAction::SetTrue)
+                .help("Create a self-signed certificate for serving shares over HTTPS and exit"),
+        )
+        .arg(
+            Arg::new("show-config-path")
+                .long("show-config-path")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print the path of the configuration file that would be loaded and exit"),
+        )
         .arg(
             Arg::new("create-config")
                 .long("create-config")
@@ -49,37 +115,37 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .get_matches();
 
     let start_path = PathBuf::from(matches.get_one::<String>("path").unwrap());
+    let path_given = matches.value_source("path") == Some(clap::parser::ValueSource::CommandLine);
     let search_pattern = matches.get_one::<String>("search");
+    // Given without a value it takes the window from the config
+    let recent_hours = matches.contains_id("recent").then(|| matches.get_one::<u64>("recent").copied());
+    let cli_search = search_pattern.is_some() || recent_hours.is_some();
     let config_file = matches.get_one::<String>("config");
     let create_config = matches.get_flag("create-config");
+    let generate_cert = matches.get_flag("generate-cert");
+    let show_config_path = matches.get_flag("show-config-path");
+    let read_only = matches.get_flag("read-only");
+    let verbose = matches.get_flag("verbose");
+    // Restricted to the known names by the value parser
+    let output_format = matches.get_one::<String>("output-format")
+        .and_then(|name| ExportFormat::from_name(name))
+        .unwrap_or(ExportFormat::Plain);
 
-    // Smart default path selection for better search performance
-    let smart_start_path = if matches.get_one::<String>("path").unwrap() == "." {
-        // User didn't specify a path, so we're using the default
-        let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let current_str = current_dir.to_string_lossy();
-        
-        // Check if we're in a potentially slow search location
-        if current_str == "/" || 
-           current_str == std::env::var("HOME").unwrap_or_default() ||
-           current_str.starts_with("/System") ||
-           current_str.starts_with("/usr") ||
-           current_str.starts_with("/Library") {
-            // Default to home directory for better performance
-            if let Ok(home) = std::env::var("HOME") {
-                eprintln!("Auto-selected home directory (~) for better search performance.");
-                eprintln!("   Use -p /path to specify a different starting directory.");
-                PathBuf::from(home)
-            } else {
-                current_dir
+    if show_config_path {
+        let config_path = config_file.map(PathBuf::from).or_else(Config::find_config_file);
+        match config_path {
+            Some(path) => {
+                let path = path.canonicalize().unwrap_or(path);
+                println!("{}", path.display());
+            }
+            None => {
+                eprintln!("No configuration file found, defaults are in use.");
+                eprintln!("Run with --create-config to create one.");
+                std::process::exit(1);
             }
-        } else {
-            current_dir
         }
-    } else {
-        // User explicitly specified a path, respect their choice
-        start_path
-    };
+        return Ok(());
+    }
 
     // Handle config creation
     if create_config {
@@ -97,22 +163,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    let explorer = FileExplorer::new(smart_start_path.clone())?;
-    let search_engine = SearchEngine::new();
-    
-    // Warn users about potentially slow search locations
-    if let Some(path_str) = smart_start_path.to_str() {
-        match path_str {
-            "/" => eprintln!("⚠️  Warning: Starting from root directory may cause slow search performance."),
-            path if path == std::env::var("HOME").unwrap_or_default() => {
-                eprintln!("Starting from home directory. Search performance should be good.");
-            }
-            _ => {}
-        }
-    }
-    
     // Load configuration from specified file or use auto-discovery
-    let config = if let Some(config_path) = config_file {
+    let mut config = if let Some(config_path) = config_file {
         match Config::load_from_file(config_path) {
             Ok(config) => {
                 eprintln!("Loaded configuration from: {}", config_path);
@@ -127,13 +179,146 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         Config::load_default()
     };
+    if read_only {
+        config.read_only = true;
+    }
+    if let Some(patterns) = matches.get_many::<String>("exclude") {
+        config.search_exclude.extend(patterns.cloned());
+    }
+    if let Some(address) = matches.get_one::<String>("bind") {
+        config.file_sharing.bind_address = Some(address.clone());
+        if let Err(e) = config.file_sharing.bind_ip() {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(ports) = matches.get_one::<String>("port") {
+        if let Err(e) = config.file_sharing.set_ports(ports) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Make a certificate for the share server in ~/.filepilot, wherever
+    // the config was loaded from
+    if generate_cert {
+        let generated = Config::default().state_file_path(tls::CERT_FILE).and_then(|cert_path| {
+            let key_path = cert_path.with_file_name(tls::KEY_FILE);
+            let fingerprint = tls::generate_self_signed(&cert_path, &key_path, tls::default_hosts(&config.file_sharing))?;
+            Ok((cert_path, key_path, fingerprint))
+        });
+        match generated {
+            Ok((cert_path, key_path, fingerprint)) => {
+                println!("✅ Created a self-signed certificate at: {}", cert_path.display());
+                println!("   Private key: {}", key_path.display());
+                println!("   SHA-256 fingerprint: {}", fingerprint);
+                println!("Serve shares over HTTPS by adding this to the file_sharing settings:");
+                println!(
+                    "  \"tls_cert\": {}, \"tls_key\": {}",
+                    serde_json::to_string(&cert_path)?, serde_json::to_string(&key_path)?
+                );
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to create certificate: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Pick up where the last interactive session left off. A saved
+    // directory that has since gone away is ignored.
+    let last_session = if config.restore_last_dir && !cli_search {
+        config.state_file_path(SESSION_FILE).ok()
+            .and_then(|path| LastSession::load(&path).ok().flatten())
+    } else {
+        None
+    };
+    if let Some(session) = &last_session {
+        config.default_sort = session.sort_mode;
+        config.default_sort_reverse = session.sort_reverse;
+        config.show_hidden = session.show_hidden;
+    }
+    let restored_dir = last_session
+        .map(|session| session.directory)
+        .filter(|dir| !path_given && dir.is_dir());
+
+    // Smart default path selection for better search performance
+    let smart_start_path = if let Some(dir) = restored_dir {
+        dir
+    } else if !path_given {
+        // User didn't specify a path, so we're using the default
+        let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        
+        // Check if we're in a potentially slow search location
+        if search::is_slow_search_root(&current_dir) {
+            // Default to the home base for better performance
+            match config.home_base().filter(|home| home.is_dir()) {
+                Some(home) => {
+                    eprintln!("Auto-selected {} for better search performance.", home.display());
+                    eprintln!("   Use -p /path to specify a different starting directory.");
+                    home
+                }
+                None => current_dir,
+            }
+        } else {
+            current_dir
+        }
+    } else {
+        // User explicitly specified a path, respect their choice
+        start_path
+    };
 
-    if let Some(pattern) = search_pattern {
-        // Command-line search mode
-        match search_engine.search(&explorer.current_path(), pattern).await {
-            Ok(results) => {
-                for result in results {
-                    println!("{}", result.file_info.path.display());
+    let explorer = FileExplorer::new(smart_start_path.clone())?;
+    let mut search_engine = SearchEngine::new();
+    search_engine.pattern_mode = config.default_pattern_mode;
+    search_engine.case_mode = config.search_case;
+    search_engine.exclude = config.search_exclude.clone();
+    search_engine.ignore_file = config.state_file_path(search::GLOBAL_IGNORE_FILE).ok();
+    search_engine.settings = config.search.clone();
+    
+    // Warn users about potentially slow search locations
+    if let Some(path_str) = smart_start_path.to_str() {
+        match path_str {
+            "/" => eprintln!("⚠️  Warning: Starting from root directory may cause slow search performance."),
+            path if Some(PathBuf::from(path)) == config.home_base() => {
+                eprintln!("Starting from home directory. Search performance should be good.");
+            }
+            _ => {}
+        }
+    }
+    
+    if cli_search {
+        // Command-line search mode, the only one where stderr is free for notes
+        search_engine.verbose = verbose;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let matched = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let roots = [explorer.current_path().to_path_buf()];
+        let started = match (recent_hours, search_pattern) {
+            (Some(hours), _) => {
+                let hours = hours.unwrap_or(search_engine.settings.recent_hours);
+                let within = std::time::Duration::from_secs(hours * 60 * 60);
+                search_engine.search_recent(&roots, within, sender, Default::default(), matched.clone())
+            }
+            (None, pattern) => {
+                let pattern = pattern.map(String::as_str).unwrap_or_default();
+                search_engine.search(&roots, pattern, sender, Default::default(), matched.clone())
+            }
+        };
+        match started {
+            Ok(()) => {
+                // Best matches first, once the walk is done
+                let mut duplicates = search::Duplicates::default();
+                let mut results = duplicates.collect(receiver.iter());
+                let directories_first = search_engine.settings.directories_first;
+                results.sort_by(|a, b| search::result_order(a, b, directories_first));
+                if let Err(e) = export::write_results(&results, output_format, std::io::stdout().lock()) {
+                    eprintln!("Failed to write results: {}", e);
+                    std::process::exit(1);
+                }
+                let matched = matched.load(std::sync::atomic::Ordering::Relaxed) - duplicates.merged();
+                if matched > results.len() {
+                    eprintln!("Showing {} of {} matches; raise search.max_results in the config to see more.", results.len(), matched);
                 }
             }
             Err(e) => {
@@ -1,287 +1,1608 @@
 use crate::file_system::FileInfo;
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
-use ignore::WalkBuilder;
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{DirEntry, Match, Walk, WalkBuilder};
 use rayon::prelude::*;
-use regex::Regex;
-use std::path::Path;
-use std::time::Duration;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
 use tokio::task;
-use tokio::time::timeout;
+
+pub mod duplicate_files;
+pub mod export;
+mod filters;
+pub mod index;
+
+use duplicate_files::DuplicateEvent;
+pub use filters::SearchFilters;
+use index::FileIndex;
+
+// Files larger than this are left out of a content search
+const CONTENT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+// Bytes checked for a NUL to tell binary files apart
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+// Longest snippet kept from a matching line, in characters
+const SNIPPET_LEN: usize = 200;
+
+/// The global ignore file, read from next to the config file
+pub const GLOBAL_IGNORE_FILE: &str = "ignore";
+
+// Per-directory ignore files ripgrep reads on top of `.ignore`
+const RG_IGNORE_FILE: &str = ".rgignore";
+
+/// Returns true for roots known to make a full recursive search slow:
+/// the filesystem root, the home directory and the large system trees.
+pub fn is_slow_search_root(path: &Path) -> bool {
+    let path_str = path.to_string_lossy();
+    let home = std::env::var("HOME").unwrap_or_default();
+    path_str == "/"
+        || (!home.is_empty() && path_str == home)
+        || path_str.starts_with("/System")
+        || path_str.starts_with("/usr")
+        || path_str.starts_with("/Library")
+}
 
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub file_info: FileInfo,
     pub score: i64,
     pub match_type: MatchType,
+    /// Where a content search found the pattern
+    pub content_match: Option<ContentMatch>,
+    /// Other paths the same file was found under, e.g. through a symlinked
+    /// directory; see `Duplicates`
+    pub also_found_at: Vec<PathBuf>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MatchType {
     FileName,
     FilePath,
+    Content,
+    /// A file with the same content as others; see `find_duplicates`
+    Duplicate,
+    /// A recently modified file; see `search_recent`
+    Modified,
+}
+
+/// Folds results for the same file, reached under different paths, into
+/// one. Files are told apart by their canonical path, so following
+/// symlinked directories doesn't list a file once per link to it.
+#[derive(Debug, Default)]
+pub struct Duplicates {
+    /// The canonical path of each file seen, to the path it is shown under
+    shown: HashMap<PathBuf, PathBuf>,
+    merged: usize,
+}
+
+impl Duplicates {
+    /// Checks `result` against the `results` kept so far. For a file
+    /// already among them, returns the merged result with the index of the
+    /// one it replaces: the higher scoring path is shown, the other goes to
+    /// `also_found_at`.
+    pub fn merge(&mut self, mut result: SearchResult, results: &[SearchResult]) -> (SearchResult, Option<usize>) {
+        let path = &result.file_info.path;
+        let real = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        let index = self.shown.get(&real)
+            .and_then(|shown| results.iter().position(|kept| kept.file_info.path == *shown));
+        let Some(index) = index else {
+            self.shown.insert(real, path.clone());
+            return (result, None);
+        };
+        let mut kept = results[index].clone();
+        if result.score > kept.score {
+            std::mem::swap(&mut kept, &mut result);
+        }
+        kept.also_found_at.push(result.file_info.path);
+        kept.also_found_at.append(&mut result.also_found_at);
+        self.shown.insert(real, kept.file_info.path.clone());
+        self.merged += 1;
+        (kept, Some(index))
+    }
+
+    /// `results` with the duplicates merged, in the order each file was
+    /// first found.
+    pub fn collect(&mut self, results: impl IntoIterator<Item = SearchResult>) -> Vec<SearchResult> {
+        let mut kept = Vec::new();
+        for result in results {
+            match self.merge(result, &kept) {
+                (result, Some(index)) => kept[index] = result,
+                (result, None) => kept.push(result),
+            }
+        }
+        kept
+    }
+
+    /// Results merged into another so far
+    pub fn merged(&self) -> usize {
+        self.merged
+    }
+}
+
+/// The first line of a file that matched a content search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentMatch {
+    /// 1-based
+    pub line_number: usize,
+    /// The line with surrounding whitespace trimmed, cut to `SNIPPET_LEN`
+    pub snippet: String,
+}
+
+/// How the search text is compared with names and paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternMode {
+    #[default]
+    Fuzzy,
+    Substring,
+    Regex,
+    /// `*.rs` matches names; a pattern with a `/`, like `src/**/*.rs`,
+    /// matches paths below the search root
+    Glob,
+}
+
+impl PatternMode {
+    pub fn next(self) -> Self {
+        match self {
+            PatternMode::Fuzzy => PatternMode::Substring,
+            PatternMode::Substring => PatternMode::Regex,
+            PatternMode::Regex => PatternMode::Glob,
+            PatternMode::Glob => PatternMode::Fuzzy,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PatternMode::Fuzzy => "Fuzzy",
+            PatternMode::Substring => "Substring",
+            PatternMode::Regex => "Regex",
+            PatternMode::Glob => "Glob",
+        }
+    }
+}
+
+/// Whether letter case matters when matching.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMode {
+    /// Case matters only when the pattern has an uppercase letter
+    #[default]
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseMode {
+    pub fn next(self) -> Self {
+        match self {
+            CaseMode::Smart => CaseMode::Sensitive,
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CaseMode::Smart => "smart case",
+            CaseMode::Sensitive => "case sensitive",
+            CaseMode::Insensitive => "ignore case",
+        }
+    }
+
+    pub fn ignores_case(self, pattern: &str) -> bool {
+        match self {
+            CaseMode::Smart => !pattern.chars().any(char::is_uppercase),
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+        }
+    }
+}
+
+/// How deep and how long searches walk and how many results they keep,
+/// from the `search` section of the config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    /// Directory levels below the root walked by the comprehensive and
+    /// content strategies; `null` is unlimited
+    pub max_depth_comprehensive: Option<usize>,
+    /// Directory levels below the root walked by the fast strategy
+    pub max_depth_fast: Option<usize>,
+    /// Results kept by the comprehensive, content and indexed strategies;
+    /// matches past it are counted but not shown
+    pub max_results: usize,
+    /// Seconds a walking search may run before it is stopped with the
+    /// results found so far
+    pub timeout_secs: u64,
+    /// The same for the fast strategy
+    pub fast_timeout_secs: u64,
+    /// Worker threads walking searches run on; `null` is one fewer than
+    /// the number of cores, and at least one
+    pub threads: Option<usize>,
+    /// Milliseconds the background index build pauses after each batch
+    /// of entries, leaving the disk and CPU to other work; 0 never pauses
+    pub index_throttle_ms: u64,
+    /// Show how many threads searches use in the status line
+    pub debug: bool,
+    /// List directory results ahead of files; the results view toggles it
+    pub directories_first: bool,
+    /// Hours back the recently modified view and `--recent` look by default
+    pub recent_hours: u64,
+    pub ranking: RankWeights,
+    #[serde(flatten)]
+    pub walk: WalkOptions,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        SearchSettings {
+            max_depth_comprehensive: Some(8),
+            max_depth_fast: Some(4),
+            max_results: 1000,
+            timeout_secs: 30,
+            fast_timeout_secs: 10,
+            threads: None,
+            index_throttle_ms: 0,
+            debug: false,
+            directories_first: false,
+            recent_hours: 24,
+            ranking: RankWeights::default(),
+            walk: WalkOptions::default(),
+        }
+    }
+}
+
+/// Which entries the walking strategies visit, on top of the exclude
+/// patterns. The config sets the defaults and search mode toggles them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalkOptions {
+    /// Walk dotfiles and dot-directories
+    pub include_hidden: bool,
+    /// Skip what `.gitignore`, `.git/info/exclude` and the global excludes
+    /// file ignore, inside git repositories
+    pub respect_gitignore: bool,
+    /// Walk into symlinked directories; loops are detected and skipped
+    pub follow_links: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions { include_hidden: true, respect_gitignore: true, follow_links: false }
+    }
+}
+
+/// One of the `WalkOptions`, for the search mode keys that flip them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkToggle {
+    Hidden,
+    Gitignore,
+    FollowLinks,
+}
+
+impl WalkOptions {
+    /// Flips `toggle` and describes its new state.
+    pub fn toggle(&mut self, toggle: WalkToggle) -> &'static str {
+        match toggle {
+            WalkToggle::Hidden => {
+                self.include_hidden = !self.include_hidden;
+                if self.include_hidden { "Hidden files: searched" } else { "Hidden files: skipped" }
+            }
+            WalkToggle::Gitignore => {
+                self.respect_gitignore = !self.respect_gitignore;
+                if self.respect_gitignore { "Gitignored files: skipped" } else { "Gitignored files: searched" }
+            }
+            WalkToggle::FollowLinks => {
+                self.follow_links = !self.follow_links;
+                if self.follow_links { "Symlinked directories: followed" } else { "Symlinked directories: not followed" }
+            }
+        }
+    }
+
+    /// The options as flags for the search box title, e.g. `[H✓ G✓ L✗]`
+    pub fn flags(&self) -> String {
+        let mark = |on: bool| if on { '✓' } else { '✗' };
+        format!("[H{} G{} L{}]", mark(self.include_hidden), mark(self.respect_gitignore), mark(self.follow_links))
+    }
+}
+
+/// How `score_result` weighs each part of a match, from `search.ranking`
+/// in the config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankWeights {
+    /// Base score for a match in the name
+    pub name_match: i64,
+    /// Base score for a match only in the path
+    pub path_match: i64,
+    /// Added when the name, with or without its extension, is the search text
+    pub exact_name: i64,
+    /// Taken off per directory level below the search root
+    pub depth_penalty: i64,
+    /// Taken off per character before the match starts in the name, for
+    /// at most `max_position` characters
+    pub position_penalty: i64,
+    pub max_position: usize,
+    /// Added for an entry modified just now, shrinking to nothing for one
+    /// `recent_days` old
+    pub recent_bonus: i64,
+    pub recent_days: u64,
+    /// Added for a directory, to rank directories above the files in them
+    pub directory_bonus: i64,
+}
+
+impl Default for RankWeights {
+    fn default() -> Self {
+        RankWeights {
+            name_match: 100,
+            path_match: 40,
+            exact_name: 200,
+            depth_penalty: 10,
+            position_penalty: 2,
+            max_position: 20,
+            recent_bonus: 30,
+            recent_days: 30,
+            directory_bonus: 0,
+        }
+    }
+}
+
+/// What matching found in one entry, before `score_result` ranks it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Found {
+    match_type: MatchType,
+    /// The fuzzy matcher's own score; 0 in the other pattern modes
+    fuzzy: i64,
+    /// The name, with or without its extension, is the search text
+    exact: bool,
+    /// Characters into the name where the match starts
+    position: usize,
+}
+
+impl Found {
+    fn content() -> Self {
+        Found { match_type: MatchType::Content, fuzzy: 0, exact: false, position: 0 }
+    }
+}
+
+/// Ranks a match: the base score for where it was found plus the fuzzy
+/// score and exact-name bonus, less penalties for each directory level
+/// below the root (`depth`) and for a match late in the name, plus bonuses
+/// for recently modified entries and for directories.
+fn score_result(found: &Found, depth: usize, is_directory: bool, modified: Option<SystemTime>, weights: &RankWeights) -> i64 {
+    let base = match found.match_type {
+        MatchType::FileName => weights.name_match,
+        MatchType::FilePath => weights.path_match,
+        MatchType::Content | MatchType::Duplicate | MatchType::Modified => 0,
+    };
+    let exact = if found.exact { weights.exact_name } else { 0 };
+    let depth = weights.depth_penalty * depth as i64;
+    let position = weights.position_penalty * found.position.min(weights.max_position) as i64;
+
+    let window = weights.recent_days * 24 * 60 * 60;
+    let age = modified
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .map(|age| age.as_secs());
+    let recent = match age {
+        Some(age) if age < window => weights.recent_bonus * (window - age) as i64 / window as i64,
+        _ => 0,
+    };
+
+    let directory = if is_directory { weights.directory_bonus } else { 0 };
+
+    base + found.fuzzy + exact - depth - position + recent + directory
+}
+
+/// The order results are listed in: best score first, and with
+/// `directories_first` all directories ahead of the files, best first
+/// within each group. Equal scores go by path.
+pub fn result_order(a: &SearchResult, b: &SearchResult, directories_first: bool) -> std::cmp::Ordering {
+    let group = if directories_first {
+        b.file_info.is_directory.cmp(&a.file_info.is_directory)
+    } else {
+        std::cmp::Ordering::Equal
+    };
+    group.then_with(|| b.score.cmp(&a.score)).then_with(|| a.file_info.path.cmp(&b.file_info.path))
+}
+
+/// Whether a query asks for empty entries and nothing else, in which case
+/// every result scores the same and they are listed by path.
+fn ranks_by_path(pattern: &str, filters: &SearchFilters) -> bool {
+    pattern.is_empty() && filters.finds_empty()
+}
+
+/// Directory levels between the search root and an entry at `relative`
+/// below it.
+fn depth_below(relative: &Path) -> usize {
+    relative.components().count().saturating_sub(1)
 }
 
 pub struct SearchEngine {
-    fuzzy_matcher: SkimMatcherV2,
+    /// How patterns are matched; kept for the whole session
+    pub pattern_mode: PatternMode,
+    pub case_mode: CaseMode,
+    pub settings: SearchSettings,
+    /// Gitignore-style globs for entries no strategy looks at, e.g.
+    /// `node_modules` or `build/*.o`
+    pub exclude: Vec<String>,
+    /// A gitignore-style file every walk honors, below all the ignore files
+    /// found in the tree; see `walker`
+    pub ignore_file: Option<PathBuf>,
+    /// Note each excluded entry on stderr, for command-line searches
+    pub verbose: bool,
+    /// Built on first use, with `settings.threads` threads
+    pool: OnceLock<Arc<ThreadPool>>,
 }
 
 impl SearchEngine {
     pub fn new() -> Self {
         SearchEngine {
-            fuzzy_matcher: SkimMatcherV2::default(),
+            pattern_mode: PatternMode::default(),
+            case_mode: CaseMode::default(),
+            settings: SearchSettings::default(),
+            exclude: Vec::new(),
+            ignore_file: None,
+            verbose: false,
+            pool: OnceLock::new(),
         }
     }
 
-    pub async fn search(
-        &self,
-        root_path: &Path,
-        pattern: &str,
-    ) -> Result<Vec<SearchResult>, Box<dyn std::error::Error + Send + Sync>> {
-        // Add timeout protection for search operations
-        let search_future = self.search_internal(root_path, pattern);
-        match timeout(Duration::from_secs(30), search_future).await {
-            Ok(result) => result,
-            Err(_) => Err("Search timed out after 30 seconds. Try a more specific search term or search from a smaller directory.".into()),
-        }
+    /// Threads walking searches run on, from `settings.threads`.
+    pub fn thread_count(&self) -> usize {
+        self.settings.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, |cores| cores.get() - 1)
+        }).max(1)
     }
 
-    async fn search_internal(
-        &self,
-        root_path: &Path,
-        pattern: &str,
-    ) -> Result<Vec<SearchResult>, Box<dyn std::error::Error + Send + Sync>> {
-        let pattern = pattern.to_string();
-        let root_path = root_path.to_path_buf();
+    /// The pool walking searches run on, rather than rayon's global one,
+    /// so a search leaves cores free for everything else.
+    fn pool(&self) -> Result<Arc<ThreadPool>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(pool) = self.pool.get() {
+            return Ok(pool.clone());
+        }
+        let pool = Arc::new(ThreadPoolBuilder::new()
+            .num_threads(self.thread_count())
+            .thread_name(|index| format!("filepilot-search-{}", index))
+            .build()?);
+        Ok(self.pool.get_or_init(|| pool).clone())
+    }
 
-        // Validate search path
-        if !root_path.exists() {
-            return Err(format!("Search path does not exist: {}", root_path.display()).into());
+    /// `exclude` compiled for walks from `root`, or an error naming the
+    /// first invalid pattern.
+    pub fn exclusions(&self, root: &Path) -> Result<Exclusions, String> {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in &self.exclude {
+            builder.add_line(None, pattern)
+                .map_err(|e| format!("Invalid exclude pattern '{}': {}", pattern, e))?;
         }
+        let matcher = builder.build().map_err(|e| e.to_string())?;
+        Ok(Exclusions { matcher, ignore_file: self.ignore_file.clone(), verbose: self.verbose })
+    }
 
-        if !root_path.is_dir() {
-            return Err(format!("Search path is not a directory: {}", root_path.display()).into());
+    /// The distinct `roots` with the exclusions to walk each with. A root
+    /// inside another one is dropped, as its entries are found from the
+    /// outer one, so no entry is matched twice.
+    fn walks(&self, roots: &[PathBuf]) -> Result<Vec<(PathBuf, Exclusions)>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut roots = roots.iter().map(|root| search_root(root)).collect::<Result<Vec<_>, _>>()?;
+        if roots.is_empty() {
+            return Err("No directory to search".into());
+        }
+        // Parents sort before their children
+        roots.sort();
+        roots.dedup();
+        let mut distinct: Vec<PathBuf> = Vec::new();
+        for root in roots {
+            if !distinct.iter().any(|outer| root.starts_with(outer)) {
+                distinct.push(root);
+            }
         }
+        distinct.into_iter()
+            .map(|root| {
+                let exclusions = self.exclusions(&root)?;
+                Ok((root, exclusions))
+            })
+            .collect()
+    }
+
+    /// Walks `roots` on a blocking task, each root concurrently on the
+    /// engine's thread pool, and sends each file whose name or path matches `pattern` as
+    /// soon as it is found, up to
+    /// `settings.max_results`. `matched` counts every match, including
+    /// those past that limit, which aren't sent. The walk stops when
+    /// `cancel` is set or the receiver is dropped; the channel disconnects
+    /// once it is over.
+    ///
+    /// `ext:`, `type:`, `size:`, `mtime:` and `is:` terms anywhere in
+    /// `pattern` become `SearchFilters`, here and in the other strategies; a
+    /// malformed one, or an invalid regex or glob, is an error.
+    /// `is:empty-dir` walks each directory found to see whether anything in
+    /// it is left once the ignore rules and exclusions are applied.
+    pub fn search(
+        &self,
+        roots: &[PathBuf],
+        pattern: &str,
+        sender: Sender<SearchResult>,
+        cancel: Arc<AtomicBool>,
+        matched: Arc<AtomicUsize>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let walks = self.walks(roots)?;
+        let pool = self.pool()?;
+        let (pattern, filters) = SearchFilters::parse(pattern)?;
+        let matcher = NameMatcher::new(&pattern, self.pattern_mode, self.case_mode)?;
+        let settings = self.settings.clone();
+        let by_path = ranks_by_path(&pattern, &filters);
 
         task::spawn_blocking(move || {
-            let fuzzy_matcher = SkimMatcherV2::default();
-            let regex = Regex::new(&pattern).ok();
-            let pattern_lower = pattern.to_lowercase();
-            
-            // Use ignore crate to respect .gitignore files with more conservative settings
-            let walker = WalkBuilder::new(&root_path)
-                .hidden(false)
-                .ignore(true)
-                .git_ignore(true)
-                .max_depth(Some(8)) // Reduced depth for better performance
-                .max_filesize(Some(100 * 1024 * 1024)) // Skip files larger than 100MB
-                .build();
-
-            // Stream processing with parallel search
-            let results: Vec<SearchResult> = walker
-                .par_bridge()
-                .filter_map(|entry| entry.ok())
-                .filter_map(|entry| {
+            // The roots share the result limit, the match count and cancel
+            pool.install(|| walks.into_par_iter().for_each(|(root_path, exclusions)| {
+                let empty_check = filters.empty_dirs.then(|| exclusions.clone());
+                // Use ignore crate to respect .gitignore files with more conservative settings
+                // Skip files larger than 100MB
+                let walker = walker(&root_path, exclusions, Some(100 * 1024 * 1024), settings.walk)
+                    .max_depth(settings.max_depth_comprehensive)
+                    .build();
+
+                let limit = settings.max_results;
+                stream_matches(walker, &filters, limit, &sender, &cancel, &matched, |entry| {
                     let path = entry.path();
-                    
-                    // Quick filename extraction without full FileInfo creation
-                    let filename = path.file_name()?.to_str()?;
-                    let filename_lower = filename.to_lowercase();
-                    let path_str = path.to_string_lossy();
-                    let path_str_lower = path_str.to_lowercase();
-                    
-                    // Fast pre-filtering: skip if no chance of match
-                    let has_substring = filename_lower.contains(&pattern_lower) || 
-                                      path_str_lower.contains(&pattern_lower);
-                    
-                    let has_regex_match = regex.as_ref()
-                        .map(|r| r.is_match(&path_str))
-                        .unwrap_or(false);
-                    
-                    if !has_substring && !has_regex_match {
-                        // Quick fuzzy check on filename only
-                        if fuzzy_matcher.fuzzy_match(filename, &pattern).is_none() {
-                            return None; // Skip this file entirely
-                        }
-                    }
-                    
-                    // Only create FileInfo for potential matches
-                    let file_info = FileInfo::from_path(path).ok()?;
-                    
-                    // Detailed scoring
-                    if let Some(score) = fuzzy_matcher.fuzzy_match(&file_info.name, &pattern) {
-                        return Some(SearchResult {
-                            file_info,
-                            score,
-                            match_type: MatchType::FileName,
-                        });
-                    }
-                    
-                    // Regex match on full path
-                    if let Some(ref regex) = regex {
-                        if regex.is_match(&path_str) {
-                            return Some(SearchResult {
-                                file_info,
-                                score: 50,
-                                match_type: MatchType::FilePath,
-                            });
-                        }
+                    let name = path.file_name()?.to_string_lossy();
+                    let relative = path.strip_prefix(&root_path).unwrap_or(path);
+                    if empty_check.as_ref().is_some_and(|exclusions| is_directory(entry) && !is_empty_dir(path, exclusions, settings.walk)) {
+                        return None;
                     }
-                    
-                    // Substring match on path
-                    if path_str_lower.contains(&pattern_lower) {
-                        // Higher score for filename matches vs path matches
-                        let score = if filename_lower.contains(&pattern_lower) { 40 } else { 30 };
-                        return Some(SearchResult {
-                            file_info,
-                            score,
-                            match_type: MatchType::FilePath,
-                        });
-                    }
-                    
-                    None
-                })
-                .collect();
-
-            // Sort by score (descending) and limit results
-            let mut sorted_results = results;
-            sorted_results.sort_by(|a, b| b.score.cmp(&a.score));
-            sorted_results.truncate(1000); // Limit to top 1000 results
-            
-            Ok(sorted_results)
-        }).await?
+                    Some((matcher.find(&name, path, relative, true)?, depth_below(relative)))
+                }, |entry, (found, depth)| {
+                    // Only create FileInfo for matches that are sent
+                    let file_info = FileInfo::from_path(entry.path()).ok()?;
+                    Some(SearchResult {
+                        score: if by_path { 0 } else { score_result(&found, depth, file_info.is_directory, file_info.modified, &settings.ranking) },
+                        file_info,
+                        match_type: found.match_type,
+                        content_match: None,
+                        also_found_at: Vec::new(),
+                    })
+                });
+            }));
+        });
+        Ok(())
     }
 
+    /// Matches the names of `files`, best first.
     pub fn search_in_files(
         &self,
         files: &[FileInfo],
         pattern: &str,
-    ) -> Vec<SearchResult> {
-        let pattern_lower = pattern.to_lowercase();
+    ) -> Result<Vec<SearchResult>, String> {
+        let (pattern, filters) = SearchFilters::parse(pattern)?;
+        let matcher = NameMatcher::new(&pattern, self.pattern_mode, self.case_mode)?;
+        let exclusions = match files.first().and_then(|file_info| file_info.path.parent()) {
+            Some(dir) => self.exclusions(dir)?,
+            None => return Ok(Vec::new()),
+        };
+        let by_path = ranks_by_path(&pattern, &filters);
         
         // Parallel search in provided files
         let results: Vec<SearchResult> = files
             .par_iter()
+            .filter(|file_info| !exclusions.excludes(&file_info.path, file_info.is_directory))
+            .filter(|file_info| filters.accepts(&file_info.path, file_info.is_directory))
+            .filter(|file_info| {
+                !filters.needs_metadata()
+                    || fs::metadata(&file_info.path).is_ok_and(|metadata| filters.accepts_metadata(&metadata))
+            })
+            .filter(|file_info| {
+                !(filters.empty_dirs && file_info.is_directory)
+                    || is_empty_dir(&file_info.path, &exclusions, self.settings.walk)
+            })
             .filter_map(|file_info| {
-                let filename_lower = file_info.name.to_lowercase();
-                
-                // Quick substring check first
-                if !filename_lower.contains(&pattern_lower) {
-                    if let Some(score) = self.fuzzy_matcher.fuzzy_match(&file_info.name, pattern) {
-                        return Some(SearchResult {
-                            file_info: file_info.clone(),
-                            score,
-                            match_type: MatchType::FileName,
-                        });
-                    }
-                    return None;
-                }
-                
-                // Fuzzy match for substring matches to get better scoring
-                let score = self.fuzzy_matcher
-                    .fuzzy_match(&file_info.name, pattern)
-                    .unwrap_or(25); // Default score for substring matches
-                
+                let relative = Path::new(&file_info.name);
+                let found = matcher.find(&file_info.name, &file_info.path, relative, false)?;
                 Some(SearchResult {
                     file_info: file_info.clone(),
-                    score,
-                    match_type: MatchType::FileName,
+                    score: if by_path { 0 } else { score_result(&found, 0, file_info.is_directory, file_info.modified, &self.settings.ranking) },
+                    match_type: found.match_type,
+                    content_match: None,
+                    also_found_at: Vec::new(),
                 })
             })
             .collect();
 
         // Sort by score (descending)
         let mut sorted_results = results;
-        sorted_results.sort_by(|a, b| b.score.cmp(&a.score));
-        sorted_results
+        sorted_results.sort_by(|a, b| result_order(a, b, false));
+        Ok(sorted_results)
     }
 
-    /// Fast search optimized for interactive use (limits results and depth)
-    pub async fn search_fast(
+    /// Matches the names and paths in `index` that lie within `dir`, best
+    /// first. Filters use the sizes and times recorded in the index, a
+    /// directory counts as empty when no entry lies within it, and
+    /// entries that have since disappeared are left out. Exclusions were
+    /// applied when the index was built. Returns at most
+    /// `settings.max_results` results, with the number of matches.
+    pub fn search_index(
         &self,
-        root_path: &Path,
+        index: &FileIndex,
+        dir: &Path,
         pattern: &str,
-        max_results: usize,
-    ) -> Result<Vec<SearchResult>, Box<dyn std::error::Error + Send + Sync>> {
-        // Add timeout protection for fast search operations  
-        let search_future = self.search_fast_internal(root_path, pattern, max_results);
-        match timeout(Duration::from_secs(10), search_future).await {
-            Ok(result) => result,
-            Err(_) => Err("Fast search timed out after 10 seconds. Try a more specific search term.".into()),
-        }
+    ) -> Result<(Vec<SearchResult>, usize), String> {
+        let (pattern, filters) = SearchFilters::parse(pattern)?;
+        let matcher = NameMatcher::new(&pattern, self.pattern_mode, self.case_mode)?;
+        let by_path = ranks_by_path(&pattern, &filters);
+        let parents: HashSet<&Path> = if filters.empty_dirs {
+            index.entries.iter().filter_map(|entry| entry.path.parent()).collect()
+        } else {
+            HashSet::new()
+        };
+
+        let mut matches: Vec<_> = index.entries
+            .par_iter()
+            .filter(|entry| entry.path.starts_with(dir))
+            .filter(|entry| filters.accepts(&entry.path, entry.is_directory))
+            .filter(|entry| filters.accepts_details(entry.is_directory, entry.size, entry.modified))
+            .filter(|entry| !(filters.empty_dirs && entry.is_directory && parents.contains(entry.path.as_path())))
+            .filter_map(|entry| {
+                let relative = entry.path.strip_prefix(dir).unwrap_or(&entry.path);
+                let found = matcher.find(&entry.name, &entry.path, relative, true)?;
+                let score = if by_path {
+                    0
+                } else {
+                    score_result(&found, depth_below(relative), entry.is_directory, entry.modified, &self.settings.ranking)
+                };
+                Some((score, found.match_type, entry))
+            })
+            .collect();
+        matches.sort_by(|(a, _, a_entry), (b, _, b_entry)| b.cmp(a).then_with(|| a_entry.path.cmp(&b_entry.path)));
+
+        let matched = matches.len();
+        let results = matches.into_iter()
+            .filter_map(|(score, match_type, entry)| Some(SearchResult {
+                file_info: FileInfo::from_path(&entry.path).ok()?,
+                score,
+                match_type,
+                content_match: None,
+                also_found_at: Vec::new(),
+            }))
+            .take(self.settings.max_results)
+            .collect();
+        Ok((results, matched))
     }
 
-    async fn search_fast_internal(
+    /// Fast search optimized for interactive use (limits results and depth,
+    /// and matches names only). Streams like `search`, sending at most
+    /// `max_results`.
+    pub fn search_fast(
         &self,
-        root_path: &Path,
+        roots: &[PathBuf],
         pattern: &str,
         max_results: usize,
-    ) -> Result<Vec<SearchResult>, Box<dyn std::error::Error + Send + Sync>> {
-        let pattern = pattern.to_string();
-        let root_path = root_path.to_path_buf();
+        sender: Sender<SearchResult>,
+        cancel: Arc<AtomicBool>,
+        matched: Arc<AtomicUsize>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let walks = self.walks(roots)?;
+        let pool = self.pool()?;
+        let (pattern, filters) = SearchFilters::parse(pattern)?;
+        let matcher = NameMatcher::new(&pattern, self.pattern_mode, self.case_mode)?;
+        let max_depth = self.settings.max_depth_fast;
+        let weights = self.settings.ranking.clone();
+        let walk = self.settings.walk;
+        let by_path = ranks_by_path(&pattern, &filters);
 
-        // Validate search path
-        if !root_path.exists() {
-            return Err(format!("Search path does not exist: {}", root_path.display()).into());
-        }
+        task::spawn_blocking(move || {
+            pool.install(|| walks.into_par_iter().for_each(|(root_path, exclusions)| {
+                let empty_check = filters.empty_dirs.then(|| exclusions.clone());
+                // Skip files larger than 50MB
+                let walker = walker(&root_path, exclusions, Some(50 * 1024 * 1024), walk)
+                    .max_depth(max_depth)
+                    .build();
 
-        if !root_path.is_dir() {
-            return Err(format!("Search path is not a directory: {}", root_path.display()).into());
-        }
+                stream_matches(walker, &filters, max_results, &sender, &cancel, &matched, |entry| {
+                    let path = entry.path();
+                    let name = path.file_name()?.to_string_lossy();
+                    let relative = path.strip_prefix(&root_path).unwrap_or(path);
+                    if empty_check.as_ref().is_some_and(|exclusions| is_directory(entry) && !is_empty_dir(path, exclusions, walk)) {
+                        return None;
+                    }
+                    Some((matcher.find(&name, path, relative, false)?, depth_below(relative)))
+                }, |entry, (found, depth)| {
+                    let file_info = FileInfo::from_path(entry.path()).ok()?;
+                    Some(SearchResult {
+                        score: if by_path { 0 } else { score_result(&found, depth, file_info.is_directory, file_info.modified, &weights) },
+                        file_info,
+                        match_type: found.match_type,
+                        content_match: None,
+                        also_found_at: Vec::new(),
+                    })
+                });
+            }));
+        });
+        Ok(())
+    }
+
+    /// Finds files under `roots` with a line matching `pattern`: as a
+    /// regex in `PatternMode::Regex`, otherwise as literal text, with case
+    /// handled per `case_mode`. Binary files and files over
+    /// `CONTENT_MAX_FILE_SIZE` are skipped. Streams like `search`.
+    pub fn search_content(
+        &self,
+        roots: &[PathBuf],
+        pattern: &str,
+        sender: Sender<SearchResult>,
+        cancel: Arc<AtomicBool>,
+        matched: Arc<AtomicUsize>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let walks = self.walks(roots)?;
+        let pool = self.pool()?;
+        let (pattern, filters) = SearchFilters::parse(pattern)?;
+        let ignore_case = self.case_mode.ignores_case(&pattern);
+        let matcher = match self.pattern_mode {
+            PatternMode::Regex => build_regex(&pattern, ignore_case)?,
+            _ => build_regex(&regex::escape(&pattern), ignore_case)?,
+        };
+        let settings = self.settings.clone();
 
         task::spawn_blocking(move || {
-            let fuzzy_matcher = SkimMatcherV2::default();
-            let pattern_lower = pattern.to_lowercase();
-            
-            let walker = WalkBuilder::new(&root_path)
-                .hidden(false)
-                .ignore(true)
-                .git_ignore(true)
-                .max_depth(Some(4)) // Very shallow search for speed
-                .max_filesize(Some(50 * 1024 * 1024)) // Skip files larger than 50MB
-                .build();
-
-            let results: Vec<SearchResult> = walker
-                .par_bridge()
-                .filter_map(|entry| entry.ok())
-                .filter_map(|entry| {
-                    let path = entry.path();
-                    let filename = path.file_name()?.to_str()?;
-                    let filename_lower = filename.to_lowercase();
-                    
-                    // Only process files that might match
-                    if filename_lower.contains(&pattern_lower) {
-                        let file_info = FileInfo::from_path(path).ok()?;
-                        let score = fuzzy_matcher
-                            .fuzzy_match(&file_info.name, &pattern)
-                            .unwrap_or(25);
-                        
-                        Some(SearchResult {
-                            file_info,
-                            score,
-                            match_type: MatchType::FileName,
-                        })
-                    } else {
-                        // Try fuzzy match for non-substring matches
-                        if let Some(score) = fuzzy_matcher.fuzzy_match(filename, &pattern) {
-                            let file_info = FileInfo::from_path(path).ok()?;
-                            Some(SearchResult {
-                                file_info,
-                                score,
-                                match_type: MatchType::FileName,
-                            })
-                        } else {
-                            None
-                        }
+            pool.install(|| walks.into_par_iter().for_each(|(root_path, exclusions)| {
+                let walker = walker(&root_path, exclusions, Some(CONTENT_MAX_FILE_SIZE), settings.walk)
+                    .max_depth(settings.max_depth_comprehensive)
+                    .build();
+
+                let limit = settings.max_results;
+                stream_matches(walker, &filters, limit, &sender, &cancel, &matched, |entry| {
+                    if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+                        return None;
                     }
-                })
-                .collect();
-
-            let mut sorted_results = results;
-            sorted_results.sort_by(|a, b| b.score.cmp(&a.score));
-            sorted_results.truncate(max_results); // Limit results after collection
-            
-            Ok(sorted_results)
-        }).await?
+                    first_matching_line(entry.path(), &matcher)
+                }, |entry, content_match| {
+                    let file_info = FileInfo::from_path(entry.path()).ok()?;
+                    let depth = depth_below(entry.path().strip_prefix(&root_path).unwrap_or(entry.path()));
+                    Some(SearchResult {
+                        score: score_result(&Found::content(), depth, false, file_info.modified, &settings.ranking),
+                        file_info,
+                        match_type: MatchType::Content,
+                        content_match: Some(content_match),
+                        also_found_at: Vec::new(),
+                    })
+                });
+            }));
+        });
+        Ok(())
+    }
+
+    /// Walks `roots` like `search`, but sends the files modified within
+    /// `within` of now instead of matching a pattern. The score is the
+    /// modification time in seconds, so the newest sort first. Streams like
+    /// `search`.
+    pub fn search_recent(
+        &self,
+        roots: &[PathBuf],
+        within: Duration,
+        sender: Sender<SearchResult>,
+        cancel: Arc<AtomicBool>,
+        matched: Arc<AtomicUsize>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let walks = self.walks(roots)?;
+        let pool = self.pool()?;
+        let settings = self.settings.clone();
+        let since = SystemTime::now().checked_sub(within).unwrap_or(SystemTime::UNIX_EPOCH);
+
+        task::spawn_blocking(move || {
+            let filters = SearchFilters::default();
+            pool.install(|| walks.into_par_iter().for_each(|(root_path, exclusions)| {
+                let walker = walker(&root_path, exclusions, None, settings.walk)
+                    .max_depth(settings.max_depth_comprehensive)
+                    .build();
+
+                stream_matches(walker, &filters, settings.max_results, &sender, &cancel, &matched, |entry| {
+                    if is_directory(entry) {
+                        return None;
+                    }
+                    let modified = entry.metadata().ok()?.modified().ok()?;
+                    (modified >= since).then_some(modified)
+                }, |entry, modified| {
+                    let seconds = modified.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs());
+                    Some(SearchResult {
+                        file_info: FileInfo::from_path(entry.path()).ok()?,
+                        score: seconds as i64,
+                        match_type: MatchType::Modified,
+                        content_match: None,
+                        also_found_at: Vec::new(),
+                    })
+                });
+            }));
+        });
+        Ok(())
+    }
+
+    /// Looks for files with identical content under `root` on a blocking
+    /// task, walking as deep as the comprehensive strategy and with the
+    /// same exclusions; see `duplicate_files::scan`. Progress and the
+    /// groups found arrive through `sender`; setting `cancel` stops it.
+    pub fn find_duplicates(
+        &self,
+        root: &Path,
+        sender: Sender<DuplicateEvent>,
+        cancel: Arc<AtomicBool>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let root = search_root(root)?;
+        let exclusions = self.exclusions(&root)?;
+        let walk = walker(&root, exclusions, None, self.settings.walk)
+            .max_depth(self.settings.max_depth_comprehensive)
+            .build();
+
+        task::spawn_blocking(move || {
+            let result = duplicate_files::scan(walk, &cancel, |progress| {
+                let _ = sender.send(DuplicateEvent::Progress(progress));
+            });
+            let _ = sender.send(DuplicateEvent::Finished(result));
+        });
+        Ok(())
+    }
+}
+
+/// Text looked for inside names and paths, lowercased up front when case
+/// is ignored.
+struct Needle {
+    text: String,
+    ignore_case: bool,
+}
+
+impl Needle {
+    fn new(text: &str, ignore_case: bool) -> Self {
+        let text = if ignore_case { text.to_lowercase() } else { text.to_string() };
+        Needle { text, ignore_case }
+    }
+
+    fn found_in(&self, haystack: &str) -> bool {
+        self.position_in(haystack).is_some()
+    }
+
+    /// Characters into `haystack` where the text first appears.
+    fn position_in(&self, haystack: &str) -> Option<usize> {
+        let haystack = if self.ignore_case { haystack.to_lowercase() } else { haystack.to_string() };
+        let at = haystack.find(&self.text)?;
+        Some(haystack[..at].chars().count())
+    }
+
+    /// Whether `name`, or `name` without its extension, is the text.
+    fn is_name(&self, name: &str) -> bool {
+        let name = if self.ignore_case { name.to_lowercase() } else { name.to_string() };
+        let stem = Path::new(&name).file_stem().map(|stem| stem.to_string_lossy());
+        name == self.text || stem.is_some_and(|stem| stem == self.text)
+    }
+}
+
+/// The search text prepared for one `PatternMode`.
+enum NameMatcher {
+    Fuzzy { pattern: String, needle: Needle, matcher: Box<SkimMatcherV2> },
+    Substring(Needle),
+    Regex(Regex),
+    Glob { glob: GlobMatcher, whole_path: bool },
+}
+
+impl NameMatcher {
+    fn new(pattern: &str, mode: PatternMode, case: CaseMode) -> Result<Self, String> {
+        // A query of only filters matches everything they let through
+        if pattern.is_empty() {
+            return Ok(NameMatcher::Substring(Needle::new("", false)));
+        }
+        let ignore_case = case.ignores_case(pattern);
+        Ok(match mode {
+            PatternMode::Fuzzy => NameMatcher::Fuzzy {
+                pattern: pattern.to_string(),
+                needle: Needle::new(pattern, ignore_case),
+                matcher: Box::new(if ignore_case {
+                    SkimMatcherV2::default().ignore_case()
+                } else {
+                    SkimMatcherV2::default().respect_case()
+                }),
+            },
+            PatternMode::Substring => NameMatcher::Substring(Needle::new(pattern, ignore_case)),
+            PatternMode::Regex => NameMatcher::Regex(build_regex(pattern, ignore_case)?),
+            PatternMode::Glob => {
+                let glob = GlobBuilder::new(pattern)
+                    .case_insensitive(ignore_case)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                NameMatcher::Glob { glob: glob.compile_matcher(), whole_path: pattern.contains('/') }
+            }
+        })
+    }
+
+    /// Matches an entry by its name and then, when `check_path` is set, by
+    /// its full path. Path globs are always checked against `relative`, the
+    /// path below the search root.
+    fn find(&self, name: &str, path: &Path, relative: &Path, check_path: bool) -> Option<Found> {
+        let by_name = match self {
+            NameMatcher::Fuzzy { pattern, needle, matcher } => matcher.fuzzy_indices(name, pattern)
+                .map(|(score, indices)| (score, indices.first().copied().unwrap_or(0)))
+                .or_else(|| needle.position_in(name).map(|position| (0, position))),
+            NameMatcher::Substring(needle) => needle.position_in(name).map(|position| (0, position)),
+            NameMatcher::Regex(regex) => regex.find(name).map(|found| (0, name[..found.start()].chars().count())),
+            NameMatcher::Glob { glob, whole_path } => (!whole_path && glob.is_match(name)).then_some((0, 0)),
+        };
+        if let Some((fuzzy, position)) = by_name {
+            let exact = match self {
+                NameMatcher::Fuzzy { needle, .. } | NameMatcher::Substring(needle) => needle.is_name(name),
+                _ => false,
+            };
+            return Some(Found { match_type: MatchType::FileName, fuzzy, exact, position });
+        }
+
+        let by_path = match self {
+            NameMatcher::Glob { glob, whole_path: true } => glob.is_match(relative),
+            _ if !check_path => false,
+            NameMatcher::Fuzzy { needle, .. } | NameMatcher::Substring(needle) => {
+                needle.found_in(&path.to_string_lossy())
+            }
+            NameMatcher::Regex(regex) => regex.is_match(&path.to_string_lossy()),
+            NameMatcher::Glob { .. } => false,
+        };
+        by_path.then_some(Found { match_type: MatchType::FilePath, fuzzy: 0, exact: false, position: 0 })
+    }
+}
+
+/// `pattern` compiled as a regex, or the reason it isn't a valid one.
+fn build_regex(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        // Syntax errors span several lines pointing at the problem; the last one says what it is
+        .map_err(|e| format!("Invalid regex '{}': {}", pattern, e.to_string().lines().last().unwrap_or_default()))
+}
+
+/// Compiled exclude patterns, checked against every entry below the root
+/// they were compiled for, and the global ignore file walks read; see
+/// `SearchEngine::exclusions`.
+#[derive(Clone)]
+pub struct Exclusions {
+    matcher: Gitignore,
+    ignore_file: Option<PathBuf>,
+    verbose: bool,
+}
+
+impl Exclusions {
+    /// Whether `path` is excluded, noting why on stderr in verbose mode.
+    pub fn excludes(&self, path: &Path, is_dir: bool) -> bool {
+        match self.matcher.matched(path, is_dir) {
+            Match::Ignore(glob) => {
+                if self.verbose {
+                    eprintln!("Excluded by search_exclude '{}': {}", glob.original(), path.display());
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A walker from `root` with the ignore rules every strategy shares, which
+/// skips excluded entries and files over `max_filesize` and honors `options`.
+/// The root itself is walked even when excluded, so searching from inside
+/// `node_modules` still works.
+///
+/// Ignore files are read in the root, in the directories above it and in
+/// every directory walked. Where their rules disagree about an entry, the
+/// first of these with a matching rule decides:
+///
+/// 1. `.rgignore` files
+/// 2. `.ignore` files
+/// 3. `.gitignore` files, then `.git/info/exclude`
+/// 4. the global git excludes file
+/// 5. the global ignore file next to the config
+///
+/// Among files of one kind the one in the deepest directory wins, so a
+/// `!pattern` in a subdirectory brings back what its parent ignores. The
+/// git files only count inside git repositories and while
+/// `respect_gitignore` is on. `search_exclude` is checked apart from all of
+/// these, so no `!pattern` brings back an excluded entry.
+fn walker(root: &Path, exclusions: Exclusions, max_filesize: Option<u64>, options: WalkOptions) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(!options.include_hidden)
+        .parents(true)
+        .ignore(true)
+        .git_ignore(options.respect_gitignore)
+        .git_exclude(options.respect_gitignore)
+        .git_global(options.respect_gitignore)
+        .follow_links(options.follow_links)
+        .add_custom_ignore_filename(RG_IGNORE_FILE);
+    if let Some(path) = exclusions.ignore_file.as_deref().filter(|path| path.is_file()) {
+        // A bad line leaves the rest of the file in force
+        if let Some(e) = builder.add_ignore(path) {
+            if exclusions.verbose {
+                eprintln!("Problem reading {}: {}", path.display(), e);
+            }
+        }
+    }
+    // Checked here rather than with `WalkBuilder::max_filesize`, which
+    // accepts files before the entry filter gets to see them
+    builder.filter_entry(move |entry| {
+        if entry.depth() == 0 {
+            return true;
+        }
+        let is_dir = is_directory(entry);
+        let too_large = !is_dir && max_filesize.is_some_and(|max| {
+            entry.metadata().is_ok_and(|metadata| metadata.len() > max)
+        });
+        !too_large && !exclusions.excludes(entry.path(), is_dir)
+    });
+    builder
+}
+
+/// Whether walking `dir` with the same rules as a search finds nothing in
+/// it, so a directory holding only ignored or excluded entries is empty.
+fn is_empty_dir(dir: &Path, exclusions: &Exclusions, options: WalkOptions) -> bool {
+    walker(dir, exclusions.clone(), None, options)
+        .max_depth(Some(1))
+        .build()
+        .flatten()
+        .all(|entry| entry.depth() == 0)
+}
+
+/// `root_path` as an owned path, or an error when it isn't a directory.
+fn search_root(root_path: &Path) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    if !root_path.exists() {
+        return Err(format!("Search path does not exist: {}", root_path.display()).into());
+    }
+    if !root_path.is_dir() {
+        return Err(format!("Search path is not a directory: {}", root_path.display()).into());
+    }
+    Ok(root_path.to_path_buf())
+}
+
+/// Counts each walked entry `filters` lets through that `find` matches in
+/// `matched`, and sends what `build` makes of the first `limit` of them.
+/// Stops once `cancel` is set or the receiver is gone.
+#[allow(clippy::too_many_arguments)]
+fn stream_matches<T, F, B>(
+    walker: Walk,
+    filters: &SearchFilters,
+    limit: usize,
+    sender: &Sender<SearchResult>,
+    cancel: &AtomicBool,
+    matched: &AtomicUsize,
+    find: F,
+    build: B,
+) where
+    F: Fn(&DirEntry) -> Option<T> + Send + Sync,
+    B: Fn(&DirEntry, T) -> Option<SearchResult> + Send + Sync,
+{
+    let _ = walker.par_bridge().try_for_each(|entry| {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(());
+        }
+        let Some((entry, found)) = entry.ok()
+            .filter(|entry| filters.accepts(entry.path(), is_directory(entry)))
+            .filter(|entry| {
+                // Only stat entries when a size or time filter asks for it
+                !filters.needs_metadata()
+                    || fs::metadata(entry.path()).is_ok_and(|metadata| filters.accepts_metadata(&metadata))
+            })
+            .and_then(|entry| find(&entry).map(|found| (entry, found)))
+        else {
+            return Ok(());
+        };
+        // Past the limit matches are only counted
+        if matched.fetch_add(1, Ordering::Relaxed) >= limit {
+            return Ok(());
+        }
+        match build(&entry, found) {
+            Some(result) => sender.send(result).map_err(|_| ()),
+            None => Ok(()),
+        }
+    });
+}
+
+/// Whether `entry` is a directory or a symlink to one.
+fn is_directory(entry: &DirEntry) -> bool {
+    match entry.file_type() {
+        Some(file_type) if file_type.is_symlink() => entry.path().is_dir(),
+        Some(file_type) => file_type.is_dir(),
+        None => false,
+    }
+}
+
+/// The first line of the file at `path` that `matcher` matches, or `None`
+/// for binary and unreadable files.
+fn first_matching_line(path: &Path, matcher: &Regex) -> Option<ContentMatch> {
+    let bytes = fs::read(path).ok()?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .enumerate()
+        .find(|(_, line)| matcher.is_match(line))
+        .map(|(index, line)| ContentMatch {
+            line_number: index + 1,
+            snippet: line.trim().chars().take(SNIPPET_LEN).collect(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use crate::test_support::TempDir;
+
+    /// Everything a search started by `start` sends before it finishes.
+    fn collect(
+        start: impl FnOnce(Sender<SearchResult>) -> Result<(), Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Vec<SearchResult> {
+        let (sender, receiver) = mpsc::channel();
+        start(sender).unwrap();
+        receiver.iter().collect()
+    }
+
+    #[tokio::test]
+    async fn test_content_search_finds_first_matching_line() {
+        let dir = TempDir::new("content-search");
+        let roots = [dir.to_path_buf()];
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {\n    let total = add(1, 2);\n    println!(\"{}\", total);\n}\n").unwrap();
+        fs::write(dir.join("notes.txt"), "nothing here\nTOTAL: 3\n").unwrap();
+        fs::write(dir.join("data.bin"), b"total\0\x01\x02").unwrap();
+
+        let engine = SearchEngine::new();
+        let mut results = collect(|sender| engine.search_content(&roots, "total", sender, Arc::default(), Arc::default()));
+        results.sort_by(|a, b| a.file_info.path.cmp(&b.file_info.path));
+        let found: Vec<_> = results.iter()
+            .map(|result| (result.file_info.name.as_str(), result.content_match.clone().unwrap()))
+            .collect();
+        assert_eq!(found, [
+            ("notes.txt", ContentMatch { line_number: 2, snippet: "TOTAL: 3".to_string() }),
+            ("main.rs", ContentMatch { line_number: 2, snippet: "let total = add(1, 2);".to_string() }),
+        ]);
+
+        // Patterns are literal text unless regex mode is on
+        let results = collect(|sender| engine.search_content(&roots, "add(1", sender, Arc::default(), Arc::default()));
+        assert_eq!(results[0].content_match.as_ref().unwrap().line_number, 2);
+        let mut engine = SearchEngine::new();
+        engine.pattern_mode = PatternMode::Regex;
+        let results = collect(|sender| engine.search_content(&roots, r"add\(\d", sender, Arc::default(), Arc::default()));
+        assert_eq!(results.len(), 1);
+        assert!(engine.search_content(&roots, "add(1", mpsc::channel().0, Arc::default(), Arc::default()).is_err());
+    }
+
+    #[test]
+    fn test_pattern_modes() {
+        let score = |pattern: &str, mode: PatternMode, path: &str| {
+            let path = Path::new(path);
+            let name = path.file_name().unwrap().to_string_lossy();
+            let relative = path.strip_prefix("/work").unwrap();
+            NameMatcher::new(pattern, mode, CaseMode::Smart).unwrap().find(&name, path, relative, true).map(|found| found.match_type)
+        };
+
+        assert!(matches!(score("mnrs", PatternMode::Fuzzy, "/work/src/main.rs"), Some(MatchType::FileName)));
+        assert!(score("mnrs", PatternMode::Substring, "/work/src/main.rs").is_none());
+        assert!(matches!(score("c++", PatternMode::Substring, "/work/notes/c++.md"), Some(MatchType::FileName)));
+        assert!(matches!(score("src/", PatternMode::Substring, "/work/src/main.rs"), Some(MatchType::FilePath)));
+        assert!(matches!(score(r"^main\.rs$", PatternMode::Regex, "/work/src/MAIN.rs"), Some(MatchType::FileName)));
+
+        assert!(matches!(score("*.rs", PatternMode::Glob, "/work/src/main.rs"), Some(MatchType::FileName)));
+        assert!(score("*.rs", PatternMode::Glob, "/work/src/main.txt").is_none());
+        assert!(matches!(score("**/*.rs", PatternMode::Glob, "/work/src/deep/lib.rs"), Some(MatchType::FilePath)));
+        assert!(matches!(score("**/*.rs", PatternMode::Glob, "/work/lib.rs"), Some(MatchType::FilePath)));
+        assert!(score("src/*.rs", PatternMode::Glob, "/work/src/deep/lib.rs").is_none());
+
+        let error = NameMatcher::new("foo(", PatternMode::Regex, CaseMode::Smart).err().unwrap();
+        assert_eq!(error, "Invalid regex 'foo(': error: unclosed group");
+        assert!(NameMatcher::new("[a", PatternMode::Glob, CaseMode::Smart).is_err());
+        // Only filters, so anything goes
+        assert!(score("", PatternMode::Glob, "/work/a.txt").is_some());
+    }
+
+    #[test]
+    fn test_ranking_order() {
+        let rank = |pattern: &str, path: &str, modified: Option<SystemTime>, weights: &RankWeights| {
+            let path = Path::new(path);
+            let name = path.file_name().unwrap().to_string_lossy();
+            let relative = path.strip_prefix("/work").unwrap();
+            let found = NameMatcher::new(pattern, PatternMode::Fuzzy, CaseMode::Smart).unwrap()
+                .find(&name, path, relative, true)
+                .unwrap();
+            score_result(&found, depth_below(relative), false, modified, weights)
+        };
+        let weights = RankWeights::default();
+        let (old, now) = (Some(SystemTime::UNIX_EPOCH), Some(SystemTime::now()));
+
+        // Exact names, then other name matches, then path matches
+        assert!(rank("config", "/work/config.rs", old, &weights) > rank("config", "/work/configure.rs", old, &weights));
+        assert!(rank("config", "/work/configure.rs", old, &weights) > rank("config", "/work/config/a.rs", old, &weights));
+        // Shallower and more recent entries first
+        assert!(rank("main", "/work/main.rs", old, &weights) > rank("main", "/work/a/b/main.rs", old, &weights));
+        assert!(rank("main", "/work/main.rs", now, &weights) > rank("main", "/work/main.rs", old, &weights));
+        // The exact name two levels down beats a fresh fuzzy match deep in node_modules
+        assert!(
+            rank("config", "/work/src/app/config.rs", old, &weights)
+                > rank("config", "/work/node_modules/pkg/lib/dist/configure.js", now, &weights)
+        );
+
+        // The same fuzzy score ranks a match at the start of the name higher
+        let at = |position| Found { match_type: MatchType::FileName, fuzzy: 50, exact: false, position };
+        assert!(score_result(&at(0), 0, false, None, &weights) > score_result(&at(5), 0, false, None, &weights));
+        assert_eq!(score_result(&at(40), 0, false, None, &weights), score_result(&at(80), 0, false, None, &weights));
+
+        // Weights come from the config, unset ones keeping their defaults
+        let settings: SearchSettings = serde_json::from_str(r#"{"ranking": {"depth_penalty": 0}}"#).unwrap();
+        assert_eq!(settings.ranking.exact_name, weights.exact_name);
+        assert_eq!(
+            rank("main", "/work/main.rs", old, &settings.ranking),
+            rank("main", "/work/a/b/main.rs", old, &settings.ranking)
+        );
+    }
+
+    #[test]
+    fn test_case_modes() {
+        let matches = |pattern: &str, mode: PatternMode, case: CaseMode, name: &str| {
+            let path = Path::new("/work").join(name);
+            NameMatcher::new(pattern, mode, case).unwrap()
+                .find(name, &path, Path::new(name), false)
+                .is_some()
+        };
+
+        for mode in [PatternMode::Fuzzy, PatternMode::Substring, PatternMode::Regex] {
+            // Smart case ignores case until the pattern has a capital
+            assert!(matches("readme", mode, CaseMode::Smart, "README.md"));
+            assert!(matches("README", mode, CaseMode::Smart, "README.md"));
+            assert!(!matches("Readme", mode, CaseMode::Smart, "README.md"));
+            assert!(!matches("readme", mode, CaseMode::Sensitive, "README.md"));
+            assert!(matches("Readme", mode, CaseMode::Insensitive, "README.md"));
+        }
+        assert!(matches("*.md", PatternMode::Glob, CaseMode::Smart, "README.MD"));
+        assert!(!matches("*.MD", PatternMode::Glob, CaseMode::Smart, "readme.md"));
+    }
+
+    #[tokio::test]
+    async fn test_search_streams_until_limit_or_cancel() {
+        let dir = TempDir::new("stream-search");
+        let roots = [dir.to_path_buf()];
+        fs::create_dir_all(dir.join("deep")).unwrap();
+        for i in 0..5 {
+            fs::write(dir.join(format!("report-{}.txt", i)), "").unwrap();
+        }
+        fs::write(dir.join("deep/report.md"), "").unwrap();
+        fs::write(dir.join("unrelated.bin"), "").unwrap();
+
+        let engine = SearchEngine::new();
+        let results = collect(|sender| engine.search(&roots, "report", sender, Arc::default(), Arc::default()));
+        assert_eq!(results.len(), 6);
+        // Matches past the limit are counted but not sent
+        let matched = Arc::new(AtomicUsize::new(0));
+        let results = collect(|sender| engine.search_fast(&roots, "report", 2, sender, Arc::default(), matched.clone()));
+        assert_eq!((results.len(), matched.load(Ordering::Relaxed)), (2, 6));
+        let mut limited = SearchEngine::new();
+        limited.settings = SearchSettings {
+            max_depth_comprehensive: Some(1),
+            max_depth_fast: None,
+            max_results: 3,
+            ..SearchSettings::default()
+        };
+        let matched = Arc::new(AtomicUsize::new(0));
+        let results = collect(|sender| limited.search(&roots, "report", sender, Arc::default(), matched.clone()));
+        assert_eq!((results.len(), matched.load(Ordering::Relaxed)), (3, 5));
+        let results = collect(|sender| limited.search_fast(&roots, "report.md", 10, sender, Arc::default(), Arc::default()));
+        assert_eq!(results.len(), 1);
+        let results = collect(|sender| engine.search(&roots, "ext:md report", sender, Arc::default(), Arc::default()));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file_info.name, "report.md");
+        let results = collect(|sender| engine.search(&roots, "type:dir deep", sender, Arc::default(), Arc::default()));
+        assert_eq!(results.len(), 1);
+        assert!(results[0].file_info.is_directory);
+        let results = collect(|sender| engine.search(&roots, "report size:<1kb mtime:<1d", sender, Arc::default(), Arc::default()));
+        assert_eq!(results.len(), 6);
+        assert!(collect(|sender| engine.search(&roots, "report size:>1kb", sender, Arc::default(), Arc::default())).is_empty());
+        assert!(engine.search(&roots, "report size:big", mpsc::channel().0, Arc::default(), Arc::default()).is_err());
+
+        let cancelled = Arc::new(AtomicBool::new(true));
+        assert!(collect(|sender| engine.search(&roots, "report", sender, cancelled, Arc::default())).is_empty());
+        assert!(engine.search(&[dir.join("missing")], "report", mpsc::channel().0, Arc::default(), Arc::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_several_roots() {
+        let dir = TempDir::new("roots-search");
+        for sub in ["work/app", "oss/lib", "other"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("work/app/notes.md"), "todo").unwrap();
+        fs::write(dir.join("oss/lib/notes.txt"), "todo").unwrap();
+        fs::write(dir.join("other/notes.rs"), "todo").unwrap();
+
+        let engine = SearchEngine::new();
+        let names = |results: Vec<SearchResult>| {
+            let mut names: Vec<String> = results.into_iter().map(|result| result.file_info.name).collect();
+            names.sort();
+            names
+        };
+        let roots = [dir.join("work"), dir.join("oss")];
+        assert_eq!(names(collect(|sender| engine.search(&roots, "notes", sender, Arc::default(), Arc::default()))), ["notes.md", "notes.txt"]);
+        assert_eq!(names(collect(|sender| engine.search_content(&roots, "todo", sender, Arc::default(), Arc::default()))), ["notes.md", "notes.txt"]);
+
+        // A root inside another, or given twice, doesn't match anything twice
+        let overlapping = [dir.join("work"), dir.join("work/app"), dir.join("oss"), dir.join("oss")];
+        let matched = Arc::new(AtomicUsize::new(0));
+        let results = collect(|sender| engine.search_fast(&overlapping, "notes", 10, sender, Arc::default(), matched.clone()));
+        assert_eq!((names(results), matched.load(Ordering::Relaxed)), (vec!["notes.md".to_string(), "notes.txt".to_string()], 2));
+        // The result limit is shared by every root
+        let results = collect(|sender| engine.search_fast(&roots, "notes", 1, sender, Arc::default(), Arc::default()));
+        assert_eq!(results.len(), 1);
+
+        assert!(engine.search(&[], "notes", mpsc::channel().0, Arc::default(), Arc::default()).is_err());
+        assert!(engine.search(&[dir.join("work"), dir.join("missing")], "notes", mpsc::channel().0, Arc::default(), Arc::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_walk_options() {
+        let dir = TempDir::new("walk-options");
+        let roots = [dir.join("repo")];
+        for sub in ["repo/.git", "repo/.config", "outside"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("repo/.gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join("repo/plan.txt"), "").unwrap();
+        fs::write(dir.join("repo/.config/plan.toml"), "").unwrap();
+        fs::write(dir.join("repo/plan.log"), "").unwrap();
+        fs::write(dir.join("outside/plan.md"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("repo/linked")).unwrap();
+
+        let mut engine = SearchEngine::new();
+        let names = |engine: &SearchEngine| {
+            let results = collect(|sender| engine.search(&roots, "plan", sender, Arc::default(), Arc::default()));
+            let mut names: Vec<String> = results.into_iter().map(|result| result.file_info.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(engine.settings.walk.flags(), "[H✓ G✓ L✗]");
+        assert_eq!(names(&engine), ["plan.toml", "plan.txt"]);
+
+        assert_eq!(engine.settings.walk.toggle(WalkToggle::Hidden), "Hidden files: skipped");
+        assert_eq!(names(&engine), ["plan.txt"]);
+        engine.settings.walk.toggle(WalkToggle::Gitignore);
+        assert_eq!(names(&engine), ["plan.log", "plan.txt"]);
+        engine.settings.walk.toggle(WalkToggle::FollowLinks);
+        assert_eq!(engine.settings.walk.flags(), "[H✗ G✗ L✓]");
+        assert_eq!(names(&engine), ["plan.log", "plan.md", "plan.txt"]);
+
+        // The defaults live in the search section of the config
+        let settings: SearchSettings = serde_json::from_str(r#"{"include_hidden": false, "max_results": 5}"#).unwrap();
+        assert_eq!(settings.walk, WalkOptions { include_hidden: false, ..WalkOptions::default() });
+        assert_eq!(settings.max_results, 5);
+    }
+
+    #[tokio::test]
+    async fn test_symlinked_duplicates() {
+        let dir = TempDir::new("duplicates");
+        let roots = [dir.to_path_buf()];
+        fs::create_dir_all(dir.join("real")).unwrap();
+        fs::create_dir_all(dir.join("deep/er")).unwrap();
+        fs::write(dir.join("real/notes.md"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("deep/er/linked")).unwrap();
+        // A loop back to the root ends the walk down that path
+        std::os::unix::fs::symlink(&dir, dir.join("real/loop")).unwrap();
+
+        let mut engine = SearchEngine::new();
+        engine.settings.walk.follow_links = true;
+        let found = collect(|sender| engine.search(&roots, "notes", sender, Arc::default(), Arc::default()));
+        assert_eq!(found.len(), 2);
+
+        let mut duplicates = Duplicates::default();
+        let results = duplicates.collect(found);
+        assert_eq!(results.len(), 1);
+        assert_eq!(duplicates.merged(), 1);
+        // The shallower path scores higher and is the one shown
+        assert_eq!(results[0].file_info.path, dir.join("real/notes.md"));
+        assert_eq!(results[0].also_found_at, [dir.join("deep/er/linked/notes.md")]);
+    }
+
+    #[tokio::test]
+    async fn test_ignore_files() {
+        let dir = TempDir::new("ignore-files");
+        fs::create_dir_all(dir.join("tree/sub")).unwrap();
+        let files = [
+            ("ignore", "*.bak\n"),
+            ("tree/.ignore", "*.tmp\n*.log\n!keep.bak\n"),
+            ("tree/a.tmp", ""),
+            ("tree/old.bak", ""),
+            ("tree/keep.bak", ""),
+            ("tree/sub/.rgignore", "!*.tmp\nsecret*\n"),
+            ("tree/sub/.ignore", "!keep.log\n"),
+            ("tree/sub/b.tmp", ""),
+            ("tree/sub/secret.txt", ""),
+            ("tree/sub/note.txt", ""),
+            ("tree/sub/keep.log", ""),
+            ("tree/sub/drop.log", ""),
+        ];
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+
+        let mut engine = SearchEngine::new();
+        engine.pattern_mode = PatternMode::Substring;
+        engine.settings.walk.include_hidden = false;
+        engine.ignore_file = Some(dir.join("ignore"));
+        let names = |root: &str| {
+            let roots = [dir.join(root)];
+            let results = collect(|sender| engine.search(&roots, ".", sender, Arc::default(), Arc::default()));
+            let mut names: Vec<String> = results.into_iter().map(|result| result.file_info.name).collect();
+            names.sort();
+            names
+        };
+        // .rgignore beats .ignore, deeper .ignore files beat shallower ones,
+        // and every ignore file in the tree beats the global one
+        assert_eq!(names("tree"), ["b.tmp", "keep.bak", "keep.log", "note.txt"]);
+        // Ignore files above the search root still count
+        assert_eq!(names("tree/sub"), ["b.tmp", "keep.log", "note.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_empty_entries() {
+        let dir = TempDir::new("empty-entries");
+        for sub in ["empty", "nested/inner", "only-ignored", "full/deeper"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join(".ignore"), "*.tmp\n").unwrap();
+        fs::write(dir.join("only-ignored/scratch.tmp"), "data").unwrap();
+        fs::write(dir.join("full/deeper/notes.txt"), "data").unwrap();
+        fs::write(dir.join("full/blank.txt"), "").unwrap();
+        fs::write(dir.join("zero.log"), "").unwrap();
+        // Empty, but ignored itself
+        fs::write(dir.join("skipped.tmp"), "").unwrap();
+
+        let engine = SearchEngine::new();
+        let roots = [dir.to_path_buf()];
+        let found = |query: &str| {
+            let mut results = collect(|sender| engine.search(&roots, query, sender, Arc::default(), Arc::default()));
+            results.sort_by(|a, b| result_order(a, b, false));
+            results.into_iter()
+                .map(|result| result.file_info.path.strip_prefix(&dir).unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        // A directory holding only ignored files counts as empty, one
+        // holding an empty directory doesn't
+        assert_eq!(found("is:empty-dir"), ["empty", "nested/inner", "only-ignored"]);
+        assert_eq!(found("is:empty-file"), ["full/blank.txt", "zero.log"]);
+        assert_eq!(found("is:empty-dir is:empty-file"), ["empty", "full/blank.txt", "nested/inner", "only-ignored", "zero.log"]);
+        assert_eq!(found("inner is:empty-dir"), ["nested/inner"]);
+
+        // The index sees the same directories as empty
+        let exclusions = engine.exclusions(&dir).unwrap();
+        let index = FileIndex::build(&dir, &exclusions, &AtomicBool::new(false), |_| {}).unwrap();
+        let (results, _) = engine.search_index(&index, &dir, "is:empty-dir").unwrap();
+        let names: Vec<_> = results.iter().map(|result| result.file_info.name.as_str()).collect();
+        assert_eq!(names, ["empty", "inner", "only-ignored"]);
+    }
+
+    #[tokio::test]
+    async fn test_thread_pool() {
+        let dir = TempDir::new("thread-pool");
+        let roots = [dir.to_path_buf()];
+        for i in 0..20 {
+            fs::create_dir_all(dir.join(format!("d{}", i))).unwrap();
+            fs::write(dir.join(format!("d{}/report.txt", i)), "report").unwrap();
+        }
+
+        let mut engine = SearchEngine::new();
+        assert!(engine.thread_count() >= 1);
+        engine.settings.threads = Some(2);
+        assert_eq!(engine.thread_count(), 2);
+        assert_eq!(engine.pool().unwrap().current_num_threads(), 2);
+        // The pool is built once and kept
+        assert!(Arc::ptr_eq(&engine.pool().unwrap(), &engine.pool().unwrap()));
+        assert_eq!(collect(|sender| engine.search(&roots, "report", sender, Arc::default(), Arc::default())).len(), 20);
+        assert_eq!(collect(|sender| engine.search_content(&roots, "report", sender, Arc::default(), Arc::default())).len(), 20);
+
+        let settings: SearchSettings = serde_json::from_str(r#"{"threads": 3, "index_throttle_ms": 5}"#).unwrap();
+        assert_eq!((settings.threads, settings.index_throttle_ms, settings.debug), (Some(3), 5, false));
+    }
+
+    #[test]
+    fn test_result_order() {
+        let result = |name: &str, is_directory: bool, score: i64| {
+            let mut file_info = FileInfo::from_path(Path::new("/")).unwrap();
+            file_info.name = name.to_string();
+            file_info.is_directory = is_directory;
+            SearchResult { file_info, score, match_type: MatchType::FileName, content_match: None, also_found_at: Vec::new() }
+        };
+        let fixture = vec![
+            result("project/README.md", false, 300),
+            result("project", true, 250),
+            result("project/src/main.rs", false, 250),
+            result("old-project", true, 120),
+            result("project.zip", false, 250),
+        ];
+        let ordered = |directories_first: bool| {
+            let mut results = fixture.clone();
+            results.sort_by(|a, b| result_order(a, b, directories_first));
+            results.into_iter().map(|result| result.file_info.name).collect::<Vec<_>>()
+        };
+
+        // Off, the order is by score alone, ties keeping their places
+        assert_eq!(ordered(false), ["project/README.md", "project", "project/src/main.rs", "project.zip", "old-project"]);
+        assert_eq!(ordered(true), ["project", "old-project", "project/README.md", "project/src/main.rs", "project.zip"]);
+
+        // Or directories can be boosted instead of grouped
+        let weights = RankWeights { directory_bonus: 100, ..RankWeights::default() };
+        let found = Found { match_type: MatchType::FileName, fuzzy: 50, exact: false, position: 0 };
+        assert_eq!(score_result(&found, 0, true, None, &weights), score_result(&found, 0, false, None, &weights) + 100);
+        assert_eq!(score_result(&found, 0, true, None, &RankWeights::default()), score_result(&found, 0, false, None, &RankWeights::default()));
+    }
+
+    #[tokio::test]
+    async fn test_exclude_patterns() {
+        let dir = TempDir::new("exclude-search");
+        let roots = [dir.to_path_buf()];
+        for sub in ["node_modules/pkg", "build", "src"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("node_modules/pkg/report.js"), "report").unwrap();
+        fs::write(dir.join("build/report.o"), "report").unwrap();
+        fs::write(dir.join("build/report.log"), "report").unwrap();
+        fs::write(dir.join("src/report.rs"), "report").unwrap();
+
+        let mut engine = SearchEngine::new();
+        engine.exclude = vec!["node_modules".to_string(), "build/*.o".to_string()];
+        let names = |results: Vec<SearchResult>| {
+            let mut names: Vec<String> = results.into_iter().map(|result| result.file_info.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(collect(|sender| engine.search(&roots, "report", sender, Arc::default(), Arc::default()))), ["report.log", "report.rs"]);
+        assert_eq!(names(collect(|sender| engine.search_content(&roots, "report", sender, Arc::default(), Arc::default()))), ["report.log", "report.rs"]);
+
+        // Searching from inside an excluded directory still looks there
+        let inside = collect(|sender| engine.search_fast(&[dir.join("node_modules")], "report", 10, sender, Arc::default(), Arc::default()));
+        assert_eq!(names(inside), ["report.js"]);
+
+        let listing: Vec<FileInfo> = ["node_modules", "src"].iter()
+            .map(|name| FileInfo::from_path(&dir.join(name)).unwrap())
+            .collect();
+        assert_eq!(names(engine.search_in_files(&listing, "s").unwrap()), ["src"]);
+
+        engine.exclude.push("a[".to_string());
+        let error = engine.search(&roots, "report", mpsc::channel().0, Arc::default(), Arc::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid exclude pattern 'a['"));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_search_stops_quickly() {
+        let dir = TempDir::new("cancel-search");
+        let roots = [dir.to_path_buf()];
+        for i in 0..200 {
+            let sub = dir.join(format!("d{}", i)).join("nested");
+            fs::create_dir_all(&sub).unwrap();
+            for j in 0..20 {
+                fs::write(sub.join(format!("file{}.txt", j)), "needle").unwrap();
+            }
+        }
+
+        // Every file matches, so a walk that ignored the cancel would find all 4000
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let matched = Arc::new(AtomicUsize::new(0));
+        SearchEngine::new().search_content(&roots, "needle", sender, cancel.clone(), matched.clone()).unwrap();
+        receiver.recv().unwrap();
+        let cancelled_at = std::time::Instant::now();
+        cancel.store(true, Ordering::Relaxed);
+        // The channel disconnects once the walk has given up
+        let received = 1 + receiver.iter().count();
+        assert!(cancelled_at.elapsed() < std::time::Duration::from_secs(1));
+        let matched = matched.load(Ordering::Relaxed);
+        assert!(matched < 4000, "the walk went on to match {} files", matched);
+        assert!(received <= matched);
     }
 }
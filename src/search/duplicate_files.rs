@@ -0,0 +1,224 @@
+//! Files with identical content below a directory, for the find duplicates
+//! key. Files are grouped by size first, then by a hash of their first
+//! bytes, and only those still alike are hashed in full, so most files are
+//! never read past their head.
+
+use crate::file_system::checksum::{self, ChecksumAlgorithm};
+use crate::file_system::FileInfo;
+use ignore::Walk;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Bytes at the start of a file hashed to tell same-sized files apart
+const HEAD_LEN: usize = 16 * 1024;
+
+// Files listed between progress reports
+const LISTED_REPORT_EVERY: usize = 1000;
+
+/// How far a scan has got, for the status line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanProgress {
+    /// Files walked so far
+    Listed(usize),
+    /// Files sharing their size with another whose heads were hashed
+    Compared { done: usize, total: usize },
+    /// Bytes hashed in full, of the files whose heads matched too
+    Hashed { done: u64, total: u64 },
+}
+
+pub enum DuplicateEvent {
+    Progress(ScanProgress),
+    Finished(io::Result<DuplicateScan>),
+}
+
+/// Files with the same content, oldest first, as that is likely the
+/// original.
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub files: Vec<FileInfo>,
+}
+
+impl DuplicateGroup {
+    /// Bytes freed by deleting all but one of the files
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DuplicateScan {
+    /// Most wasted space first
+    pub groups: Vec<DuplicateGroup>,
+    /// Files that couldn't be read and were left out
+    pub unreadable: usize,
+}
+
+impl DuplicateScan {
+    pub fn wasted(&self) -> u64 {
+        self.groups.iter().map(DuplicateGroup::wasted).sum()
+    }
+}
+
+/// The same file reached twice, through a symlinked directory or a hard
+/// link, is one file rather than a copy of itself.
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &fs::Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &fs::Metadata) -> Option<FileId> {
+    fs::canonicalize(path).ok()
+}
+
+/// Groups the files `walk` visits by content. Empty files are left out, as
+/// are files that can't be read, which are counted. `progress` is called
+/// as each stage goes; setting `cancel` stops the scan with an
+/// `Interrupted` error.
+pub fn scan(walk: Walk, cancel: &AtomicBool, mut progress: impl FnMut(ScanProgress)) -> io::Result<DuplicateScan> {
+    let cancelled = || io::Error::new(io::ErrorKind::Interrupted, "cancelled");
+
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let mut seen: HashSet<FileId> = HashSet::new();
+    let mut listed = 0;
+    for entry in walk.flatten() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(cancelled());
+        }
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.len() == 0 || !file_id(entry.path(), &metadata).is_some_and(|id| seen.insert(id)) {
+            continue;
+        }
+        by_size.entry(metadata.len()).or_default().push(entry.into_path());
+        listed += 1;
+        if listed % LISTED_REPORT_EVERY == 0 {
+            progress(ScanProgress::Listed(listed));
+        }
+    }
+    progress(ScanProgress::Listed(listed));
+    by_size.retain(|_, paths| paths.len() > 1);
+
+    let mut unreadable = 0;
+    let total = by_size.values().map(Vec::len).sum();
+    let mut done = 0;
+    let mut candidates: Vec<(u64, Vec<PathBuf>)> = Vec::new();
+    for (size, paths) in by_size {
+        let mut by_head: HashMap<u32, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(cancelled());
+            }
+            match head_hash(&path) {
+                Ok(hash) => by_head.entry(hash).or_default().push(path),
+                Err(_) => unreadable += 1,
+            }
+            done += 1;
+            progress(ScanProgress::Compared { done, total });
+        }
+        candidates.extend(by_head.into_values().filter(|paths| paths.len() > 1).map(|paths| (size, paths)));
+    }
+
+    let total = candidates.iter().map(|(size, paths)| size * paths.len() as u64).sum();
+    let mut hashed = 0;
+    let mut groups = Vec::new();
+    for (size, paths) in candidates {
+        let mut by_digest: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            let before = hashed;
+            let digest = checksum::checksum(&path, ChecksumAlgorithm::Sha256, cancel, |done, _| {
+                progress(ScanProgress::Hashed { done: before + done, total });
+            });
+            match digest {
+                Ok(digest) => by_digest.entry(digest).or_default().push(path),
+                Err(_) if cancel.load(Ordering::Relaxed) => return Err(cancelled()),
+                Err(_) => unreadable += 1,
+            }
+            hashed = before + size;
+        }
+        for paths in by_digest.into_values() {
+            let mut files: Vec<FileInfo> = paths.iter().filter_map(|path| FileInfo::from_path(path).ok()).collect();
+            if files.len() < 2 {
+                continue;
+            }
+            files.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.path.cmp(&b.path)));
+            groups.push(DuplicateGroup { size, files });
+        }
+    }
+    groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then_with(|| a.files[0].path.cmp(&b.files[0].path)));
+    Ok(DuplicateScan { groups, unreadable })
+}
+
+/// CRC32 of the first `HEAD_LEN` bytes of the file at `path`.
+fn head_hash(path: &Path) -> io::Result<u32> {
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    File::open(path)?.take(HEAD_LEN as u64).read_to_end(&mut head)?;
+    Ok(crc32fast::hash(&head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ignore::WalkBuilder;
+    use std::time::{Duration, SystemTime};
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_groups_identical_files() {
+        let dir = TempDir::new("duplicate-scan");
+        fs::create_dir_all(dir.join("backup")).unwrap();
+        let big = vec![b'x'; HEAD_LEN * 2];
+        let mut same_head = big.clone();
+        *same_head.last_mut().unwrap() = b'y';
+        fs::write(dir.join("photo.jpg"), &big).unwrap();
+        fs::write(dir.join("backup/photo.jpg"), &big).unwrap();
+        fs::write(dir.join("backup/photo (1).jpg"), &big).unwrap();
+        // Same size and head, different tail
+        fs::write(dir.join("edited.jpg"), &same_head).unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        fs::write(dir.join("b.txt"), "hello").unwrap();
+        fs::write(dir.join("c.txt"), "world").unwrap();
+        fs::write(dir.join("empty1"), "").unwrap();
+        fs::write(dir.join("empty2"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("backup"), dir.join("backup-link")).unwrap();
+        // The copy in the root is the oldest, so it comes first
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        File::options().write(true).open(dir.join("photo.jpg")).unwrap().set_modified(old).unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let walk = || WalkBuilder::new(&dir).follow_links(true).build();
+        let mut stages = Vec::new();
+        let found = scan(walk(), &cancel, |progress| stages.push(progress)).unwrap();
+
+        assert_eq!(found.groups.len(), 2);
+        let photos = &found.groups[0];
+        let names: Vec<PathBuf> = photos.files.iter().map(|file| file.path.strip_prefix(&dir).unwrap().to_path_buf()).collect();
+        assert_eq!(names[0], Path::new("photo.jpg"));
+        // Reached again through the symlink, but counted once
+        assert_eq!(names.len(), 3);
+        assert_eq!(photos.wasted(), 2 * big.len() as u64);
+        assert_eq!(found.groups[1].files.len(), 2);
+        assert_eq!(found.wasted(), 2 * big.len() as u64 + 5);
+        assert_eq!(found.unreadable, 0);
+        assert!(matches!(stages.last(), Some(ScanProgress::Hashed { done, total }) if done == total));
+
+        cancel.store(true, Ordering::Relaxed);
+        let err = scan(walk(), &cancel, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,177 @@
+//! Search results written out as plain paths, CSV or JSON, for the export
+//! key in the results view and `--output-format` on the command line.
+
+use super::{MatchType, SearchResult};
+use crate::file_system::utc_date_time;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One path per line
+    Plain,
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// The format called `name` on the command line: "plain", "csv" or "json".
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "plain" => Some(ExportFormat::Plain),
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// The format the extension of `path` asks for; plain for any other.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+            Some("csv") => ExportFormat::Csv,
+            Some("json") => ExportFormat::Json,
+            _ => ExportFormat::Plain,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Plain => "txt",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ExportFormat::Plain => ExportFormat::Csv,
+            ExportFormat::Csv => ExportFormat::Json,
+            ExportFormat::Json => ExportFormat::Plain,
+        }
+    }
+}
+
+/// A file name for results exported at `now`, e.g.
+/// `filepilot-search-20240501-093000.csv` (in UTC).
+pub fn default_file_name(format: ExportFormat, now: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_date_time(now);
+    format!("filepilot-search-{:04}{:02}{:02}-{:02}{:02}{:02}.{}",
+        year, month, day, hour, minute, second, format.extension())
+}
+
+/// One result as it is exported; the CSV header is the field names.
+#[derive(Serialize)]
+struct Record<'a> {
+    path: String,
+    size: u64,
+    /// UTC, e.g. `2024-05-01T09:30:00Z`
+    mtime: Option<String>,
+    score: i64,
+    match_type: &'static str,
+    /// Where a content search matched
+    line: Option<usize>,
+    snippet: Option<&'a str>,
+}
+
+impl<'a> Record<'a> {
+    fn new(result: &'a SearchResult) -> Self {
+        let file_info = &result.file_info;
+        Record {
+            path: file_info.path.display().to_string(),
+            size: file_info.size,
+            mtime: file_info.modified.map(|time| {
+                let (year, month, day, hour, minute, second) = utc_date_time(time);
+                format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second)
+            }),
+            score: result.score,
+            match_type: match result.match_type {
+                MatchType::FileName => "file_name",
+                MatchType::FilePath => "file_path",
+                MatchType::Content => "content",
+                MatchType::Duplicate => "duplicate",
+                MatchType::Modified => "modified",
+            },
+            line: result.content_match.as_ref().map(|found| found.line_number),
+            snippet: result.content_match.as_ref().map(|found| found.snippet.as_str()),
+        }
+    }
+}
+
+/// Writes `results`, in their order, to `out` as `format`.
+pub fn write_results(results: &[SearchResult], format: ExportFormat, mut out: impl Write) -> io::Result<()> {
+    match format {
+        ExportFormat::Plain => {
+            for result in results {
+                writeln!(out, "{}", result.file_info.path.display())?;
+            }
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut out);
+            for result in results {
+                writer.serialize(Record::new(result)).map_err(io::Error::other)?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Json => {
+            let records: Vec<Record> = results.iter().map(Record::new).collect();
+            serde_json::to_writer_pretty(&mut out, &records).map_err(io::Error::other)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::FileInfo;
+    use crate::search::ContentMatch;
+    use std::time::Duration;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_formats() {
+        let dir = TempDir::new("export");
+        std::fs::write(dir.join("notes, 2024.md"), "todo: ship it").unwrap();
+        let mut file_info = FileInfo::from_path(&dir.join("notes, 2024.md")).unwrap();
+        file_info.modified = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_555_800));
+        let path = file_info.path.display().to_string();
+        let results = vec![
+            SearchResult { file_info: file_info.clone(), score: 120, match_type: MatchType::FileName, content_match: None, also_found_at: Vec::new() },
+            SearchResult {
+                file_info,
+                score: 0,
+                match_type: MatchType::Content,
+                content_match: Some(ContentMatch { line_number: 1, snippet: "todo: ship it".to_string() }),
+                also_found_at: Vec::new(),
+            },
+        ];
+        let written = |format| {
+            let mut out = Vec::new();
+            write_results(&results, format, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(written(ExportFormat::Plain), format!("{}\n{}\n", path, path));
+
+        let csv = written(ExportFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "path,size,mtime,score,match_type,line,snippet");
+        assert_eq!(lines[1], format!("\"{}\",13,2024-05-01T09:30:00Z,120,file_name,,", path));
+        assert_eq!(lines[2], format!("\"{}\",13,2024-05-01T09:30:00Z,0,content,1,todo: ship it", path));
+
+        let json: serde_json::Value = serde_json::from_str(&written(ExportFormat::Json)).unwrap();
+        assert_eq!(json[0]["path"], path.as_str());
+        assert_eq!(json[0]["line"], serde_json::Value::Null);
+        assert_eq!(json[1]["match_type"], "content");
+        assert_eq!(json[1]["snippet"], "todo: ship it");
+
+        assert_eq!(ExportFormat::from_path(Path::new("out.JSON")), ExportFormat::Json);
+        assert_eq!(ExportFormat::from_path(Path::new("out")), ExportFormat::Plain);
+        assert_eq!(ExportFormat::from_name("csv"), Some(ExportFormat::Csv));
+        assert_eq!(default_file_name(ExportFormat::Csv, SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_555_800)),
+            "filepilot-search-20240501-093000.csv");
+    }
+}
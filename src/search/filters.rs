@@ -0,0 +1,347 @@
+//! Filters written into a search query: `ext:pdf`, `type:dir`,
+//! `size:>10mb`, `mtime:<7d` and `is:empty-dir`. They are split off the text to match and
+//! checked before an entry is scored, the cheap name and type checks
+//! first so entries they rule out are never stat-ed.
+
+use crate::file_system::utc_date_time;
+use std::fmt;
+use std::fs::Metadata;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+// Multipliers for the size units, largest first so `Display` picks the
+// biggest one that fits exactly
+const SIZE_UNITS: [(&str, u64); 4] = [("gb", 1 << 30), ("mb", 1 << 20), ("kb", 1 << 10), ("b", 1)];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchFilters {
+    /// Lowercase and without the dot; any of them will do
+    pub extensions: Vec<String>,
+    pub dirs_only: bool,
+    pub files_only: bool,
+    /// Bounds on the size of files, in bytes, both exclusive. Directories
+    /// never pass a size filter.
+    pub larger_than: Option<u64>,
+    pub smaller_than: Option<u64>,
+    pub modified_after: Option<SystemTime>,
+    pub modified_before: Option<SystemTime>,
+    /// Directories with nothing in them but ignored entries; the walk
+    /// checks their contents, see `SearchEngine`
+    pub empty_dirs: bool,
+    /// Files of zero bytes
+    pub empty_files: bool,
+}
+
+impl SearchFilters {
+    /// Splits `query` into the text to search for and its filters. Filters
+    /// may appear anywhere; `ext:` can be repeated or list several
+    /// extensions separated by commas. `is:empty-dir` and `is:empty-file`
+    /// together find either kind. `ext:`, `type:` and `is:` terms that aren't
+    /// valid filters are kept as search text, while a malformed `size:` or
+    /// `mtime:` term is an error explaining what was expected.
+    pub fn parse(query: &str) -> Result<(String, SearchFilters), String> {
+        let mut filters = SearchFilters::default();
+        let mut words = Vec::new();
+        for word in query.split_whitespace() {
+            let (key, value) = word.split_once(':').unwrap_or((word, ""));
+            match (key.to_lowercase().as_str(), value.to_lowercase().as_str()) {
+                ("ext", extensions) if !extensions.is_empty() => {
+                    for extension in extensions.split(',').map(|ext| ext.trim_start_matches('.')) {
+                        if !extension.is_empty() && !filters.extensions.iter().any(|known| known == extension) {
+                            filters.extensions.push(extension.to_string());
+                        }
+                    }
+                }
+                ("type", "dir" | "d" | "directory" | "folder") => filters.dirs_only = true,
+                ("type", "file" | "f") => filters.files_only = true,
+                ("is", "empty-dir" | "empty-directory" | "empty-folder") => filters.empty_dirs = true,
+                ("is", "empty-file") => filters.empty_files = true,
+                ("size", bound) => {
+                    let (greater, amount) = comparison(word, bound, "size:>10mb")?;
+                    let bytes = parse_size(amount).ok_or_else(|| {
+                        format!("Can't read the size in '{}' - use a number with b, kb, mb or gb, as in size:<500kb", word)
+                    })?;
+                    if greater {
+                        filters.larger_than = Some(bytes);
+                    } else {
+                        filters.smaller_than = Some(bytes);
+                    }
+                }
+                ("mtime", bound) => {
+                    let (greater, when) = comparison(word, bound, "mtime:<7d")?;
+                    let time = parse_time(when, SystemTime::now()).ok_or_else(|| {
+                        format!("Can't read the time in '{}' - use an age like 3d, 2w or 6m, or a date like 2024-01-01", word)
+                    })?;
+                    // An age counts back from now, so "<7d" means newer than a week ago
+                    let is_age = !when.contains('-');
+                    if greater != is_age {
+                        filters.modified_after = Some(time);
+                    } else {
+                        filters.modified_before = Some(time);
+                    }
+                }
+                _ => words.push(word),
+            }
+        }
+        Ok((words.join(" "), filters))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == SearchFilters::default()
+    }
+
+    /// Whether only empty entries are asked for
+    pub fn finds_empty(&self) -> bool {
+        self.empty_dirs || self.empty_files
+    }
+
+    /// Checks the extension and type filters, which need no metadata.
+    pub fn accepts(&self, path: &Path, is_directory: bool) -> bool {
+        if (self.dirs_only && !is_directory) || (self.files_only && is_directory) {
+            return false;
+        }
+        if self.finds_empty() && !(if is_directory { self.empty_dirs } else { self.empty_files }) {
+            return false;
+        }
+        self.extensions.is_empty()
+            || path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| self.extensions.contains(&ext))
+    }
+
+    /// Whether `accepts_metadata` has anything to check, so callers can
+    /// skip reading metadata when it hasn't.
+    pub fn needs_metadata(&self) -> bool {
+        self.larger_than.is_some() || self.smaller_than.is_some()
+            || self.modified_after.is_some() || self.modified_before.is_some()
+            || self.empty_files
+    }
+
+    /// Checks the size and modification time filters, and that files are
+    /// empty for `is:empty-file`.
+    pub fn accepts_metadata(&self, metadata: &Metadata) -> bool {
+        self.accepts_details(metadata.is_dir(), metadata.len(), metadata.modified().ok())
+    }
+
+    /// `accepts_metadata` for details already read, such as an index entry's.
+    pub fn accepts_details(&self, is_directory: bool, size: u64, modified: Option<SystemTime>) -> bool {
+        if self.empty_files && !is_directory && size > 0 {
+            return false;
+        }
+        if (self.larger_than.is_some() || self.smaller_than.is_some())
+            && (is_directory
+                || self.larger_than.is_some_and(|bound| size <= bound)
+                || self.smaller_than.is_some_and(|bound| size >= bound))
+        {
+            return false;
+        }
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let Some(modified) = modified else {
+                return false;
+            };
+            if self.modified_after.is_some_and(|bound| modified <= bound)
+                || self.modified_before.is_some_and(|bound| modified >= bound)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl fmt::Display for SearchFilters {
+    /// The filters in query syntax, e.g. "ext:pdf,docx type:file size:>10mb".
+    /// Ages are shown as the date they work out to.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms = Vec::new();
+        if !self.extensions.is_empty() {
+            terms.push(format!("ext:{}", self.extensions.join(",")));
+        }
+        if self.dirs_only {
+            terms.push("type:dir".to_string());
+        }
+        if self.files_only {
+            terms.push("type:file".to_string());
+        }
+        if let Some(bytes) = self.larger_than {
+            terms.push(format!("size:>{}", size_term(bytes)));
+        }
+        if let Some(bytes) = self.smaller_than {
+            terms.push(format!("size:<{}", size_term(bytes)));
+        }
+        if let Some(time) = self.modified_after {
+            terms.push(format!("mtime:>{}", date_term(time)));
+        }
+        if let Some(time) = self.modified_before {
+            terms.push(format!("mtime:<{}", date_term(time)));
+        }
+        if self.empty_dirs {
+            terms.push("is:empty-dir".to_string());
+        }
+        if self.empty_files {
+            terms.push("is:empty-file".to_string());
+        }
+        write!(f, "{}", terms.join(" "))
+    }
+}
+
+/// Splits a `>x` or `<x` bound into whether it is `>` and the `x`.
+fn comparison<'a>(word: &str, bound: &'a str, example: &str) -> Result<(bool, &'a str), String> {
+    match bound.chars().next() {
+        Some('>') => Ok((true, &bound[1..])),
+        Some('<') => Ok((false, &bound[1..])),
+        _ => Err(format!("'{}' needs > or < before the value, as in {}", word, example)),
+    }
+}
+
+/// "10mb", "1.5gb", "300" (bytes)
+fn parse_size(amount: &str) -> Option<u64> {
+    let unit_start = amount.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(amount.len());
+    let (number, unit) = amount.split_at(unit_start);
+    let number: f64 = number.parse().ok().filter(|number: &f64| *number >= 0.0)?;
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" | "kb" => 1 << 10,
+        "m" | "mb" => 1 << 20,
+        "g" | "gb" => 1 << 30,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// An age such as "7d", "2w" or "6m" (30-day months) before `now`, or a
+/// "YYYY-MM-DD" date at midnight UTC.
+fn parse_time(when: &str, now: SystemTime) -> Option<SystemTime> {
+    if let Some((year, rest)) = when.split_once('-') {
+        let (month, day) = rest.split_once('-')?;
+        return utc_midnight(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    }
+    let (count, unit) = when.split_at(when.len().checked_sub(1)?);
+    let days = match unit {
+        "d" => 1,
+        "w" => 7,
+        "m" => 30,
+        _ => return None,
+    };
+    now.checked_sub(Duration::from_secs(count.parse::<u64>().ok()? * days * DAY_SECS))
+}
+
+/// Midnight UTC at the start of the given day, or `None` for a day that
+/// doesn't exist.
+fn utc_midnight(year: i64, month: u32, day: u32) -> Option<SystemTime> {
+    // Days since 1970-01-01 from a civil date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146_097 + day_of_era - 719_468).ok()?;
+
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(days * DAY_SECS);
+    // Rejects month 13, February 30th and the like
+    let (y, m, d, ..) = utc_date_time(time);
+    (y == year && m == month && d == day).then_some(time)
+}
+
+fn size_term(bytes: u64) -> String {
+    let (unit, multiplier) = SIZE_UNITS.iter()
+        .find(|(_, multiplier)| bytes >= *multiplier && bytes.is_multiple_of(*multiplier))
+        .unwrap_or(&("b", 1));
+    format!("{}{}", bytes / multiplier, unit)
+}
+
+fn date_term(time: SystemTime) -> String {
+    let (year, month, day, ..) = utc_date_time(time);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_parse_filters() {
+        assert_eq!(SearchFilters::parse("report").unwrap(), ("report".to_string(), SearchFilters::default()));
+
+        let (text, filters) = SearchFilters::parse("annual ext:pdf report EXT:.Docx,md type:file").unwrap();
+        assert_eq!(text, "annual report");
+        assert_eq!(filters.extensions, ["pdf", "docx", "md"]);
+        assert!(filters.files_only && !filters.dirs_only);
+        assert_eq!(filters.to_string(), "ext:pdf,docx,md type:file");
+
+        let (text, filters) = SearchFilters::parse("type:dir src").unwrap();
+        assert_eq!(text, "src");
+        assert!(filters.dirs_only);
+
+        // Anything that isn't a complete filter stays in the search text
+        let (text, filters) = SearchFilters::parse("ext: type:socket http://host").unwrap();
+        assert_eq!(text, "ext: type:socket http://host");
+        assert!(filters.is_empty());
+    }
+
+    #[test]
+    fn test_parse_size_and_time_filters() {
+        let (text, filters) = SearchFilters::parse("size:>10mb log size:<1.5GB mtime:>2024-01-01").unwrap();
+        assert_eq!(text, "log");
+        assert_eq!(filters.larger_than, Some(10 << 20));
+        assert_eq!(filters.smaller_than, Some(3 << 29));
+        assert_eq!(filters.modified_after, Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200)));
+        assert_eq!(filters.to_string(), "size:>10mb size:<1536mb mtime:>2024-01-01");
+
+        let now = SystemTime::now();
+        let week_ago = parse_time("1w", now).unwrap();
+        assert_eq!(now.duration_since(week_ago).unwrap(), Duration::from_secs(7 * DAY_SECS));
+        // "<7d" is younger than a week, ">7d" older
+        let recent = SearchFilters::parse("mtime:<7d").unwrap().1;
+        assert!(recent.modified_after.is_some() && recent.modified_before.is_none());
+        let old = SearchFilters::parse("mtime:>7d").unwrap().1;
+        assert!(old.modified_before.is_some() && old.modified_after.is_none());
+
+        assert_eq!(SearchFilters::parse("size:10mb").unwrap_err(), "'size:10mb' needs > or < before the value, as in size:>10mb");
+        assert!(SearchFilters::parse("size:>10tb").unwrap_err().contains("use a number with b, kb, mb or gb"));
+        assert!(SearchFilters::parse("mtime:<7y").unwrap_err().contains("an age like 3d"));
+        assert!(SearchFilters::parse("mtime:>2024-02-30").is_err());
+        assert!(SearchFilters::parse("size:").is_err());
+    }
+
+    #[test]
+    fn test_filters_accept() {
+        let (_, pdfs) = SearchFilters::parse("ext:pdf").unwrap();
+        assert!(pdfs.accepts(Path::new("/docs/Report.PDF"), false));
+        assert!(!pdfs.accepts(Path::new("/docs/report.txt"), false));
+        assert!(!pdfs.accepts(Path::new("/docs/README"), false));
+        assert!(!pdfs.needs_metadata());
+
+        let (_, dirs) = SearchFilters::parse("type:dir").unwrap();
+        assert!(dirs.accepts(Path::new("/src"), true));
+        assert!(!dirs.accepts(Path::new("/src/main.rs"), false));
+
+        let dir = TempDir::new("search-filters");
+        std::fs::write(dir.join("big"), vec![0u8; 2048]).unwrap();
+        std::fs::write(dir.join("small"), "x").unwrap();
+        let metadata = |name: &str| std::fs::metadata(dir.join(name)).unwrap();
+
+        let (_, large) = SearchFilters::parse("size:>1kb").unwrap();
+        assert!(large.needs_metadata());
+        assert!(large.accepts_metadata(&metadata("big")));
+        assert!(!large.accepts_metadata(&metadata("small")));
+        assert!(!large.accepts_metadata(&std::fs::metadata(&dir).unwrap()));
+        assert!(SearchFilters::parse("mtime:<1d").unwrap().1.accepts_metadata(&metadata("small")));
+        assert!(!SearchFilters::parse("mtime:>1d").unwrap().1.accepts_metadata(&metadata("small")));
+
+        std::fs::write(dir.join("empty"), "").unwrap();
+        let (text, empty) = SearchFilters::parse("is:empty-file IS:Empty-Dir").unwrap();
+        assert!(text.is_empty() && empty.needs_metadata());
+        assert_eq!(empty.to_string(), "is:empty-dir is:empty-file");
+        assert!(empty.accepts_metadata(&metadata("empty")));
+        assert!(!empty.accepts_metadata(&metadata("small")));
+        assert!(empty.accepts(&dir, true));
+        let (_, empty_files) = SearchFilters::parse("is:empty-file").unwrap();
+        assert!(!empty_files.accepts(&dir, true));
+        assert_eq!(SearchFilters::parse("is:big").unwrap().0, "is:big");
+    }
+}
@@ -0,0 +1,390 @@
+//! A file-name index of one directory tree, saved to disk so later runs can
+//! search it without walking again. A refresh stats every directory but
+//! only lists the ones whose modification time changed since the last
+//! walk, so sizes and times of files in unchanged directories are the ones
+//! seen then.
+
+use super::{Exclusions, WalkOptions};
+use crate::file_system;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Directory next to the config file that holds one index per root
+pub const INDEX_DIR: &str = "index";
+
+// Written first so a file in another format is rebuilt rather than misread
+const MAGIC: &[u8; 8] = b"FPINDEX1";
+
+// Entries between progress reports
+const PROGRESS_STEP: usize = 1000;
+
+const FLAG_DIRECTORY: u8 = 1;
+const FLAG_MODIFIED: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub path: PathBuf,
+    /// Display name, derived from `path` rather than stored
+    pub name: String,
+    /// Symlinks are indexed as themselves, never followed
+    pub is_directory: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl IndexEntry {
+    fn read(path: PathBuf) -> Option<Self> {
+        let metadata = fs::symlink_metadata(&path).ok()?;
+        Some(IndexEntry {
+            name: file_system::display_name(&path),
+            is_directory: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            path,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FileIndex {
+    pub root: PathBuf,
+    /// When the walk that produced this index finished
+    pub built_at: SystemTime,
+    root_modified: Option<SystemTime>,
+    /// Everything below `root`, parents before their children
+    pub entries: Vec<IndexEntry>,
+}
+
+impl FileIndex {
+    /// Walks `root` from scratch with the same ignore rules and exclusions
+    /// as a search, calling `progress` with the number of entries found so
+    /// far. `cancel` stops the walk with an `Interrupted` error.
+    pub fn build(
+        root: &Path,
+        exclusions: &Exclusions,
+        cancel: &AtomicBool,
+        mut progress: impl FnMut(usize),
+    ) -> io::Result<FileIndex> {
+        let root_modified = fs::metadata(root)?.modified().ok();
+        let mut entries = Vec::new();
+        walk_into(&mut entries, root, exclusions, cancel, &mut progress)?;
+        Ok(FileIndex { root: root.to_path_buf(), built_at: SystemTime::now(), root_modified, entries })
+    }
+
+    /// A fresh index of the same root. Directories whose modification time
+    /// is unchanged keep their listed children; changed ones are listed
+    /// again and directories that weren't indexed before are walked.
+    pub fn refresh(
+        &self,
+        exclusions: &Exclusions,
+        cancel: &AtomicBool,
+        mut progress: impl FnMut(usize),
+    ) -> io::Result<FileIndex> {
+        let mut children: HashMap<&Path, Vec<&IndexEntry>> = HashMap::new();
+        let mut known_modified: HashMap<&Path, Option<SystemTime>> = HashMap::new();
+        known_modified.insert(&self.root, self.root_modified);
+        for entry in &self.entries {
+            if let Some(parent) = entry.path.parent() {
+                children.entry(parent).or_default().push(entry);
+            }
+            if entry.is_directory {
+                known_modified.insert(&entry.path, entry.modified);
+            }
+        }
+
+        let root_modified = fs::metadata(&self.root)?.modified().ok();
+        let mut entries: Vec<IndexEntry> = Vec::with_capacity(self.entries.len());
+        // Directories still to look at, with where their own entry is in `entries`
+        let mut pending: Vec<(PathBuf, Option<usize>)> = vec![(self.root.clone(), None)];
+        while let Some((dir, position)) = pending.pop() {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(cancelled());
+            }
+            let modified = match position {
+                Some(position) => {
+                    // Unchanged parents carry the old time over, so read it again
+                    let modified = fs::symlink_metadata(&dir).and_then(|metadata| metadata.modified()).ok();
+                    entries[position].modified = modified;
+                    modified
+                }
+                None => root_modified,
+            };
+
+            let listing: Vec<IndexEntry> = match known_modified.get(dir.as_path()) {
+                Some(known) if modified.is_some() && *known == modified => {
+                    children.get(dir.as_path()).into_iter().flatten().map(|entry| (*entry).clone()).collect()
+                }
+                Some(_) => list_directory(&dir, exclusions),
+                None => {
+                    walk_into(&mut entries, &dir, exclusions, cancel, &mut progress)?;
+                    continue;
+                }
+            };
+            for entry in listing {
+                if entry.is_directory {
+                    pending.push((entry.path.clone(), Some(entries.len())));
+                }
+                entries.push(entry);
+                if entries.len().is_multiple_of(PROGRESS_STEP) {
+                    progress(entries.len());
+                }
+            }
+        }
+        Ok(FileIndex { root: self.root.clone(), built_at: SystemTime::now(), root_modified, entries })
+    }
+
+    /// File name of the index for `root` inside `INDEX_DIR`.
+    pub fn file_name(root: &Path) -> String {
+        // FNV-1a, which unlike the std hasher is the same in every build
+        let hash = path_bytes(root).iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        format!("{:016x}.idx", hash)
+    }
+
+    /// Reads an index written by `save`, or `None` if there isn't one.
+    pub fn load(path: &Path) -> io::Result<Option<FileIndex>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut reader = Reader { bytes: &bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(damaged());
+        }
+        let root = reader.path()?;
+        let built_at = reader.time()?.ok_or_else(damaged)?;
+        let root_modified = reader.time()?;
+        let count = reader.u64()?;
+        let mut entries = Vec::with_capacity(count.min(bytes.len() as u64) as usize);
+        for _ in 0..count {
+            let flags = reader.take(1)?[0];
+            let size = reader.u64()?;
+            let modified = reader.time()?.filter(|_| flags & FLAG_MODIFIED != 0);
+            let path = reader.path()?;
+            entries.push(IndexEntry {
+                name: file_system::display_name(&path),
+                path,
+                is_directory: flags & FLAG_DIRECTORY != 0,
+                size,
+                modified,
+            });
+        }
+        Ok(Some(FileIndex { root, built_at, root_modified, entries }))
+    }
+
+    /// Writes the index to `path`, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        out.write_all(MAGIC)?;
+        write_path(&mut out, &self.root)?;
+        write_time(&mut out, Some(self.built_at))?;
+        write_time(&mut out, self.root_modified)?;
+        out.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for entry in &self.entries {
+            let mut flags = 0;
+            if entry.is_directory {
+                flags |= FLAG_DIRECTORY;
+            }
+            if entry.modified.is_some() {
+                flags |= FLAG_MODIFIED;
+            }
+            out.write_all(&[flags])?;
+            out.write_all(&entry.size.to_le_bytes())?;
+            write_time(&mut out, entry.modified)?;
+            write_path(&mut out, &entry.path)?;
+        }
+        out.flush()
+    }
+}
+
+/// Appends everything below `dir` to `entries`.
+fn walk_into(
+    entries: &mut Vec<IndexEntry>,
+    dir: &Path,
+    exclusions: &Exclusions,
+    cancel: &AtomicBool,
+    progress: &mut impl FnMut(usize),
+) -> io::Result<()> {
+    for entry in super::walker(dir, exclusions.clone(), None, WalkOptions::default()).build() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(cancelled());
+        }
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if entry.depth() == 0 {
+            continue;
+        }
+        if let Some(entry) = IndexEntry::read(entry.into_path()) {
+            entries.push(entry);
+            if entries.len().is_multiple_of(PROGRESS_STEP) {
+                progress(entries.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The entries directly inside `dir`, following the same ignore rules as
+/// a full walk. An unreadable directory lists as empty.
+fn list_directory(dir: &Path, exclusions: &Exclusions) -> Vec<IndexEntry> {
+    super::walker(dir, exclusions.clone(), None, WalkOptions::default())
+        .max_depth(Some(1))
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.depth() == 1)
+        .filter_map(|entry| IndexEntry::read(entry.into_path()))
+        .collect()
+}
+
+fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "cancelled")
+}
+
+fn damaged() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "the index file is damaged")
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn write_path(out: &mut impl Write, path: &Path) -> io::Result<()> {
+    let bytes = path_bytes(path);
+    out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    out.write_all(&bytes)
+}
+
+/// Seconds and nanoseconds since the epoch; times before it are stored
+/// as the epoch itself.
+fn write_time(out: &mut impl Write, time: Option<SystemTime>) -> io::Result<()> {
+    let since_epoch = time
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    out.write_all(&since_epoch.as_secs().to_le_bytes())?;
+    out.write_all(&since_epoch.subsec_nanos().to_le_bytes())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(damaged());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().map_err(|_| damaged())?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().map_err(|_| damaged())?))
+    }
+
+    /// A time from `write_time`; the epoch itself reads as `None`.
+    fn time(&mut self) -> io::Result<Option<SystemTime>> {
+        let since_epoch = Duration::new(self.u64()?, self.u32()?);
+        Ok((!since_epoch.is_zero()).then(|| SystemTime::UNIX_EPOCH + since_epoch))
+    }
+
+    fn path(&mut self) -> io::Result<PathBuf> {
+        let len = self.u32()? as usize;
+        Ok(path_from_bytes(self.take(len)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::SearchEngine;
+    use crate::test_support::TempDir;
+
+    fn names(index: &FileIndex) -> Vec<String> {
+        let mut names: Vec<String> = index.entries.iter()
+            .map(|entry| entry.path.strip_prefix(&index.root).unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_build_save_load_and_refresh() {
+        let dir = TempDir::new("index");
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join("src/nested/lib.rs"), "").unwrap();
+        fs::write(dir.join("docs/guide.md"), "").unwrap();
+
+        fs::create_dir_all(dir.join("node_modules/pkg")).unwrap();
+        let mut engine = SearchEngine::new();
+        engine.exclude = vec!["node_modules".to_string()];
+        let exclusions = engine.exclusions(&dir).unwrap();
+        let never = AtomicBool::new(false);
+        let index = FileIndex::build(&dir, &exclusions, &never, |_| {}).unwrap();
+        assert_eq!(names(&index), ["docs", "docs/guide.md", "src", "src/main.rs", "src/nested", "src/nested/lib.rs"]);
+        let main = index.entries.iter().find(|entry| entry.name == "main.rs").unwrap();
+        assert_eq!((main.size, main.is_directory), (12, false));
+
+        let file = dir.join("store").join(FileIndex::file_name(&dir));
+        index.save(&file).unwrap();
+        let loaded = FileIndex::load(&file).unwrap().unwrap();
+        assert_eq!(loaded.root, dir.path());
+        assert_eq!(loaded.entries.len(), index.entries.len());
+        assert_eq!(loaded.entries.iter().find(|entry| entry.name == "main.rs"), Some(main));
+        assert!(FileIndex::load(&dir.join("missing.idx")).unwrap().is_none());
+        fs::write(&file, b"FPINDEX1\x01").unwrap();
+        assert_eq!(FileIndex::load(&file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(dir.join("store")).unwrap();
+
+        // A file added deep down, one removed, and a new directory
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("src/nested/extra.rs"), "").unwrap();
+        fs::remove_file(dir.join("docs/guide.md")).unwrap();
+        fs::create_dir_all(dir.join("assets/icons")).unwrap();
+        fs::write(dir.join("assets/icons/logo.svg"), "").unwrap();
+        let refreshed = index.refresh(&exclusions, &never, |_| {}).unwrap();
+        assert_eq!(names(&refreshed), [
+            "assets", "assets/icons", "assets/icons/logo.svg", "docs",
+            "src", "src/main.rs", "src/nested", "src/nested/extra.rs", "src/nested/lib.rs",
+        ]);
+        // Nothing changed since, so nothing is listed again
+        assert_eq!(names(&refreshed.refresh(&exclusions, &never, |_| {}).unwrap()), names(&refreshed));
+
+        let cancelled = FileIndex::build(&dir, &exclusions, &AtomicBool::new(true), |_| {});
+        assert_eq!(cancelled.unwrap_err().kind(), io::ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,58 @@
+//! Where the last run ended, so `restore_last_dir` can start the next one
+//! in the same directory with the same sort order and hidden-files setting.
+
+use crate::file_system::SortMode;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SESSION_FILE: &str = "last_session.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastSession {
+    pub directory: PathBuf,
+    pub sort_mode: SortMode,
+    pub sort_reverse: bool,
+    pub show_hidden: bool,
+}
+
+impl LastSession {
+    /// Reads the session written by `save`, or `None` if there isn't one.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map(Some).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("session");
+        let path = dir.join("session.json");
+        assert_eq!(LastSession::load(&path).unwrap(), None);
+
+        let session = LastSession {
+            directory: PathBuf::from("/home/me/projects"),
+            sort_mode: SortMode::Size,
+            sort_reverse: true,
+            show_hidden: true,
+        };
+        session.save(&path).unwrap();
+        assert_eq!(LastSession::load(&path).unwrap(), Some(session));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(LastSession::load(&path).is_err());
+    }
+}
@@ -1,31 +1,118 @@
-use crate::file_system::{FileExplorer, FileInfo};
-use crate::search::{SearchEngine, SearchResult};
-use crate::file_sharing::FileShareServer;
-use crate::config::Config;
+use crate::file_system::{self, format_size, FileExplorer, FileInfo};
+use crate::file_system::archive::{self, ArchiveKind};
+use crate::file_system::batch_rename;
+use crate::file_system::checksum::{self, ChecksumAlgorithm};
+use crate::file_system::compare;
+use crate::file_system::completion;
+use crate::file_system::frecency::Frecency;
+use crate::file_system::git_status::{self, GitStatus, RepoStatus};
+use crate::file_system::mounts::{self, Volume};
+use crate::file_system::open_with::{self, OpenWithApp};
+use crate::file_system::transfer::{self, ConflictPolicy, Reporter, TransferEvent, TransferMode, TransferProgress};
+use crate::file_system::watcher::DirectoryWatcher;
+use crate::search::{self, SearchEngine, SearchResult, SearchSettings, WalkToggle};
+use crate::search::duplicate_files::{DuplicateEvent, DuplicateScan, ScanProgress};
+use crate::search::export::{self, ExportFormat};
+use crate::search::index::{FileIndex, INDEX_DIR};
+use crate::file_sharing::{self, AccessRecord, FileShareServer, ShareInfo, ShareOptions, ShareProtection};
+use crate::config::{Config, KeyBindings};
+use crate::session::{LastSession, SESSION_FILE};
 use arboard::Clipboard;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io;
-use std::time::Instant;
-use std::path::PathBuf;
-use tokio::time::{sleep, Duration};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Instant, SystemTime};
+use std::path::{Path, PathBuf};
+use tokio::time::Duration;
+
+mod debounce;
+mod preview;
+mod properties;
+mod search_history;
+mod tree;
+mod view_state;
+
+use debounce::{Debouncer, SEARCH_DEBOUNCE};
+use preview::{PreviewHandler, PreviewRegistry};
+use tree::{DirectoryTree, TreeRow};
+use search_history::SearchHistory;
+use view_state::{ViewState, ViewStates};
+
+// Two presses of the quit key within this window skip all confirmations
+const FORCE_QUIT_WINDOW: Duration = Duration::from_millis(500);
+
+// Lines PageUp/PageDown scroll the diff popup by
+const DIFF_PAGE: usize = 20;
+
+// Old -> new pairs listed in the batch rename popup
+const BATCH_RENAME_PREVIEW_LINES: usize = 10;
+
+// Entries read per batch when filling in a large directory's metadata
+const METADATA_BATCH: usize = 500;
+
+// Saved next to the config file when `remember_view_state` is on
+const VIEW_STATE_FILE: &str = "view_state.json";
+const SEARCH_HISTORY_FILE: &str = "search_history.json";
+const FRECENCY_FILE: &str = "frecency.json";
+
+// Directories listed in the jump popup
+const JUMP_CANDIDATES: usize = 10;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SearchStrategy {
     Fast,        // Quick search with limited depth and results
     Comprehensive, // Full search with all features
     LocalOnly,   // Search only in current directory files
+    Content,     // Search inside text files for the pattern
+    Indexed,     // Match names in the saved file index without walking
+}
+
+/// Which kinds of entries the search results view shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResultTypeFilter {
+    All,
+    FilesOnly,
+    DirectoriesOnly,
+}
+
+impl ResultTypeFilter {
+    pub fn next(&self) -> Self {
+        match self {
+            ResultTypeFilter::All => ResultTypeFilter::FilesOnly,
+            ResultTypeFilter::FilesOnly => ResultTypeFilter::DirectoriesOnly,
+            ResultTypeFilter::DirectoriesOnly => ResultTypeFilter::All,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            ResultTypeFilter::All => "files & directories",
+            ResultTypeFilter::FilesOnly => "files only",
+            ResultTypeFilter::DirectoriesOnly => "directories only",
+        }
+    }
+
+    fn accepts(&self, file_info: &FileInfo) -> bool {
+        match self {
+            ResultTypeFilter::All => true,
+            ResultTypeFilter::FilesOnly => !file_info.is_directory,
+            ResultTypeFilter::DirectoriesOnly => file_info.is_directory,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -36,10 +123,311 @@ pub enum ClipboardOperation {
 
 #[derive(Debug, Clone)]
 pub struct ClipboardEntry {
-    pub file_path: PathBuf,
+    pub file_paths: Vec<PathBuf>,
     pub operation: ClipboardOperation,
 }
 
+/// A paste stopped at an entry that already exists, waiting for the user
+/// to choose what to do with it.
+#[derive(Debug, Clone)]
+pub struct PasteConflict {
+    pub name: String,
+    pub is_directory: bool,
+    /// Use the choice for every later conflict, including inside merged folders
+    pub apply_to_all: bool,
+    job: PasteJob,
+}
+
+#[derive(Debug, Clone)]
+struct PasteJob {
+    operation: ClipboardOperation,
+    /// Where the paste was started; the user may browse elsewhere meanwhile
+    destination_dir: PathBuf,
+    total: usize,
+    queue: VecDeque<PathBuf>,
+    /// Set once the user picks a choice with "apply to all"
+    policy: Option<ConflictPolicy>,
+    pasted: Vec<(PathBuf, String)>,
+    skipped: usize,
+    errors: Vec<String>,
+    gone: Vec<PathBuf>,
+}
+
+impl PasteJob {
+    fn record(&mut self, source_path: PathBuf, outcome: Result<PasteOutcome, String>) {
+        match outcome {
+            Ok(PasteOutcome::Pasted(name)) => self.pasted.push((source_path, name)),
+            Ok(PasteOutcome::Skipped) => self.skipped += 1,
+            Err(e) => {
+                if !source_path.exists() {
+                    self.gone.push(source_path);
+                }
+                self.errors.push(e);
+            }
+        }
+    }
+}
+
+impl PasteJob {
+    fn mode(&self) -> TransferMode {
+        match self.operation {
+            ClipboardOperation::Copy => TransferMode::Copy,
+            ClipboardOperation::Cut => TransferMode::Move,
+        }
+    }
+}
+
+enum PasteOutcome {
+    Pasted(String),
+    Skipped,
+}
+
+/// What to do with one clipboard entry once its destination is checked.
+enum PastePlan {
+    Conflict,
+    Transfer { destination: PathBuf, policy: ConflictPolicy, merge: bool },
+}
+
+enum ChecksumEvent {
+    Progress(u64, u64),
+    Finished(io::Result<String>),
+}
+
+/// A search streaming results from a blocking task; `poll_search` moves
+/// them into the list as they arrive. Each search gets its own channel, so
+/// once a newer one replaces it nothing it still finds reaches the list.
+pub struct RunningSearch {
+    results: mpsc::Receiver<SearchResult>,
+    cancel: Arc<AtomicBool>,
+    /// Matches found so far, including those past the result limit
+    matched: Arc<AtomicUsize>,
+    /// `matched` as last reported in the status line
+    reported_matches: usize,
+    /// When the search is stopped if it is still walking
+    deadline: Instant,
+}
+
+enum IndexEvent {
+    /// The index saved by an earlier run, usable while it is refreshed
+    Loaded(Arc<FileIndex>),
+    Progress(usize),
+    /// The new index, and whether it could be saved
+    Finished(io::Result<(Arc<FileIndex>, io::Result<()>)>),
+}
+
+/// A file index being loaded, refreshed or rebuilt on a blocking task.
+pub struct RunningIndex {
+    root: PathBuf,
+    /// Started by the rebuild key rather than at startup, so progress is
+    /// reported in the status line
+    manual: bool,
+    /// Entries walked so far
+    found: usize,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<IndexEvent>,
+}
+
+/// A look for duplicate files under `root` on a blocking task, see
+/// `start_find_duplicates`.
+pub struct RunningDuplicates {
+    root: PathBuf,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<DuplicateEvent>,
+}
+
+/// The groups of identical files listed in the results view, in the order
+/// the scan found them. Each result's group is looked up by path.
+pub struct DuplicateResults {
+    root: PathBuf,
+    /// Size of each group's files
+    sizes: Vec<u64>,
+    group_of: HashMap<PathBuf, usize>,
+}
+
+/// One narrowing of the search results by another pattern, see
+/// `start_refining`. The last of `App::refinements` is the level
+/// `search_input` edits.
+pub struct Refinement {
+    /// What `search_input` held before this level, for the breadcrumb
+    parent_pattern: String,
+    /// The results this level narrows, restored when it is dropped
+    base: Vec<SearchResult>,
+}
+
+/// A checksum being computed on a blocking task for the selected file.
+pub struct RunningChecksum {
+    path: PathBuf,
+    name: String,
+    algorithm: ChecksumAlgorithm,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<ChecksumEvent>,
+}
+
+enum ExtractEvent {
+    Progress(usize, Option<usize>),
+    Finished(io::Result<usize>),
+}
+
+/// An archive being unpacked on a blocking task.
+pub struct RunningExtraction {
+    name: String,
+    destination: PathBuf,
+    /// Whether the destination was created for this extraction, so a
+    /// failed or cancelled run can remove it again
+    created: bool,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<ExtractEvent>,
+}
+
+enum CompressEvent {
+    Progress(u64, u64),
+    Finished(io::Result<usize>),
+}
+
+/// A zip archive being written on a blocking task.
+pub struct RunningCompression {
+    output: PathBuf,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<CompressEvent>,
+}
+
+enum CompareEvent {
+    Progress(u64, u64),
+    Finished(io::Result<compare::Comparison>),
+}
+
+/// Two marked files being compared on a blocking task.
+pub struct RunningCompare {
+    left: PathBuf,
+    right: PathBuf,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<CompareEvent>,
+}
+
+/// `git status` of the current directory being read on a blocking task.
+pub struct RunningGitStatus {
+    events: mpsc::Receiver<io::Result<Option<RepoStatus>>>,
+}
+
+/// Metadata for the entries of a large directory, which was listed from
+/// names only, being read on a blocking task and sent back in batches.
+pub struct RunningMetadata {
+    dir: PathBuf,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<Vec<(PathBuf, Option<FileInfo>)>>,
+}
+
+/// The selected directory being listed for the preview pane on a blocking task.
+struct RunningDirectoryPreview {
+    path: PathBuf,
+    events: mpsc::Receiver<Vec<Line<'static>>>,
+}
+
+/// Mounted volumes shown in place of the file list.
+pub struct DrivesView {
+    pub volumes: Vec<Volume>,
+    pub state: ListState,
+}
+
+/// The comparison of two marked files, scrolled to `scroll`.
+pub struct DiffPopup {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub scroll: usize,
+}
+
+/// The directory tree pane, rooted at the starting directory.
+pub struct TreePane {
+    pub tree: DirectoryTree,
+    pub state: ListState,
+    /// Whether keys go to the tree rather than the file list
+    pub focused: bool,
+}
+
+/// The properties popup. Rows still loading hold `properties::LOADING`
+/// until their value arrives from `events`.
+pub struct PropertiesPopup {
+    pub path: PathBuf,
+    pub rows: Vec<(&'static str, String)>,
+    cancel: Arc<AtomicBool>,
+    events: Option<mpsc::Receiver<(&'static str, String)>>,
+}
+
+/// The open-with popup for one file.
+pub struct OpenWithMenu {
+    pub path: PathBuf,
+    pub apps: Vec<OpenWithApp>,
+    pub state: ListState,
+}
+
+/// The share manager popup, listing what was shared when it was last
+/// refreshed.
+pub struct ShareManager {
+    pub shares: Vec<ShareInfo>,
+    pub state: ListState,
+}
+
+/// A share link shown as a QR code, until the next key press.
+pub struct QrPopup {
+    pub name: String,
+    pub url: String,
+    pub lines: Vec<String>,
+}
+
+/// A paste entry being copied or moved on a blocking task.
+pub struct RunningTransfer {
+    pub name: String,
+    pub progress: Option<TransferProgress>,
+    job: PasteJob,
+    source_path: PathBuf,
+    cancel: Arc<AtomicBool>,
+    events: mpsc::Receiver<TransferEvent>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PendingAction {
+    Delete(Vec<FileInfo>),
+    Trash(Vec<FileInfo>),
+    EmptyTrash,
+    Extract { archive: PathBuf, destination: PathBuf },
+    Search,
+    /// Marks every shown search result
+    MarkResults,
+    /// Stops every share; the caller revokes them
+    RevokeShares,
+    Quit,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfirmDialog {
+    pub title: String,
+    pub message: String,
+    pub action: PendingAction,
+}
+
+#[derive(Debug, Clone)]
+pub enum InputPurpose {
+    Rename(FileInfo),
+    /// Marked entries renamed together from a pattern
+    BatchRename(Vec<PathBuf>),
+    NewFile,
+    NewDir,
+    Goto,
+    /// A fuzzy pattern picking one of the most visited directories
+    Jump,
+    /// Where to write the shown search results
+    Export,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputPrompt {
+    pub title: String,
+    pub value: String,
+    pub purpose: InputPurpose,
+    /// Entries matching the last Tab completion, shown under the input
+    pub completions: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub enum MessageType {
     Info,
@@ -60,7 +448,17 @@ impl SearchStrategy {
         match self {
             SearchStrategy::Fast => SearchStrategy::Comprehensive,
             SearchStrategy::Comprehensive => SearchStrategy::LocalOnly,
-            SearchStrategy::LocalOnly => SearchStrategy::Fast,
+            SearchStrategy::LocalOnly => SearchStrategy::Content,
+            SearchStrategy::Content => SearchStrategy::Indexed,
+            SearchStrategy::Indexed => SearchStrategy::Fast,
+        }
+    }
+
+    /// How long a search may run before it is stopped
+    pub fn time_limit(&self, settings: &SearchSettings) -> Duration {
+        match self {
+            SearchStrategy::Fast => Duration::from_secs(settings.fast_timeout_secs),
+            _ => Duration::from_secs(settings.timeout_secs),
         }
     }
 
@@ -69,6 +467,8 @@ impl SearchStrategy {
             SearchStrategy::Fast => "Fast (limited depth)",
             SearchStrategy::Comprehensive => "Comprehensive (full search)",
             SearchStrategy::LocalOnly => "Local (current dir only)",
+            SearchStrategy::Content => "Content (inside files)",
+            SearchStrategy::Indexed => "Indexed (file name index)",
         }
     }
 }
@@ -80,26 +480,151 @@ pub struct App {
     pub config: Config,
     pub list_state: ListState,
     pub search_mode: bool,
+    /// Typing narrows the current listing (see `FileExplorer::set_filter`)
+    pub filter_mode: bool,
+    /// Free space shown in the header, and the directory it was read for
+    disk_usage: Option<file_system::DiskUsage>,
+    disk_usage_path: PathBuf,
+    /// Blocks every operation that changes files; starts from `read_only`
+    /// in the config and can be toggled at runtime
+    pub read_only: bool,
     pub search_input: String,
+    /// Results as shown, after `result_type_filter` is applied to `all_search_results`
     pub search_results: Vec<SearchResult>,
+    pub all_search_results: Vec<SearchResult>,
+    /// Files the running search found under more than one path
+    search_duplicates: search::Duplicates,
+    pub result_type_filter: ResultTypeFilter,
     pub search_list_state: ListState,
     pub status_message: Option<StatusMessage>,
     pub search_strategy: SearchStrategy,
     pub showing_search_results: bool,
+    /// The results view's preview pane is collapsed to give the paths the full width
+    pub search_preview_hidden: bool,
     pub clipboard: Option<ClipboardEntry>,
+    /// Entries marked for bulk operations; cleared when the listing changes directory
+    pub marked: HashSet<PathBuf>,
+    /// Large root the user already agreed to search comprehensively this search session
+    pub slow_search_confirmed: Option<PathBuf>,
+    /// Directories the walking strategies search together instead of the
+    /// current one; empty searches the current directory
+    pub search_roots: Vec<PathBuf>,
+    pub running_search: Option<RunningSearch>,
+    /// The last search ran out of time, so its results are partial
+    pub search_timed_out: bool,
+    /// Typing in the search input, searched once it pauses
+    search_debounce: Debouncer,
+    /// Patterns narrowing the results without searching again, oldest first
+    pub refinements: Vec<Refinement>,
+    search_history: SearchHistory,
+    /// Entry of `search_history` recalled into the search input
+    history_cursor: Option<usize>,
+    /// Names below the start path for the indexed strategy, possibly
+    /// older than the disk while `running_index` refreshes it
+    pub file_index: Option<Arc<FileIndex>>,
+    pub running_index: Option<RunningIndex>,
+    pub running_duplicates: Option<RunningDuplicates>,
+    /// Set while the results are groups of duplicate files rather than matches
+    pub duplicates: Option<DuplicateResults>,
+    /// Set while the results are the files modified within this long, see
+    /// `start_recent_search`
+    pub recent_window: Option<Duration>,
+    pub confirm_dialog: Option<ConfirmDialog>,
+    pub paste_conflict: Option<PasteConflict>,
+    pub running_transfer: Option<RunningTransfer>,
+    pub running_checksum: Option<RunningChecksum>,
+    pub running_extraction: Option<RunningExtraction>,
+    pub running_compression: Option<RunningCompression>,
+    pub running_compare: Option<RunningCompare>,
+    pub running_git_status: Option<RunningGitStatus>,
+    /// Git status glyphs for the listing; `None` outside repositories
+    pub git_status: Option<RepoStatus>,
+    /// Directory the last git status read was started for, cleared to
+    /// read it again after a refresh
+    git_status_dir: Option<PathBuf>,
+    pub running_metadata: Option<RunningMetadata>,
+    running_directory_preview: Option<RunningDirectoryPreview>,
+    /// Preview lines of the selected directory, once listed
+    directory_preview: Option<(PathBuf, Vec<Line<'static>>)>,
+    pub input_prompt: Option<InputPrompt>,
+    /// Selection in the bookmarks popup while it is open
+    pub bookmark_picker: Option<ListState>,
+    pub share_manager: Option<ShareManager>,
+    pub qr_popup: Option<QrPopup>,
+    pub open_with_menu: Option<OpenWithMenu>,
+    pub drives_view: Option<DrivesView>,
+    pub properties: Option<PropertiesPopup>,
+    pub diff_popup: Option<DiffPopup>,
+    pub tree_pane: TreePane,
+    pub config_edit_offered: bool,
+    pub last_quit_press: Option<Instant>,
+    pub should_quit: bool,
+    pub force_quit: bool,
+    pub show_bookmarks_bar: bool,
+    pub watcher: Option<DirectoryWatcher>,
+    /// Handlers that fill the preview pane
+    previews: PreviewRegistry,
+    /// How recently left directories were being viewed
+    view_states: ViewStates,
+    /// First visible row of the file list as last drawn
+    list_offset: std::cell::Cell<usize>,
 }
 
 impl App {
-    pub fn new(explorer: FileExplorer, search_engine: SearchEngine, config: Config) -> App {
+    pub fn new(mut explorer: FileExplorer, search_engine: SearchEngine, config: Config) -> App {
+        if explorer.show_hidden() != config.show_hidden {
+            let _ = explorer.set_show_hidden(config.show_hidden);
+        }
+        explorer.set_sort(config.default_sort, config.default_sort_reverse);
+        explorer.set_open_commands(config.open_commands.clone());
+        explorer.set_resolve_symlinks(config.resolve_symlinks_on_navigate);
+        explorer.set_hide_ignored(config.hide_gitignored);
+        if config.persist_frecency {
+            if let Some(frecency) = config.state_file_path(FRECENCY_FILE).ok().and_then(|path| Frecency::load(&path).ok()) {
+                *explorer.frecency_mut() = frecency;
+            }
+        }
+
+        let show_bookmarks_bar = config.show_bookmarks_bar;
+        let read_only = config.read_only;
+        let search_roots = config.search_roots();
+        let view_states = if config.remember_view_state {
+            config.state_file_path(VIEW_STATE_FILE).ok()
+                .and_then(|path| ViewStates::load(&path).ok())
+                .unwrap_or_default()
+        } else {
+            ViewStates::default()
+        };
+        let search_history = if config.persist_search_history {
+            config.state_file_path(SEARCH_HISTORY_FILE).ok()
+                .and_then(|path| SearchHistory::load(&path).ok())
+                .unwrap_or_default()
+        } else {
+            SearchHistory::default()
+        };
+        let mut tree = DirectoryTree::new(explorer.current_path().to_path_buf());
+        if config.show_tree_pane {
+            let _ = tree.expand(0, explorer.show_hidden());
+        }
+        let kb = &config.key_bindings;
+        let previews = PreviewRegistry::new(kb.get_key_display(&kb.actions.extract));
+        let watcher = config.watch_directory.then(DirectoryWatcher::new);
         let mut app = App {
             explorer,
             search_engine,
-            file_share_server: FileShareServer::new(),
+            file_share_server: FileShareServer::new(config.clone()),
             config,
             list_state: ListState::default(),
             search_mode: false,
+            filter_mode: false,
+            read_only,
+            disk_usage: None,
+            disk_usage_path: PathBuf::new(),
             search_input: String::new(),
             search_results: Vec::new(),
+            all_search_results: Vec::new(),
+            search_duplicates: search::Duplicates::default(),
+            result_type_filter: ResultTypeFilter::All,
             search_list_state: ListState::default(),
             status_message: Some(StatusMessage {
                 text: "Press '/' to search, 'q' to quit, Enter to navigate, 'x' to cut, 'c' to copy, 'v' to paste".to_string(),
@@ -109,7 +634,53 @@ impl App {
             }),
             search_strategy: SearchStrategy::Fast,
             showing_search_results: false,
+            search_preview_hidden: false,
             clipboard: None,
+            marked: HashSet::new(),
+            slow_search_confirmed: None,
+            search_roots,
+            confirm_dialog: None,
+            paste_conflict: None,
+            running_transfer: None,
+            running_search: None,
+            search_timed_out: false,
+            search_debounce: Debouncer::new(SEARCH_DEBOUNCE),
+            refinements: Vec::new(),
+            search_history,
+            history_cursor: None,
+            file_index: None,
+            running_index: None,
+            running_duplicates: None,
+            duplicates: None,
+            recent_window: None,
+            running_checksum: None,
+            running_extraction: None,
+            running_compression: None,
+            running_compare: None,
+            running_git_status: None,
+            git_status: None,
+            git_status_dir: None,
+            running_metadata: None,
+            running_directory_preview: None,
+            directory_preview: None,
+            input_prompt: None,
+            bookmark_picker: None,
+            share_manager: None,
+            qr_popup: None,
+            open_with_menu: None,
+            drives_view: None,
+            properties: None,
+            diff_popup: None,
+            tree_pane: TreePane { tree, state: ListState::default().with_selected(Some(0)), focused: false },
+            config_edit_offered: false,
+            last_quit_press: None,
+            should_quit: false,
+            force_quit: false,
+            show_bookmarks_bar,
+            watcher,
+            previews,
+            view_states,
+            list_offset: std::cell::Cell::new(0),
         };
         app.list_state.select(Some(0));
         app
@@ -218,978 +789,7231 @@ impl App {
         }
     }
 
-    pub async fn perform_search(&mut self) {
-        if !self.search_input.is_empty() {
-            // Show searching indicator
-            self.set_info_message(format!("Searching for '{}' in {}...", 
-                self.search_input,
-                self.explorer.current_path().display()
-            ));
-
-            let result = match self.search_strategy {
-                SearchStrategy::Fast => {
-                    self.search_engine.search_fast(self.explorer.current_path(), &self.search_input, 100).await
-                }
-                SearchStrategy::Comprehensive => {
-                    self.search_engine.search(self.explorer.current_path(), &self.search_input).await
-                }
-                SearchStrategy::LocalOnly => {
-                    let results = self.search_engine.search_in_files(self.explorer.files(), &self.search_input);
-                    Ok(results)
-                }
-            };
+    /// Starts searching for `search_input`, replacing any search still
+    /// running. Results arrive through `poll_search`, except for the local
+    /// and indexed strategies, which don't touch the disk.
+    pub fn perform_search(&mut self) {
+        self.cancel_search();
+        if !self.refinements.is_empty() {
+            self.refine_results();
+            return;
+        }
+        self.duplicates = None;
+        self.recent_window = None;
+        self.search_timed_out = false;
+        if self.request_slow_search_confirmation() || self.search_input.is_empty() {
+            return;
+        }
 
-            match result {
-                Ok(results) => {
-                    self.search_results = results;
-                    self.search_list_state.select(if self.search_results.is_empty() { None } else { Some(0) });
-                    if self.search_results.is_empty() {
-                        self.set_warning_message(format!("No results found for '{}' ({})", 
-                            self.search_input,
-                            self.search_strategy.description()
-                        ));
-                    } else {
-                        self.set_info_message(format!("Found {} results ({})", 
-                            self.search_results.len(), 
-                            self.search_strategy.description()
-                        ));
+        let (sender, results) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let matched = Arc::new(AtomicUsize::new(0));
+        let roots = self.walk_roots();
+        let started = match self.search_strategy {
+            SearchStrategy::Fast => {
+                self.search_engine.search_fast(&roots, &self.search_input, 100, sender, cancel.clone(), matched.clone())
+            }
+            SearchStrategy::Comprehensive => {
+                self.search_engine.search(&roots, &self.search_input, sender, cancel.clone(), matched.clone())
+            }
+            SearchStrategy::LocalOnly | SearchStrategy::Indexed => {
+                let results = if self.search_strategy == SearchStrategy::Indexed {
+                    self.search_index()
+                } else {
+                    self.search_engine.search_in_files(self.explorer.files(), &self.search_input)
+                        .map(|results| {
+                            let matched = results.len();
+                            (results, matched)
+                        })
+                };
+                match results {
+                    Ok((results, matched)) => {
+                        self.all_search_results = results;
+                        self.apply_result_filter();
+                        self.report_search_results(matched);
                     }
+                    Err(e) => self.set_error_message(format!("Search error: {}", e)),
                 }
-                Err(e) => {
-                    self.set_error_message(format!("Search error: {}", e));
-                }
+                return;
             }
-        }
+            SearchStrategy::Content => {
+                self.search_engine.search_content(&roots, &self.search_input, sender, cancel.clone(), matched.clone())
+            }
+        };
+        self.track_search(started, &roots, results, cancel, matched);
     }
 
-    pub fn toggle_search_strategy(&mut self) {
-        self.search_strategy = self.search_strategy.next();
-        self.set_info_message(format!("Search strategy: {}", self.search_strategy.description()));
-        
-        // Re-run search if we're in search mode and have input
-        if self.search_mode && !self.search_input.is_empty() {
-            // We'll trigger a search on the next event loop iteration
-            if let Some(ref mut msg) = self.status_message {
-                msg.text.push_str(" - type to search again");
-            }
-        }
+    /// Lists the files under the search roots modified within the last
+    /// `search.recent_hours`, newest first. Streams in like a walking
+    /// search, with the same time limit and cancelling.
+    pub fn start_recent_search(&mut self) {
+        let within = Duration::from_secs(self.search_engine.settings.recent_hours * 60 * 60);
+        self.clear_search_results();
+        self.recent_window = Some(within);
+        self.showing_search_results = true;
+
+        let (sender, results) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let matched = Arc::new(AtomicUsize::new(0));
+        let roots = self.walk_roots();
+        let started = self.search_engine.search_recent(&roots, within, sender, cancel.clone(), matched.clone());
+        self.track_search(started, &roots, results, cancel, matched);
     }
 
-    pub fn navigate_to_selected(&mut self) -> Result<(), std::io::Error> {
-        if self.search_mode || self.showing_search_results {
-            if let Some(selected) = self.search_list_state.selected() {
-                if let Some(result) = self.search_results.get(selected) {
-                    if result.file_info.is_directory {
-                        self.explorer.navigate_to(result.file_info.path.clone())?;
-                        self.clear_search_results();
-                    }
-                }
+    /// Takes over a walking search that `started` on `roots`: its results
+    /// come in through `poll_search` until it ends, is cancelled or runs
+    /// out of time.
+    fn track_search(
+        &mut self,
+        started: Result<(), Box<dyn std::error::Error + Send + Sync>>,
+        roots: &[PathBuf],
+        results: mpsc::Receiver<SearchResult>,
+        cancel: Arc<AtomicBool>,
+        matched: Arc<AtomicUsize>,
+    ) {
+        self.all_search_results.clear();
+        self.search_duplicates = search::Duplicates::default();
+        self.apply_result_filter();
+        match started {
+            Ok(()) => {
+                let places: Vec<String> = roots.iter().map(|root| root.display().to_string()).collect();
+                self.set_info_message(format!("Searching for {} in {}...", 
+                    self.search_subject(),
+                    places.join(", ")
+                ));
+                self.running_search = Some(RunningSearch {
+                    results,
+                    cancel,
+                    matched,
+                    reported_matches: 0,
+                    deadline: Instant::now() + self.search_time_limit(),
+                });
             }
-        } else if let Some(selected) = self.list_state.selected() {
-            if let Some(file) = self.explorer.files().get(selected) {
-                if file.is_directory {
-                    self.explorer.navigate_to(file.path.clone())?;
-                    self.list_state.select(Some(0));
-                }
+            Err(e) => {
+                self.set_error_message(format!("Search error: {}", e));
             }
         }
-        Ok(())
-    }
-
-    pub fn go_up(&mut self) -> Result<(), std::io::Error> {
-        self.explorer.go_up()?;
-        self.list_state.select(Some(0));
-        Ok(())
     }
 
-    pub fn enter_search_mode(&mut self) {
-        self.search_mode = true;
-        self.showing_search_results = false;
-        self.search_input.clear();
-        self.search_results.clear();
-        self.set_info_message(format!("Search mode: {} - Type to search, F2 to toggle strategy, ESC to exit, Enter to keep results", 
-            self.search_strategy.description()));
+    /// What the search looks for, for status messages: the quoted query,
+    /// or e.g. "files modified in the last 24h"
+    fn search_subject(&self) -> String {
+        match self.recent_window {
+            Some(window) => format!("files modified in the last {}h", window.as_secs() / (60 * 60)),
+            None => format!("'{}'", self.search_input),
+        }
     }
 
-    pub fn exit_search_mode(&mut self) {
-        if !self.search_results.is_empty() {
-            // Keep search results and switch to showing them
-            self.search_mode = false;
-            self.showing_search_results = true;
-            self.set_info_message(format!("Search results ({} items) - Navigate with ↑↓, Enter to open, '/' to search again", 
-                self.search_results.len()));
+    fn search_description(&self) -> &str {
+        if self.recent_window.is_some() {
+            "Recently modified (newest first)"
         } else {
-            // No results, clear everything
-            self.search_mode = false;
-            self.showing_search_results = false;
-            self.search_input.clear();
-            self.set_info_message("Press '/' to search, 'q' to quit, Enter to navigate".to_string());
+            self.search_strategy.description()
         }
     }
 
-    pub fn clear_search_results(&mut self) {
-        self.search_mode = false;
-        self.showing_search_results = false;
-        self.search_input.clear();
-        self.search_results.clear();
-        self.search_list_state = ListState::default();
-        self.list_state.select(Some(0));
-        self.set_info_message("Press '/' to search, 'q' to quit, Enter to navigate, 'x' to cut, 'c' to copy, 'v' to paste".to_string());
+    /// How long a walking search may run; the recently modified view walks
+    /// as far as a comprehensive search
+    fn search_time_limit(&self) -> Duration {
+        let strategy = if self.recent_window.is_some() { &SearchStrategy::Comprehensive } else { &self.search_strategy };
+        strategy.time_limit(&self.search_engine.settings)
     }
 
-    pub fn open_selected_file(&mut self) -> Result<String, String> {
-        let selected_file = self.get_selected_file()?;
-
-        if selected_file.is_directory {
-            return Err("Cannot open directory as file. Use Enter to navigate.".to_string());
+    /// Where the walking strategies search: the active root set, or the
+    /// current directory when it is empty.
+    fn walk_roots(&self) -> Vec<PathBuf> {
+        if self.search_roots.is_empty() {
+            vec![self.explorer.current_path().to_path_buf()]
+        } else {
+            self.search_roots.clone()
         }
+    }
 
-        match self.explorer.open_file(selected_file) {
-            Ok(_) => Ok(format!("Opened '{}' with default application", selected_file.name)),
-            Err(e) => Err(format!("Failed to open '{}': {}", selected_file.name, e)),
+    /// Adds the current directory to the search roots, or removes it when
+    /// it is already one of them.
+    pub fn toggle_search_root(&mut self) -> Result<String, String> {
+        let dir = self.explorer.current_path().to_path_buf();
+        if let Some(at) = self.search_roots.iter().position(|root| *root == dir) {
+            self.search_roots.remove(at);
+            if self.search_roots.is_empty() {
+                return Ok(format!("Removed {} from the search roots - searching the current directory again", dir.display()));
+            }
+            return Ok(format!("Removed {} from the search roots ({} left)", dir.display(), self.search_roots.len()));
+        }
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()));
         }
+        let message = format!("Added {} to the search roots ({} in all)", dir.display(), self.search_roots.len() + 1);
+        self.search_roots.push(dir);
+        Ok(message)
     }
 
-    pub fn reveal_selected_in_file_manager(&mut self) -> Result<String, String> {
-        let selected_file = self.get_selected_file()?;
-
-        match self.explorer.reveal_in_file_manager(selected_file) {
-            Ok(_) => Ok(format!("Revealed '{}' in file manager", selected_file.name)),
-            Err(e) => Err(format!("Failed to reveal '{}': {}", selected_file.name, e)),
+    /// The active search roots, for the results title
+    fn roots_note(&self) -> String {
+        if self.search_roots.is_empty()
+            || matches!(self.search_strategy, SearchStrategy::LocalOnly | SearchStrategy::Indexed)
+        {
+            return String::new();
         }
+        let roots: Vec<String> = self.search_roots.iter().map(|root| root.display().to_string()).collect();
+        format!(" - in {}", roots.join(", "))
     }
 
-    fn get_selected_file(&self) -> Result<&FileInfo, String> {
-        if self.showing_search_results {
-            if let Some(selected_idx) = self.search_list_state.selected() {
-                if selected_idx < self.search_results.len() {
-                    Ok(&self.search_results[selected_idx].file_info)
-                } else {
-                    Err("Invalid selection".to_string())
-                }
-            } else {
-                Err("No file selected".to_string())
+    /// Matches `search_input` against the part of the file index below the
+    /// current directory; see `SearchEngine::search_index`.
+    fn search_index(&self) -> Result<(Vec<SearchResult>, usize), String> {
+        let dir = self.explorer.current_path();
+        let kb = &self.config.key_bindings;
+        let rebuild_key = kb.get_key_display(&kb.actions.rebuild_index);
+        let index = match (&self.file_index, &self.running_index) {
+            (Some(index), _) if dir.starts_with(&index.root) => index,
+            (_, Some(running)) if dir.starts_with(&running.root) => {
+                return Err(format!("still indexing {} ({} entries so far)", running.root.display(), running.found));
             }
-        } else {
-            if let Some(selected_idx) = self.list_state.selected() {
-                if selected_idx < self.explorer.files().len() {
-                    Ok(&self.explorer.files()[selected_idx])
-                } else {
-                    Err("Invalid selection".to_string())
-                }
-            } else {
-                Err("No file selected".to_string())
+            (Some(index), _) => {
+                return Err(format!("{} is outside the file index of {} - press {} to index it",
+                    dir.display(), index.root.display(), rebuild_key));
             }
-        }
+            (None, _) => return Err(format!("no file index yet - press {} to build one", rebuild_key)),
+        };
+        self.search_engine.search_index(index, dir, &self.search_input)
     }
 
-    pub async fn share_selected_file(&mut self) -> Result<String, String> {
-        let selected_file_path = {
-            let selected_file = self.get_selected_file()?;
-            if selected_file.is_directory {
-                return Err("Cannot share directories. Please select a file.".to_string());
-            }
-            selected_file.path.clone()
+    /// Moves results the running search has found into the list, keeping
+    /// it sorted by score, and stops the search once its time is up.
+    pub fn poll_search(&mut self) {
+        let Some(running) = self.running_search.as_mut() else {
+            return;
         };
 
-        let file_name = selected_file_path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
+        let mut found = Vec::new();
+        let finished = loop {
+            match running.results.try_recv() {
+                Ok(result) => found.push(result),
+                Err(mpsc::TryRecvError::Empty) => break false,
+                Err(mpsc::TryRecvError::Disconnected) => break true,
+            }
+        };
+        let timed_out = !finished && Instant::now() >= running.deadline;
+        let matched = running.matched.load(Ordering::Relaxed);
+        let progressed = !found.is_empty() || matched != running.reported_matches;
+        running.reported_matches = matched;
+        for result in found {
+            self.insert_search_result(result);
+        }
+        // A file found again under another path isn't another match
+        let matched = matched.saturating_sub(self.search_duplicates.merged());
 
-        match self.file_share_server.share_file(&selected_file_path).await {
-            Ok(url) => Ok(format!("Shared '{}' - Link copied to clipboard: {}", file_name, url)),
-            Err(e) => Err(format!("Failed to share '{}': {}", file_name, e)),
+        if finished {
+            self.running_search = None;
+            self.report_search_results(matched);
+        } else if timed_out {
+            self.cancel_search();
+            self.search_timed_out = true;
+            self.set_warning_message(format!("Partial results - search timed out after {}s with {} results ({}){} - try a more specific term", 
+                self.search_time_limit().as_secs(),
+                self.all_search_results.len(),
+                self.search_description(),
+                self.truncation_note(matched)
+            ));
+        } else if progressed {
+            self.set_info_message(format!("Searching for {}... {} results so far{}{}", 
+                self.search_subject(),
+                self.all_search_results.len(),
+                self.truncation_note(matched),
+                self.result_filter_note()
+            ));
         }
     }
 
-    pub fn cut_selected_file(&mut self) -> Result<String, String> {
-        let (file_path, file_name) = {
-            let selected_file = self.get_selected_file()?;
-            (selected_file.path.clone(), selected_file.name.clone())
-        };
-        
-        self.clipboard = Some(ClipboardEntry {
-            file_path,
-            operation: ClipboardOperation::Cut,
+    /// Opens the search input to narrow the current results by another
+    /// pattern, matching names only and without walking the disk.
+    pub fn start_refining(&mut self) {
+        self.cancel_search();
+        self.refinements.push(Refinement {
+            parent_pattern: std::mem::take(&mut self.search_input),
+            base: self.all_search_results.clone(),
         });
-        
-        Ok(format!("Cut '{}' - navigate to destination and press 'v' to paste", file_name))
+        self.search_mode = true;
+        self.showing_search_results = false;
+        self.set_info_message(format!("Refining {} results - type to narrow them, Backspace on an empty pattern goes back a level, ESC restores them",
+            self.all_search_results.len()));
     }
 
-    pub fn copy_selected_file(&mut self) -> Result<String, String> {
-        let (file_path, file_name) = {
-            let selected_file = self.get_selected_file()?;
-            (selected_file.path.clone(), selected_file.name.clone())
+    /// Matches `search_input` against the results the current refinement
+    /// narrows; an empty pattern keeps them all.
+    fn refine_results(&mut self) {
+        let Some(level) = self.refinements.last() else {
+            return;
         };
-        
-        self.clipboard = Some(ClipboardEntry {
-            file_path,
-            operation: ClipboardOperation::Copy,
-        });
-        
-        Ok(format!("Copied '{}' - navigate to destination and press 'v' to paste", file_name))
-    }
-
-    pub fn paste_file(&mut self) -> Result<String, String> {
-        let clipboard_entry = match &self.clipboard {
-            Some(entry) => entry.clone(),
-            None => return Err("Nothing to paste - cut or copy a file first".to_string()),
+        let results = if self.search_input.is_empty() {
+            Ok(level.base.clone())
+        } else {
+            let files: Vec<FileInfo> = level.base.iter().map(|result| result.file_info.clone()).collect();
+            self.search_engine.search_in_files(&files, &self.search_input)
         };
+        match results {
+            Ok(results) => {
+                self.all_search_results = results;
+                self.apply_result_filter();
+                self.report_search_results(self.all_search_results.len());
+            }
+            Err(e) => self.set_error_message(format!("Search error: {}", e)),
+        }
+    }
 
-        // Check if source file still exists
-        if !clipboard_entry.file_path.exists() {
-            self.clipboard = None;
-            return Err("Source file no longer exists".to_string());
+    /// Drops the current refinement, bringing back the results it narrowed
+    /// and the pattern that produced them. False when there is none.
+    pub fn pop_refinement(&mut self) -> bool {
+        let Some(level) = self.refinements.pop() else {
+            return false;
+        };
+        self.search_input = level.parent_pattern;
+        self.all_search_results = level.base;
+        self.apply_result_filter();
+        self.set_info_message(format!("Back to {} results for {}", self.all_search_results.len(), self.refinement_breadcrumb()));
+        true
+    }
+
+    /// The patterns that produced the current results, e.g.
+    /// "report > 2024 > pdf"
+    fn refinement_breadcrumb(&self) -> String {
+        self.refinements.iter()
+            .map(|level| level.parent_pattern.as_str())
+            .chain([self.search_input.as_str()])
+            .collect::<Vec<_>>()
+            .join(" > ")
+    }
+
+    /// Keeps the results and records the query in the search history,
+    /// unless it only refines earlier results.
+    pub fn submit_search(&mut self) {
+        // Typing still waiting for its pause is searched before leaving
+        if self.search_debounce.is_pending() {
+            self.perform_search();
         }
+        if self.refinements.is_empty() {
+            self.search_history.push(&self.search_input);
+        }
+        self.history_cursor = None;
+        self.exit_search_mode();
+    }
 
-        let source_path = &clipboard_entry.file_path;
-        let current_dir = self.explorer.current_path();
-        
-        // Get the filename from the source path
-        let file_name = source_path.file_name()
-            .ok_or("Invalid source file path")?;
-        
-        let destination_path = current_dir.join(file_name);
+    /// Brings back the query before the recalled one, or the latest one
+    /// when the input is empty. False when the input holds something
+    /// else, so Up can move through the results instead.
+    pub fn recall_previous_search(&mut self) -> bool {
+        let index = match self.browsed_history_entry() {
+            Some(index) => index.saturating_sub(1),
+            None if self.search_input.is_empty() && !self.search_history.is_empty() => self.search_history.len() - 1,
+            None => return false,
+        };
+        self.recall_search(index);
+        true
+    }
 
-        // Check if destination already exists
-        if destination_path.exists() {
-            return Err(format!("File '{}' already exists in destination directory", file_name.to_string_lossy()));
+    /// Brings back the query after the recalled one, or an empty input
+    /// past the latest. False when no unedited entry is recalled.
+    pub fn recall_next_search(&mut self) -> bool {
+        let Some(index) = self.browsed_history_entry() else {
+            return false;
+        };
+        if index + 1 < self.search_history.len() {
+            self.recall_search(index + 1);
+            return true;
+        }
+        self.history_cursor = None;
+        self.search_input.clear();
+        if self.refinements.is_empty() {
+            self.cancel_search();
+            self.search_results.clear();
+            self.all_search_results.clear();
+        } else {
+            self.perform_search();
         }
+        true
+    }
 
-        // Check if we're trying to move/copy to the same directory
-        if let Some(source_parent) = source_path.parent() {
-            if source_parent == current_dir {
-                return Err("Cannot paste file to the same directory".to_string());
-            }
+    /// The recalled history entry, as long as the input hasn't been edited
+    fn browsed_history_entry(&self) -> Option<usize> {
+        self.history_cursor
+            .filter(|&index| self.search_history.get(index) == Some(self.search_input.as_str()))
+    }
+
+    fn recall_search(&mut self, index: usize) {
+        self.history_cursor = Some(index);
+        self.search_input = self.search_history.get(index).unwrap_or_default().to_string();
+        self.perform_search();
+    }
+
+    /// Where the recalled query is in the history, e.g. "(3/17)"
+    fn history_note(&self) -> Option<String> {
+        self.browsed_history_entry()
+            .map(|index| format!("({}/{})", index + 1, self.search_history.len()))
+    }
+
+    /// Stops the running search, keeping whatever it has found so far.
+    /// Searches for the input once typing pauses for `SEARCH_DEBOUNCE`,
+    /// see `poll_search_debounce`.
+    pub fn schedule_search(&mut self) {
+        self.search_debounce.input(Instant::now());
+    }
+
+    /// Starts the search scheduled by `schedule_search` once typing paused.
+    pub fn poll_search_debounce(&mut self) {
+        if self.search_debounce.ready(Instant::now()) {
+            self.perform_search();
         }
+    }
+
+    pub fn cancel_search(&mut self) {
+        self.search_debounce.cancel();
+        if let Some(running) = self.running_search.take() {
+            running.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Refreshes the saved index of `root`, or walks `root` from scratch
+    /// when `rebuild` is set or nothing was saved, on a blocking task. A
+    /// saved index can be searched as soon as it is read; the refreshed
+    /// one replaces it through `poll_index`.
+    pub fn start_indexing(&mut self, root: PathBuf, rebuild: bool, manual: bool) -> Result<String, String> {
+        let file = self.config.state_file_path(INDEX_DIR)
+            .map_err(|e| format!("Cannot locate the index directory: {}", e))?
+            .join(FileIndex::file_name(&root));
+        let exclusions = self.search_engine.exclusions(&root)?;
+        if let Some(running) = self.running_index.take() {
+            running.cancel.store(true, Ordering::Relaxed);
+        }
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+        let task_root = root.clone();
+        let task_cancel = cancel.clone();
+        let throttle = Duration::from_millis(self.search_engine.settings.index_throttle_ms);
+        tokio::task::spawn_blocking(move || {
+            // A damaged file, or another root with the same hash, is walked again
+            let saved = if rebuild {
+                None
+            } else {
+                FileIndex::load(&file).ok().flatten().filter(|index| index.root == task_root)
+            };
+            let progress = |found| {
+                let _ = sender.send(IndexEvent::Progress(found));
+                // Progress comes every few thousand entries, which makes
+                // for evenly sized batches between pauses
+                if !throttle.is_zero() {
+                    std::thread::sleep(throttle);
+                }
+            };
+            let result = match saved {
+                Some(saved) => {
+                    let saved = Arc::new(saved);
+                    let _ = sender.send(IndexEvent::Loaded(saved.clone()));
+                    saved.refresh(&exclusions, &task_cancel, progress)
+                }
+                None => FileIndex::build(&task_root, &exclusions, &task_cancel, progress),
+            };
+            let result = result.map(|index| {
+                let saved = index.save(&file);
+                (Arc::new(index), saved)
+            });
+            let _ = sender.send(IndexEvent::Finished(result));
+        });
+
+        let message = format!("Indexing {}...", root.display());
+        self.running_index = Some(RunningIndex { root, manual, found: 0, cancel, events });
+        Ok(message)
+    }
 
-        match clipboard_entry.operation {
-            ClipboardOperation::Copy => {
-                match self.copy_file_operation(source_path, &destination_path) {
-                    Ok(_) => {
-                        self.explorer.refresh().map_err(|e| format!("Failed to refresh: {}", e))?;
-                        Ok(format!("Copied '{}' to current directory", file_name.to_string_lossy()))
+    /// Rebuilds the file index from scratch: the one the current directory
+    /// is in, or a new one rooted here.
+    pub fn rebuild_index(&mut self) -> Result<String, String> {
+        let dir = self.explorer.current_path();
+        let root = match &self.file_index {
+            Some(index) if dir.starts_with(&index.root) => index.root.clone(),
+            _ => dir.to_path_buf(),
+        };
+        self.start_indexing(root, true, true)
+    }
+
+    /// Takes over the saved index as soon as it is read and the new one
+    /// once it is finished. Only indexing started by the rebuild key
+    /// reports progress.
+    pub fn poll_index(&mut self) {
+        let Some(running) = self.running_index.as_mut() else {
+            return;
+        };
+
+        let mut progressed = false;
+        let result = loop {
+            match running.events.try_recv() {
+                Ok(IndexEvent::Loaded(index)) => self.file_index = Some(index),
+                Ok(IndexEvent::Progress(found)) => {
+                    running.found = found;
+                    progressed = true;
+                }
+                Ok(IndexEvent::Finished(result)) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    if progressed && running.manual {
+                        let msg = format!("Indexing {}... {} entries", running.root.display(), running.found);
+                        self.set_info_message(msg);
                     }
-                    Err(e) => Err(format!("Failed to copy file: {}", e)),
+                    return;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(io::Error::other("indexing stopped unexpectedly"));
                 }
             }
-            ClipboardOperation::Cut => {
-                match self.move_file_operation(source_path, &destination_path) {
-                    Ok(_) => {
-                        self.clipboard = None; // Clear clipboard after successful cut operation
-                        self.explorer.refresh().map_err(|e| format!("Failed to refresh: {}", e))?;
-                        Ok(format!("Moved '{}' to current directory", file_name.to_string_lossy()))
-                    }
-                    Err(e) => Err(format!("Failed to move file: {}", e)),
+        };
+
+        let Some(running) = self.running_index.take() else {
+            return;
+        };
+        match result {
+            Ok((index, saved)) => {
+                let count = index.entries.len();
+                self.file_index = Some(index);
+                if let Err(e) = saved {
+                    self.set_warning_message(format!("Indexed {} entries but could not save the index: {}", count, e));
+                } else if running.manual {
+                    self.set_info_message(format!("Indexed {} entries under {}", count, running.root.display()));
                 }
             }
+            Err(_) if running.cancel.load(Ordering::Relaxed) => {
+                self.set_info_message(format!("Indexing {} cancelled", running.root.display()));
+            }
+            Err(e) => self.set_error_message(format!("Failed to index {}: {}", running.root.display(), e)),
         }
     }
 
-    pub fn copy_selected_file_path(&self) -> Result<String, String> {
-        let file_info = if self.showing_search_results {
-            if let Some(selected) = self.search_list_state.selected() {
-                if selected < self.search_results.len() {
-                    &self.search_results[selected].file_info
-                } else {
-                    return Err("No file selected".to_string());
+    /// Starts looking for files with identical content under the current
+    /// directory; the groups found replace the results through
+    /// `poll_duplicates`.
+    pub fn start_find_duplicates(&mut self) -> Result<String, String> {
+        if let Some(running) = &self.running_duplicates {
+            return Err(format!("Already looking for duplicates in {}", running.root.display()));
+        }
+        let root = self.explorer.current_path().to_path_buf();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+        self.search_engine.find_duplicates(&root, sender, cancel.clone())
+            .map_err(|e| format!("Cannot look for duplicates in {}: {}", root.display(), e))?;
+        let msg = format!("Looking for duplicate files in {}...", root.display());
+        self.running_duplicates = Some(RunningDuplicates { root, cancel, events });
+        Ok(msg)
+    }
+
+    /// Reports how far the look for duplicates has got, and lists the
+    /// groups found once it is done.
+    pub fn poll_duplicates(&mut self) {
+        let Some(running) = self.running_duplicates.as_ref() else {
+            return;
+        };
+
+        let mut progress = None;
+        let result = loop {
+            match running.events.try_recv() {
+                Ok(DuplicateEvent::Progress(latest)) => progress = Some(latest),
+                Ok(DuplicateEvent::Finished(result)) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    let msg = match progress {
+                        Some(ScanProgress::Listed(files)) => format!("Finding duplicates: {} files listed", files),
+                        Some(ScanProgress::Compared { done, total }) => {
+                            format!("Finding duplicates: comparing {}/{} files of the same size", done, total)
+                        }
+                        Some(ScanProgress::Hashed { done, total }) => {
+                            format!("Finding duplicates: hashing {}%", (done * 100).checked_div(total).unwrap_or(100))
+                        }
+                        None => return,
+                    };
+                    self.set_info_message(msg);
+                    return;
                 }
-            } else {
-                return Err("No file selected".to_string());
-            }
-        } else {
-            if let Some(selected) = self.list_state.selected() {
-                let files = self.explorer.files();
-                if selected < files.len() {
-                    &files[selected]
-                } else {
-                    return Err("No file selected".to_string());
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(io::Error::other("the look for duplicates stopped unexpectedly"));
                 }
-            } else {
-                return Err("No file selected".to_string());
             }
         };
 
-        let path_str = file_info.path.to_string_lossy().to_string();
-        
-        // Copy to system clipboard
-        match Clipboard::new().and_then(|mut clipboard| clipboard.set_text(&path_str)) {
-            Ok(_) => Ok(format!("Copied path to clipboard: {}", path_str)),
-            Err(e) => Err(format!("Failed to copy path to clipboard: {}", e)),
+        let Some(running) = self.running_duplicates.take() else {
+            return;
+        };
+        match result {
+            Ok(scan) if scan.groups.is_empty() => {
+                let unreadable = unreadable_note(scan.unreadable);
+                self.set_info_message(format!("No duplicate files in {}{}", running.root.display(), unreadable));
+            }
+            Ok(scan) => {
+                let msg = self.show_duplicates(running.root, scan);
+                self.set_info_message(msg);
+            }
+            Err(_) if running.cancel.load(Ordering::Relaxed) => {
+                self.set_info_message("Look for duplicates cancelled".to_string());
+            }
+            Err(e) => self.set_error_message(format!("Failed to look for duplicates in {}: {}", running.root.display(), e)),
         }
     }
 
-    fn copy_file_operation(&self, source: &PathBuf, destination: &PathBuf) -> Result<(), std::io::Error> {
-        if source.is_dir() {
-            self.copy_directory_recursive(source, destination)
-        } else {
-            std::fs::copy(source, destination)?;
-            Ok(())
+    /// Lists the groups in `scan` as the search results, each group's
+    /// files together and the oldest first.
+    fn show_duplicates(&mut self, root: PathBuf, scan: DuplicateScan) -> String {
+        self.clear_search_results();
+        let wasted = scan.wasted();
+        let count = scan.groups.len();
+        let mut sizes = Vec::with_capacity(count);
+        let mut group_of = HashMap::new();
+        // Falling from file to file, so sorting keeps the scan's order
+        let mut score = scan.groups.iter().map(|group| group.files.len()).sum::<usize>() as i64;
+        for (index, group) in scan.groups.into_iter().enumerate() {
+            sizes.push(group.size);
+            for file_info in group.files {
+                group_of.insert(file_info.path.clone(), index);
+                score -= 1;
+                self.all_search_results.push(SearchResult {
+                    file_info,
+                    score,
+                    match_type: search::MatchType::Duplicate,
+                    content_match: None,
+                    also_found_at: Vec::new(),
+                });
+            }
         }
+        self.duplicates = Some(DuplicateResults { root, sizes, group_of });
+        self.showing_search_results = true;
+        self.apply_result_filter();
+        let kb = &self.config.key_bindings;
+        format!("{} groups of duplicates, {} reclaimable{} - {} marks all but the oldest copy of each",
+            count, format_size(wasted), unreadable_note(scan.unreadable),
+            kb.get_key_display(&kb.search_results.mark_copies))
     }
 
-    fn copy_directory_recursive(&self, source: &PathBuf, destination: &PathBuf) -> Result<(), std::io::Error> {
-        std::fs::create_dir_all(destination)?;
-        
-        for entry in std::fs::read_dir(source)? {
-            let entry = entry?;
-            let source_path = entry.path();
-            let dest_path = destination.join(entry.file_name());
-            
-            if source_path.is_dir() {
-                self.copy_directory_recursive(&source_path, &dest_path)?;
-            } else {
-                std::fs::copy(&source_path, &dest_path)?;
+    /// Marks every shown duplicate but the first, oldest, of its group,
+    /// ready for the delete key.
+    pub fn mark_duplicate_copies(&mut self) -> Result<String, String> {
+        let Some(duplicates) = &self.duplicates else {
+            return Err("Marking copies needs the results of a look for duplicates".to_string());
+        };
+        let mut kept = HashSet::new();
+        let mut marked = 0;
+        let mut size = 0;
+        for result in &self.search_results {
+            let Some(&group) = duplicates.group_of.get(&result.file_info.path) else {
+                continue;
+            };
+            if kept.insert(group) {
+                continue;
+            }
+            if self.marked.insert(result.file_info.path.clone()) {
+                marked += 1;
+                size += duplicates.sizes[group];
             }
         }
-        
-        Ok(())
+        if marked == 0 {
+            return Err("No copies left to mark".to_string());
+        }
+        Ok(format!("Marked {} copies ({}), keeping the oldest of each group", marked, format_size(size)))
     }
 
-    fn move_file_operation(&self, source: &PathBuf, destination: &PathBuf) -> Result<(), std::io::Error> {
-        std::fs::rename(source, destination)
+    /// How current the file index is, for the results title
+    fn index_note(&self) -> String {
+        if self.search_strategy != SearchStrategy::Indexed {
+            return String::new();
+        }
+        match (&self.file_index, &self.running_index) {
+            (_, Some(_)) => " - index refreshing".to_string(),
+            (Some(index), None) => {
+                let age = index.built_at.elapsed().unwrap_or_default();
+                format!(" - index {} old", format_age(age))
+            }
+            (None, None) => String::new(),
+        }
     }
 
-    pub fn get_file_preview(&self) -> Vec<String> {
-        let files = self.explorer.files();
-        let selected_index = match self.list_state.selected() {
-            Some(index) => index,
-            None => return vec!["No file selected".to_string()],
+    /// Inserts `result` after those ordered ahead of or level with it,
+    /// see `search::result_order`. The
+    /// highlighted result stays where it is unless it is the top one. A
+    /// file already listed under another path is merged with that result.
+    fn insert_search_result(&mut self, result: SearchResult) {
+        let directories_first = self.search_engine.settings.directories_first;
+        let after = |other: &SearchResult, result: &SearchResult| {
+            search::result_order(other, result, directories_first) != std::cmp::Ordering::Greater
         };
-        
-        if selected_index >= files.len() {
-            return vec!["No file selected".to_string()];
+        let (result, replaces) = self.search_duplicates.merge(result, &self.all_search_results);
+        if let Some(index) = replaces {
+            let replaced = self.all_search_results.remove(index);
+            if let Some(at) = self.search_results.iter().position(|shown| shown.file_info.path == replaced.file_info.path) {
+                self.search_results.remove(at);
+                match self.search_list_state.selected() {
+                    Some(selected) if at < selected => self.search_list_state.select(Some(selected - 1)),
+                    Some(_) if self.search_results.is_empty() => self.search_list_state.select(None),
+                    _ => {}
+                }
+            }
         }
-        
-        let selected_file = &files[selected_index];
+        if self.result_type_filter.accepts(&result.file_info) {
+            let at = self.search_results.partition_point(|other| after(other, &result));
+            self.search_results.insert(at, result.clone());
+            match self.search_list_state.selected() {
+                None => self.search_list_state.select(Some(0)),
+                Some(selected) if selected > 0 && at <= selected => self.search_list_state.select(Some(selected + 1)),
+                Some(_) => {}
+            }
+        }
+        let at = self.all_search_results.partition_point(|other| after(other, &result));
+        self.all_search_results.insert(at, result);
+    }
 
-        if selected_file.is_directory {
-            // For directories, show the contents
-            match std::fs::read_dir(&selected_file.path) {
-                Ok(entries) => {
-                    let mut items = Vec::new();
-                    items.push(format!("📁 Directory: {}", selected_file.name));
-                    items.push("".to_string());
-                    
-                    let mut dir_entries: Vec<_> = entries.collect();
-                    dir_entries.sort_by(|a, b| {
-                        match (a.as_ref().unwrap().path().is_dir(), b.as_ref().unwrap().path().is_dir()) {
-                            (true, false) => std::cmp::Ordering::Less,
-                            (false, true) => std::cmp::Ordering::Greater,
-                            _ => a.as_ref().unwrap().file_name().cmp(&b.as_ref().unwrap().file_name()),
-                        }
-                    });
+    /// Reports how the finished search went; `matched` counts matches
+    /// past the result limit too.
+    fn report_search_results(&mut self, matched: usize) {
+        if self.all_search_results.is_empty() {
+            self.set_warning_message(format!("No results found for {} ({})", 
+                self.search_subject(),
+                self.search_description()
+            ));
+        } else {
+            self.set_info_message(format!("Found {} results ({}){}{}{}", 
+                self.all_search_results.len(), 
+                self.search_description(),
+                self.truncation_note(matched),
+                self.result_filter_note(),
+                self.threads_note()
+            ));
+        }
+    }
 
-                    for (i, entry) in dir_entries.iter().enumerate() {
-                        if i >= 10 { // Limit to 10 items
-                            items.push(format!("... and {} more items", dir_entries.len() - 10));
-                            break;
-                        }
-                        if let Ok(entry) = entry {
-                            let icon = if entry.path().is_dir() { "📁" } else { "📄" };
-                            items.push(format!("{} {}", icon, entry.file_name().to_string_lossy()));
-                        }
-                    }
-                    items
-                }
-                Err(_) => vec!["Error reading directory".to_string()],
-            }
+    /// Says how many matches a search found beyond those it kept, if any
+    /// " - 7 threads" for walking searches when `debug` is on in the
+    /// search settings.
+    fn threads_note(&self) -> String {
+        let walks = self.recent_window.is_some()
+            || !matches!(self.search_strategy, SearchStrategy::LocalOnly | SearchStrategy::Indexed);
+        if self.search_engine.settings.debug && walks {
+            format!(" - {} threads", self.search_engine.thread_count())
         } else {
-            // For files, show the first 10 lines
-            match std::fs::read_to_string(&selected_file.path) {
-                Ok(content) => {
-                    let mut lines = Vec::new();
-                    lines.push(format!("📄 File: {} ({:.1} KB)", 
-                        selected_file.name, 
-                        selected_file.size as f64 / 1024.0));
-                    lines.push("".to_string());
-                    
-                    let file_lines: Vec<&str> = content.lines().collect();
-                    let preview_lines = if file_lines.len() > 10 {
-                        &file_lines[..10]
-                    } else {
-                        &file_lines
-                    };
-                    
-                    for (i, line) in preview_lines.iter().enumerate() {
-                        // Truncate very long lines
-                        let truncated_line = if line.len() > 60 {
-                            format!("{}...", &line[..57])
-                        } else {
-                            line.to_string()
-                        };
-                        lines.push(format!("{:2}: {}", i + 1, truncated_line));
-                    }
-                    
-                    if file_lines.len() > 10 {
-                        lines.push("".to_string());
-                        lines.push(format!("... ({} more lines)", file_lines.len() - 10));
+            String::new()
+        }
+    }
+
+    fn truncation_note(&self, matched: usize) -> String {
+        if matched > self.all_search_results.len() {
+            format!(" - showing {} of {} matches", self.all_search_results.len(), matched)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Asks before a comprehensive or content search from a root known to be slow, since it
+    /// can keep the disk busy for a long time. Returns true when a confirmation is pending.
+    fn request_slow_search_confirmation(&mut self) -> bool {
+        if self.search_input.is_empty()
+            || !matches!(self.search_strategy, SearchStrategy::Comprehensive | SearchStrategy::Content)
+        {
+            return false;
+        }
+        let Some(root) = self.slow_search_root() else {
+            return false;
+        };
+        if self.slow_search_confirmed.as_ref() == Some(&root) {
+            return false;
+        }
+
+        self.confirm_dialog = Some(ConfirmDialog {
+            title: "Slow Search".to_string(),
+            message: format!(
+                "A comprehensive search from {} may take up to {}s. Continue?",
+                root.display(),
+                self.search_engine.settings.timeout_secs
+            ),
+            action: PendingAction::Search,
+        });
+        true
+    }
+
+    /// The first search root known to be slow to walk, if any.
+    fn slow_search_root(&self) -> Option<PathBuf> {
+        self.walk_roots().into_iter().find(|root| search::is_slow_search_root(root))
+    }
+
+    /// Rebuilds the visible results from the last search without searching again.
+    fn apply_result_filter(&mut self) {
+        let directories_first = self.search_engine.settings.directories_first;
+        // Stable, so results level in the order keep their places
+        self.all_search_results.sort_by(|a, b| search::result_order(a, b, directories_first));
+        let filter = self.result_type_filter;
+        self.search_results = self.all_search_results.iter()
+            .filter(|result| filter.accepts(&result.file_info))
+            .cloned()
+            .collect();
+        self.search_list_state.select(if self.search_results.is_empty() { None } else { Some(0) });
+    }
+
+    fn result_filter_note(&self) -> String {
+        if self.result_type_filter == ResultTypeFilter::All {
+            String::new()
+        } else {
+            format!(" - {} shown, {}", self.search_results.len(), self.result_type_filter.label())
+        }
+    }
+
+    /// Switches between listing results by score alone and with the
+    /// directories ahead of the files.
+    pub fn toggle_directories_first(&mut self) -> String {
+        let settings = &mut self.search_engine.settings;
+        settings.directories_first = !settings.directories_first;
+        let directories_first = settings.directories_first;
+        self.apply_result_filter();
+        if directories_first {
+            "Directories listed first".to_string()
+        } else {
+            "Results ranked by score".to_string()
+        }
+    }
+
+    /// Collapses the results view's preview pane, or brings it back.
+    pub fn toggle_search_preview(&mut self) -> String {
+        self.search_preview_hidden = !self.search_preview_hidden;
+        if self.search_preview_hidden {
+            "Preview hidden".to_string()
+        } else {
+            "Preview shown".to_string()
+        }
+    }
+
+    pub fn cycle_result_type_filter(&mut self) -> String {
+        self.result_type_filter = self.result_type_filter.next();
+        self.apply_result_filter();
+        format!("Showing {} ({} of {} results)",
+            self.result_type_filter.label(),
+            self.search_results.len(),
+            self.all_search_results.len())
+    }
+
+    pub fn cycle_pattern_mode(&mut self) {
+        self.search_engine.pattern_mode = self.search_engine.pattern_mode.next();
+        self.set_info_message(format!("Match mode: {}", self.search_engine.pattern_mode.label()));
+    }
+
+    pub fn cycle_case_mode(&mut self) {
+        self.search_engine.case_mode = self.search_engine.case_mode.next();
+        self.set_info_message(format!("Case: {}", self.search_engine.case_mode.label()));
+    }
+
+    /// Flips which entries walking searches visit, for this session.
+    pub fn toggle_walk_option(&mut self, toggle: WalkToggle) {
+        let state = self.search_engine.settings.walk.toggle(toggle);
+        self.set_info_message(state.to_string());
+    }
+
+    pub fn set_search_strategy(&mut self, strategy: SearchStrategy) {
+        self.search_strategy = strategy;
+        self.set_info_message(format!("Search strategy: {}", self.search_strategy.description()));
+    }
+
+    pub fn toggle_search_strategy(&mut self) {
+        self.search_strategy = self.search_strategy.next();
+        self.set_info_message(format!("Search strategy: {}", self.search_strategy.description()));
+        
+        // Re-run search if we're in search mode and have input
+        if self.search_mode && !self.search_input.is_empty() {
+            // We'll trigger a search on the next event loop iteration
+            if let Some(ref mut msg) = self.status_message {
+                msg.text.push_str(" - type to search again");
+            }
+        }
+    }
+
+    pub fn navigate_to_selected(&mut self) -> Result<(), std::io::Error> {
+        if self.search_mode || self.showing_search_results {
+            if let Some(selected) = self.search_list_state.selected() {
+                if let Some(result) = self.search_results.get(selected) {
+                    if result.file_info.is_directory {
+                        let path = result.file_info.path.clone();
+                        let left_selection = self.selected_list_path();
+                        self.remember_view_state();
+                        self.explorer.navigate_to(path, left_selection)?;
+                        self.marked.clear();
+                        self.clear_search_results();
+                        self.restore_view_state();
                     }
-                    
-                    lines
                 }
-                Err(_) => {
-                    // For binary files or files that can't be read as text
-                    let extension = selected_file.path.extension()
-                        .and_then(|ext| ext.to_str())
-                        .unwrap_or("")
-                        .to_lowercase();
-                    
-                    match extension.as_str() {
-                        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "svg" | "ico" | "webp" => {
-                            vec![
-                                format!("Image: {}", selected_file.name),
-                                format!("Size: {:.1} KB", selected_file.size as f64 / 1024.0),
-                                "".to_string(),
-                                "Image file - use 'o' to open".to_string(),
-                                "or 's' to share via web".to_string(),
-                            ]
-                        }
-                        "mp4" | "avi" | "mov" | "wmv" | "flv" | "webm" | "mkv" => {
-                            vec![
-                                format!("🎥 Video: {}", selected_file.name),
-                                format!("Size: {:.1} MB", selected_file.size as f64 / (1024.0 * 1024.0)),
-                                "".to_string(),
-                                "Video file - use 'o' to open".to_string(),
-                                "or 's' to share via web".to_string(),
-                            ]
-                        }
-                        "mp3" | "wav" | "flac" | "ogg" | "m4a" | "aac" => {
-                            vec![
-                                format!("🎵 Audio: {}", selected_file.name),
-                                format!("Size: {:.1} MB", selected_file.size as f64 / (1024.0 * 1024.0)),
-                                "".to_string(),
-                                "Audio file - use 'o' to open".to_string(),
-                                "or 's' to share via web".to_string(),
-                            ]
-                        }
-                        "pdf" => {
-                            vec![
-                                format!("PDF: {}", selected_file.name),
-                                format!("Size: {:.1} MB", selected_file.size as f64 / (1024.0 * 1024.0)),
-                                "".to_string(),
-                                "PDF document - use 'o' to open".to_string(),
-                                "or 's' to share via web".to_string(),
-                            ]
-                        }
-                        "zip" | "tar" | "gz" | "rar" | "7z" => {
-                            vec![
-                                format!("Archive: {}", selected_file.name),
-                                format!("Size: {:.1} MB", selected_file.size as f64 / (1024.0 * 1024.0)),
-                                "".to_string(),
-                                "Archive file - use 'o' to open".to_string(),
-                                "with system default".to_string(),
-                            ]
-                        }
-                        _ => {
-                            vec![
-                                format!("Binary: {}", selected_file.name),
-                                format!("Size: {:.1} KB", selected_file.size as f64 / 1024.0),
-                                "".to_string(),
-                                "Binary file - cannot preview".to_string(),
-                                "Use 'o' to open with default app".to_string(),
-                            ]
-                        }
-                    }
+            }
+        } else if let Some(selected) = self.list_state.selected() {
+            if let Some(file) = self.explorer.files().get(selected) {
+                if file.is_directory {
+                    let path = file.path.clone();
+                    self.remember_view_state();
+                    self.explorer.navigate_to(path.clone(), Some(path))?;
+                    self.marked.clear();
+                    self.restore_view_state();
                 }
             }
         }
+        Ok(())
     }
-}
 
-pub async fn run_ui(
-    explorer: FileExplorer,
-    search_engine: SearchEngine,
-    config: Config,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    pub fn go_up(&mut self) -> Result<(), std::io::Error> {
+        // Land on the directory we just left, like most file managers
+        let previous_dir = self.explorer.current_path().to_path_buf();
+        self.remember_view_state();
+        self.explorer.go_up(self.selected_list_path())?;
+        self.marked.clear();
+        self.restore_view_state();
+        self.restore_selection(Some(&previous_dir));
+        Ok(())
+    }
 
-    // Create app
-    let mut app = App::new(explorer, search_engine, config);
+    /// Steps back through the directory history, landing on the entry that
+    /// was selected there.
+    pub fn go_back(&mut self) -> Result<String, String> {
+        self.remember_view_state();
+        let entry = self.explorer.back(self.selected_list_path())
+            .map_err(|e| format!("Failed to go back: {}", e))?
+            .ok_or("No earlier directory to go back to")?;
+        Ok(self.restore_history_entry(entry))
+    }
 
-    let res = run_app(&mut terminal, &mut app).await;
+    pub fn go_forward(&mut self) -> Result<String, String> {
+        self.remember_view_state();
+        let entry = self.explorer.forward(self.selected_list_path())
+            .map_err(|e| format!("Failed to go forward: {}", e))?
+            .ok_or("No later directory to go forward to")?;
+        Ok(self.restore_history_entry(entry))
+    }
 
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    fn restore_history_entry(&mut self, entry: file_system::HistoryEntry) -> String {
+        self.marked.clear();
+        self.restore_view_state();
+        if entry.selected.is_some() {
+            self.restore_selection(entry.selected.as_deref());
+        }
+        format!("Now in {}", entry.path.display())
+    }
 
-    if let Err(err) = res {
-        println!("{:?}", err);
+    pub fn enter_filter_mode(&mut self) -> Result<(), String> {
+        if self.showing_search_results {
+            return Err("Filtering applies to the directory listing, not search results".to_string());
+        }
+        self.filter_mode = true;
+        self.set_info_message("Filter: type to narrow the list, Enter to keep the filter, Esc to clear it".to_string());
+        Ok(())
     }
 
-    Ok(())
-}
+    pub fn push_filter_char(&mut self, c: char) {
+        let filter = format!("{}{}", self.explorer.filter(), c);
+        self.apply_filter(&filter);
+    }
 
-async fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    app: &mut App,
-) -> io::Result<()> {
-    loop {
-        // Update message fade status
-        app.update_message_fade();
-        
-        terminal.draw(|f| ui(f, app))?;
+    pub fn pop_filter_char(&mut self) {
+        let mut filter = self.explorer.filter().to_string();
+        filter.pop();
+        self.apply_filter(&filter);
+    }
 
-        if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    // Handle search mode keys
-                    if app.search_mode {
-                        let key_bindings = &app.config.key_bindings;
-                        if key_bindings.matches_key(&key_bindings.search_mode.exit_search, &key.code) {
-                            app.exit_search_mode();
-                        } else if key_bindings.matches_key(&key_bindings.search_mode.exit_to_results, &key.code) {
-                            app.exit_search_mode();
-                        } else if key_bindings.matches_key(&key_bindings.search_mode.toggle_strategy, &key.code) {
-                            app.toggle_search_strategy();
-                            // Re-run search if we have input
-                            if !app.search_input.is_empty() {
-                                sleep(Duration::from_millis(50)).await;
-                                app.perform_search().await;
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.search_mode.backspace, &key.code) {
-                            app.search_input.pop();
-                            if !app.search_input.is_empty() {
-                                app.perform_search().await;
-                            } else {
-                                app.search_results.clear();
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.navigation.up, &key.code) {
-                            app.previous_item();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.down, &key.code) {
-                            app.next_item();
-                        } else if key_bindings.matches_key(&key_bindings.search_mode.navigate_tab, &key.code) {
-                            app.navigate_to_selected().ok();
-                        } else {
-                            match key.code {
-                                KeyCode::Char(c) => {
-                                    app.search_input.push(c);
-                                    // Shorter delay for more responsive search
-                                    sleep(Duration::from_millis(100)).await;
-                                    app.perform_search().await;
-                                }
-                                _ => {}
-                            }
-                        }
-                    } else if app.showing_search_results {
-                        // Handle search results viewing mode keys
-                        let key_bindings = &app.config.key_bindings;
-                        if key_bindings.matches_key(&key_bindings.actions.quit, &key.code) {
-                            // Properly shutdown the file sharing server
-                            let _ = app.file_share_server.shutdown().await;
-                            return Ok(());
-                        } else if key_bindings.matches_key(&key_bindings.actions.search, &key.code) {
-                            app.enter_search_mode();
-                        } else if key_bindings.matches_key(&key_bindings.actions.open, &key.code) {
-                            match app.open_selected_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.reveal, &key.code) {
-                            match app.reveal_selected_in_file_manager() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.share, &key.code) {
-                            match app.share_selected_file().await {
-                                Ok(msg) => {
-                                    if msg.contains("Warning:") {
-                                        app.set_warning_message(msg);
-                                    } else {
-                                        app.set_info_message(msg);
-                                    }
-                                },
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.cut, &key.code) {
-                            match app.cut_selected_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.copy, &key.code) {
-                            match app.copy_selected_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.paste, &key.code) {
-                            match app.paste_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.copy_path, &key.code) {
-                            match app.copy_selected_file_path() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.search_results.back, &key.code) {
-                            app.clear_search_results();
-                        } else if key_bindings.matches_key(&key_bindings.search_mode.toggle_strategy, &key.code) {
-                            app.toggle_search_strategy();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.enter, &key.code) {
-                            let _ = app.navigate_to_selected();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.up, &key.code) {
-                            app.previous_item();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.down, &key.code) {
-                            app.next_item();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.left, &key.code) {
-                            app.clear_search_results();
-                        }
-                    } else {
-                        // Handle normal navigation mode keys
-                        let key_bindings = &app.config.key_bindings;
-                        if key_bindings.matches_key(&key_bindings.actions.quit, &key.code) {
-                            // Properly shutdown the file sharing server
-                            let _ = app.file_share_server.shutdown().await;
-                            return Ok(());
-                        } else if key_bindings.matches_key(&key_bindings.actions.search, &key.code) {
-                            app.enter_search_mode();
-                        } else if key_bindings.matches_key(&key_bindings.actions.open, &key.code) {
-                            match app.open_selected_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.reveal, &key.code) {
-                            match app.reveal_selected_in_file_manager() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.share, &key.code) {
-                            match app.share_selected_file().await {
-                                Ok(msg) => {
-                                    if msg.contains("Warning:") {
-                                        app.set_warning_message(msg);
-                                    } else {
-                                        app.set_info_message(msg);
-                                    }
-                                },
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.cut, &key.code) {
-                            match app.cut_selected_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.copy, &key.code) {
-                            match app.copy_selected_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.paste, &key.code) {
-                            match app.paste_file() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.actions.copy_path, &key.code) {
-                            match app.copy_selected_file_path() {
-                                Ok(msg) => app.set_info_message(msg),
-                                Err(err) => app.set_error_message(err),
-                            }
-                        } else if key_bindings.matches_key(&key_bindings.search_mode.toggle_strategy, &key.code) {
-                            app.toggle_search_strategy();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.enter, &key.code) {
-                            let _ = app.navigate_to_selected();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.up, &key.code) {
-                            app.previous_item();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.down, &key.code) {
-                            app.next_item();
-                        } else if key_bindings.matches_key(&key_bindings.navigation.left, &key.code) {
-                            let _ = app.go_up();
-                        }
-                    }
+    /// Leaves typing mode with the filter still applied, so the usual keys
+    /// act on the filtered entries.
+    pub fn finish_filter(&mut self) {
+        self.filter_mode = false;
+        let msg = if self.explorer.filter().is_empty() {
+            "Filter cleared".to_string()
+        } else {
+            format!("Showing {} of {} entries matching '{}'", self.explorer.files().len(), self.explorer.unfiltered_len(), self.explorer.filter())
+        };
+        self.set_info_message(msg);
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter_mode = false;
+        self.apply_filter("");
+        self.set_info_message("Filter cleared".to_string());
+    }
+
+    fn apply_filter(&mut self, filter: &str) {
+        let selected_path = self.selected_list_path();
+        self.explorer.set_filter(filter);
+        self.list_state.select(Some(0));
+        self.restore_selection(selected_path.as_deref());
+    }
+
+    pub fn enter_search_mode(&mut self) {
+        self.cancel_search();
+        self.refinements.clear();
+        self.marked.clear();
+        self.slow_search_confirmed = None;
+        self.history_cursor = None;
+        self.search_mode = true;
+        self.showing_search_results = false;
+        self.search_input.clear();
+        self.search_results.clear();
+        self.all_search_results.clear();
+        self.set_info_message(format!("Search mode: {} - Type to search, F2 to toggle strategy, F3 to change match mode, ESC to exit, Enter to keep results", 
+            self.search_strategy.description()));
+    }
+
+    pub fn exit_search_mode(&mut self) {
+        self.cancel_search();
+        if !self.refinements.is_empty() && self.all_search_results.is_empty() {
+            // A refinement that left nothing gives its results back
+            self.pop_refinement();
+            self.search_mode = false;
+            self.showing_search_results = true;
+        } else if !self.all_search_results.is_empty() {
+            // Keep search results and switch to showing them
+            self.search_mode = false;
+            self.showing_search_results = true;
+            self.set_info_message(format!("Search results ({} items) - Navigate with ↑↓, Enter to open, '/' to search again", 
+                self.search_results.len()));
+        } else {
+            // No results, clear everything
+            self.search_mode = false;
+            self.showing_search_results = false;
+            self.search_input.clear();
+            self.set_info_message("Press '/' to search, 'q' to quit, Enter to navigate".to_string());
+        }
+    }
+
+    pub fn clear_search_results(&mut self) {
+        self.cancel_search();
+        self.duplicates = None;
+        self.recent_window = None;
+        self.refinements.clear();
+        self.marked.clear();
+        self.search_mode = false;
+        self.showing_search_results = false;
+        self.search_input.clear();
+        self.search_results.clear();
+        self.all_search_results.clear();
+        self.search_list_state = ListState::default();
+        self.list_state.select(Some(0));
+        self.set_info_message("Press '/' to search, 'q' to quit, Enter to navigate, 'x' to cut, 'c' to copy, 'v' to paste".to_string());
+    }
+
+    pub fn open_selected_file(&mut self) -> Result<String, String> {
+        let selected_file = self.get_selected_file()?;
+
+        match self.explorer.open_file(selected_file) {
+            Ok(_) if selected_file.is_directory => {
+                Ok(format!("Opened '{}' in file manager", selected_file.name))
+            }
+            Ok(_) => Ok(format!("Opened '{}' with default application", selected_file.name)),
+            Err(e) => Err(format!("Failed to open '{}': {}", selected_file.name, e)),
+        }
+    }
+
+    /// Runs the editor on the selected file in this terminal and waits for
+    /// it; call through `suspend_tui` so it gets the screen.
+    pub fn edit_selected_file(&mut self) -> Result<String, String> {
+        self.ensure_writable()?;
+        let selected_file = self.get_selected_file()?.clone();
+        if selected_file.is_directory {
+            return Err("Select a file to edit".to_string());
+        }
+
+        let template = self.config.editor_command();
+        let mut command = file_system::template_command(&template, "{file}", &selected_file.path, true)
+            .ok_or("No editor command configured")?;
+        let program = command.get_program().to_string_lossy().to_string();
+        let status = command.status()
+            .map_err(|e| format!("Failed to start editor '{}': {}", program, e))?;
+
+        // The size or name on disk may have changed while editing
+        if !self.showing_search_results {
+            let _ = self.refresh_listing();
+        }
+        if status.success() {
+            Ok(format!("Finished editing '{}'", selected_file.name))
+        } else {
+            Err(format!("{} exited with {}", program, status))
+        }
+    }
+
+    /// Opens a new terminal window in the current directory.
+    pub fn open_terminal_here(&self) -> Result<String, String> {
+        let dir = self.explorer.current_path();
+        let template = self.config.terminal_command();
+        let mut command = file_system::template_command(&template, "{dir}", dir, false)
+            .ok_or("No terminal command configured")?;
+        let program = command.get_program().to_string_lossy().to_string();
+        file_system::spawn_detached(command.current_dir(dir)).map_err(|e| format!("Failed to start terminal '{}': {}", program, e))?;
+        Ok(format!("Opened {} in {}", program, dir.display()))
+    }
+
+    /// Opens the open-with popup for the selected file: configured apps,
+    /// then installed ones that handle its type, last used first.
+    pub fn request_open_with(&mut self) -> Result<(), String> {
+        let selected_file = self.get_selected_file()?;
+        if selected_file.is_directory {
+            return Err("Open with only applies to files".to_string());
+        }
+
+        let path = selected_file.path.clone();
+        let installed = open_with::installed_apps(file_sharing::content_mime_type(&path));
+        let last_used = self.config.open_with_recent.get(&extension_key(&path)).map(String::as_str);
+        let apps = open_with::menu_entries(&self.config.open_with, installed, last_used);
+        if apps.is_empty() {
+            return Err("No applications found - add some under \"open_with\" in the config file".to_string());
+        }
+        let mut state = ListState::default();
+        state.select(Some(0));
+        self.open_with_menu = Some(OpenWithMenu { path, apps, state });
+        Ok(())
+    }
+
+    pub fn close_open_with(&mut self) {
+        self.open_with_menu = None;
+    }
+
+    /// Moves the open-with selection by `offset`, wrapping around.
+    pub fn move_open_with_selection(&mut self, offset: isize) {
+        if let Some(menu) = self.open_with_menu.as_mut() {
+            let count = menu.apps.len() as isize;
+            let current = menu.state.selected().unwrap_or(0) as isize;
+            menu.state.select(Some((current + offset).rem_euclid(count) as usize));
+        }
+    }
+
+    /// Starts the highlighted app on the file, detached from the TUI, and
+    /// remembers it for files with the same extension.
+    pub fn open_with_picked(&mut self) -> Result<String, String> {
+        let menu = self.open_with_menu.as_ref().ok_or("No application selected")?;
+        let app = menu.state.selected()
+            .and_then(|index| menu.apps.get(index))
+            .ok_or("No application selected")?
+            .clone();
+        let path = menu.path.clone();
+        app.spawn(&path).map_err(|e| format!("Failed to start {}: {}", app.name, e))?;
+        self.open_with_menu = None;
+
+        let name = file_system::display_name(&path);
+        let key = extension_key(&path);
+        if self.config.open_with_recent.get(&key) != Some(&app.name) {
+            self.config.open_with_recent.insert(key, app.name.clone());
+            self.config.save_open_with_recent()
+                .map_err(|e| format!("Opened '{}' with {} but failed to remember it: {}", name, app.name, e))?;
+        }
+        Ok(format!("Opened '{}' with {}", name, app.name))
+    }
+
+    pub fn has_custom_open_command(&self) -> bool {
+        self.get_selected_file()
+            .is_ok_and(|file| self.explorer.custom_open_command(file).is_some())
+    }
+
+    pub fn reveal_selected_in_file_manager(&mut self) -> Result<String, String> {
+        let selected_file = self.get_selected_file()?;
+
+        match self.explorer.reveal_in_file_manager(selected_file) {
+            Ok(_) => Ok(format!("Revealed '{}' in file manager", selected_file.name)),
+            Err(e) => Err(format!("Failed to reveal '{}': {}", selected_file.name, e)),
+        }
+    }
+
+    fn get_selected_file(&self) -> Result<&FileInfo, String> {
+        if self.showing_search_results {
+            if let Some(selected_idx) = self.search_list_state.selected() {
+                if selected_idx < self.search_results.len() {
+                    Ok(&self.search_results[selected_idx].file_info)
+                } else {
+                    Err("Invalid selection".to_string())
                 }
+            } else {
+                Err("No file selected".to_string())
             }
+        } else {
+            if let Some(selected_idx) = self.list_state.selected() {
+                if selected_idx < self.explorer.files().len() {
+                    Ok(&self.explorer.files()[selected_idx])
+                } else {
+                    Err("Invalid selection".to_string())
+                }
+            } else {
+                Err("No file selected".to_string())
+            }
+        }
+    }
+
+    /// Shares the selection with the configured expiry and protection;
+    /// `protect` adds a token or password even when the config doesn't.
+    pub async fn share_selected_file(&mut self, protect: bool) -> Result<String, String> {
+        let targets = self.selected_targets()?;
+        let settings = &self.config.file_sharing;
+        let mut options = ShareOptions::from(settings);
+        if protect {
+            options.protection = Some(ShareProtection::configured(settings));
+        }
+        let mut shared = Vec::new();
+        let mut errors = Vec::new();
+        // Folders expose everything under them, which is worth spelling out
+        let mut exposed_files = 0;
+        let mut shared_folder = false;
+        for target in targets {
+            match self.file_share_server.share_file(&target.path, options.clone()).await {
+                Ok(url) => {
+                    if target.is_directory {
+                        let path = target.path.clone();
+                        exposed_files += tokio::task::spawn_blocking(move || file_sharing::exposed_file_count(&path))
+                            .await
+                            .unwrap_or_default();
+                        shared_folder = true;
+                    } else {
+                        exposed_files += 1;
+                    }
+                    shared.push((target.name, url));
+                }
+                Err(e) => errors.push(format!("Failed to share '{}': {}", target.name, e)),
+            }
+        }
+        self.marked.clear();
+
+        let expiry = options.describe().map(|expiry| format!(", {}", expiry)).unwrap_or_default();
+        let kb = &self.config.key_bindings;
+        let mut hints = format!(" ({}: QR code)", kb.get_key_display(&kb.actions.share_qr));
+        // Recipients can check the certificate against what their browser shows
+        if let Some(fingerprint) = self.file_share_server.tls_fingerprint() {
+            hints.push_str(&format!(" - certificate SHA-256 {}", fingerprint));
+        } else if let Some(warning) = self.file_share_server.tls_warning() {
+            hints.push_str(&format!(" (Warning: {})", warning));
+        }
+        let exposed = format!("{} file{}", exposed_files, if exposed_files == 1 { "" } else { "s" });
+        if shared.len() == 1 && errors.is_empty() {
+            let (name, url) = &shared[0];
+            if shared_folder {
+                return Ok(format!("Shared folder '{}' ({}){} - Link copied to clipboard: {}{}", name, exposed, expiry, url, hints));
+            }
+            return Ok(format!("Shared '{}'{} - Link copied to clipboard: {}{}", name, expiry, url, hints));
+        }
+        if shared.is_empty() && errors.len() == 1 {
+            return Err(errors.remove(0));
+        }
+        let done = if shared_folder {
+            format!("Shared {} items ({}){}", shared.len(), exposed, expiry)
+        } else {
+            format!("Shared {} files{}", shared.len(), expiry)
+        };
+        batch_result(format!("{} (last link copied to clipboard){}", done, hints), errors)
+    }
+
+    /// Opens the page listing everything shared this session in the
+    /// browser and copies its URL.
+    pub async fn open_share_list(&self) -> Result<String, String> {
+        let Some(url) = self.file_share_server.list_url().await else {
+            let kb = &self.config.key_bindings;
+            return Err(format!(
+                "Nothing shared yet - press {} to share a file",
+                kb.get_key_display(&kb.actions.share)
+            ));
+        };
+
+        let copied = copy_to_clipboard(&url).is_ok();
+        open::that(&url).map_err(|e| format!("Failed to open {}: {}", url, e))?;
+        if copied {
+            Ok(format!("Opened shared files list - link copied to clipboard: {}", url))
+        } else {
+            Ok(format!("Opened shared files list: {}", url))
+        }
+    }
+
+    /// Turns the share server's upload page on or off. Files are saved to
+    /// `upload_dir` from the config, or else the directory being viewed.
+    pub async fn toggle_uploads(&mut self) -> Result<String, String> {
+        if let Some(dir) = self.file_share_server.upload_dir().await {
+            self.file_share_server.disable_uploads().await;
+            return Ok(format!("Stopped accepting uploads into '{}'", dir.display()));
+        }
+        self.ensure_writable()?;
+        let settings = &self.config.file_sharing;
+        let dir = settings.upload_dir().unwrap_or_else(|| self.explorer.current_path().to_path_buf());
+        let max_bytes = settings.max_upload_mb.saturating_mul(1024 * 1024);
+        let url = self.file_share_server.enable_uploads(dir.clone(), max_bytes).await
+            .map_err(|e| format!("Failed to accept uploads: {}", e))?;
+        if copy_to_clipboard(&url).is_ok() {
+            Ok(format!("Accepting uploads into '{}' - link copied to clipboard: {}", dir.display(), url))
+        } else {
+            Ok(format!("Accepting uploads into '{}' at {}", dir.display(), url))
+        }
+    }
+
+    /// Reports files other devices uploaded, refreshing the listing when
+    /// they landed in the directory being viewed.
+    pub fn poll_uploads(&mut self) {
+        let received = self.file_share_server.take_received();
+        let Some(last) = received.last() else {
+            return;
+        };
+        let msg = if received.len() == 1 {
+            format!("Received '{}' ({})", file_system::display_name(&last.path), format_size(last.size))
+        } else {
+            let total = received.iter().map(|file| file.size).sum();
+            format!("Received {} files ({})", received.len(), format_size(total))
+        };
+        if received.iter().any(|file| file.path.parent() == Some(self.explorer.current_path())) {
+            let _ = self.refresh_listing();
+        }
+        self.set_info_message(msg);
+    }
+
+    /// Reports who opened or downloaded a share since the last check.
+    pub fn poll_share_access(&mut self) {
+        let summaries: Vec<String> = self.file_share_server.take_access_events()
+            .iter()
+            .filter_map(AccessRecord::summary)
+            .collect();
+        let Some(last) = summaries.last() else {
+            return;
+        };
+        let msg = match summaries.len() {
+            1 => last.clone(),
+            count => format!("{} (and {} more)", last, count - 1),
+        };
+        self.set_info_message(msg);
+    }
+
+    /// Shows the link of the most recent share as a QR code.
+    pub async fn show_share_qr(&mut self) -> Result<(), String> {
+        let Some(share) = self.file_share_server.list_shares().await.into_iter().next() else {
+            let kb = &self.config.key_bindings;
+            return Err(format!(
+                "Nothing shared yet - press {} to share a file",
+                kb.get_key_display(&kb.actions.share)
+            ));
+        };
+        self.open_qr_popup(&share)
+    }
+
+    /// Shows the link of the share highlighted in the share manager as a
+    /// QR code.
+    pub fn show_picked_share_qr(&mut self) -> Result<(), String> {
+        let share = self.picked_share()?.clone();
+        self.open_qr_popup(&share)
+    }
+
+    fn open_qr_popup(&mut self, share: &ShareInfo) -> Result<(), String> {
+        let lines = file_sharing::qr_code_text(&share.url).ok_or("The link is too long for a QR code")?;
+        self.qr_popup = Some(QrPopup {
+            name: file_system::display_name(&share.path),
+            url: share.url.clone(),
+            lines,
+        });
+        Ok(())
+    }
+
+    pub fn close_qr_popup(&mut self) {
+        self.qr_popup = None;
+    }
+
+    /// Opens the popup listing every active share.
+    pub async fn open_share_manager(&mut self) -> Result<(), String> {
+        let shares = self.file_share_server.list_shares().await;
+        if shares.is_empty() {
+            let kb = &self.config.key_bindings;
+            return Err(format!(
+                "Nothing shared yet - press {} to share a file",
+                kb.get_key_display(&kb.actions.share)
+            ));
+        }
+        self.share_manager = Some(ShareManager { shares, state: ListState::default().with_selected(Some(0)) });
+        Ok(())
+    }
+
+    pub fn close_share_manager(&mut self) {
+        self.share_manager = None;
+    }
+
+    /// Reloads the shares in the open popup, so hit counts stay current,
+    /// keeping the selection on the same share where it still exists.
+    pub async fn refresh_share_manager(&mut self) {
+        if self.share_manager.is_none() {
+            return;
+        }
+        let shares = self.file_share_server.list_shares().await;
+        let Some(manager) = self.share_manager.as_mut() else {
+            return;
+        };
+        if shares.is_empty() {
+            self.share_manager = None;
+            return;
+        }
+        let selected = manager.state.selected().unwrap_or(0);
+        let index = manager.shares.get(selected)
+            .and_then(|picked| shares.iter().position(|share| share.file_id == picked.file_id))
+            .unwrap_or(selected.min(shares.len() - 1));
+        manager.state.select(Some(index));
+        manager.shares = shares;
+    }
+
+    /// Moves the share manager selection by `offset`, wrapping around.
+    pub fn move_share_selection(&mut self, offset: isize) {
+        if let Some(manager) = self.share_manager.as_mut() {
+            let count = manager.shares.len() as isize;
+            if count > 0 {
+                let current = manager.state.selected().unwrap_or(0) as isize;
+                manager.state.select(Some((current + offset).rem_euclid(count) as usize));
+            }
+        }
+    }
+
+    fn picked_share(&self) -> Result<&ShareInfo, String> {
+        self.share_manager.as_ref()
+            .and_then(|manager| manager.shares.get(manager.state.selected()?))
+            .ok_or_else(|| "No share selected".to_string())
+    }
+
+    pub fn copy_picked_share_url(&self) -> Result<String, String> {
+        let share = self.picked_share()?;
+        copy_to_clipboard(&share.url).map_err(|e| format!("Failed to copy to clipboard: {}", e))?;
+        Ok(format!("Copied {}", share.url))
+    }
+
+    pub fn open_picked_share(&self) -> Result<String, String> {
+        let share = self.picked_share()?;
+        open::that(&share.url).map_err(|e| format!("Failed to open {}: {}", share.url, e))?;
+        Ok(format!("Opened {}", share.url))
+    }
+
+    /// Stops sharing the file highlighted in the popup; its links answer
+    /// 404 from then on.
+    pub async fn revoke_picked_share(&mut self) -> Result<String, String> {
+        let file_id = self.picked_share()?.file_id.clone();
+        let path = self.file_share_server.revoke(&file_id).await.ok_or("That share was already revoked")?;
+        self.refresh_share_manager().await;
+        Ok(format!("Stopped sharing '{}'", file_system::display_name(&path)))
+    }
+
+    /// Asks before stopping every share.
+    pub fn request_revoke_all_shares(&mut self) -> Result<(), String> {
+        let count = self.share_manager.as_ref().map_or(0, |manager| manager.shares.len());
+        if count == 0 {
+            return Err("Nothing is being shared".to_string());
+        }
+        self.confirm_dialog = Some(ConfirmDialog {
+            title: "Revoke All Shares".to_string(),
+            message: format!("Stop sharing {} file(s)? Their links will stop working. (y/n)", count),
+            action: PendingAction::RevokeShares,
+        });
+        Ok(())
+    }
+
+    pub async fn revoke_all_shares(&mut self) -> String {
+        let count = self.file_share_server.revoke_all().await;
+        self.share_manager = None;
+        format!("Stopped sharing {} file(s)", count)
+    }
+
+    /// Fails with a hint on how to leave read-only mode while it is on.
+    fn ensure_writable(&self) -> Result<(), String> {
+        if !self.read_only {
+            return Ok(());
+        }
+        let kb = &self.config.key_bindings;
+        Err(format!(
+            "Read-only mode - press {} to allow changes",
+            kb.get_key_display(&kb.actions.toggle_read_only)
+        ))
+    }
+
+    pub fn toggle_read_only(&mut self) -> String {
+        self.read_only = !self.read_only;
+        if self.read_only {
+            "Read-only mode on - changes to files are blocked".to_string()
+        } else {
+            "Read-only mode off".to_string()
+        }
+    }
+
+    pub fn cut_selected_file(&mut self) -> Result<String, String> {
+        self.ensure_writable()?;
+        self.set_clipboard(ClipboardOperation::Cut)
+    }
+
+    pub fn copy_selected_file(&mut self) -> Result<String, String> {
+        self.set_clipboard(ClipboardOperation::Copy)
+    }
+
+    fn set_clipboard(&mut self, operation: ClipboardOperation) -> Result<String, String> {
+        let targets = self.selected_targets()?;
+        let verb = match operation {
+            ClipboardOperation::Cut => "Cut",
+            ClipboardOperation::Copy => "Copied",
+        };
+        let what = if targets.len() == 1 {
+            format!("'{}'", targets[0].name)
+        } else {
+            format!("{} items", targets.len())
+        };
+
+        self.clipboard = Some(ClipboardEntry {
+            file_paths: targets.into_iter().map(|file| file.path).collect(),
+            operation,
+        });
+        self.marked.clear();
+
+        Ok(format!("{} {} - navigate to destination and press 'v' to paste", verb, what))
+    }
+
+    pub fn paste_file(&mut self) -> Result<String, String> {
+        self.ensure_writable()?;
+        if self.running_transfer.is_some() {
+            return Err("A paste is already running".to_string());
+        }
+        let clipboard_entry = match &self.clipboard {
+            Some(entry) => entry.clone(),
+            None => return Err("Nothing to paste - cut or copy a file first".to_string()),
+        };
+
+        self.run_paste(PasteJob {
+            operation: clipboard_entry.operation,
+            destination_dir: self.explorer.current_path().to_path_buf(),
+            total: clipboard_entry.file_paths.len(),
+            queue: clipboard_entry.file_paths.into(),
+            policy: None,
+            pasted: Vec::new(),
+            skipped: 0,
+            errors: Vec::new(),
+            gone: Vec::new(),
+        })
+    }
+
+    /// Starts on the next queued entry. The job pauses behind a conflict
+    /// prompt when an entry clashes, and continues from `poll_transfer`
+    /// once a started transfer finishes.
+    fn run_paste(&mut self, mut job: PasteJob) -> Result<String, String> {
+        while let Some(source_path) = job.queue.pop_front() {
+            let conflict = job.policy.map(|policy| (policy, true));
+            match self.plan_paste(&job, &source_path, conflict) {
+                Ok(PastePlan::Conflict) => {
+                    let name = file_system::display_name(&source_path);
+                    let is_directory = source_path.is_dir();
+                    job.queue.push_front(source_path);
+                    self.paste_conflict = Some(PasteConflict { name: name.clone(), is_directory, apply_to_all: false, job });
+                    return Ok(format!("'{}' already exists here", name));
+                }
+                Ok(PastePlan::Transfer { destination, policy, merge }) => {
+                    return Ok(self.start_transfer(job, source_path, destination, policy, merge));
+                }
+                Err(e) => job.record(source_path, Err(e)),
+            }
+        }
+        self.finish_paste(job, false)
+    }
+
+    /// Answers the conflict prompt; `None` cancels the rest of the paste.
+    pub fn resolve_paste_conflict(&mut self, choice: Option<ConflictPolicy>) -> Result<String, String> {
+        let conflict = self.paste_conflict.take().ok_or("No paste in progress")?;
+        let mut job = conflict.job;
+        let policy = match choice {
+            Some(policy) => policy,
+            None => return self.finish_paste(job, true),
+        };
+
+        if conflict.apply_to_all {
+            job.policy = Some(policy);
+        }
+        if let Some(source_path) = job.queue.pop_front() {
+            match self.plan_paste(&job, &source_path, Some((policy, conflict.apply_to_all))) {
+                Ok(PastePlan::Transfer { destination, policy, merge }) => {
+                    return Ok(self.start_transfer(job, source_path, destination, policy, merge));
+                }
+                Ok(PastePlan::Conflict) => unreachable!("a resolved entry can't conflict"),
+                Err(e) => job.record(source_path, Err(e)),
+            }
+        }
+        self.run_paste(job)
+    }
+
+    pub fn toggle_paste_conflict_apply_to_all(&mut self) {
+        if let Some(conflict) = &mut self.paste_conflict {
+            conflict.apply_to_all = !conflict.apply_to_all;
+        }
+    }
+
+    /// Copies or moves one entry on a blocking task so the UI stays usable;
+    /// progress and the result come back over a channel.
+    fn start_transfer(
+        &mut self,
+        job: PasteJob,
+        source_path: PathBuf,
+        destination: PathBuf,
+        policy: ConflictPolicy,
+        merge: bool,
+    ) -> String {
+        let mode = job.mode();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+
+        let task_source = source_path.clone();
+        let task_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            // A same-filesystem move is a rename, so only copies pay for sizing up front
+            let total = match mode {
+                TransferMode::Copy => transfer::total_size(&task_source),
+                TransferMode::Move => 0,
+            };
+            let mut reporter = Reporter::new(total, task_cancel, sender.clone());
+            let result = transfer::transfer(&task_source, &destination, mode, policy, merge, &mut reporter);
+            let _ = sender.send(TransferEvent::Finished(result));
+        });
+
+        let name = file_system::display_name(&source_path);
+        let verb = match mode {
+            TransferMode::Copy => "Copying",
+            TransferMode::Move => "Moving",
+        };
+        let message = format!("{} '{}'...", verb, name);
+        self.running_transfer = Some(RunningTransfer { name, progress: None, job, source_path, cancel, events });
+        message
+    }
+
+    /// Picks up progress from a running paste and, when the current entry
+    /// is done, moves on to the next one. Called once per event loop tick.
+    pub fn poll_transfer(&mut self) {
+        let Some(running) = self.running_transfer.as_mut() else {
+            return;
+        };
+
+        let result = loop {
+            match running.events.try_recv() {
+                Ok(TransferEvent::Progress(progress)) => running.progress = Some(progress),
+                Ok(TransferEvent::Finished(result)) => break result,
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(io::Error::other("the transfer stopped unexpectedly"));
+                }
+            }
+        };
+
+        let Some(running) = self.running_transfer.take() else {
+            return;
+        };
+        let mut job = running.job;
+        let cancelled = running.cancel.load(Ordering::Relaxed);
+        let action = match job.mode() {
+            TransferMode::Copy => "copy",
+            TransferMode::Move => "move",
+        };
+        match result {
+            Ok(Some(path)) => job.record(running.source_path, Ok(PasteOutcome::Pasted(file_system::display_name(&path)))),
+            Ok(None) => job.record(running.source_path, Ok(PasteOutcome::Skipped)),
+            // The interrupted entry counts as cancelled, not failed
+            Err(_) if cancelled => job.queue.push_front(running.source_path),
+            Err(e) => job.record(running.source_path, Err(format!("Failed to {} '{}': {}", action, running.name, e))),
+        }
+        let outcome = if cancelled {
+            self.finish_paste(job, true)
+        } else {
+            self.run_paste(job)
+        };
+
+        match outcome {
+            Ok(msg) => self.set_info_message(msg),
+            Err(err) => self.set_error_message(err),
+        }
+    }
+
+    /// Cancels a running paste, archive extraction, compression, comparison
+    /// or indexing.
+    pub fn cancel_transfer(&mut self) {
+        if let Some(running) = &self.running_transfer {
+            running.cancel.store(true, Ordering::Relaxed);
+            self.set_info_message(format!("Cancelling paste of '{}'...", running.name));
+        }
+        if let Some(running) = &self.running_extraction {
+            running.cancel.store(true, Ordering::Relaxed);
+            self.set_info_message(format!("Cancelling extraction of '{}'...", running.name));
+        }
+        if let Some(running) = &self.running_compression {
+            running.cancel.store(true, Ordering::Relaxed);
+            self.set_info_message(format!("Cancelling '{}'...", file_system::display_name(&running.output)));
+        }
+        if let Some(running) = &self.running_compare {
+            running.cancel.store(true, Ordering::Relaxed);
+            self.set_info_message("Cancelling the comparison...".to_string());
+        }
+        if let Some(running) = &self.running_index {
+            running.cancel.store(true, Ordering::Relaxed);
+            self.set_info_message(format!("Cancelling indexing of {}...", running.root.display()));
+        }
+        if let Some(running) = &self.running_duplicates {
+            running.cancel.store(true, Ordering::Relaxed);
+            self.set_info_message(format!("Cancelling the look for duplicates in {}...", running.root.display()));
+        }
+    }
+
+    /// Unpacks the selected archive into a directory named after it, asking
+    /// first when that directory already exists.
+    pub fn request_extract_selected(&mut self) -> Result<(), String> {
+        self.ensure_writable()?;
+        let file = self.get_selected_file()?.clone();
+        if file.is_directory || ArchiveKind::from_path(&file.path).is_none() {
+            return Err("Only .zip, .tar, .tar.gz and .tgz archives can be extracted".to_string());
+        }
+        if self.running_extraction.is_some() {
+            return Err("An extraction is already running".to_string());
+        }
+
+        let destination = archive::extraction_dir(&file.path);
+        if destination.symlink_metadata().is_ok() {
+            self.confirm_dialog = Some(ConfirmDialog {
+                title: "Extract Archive".to_string(),
+                message: format!(
+                    "'{}' already exists. Extract into it, overwriting files with the same names?",
+                    file_system::display_name(&destination)
+                ),
+                action: PendingAction::Extract { archive: file.path, destination },
+            });
+            return Ok(());
+        }
+
+        let msg = self.start_extraction(file.path, destination, true);
+        self.set_info_message(msg);
+        Ok(())
+    }
+
+    fn start_extraction(&mut self, archive_path: PathBuf, destination: PathBuf, created: bool) -> String {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+        let task_destination = destination.clone();
+        let task_cancel = cancel.clone();
+        let task_archive = archive_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut last_update = Instant::now();
+            let result = archive::extract(&task_archive, &task_destination, &task_cancel, |done, total| {
+                if last_update.elapsed() >= Duration::from_millis(100) {
+                    last_update = Instant::now();
+                    let _ = sender.send(ExtractEvent::Progress(done, total));
+                }
+            });
+            let _ = sender.send(ExtractEvent::Finished(result));
+        });
+
+        let name = file_system::display_name(&archive_path);
+        let message = format!("Extracting '{}'...", name);
+        self.running_extraction = Some(RunningExtraction { name, destination, created, cancel, events });
+        message
+    }
+
+    /// Reports extraction progress; when done, refreshes and selects the
+    /// extracted directory if it is in view.
+    pub fn poll_extraction(&mut self) {
+        let Some(running) = self.running_extraction.as_ref() else {
+            return;
+        };
+
+        let mut progress = None;
+        let result = loop {
+            match running.events.try_recv() {
+                Ok(ExtractEvent::Progress(done, total)) => progress = Some((done, total)),
+                Ok(ExtractEvent::Finished(result)) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    let msg = match progress {
+                        Some((done, Some(total))) => format!("Extracting '{}'... {}/{} entries", running.name, done, total),
+                        Some((done, None)) => format!("Extracting '{}'... {} entries", running.name, done),
+                        None => return,
+                    };
+                    self.set_info_message(msg);
+                    return;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(io::Error::other("extraction stopped unexpectedly"));
+                }
+            }
+        };
+
+        let Some(running) = self.running_extraction.take() else {
+            return;
+        };
+        let cancelled = running.cancel.load(Ordering::Relaxed);
+        match result {
+            Ok(count) => {
+                let in_view = running.destination.parent() == Some(self.explorer.current_path());
+                if in_view && !self.showing_search_results {
+                    let _ = self.refresh_listing();
+                    self.select_path(&running.destination);
+                }
+                self.set_info_message(format!(
+                    "Extracted {} entries into '{}'",
+                    count,
+                    file_system::display_name(&running.destination)
+                ));
+            }
+            Err(e) => {
+                if running.created {
+                    let _ = std::fs::remove_dir_all(&running.destination);
+                }
+                if cancelled {
+                    self.set_info_message(format!("Extraction of '{}' cancelled", running.name));
+                } else {
+                    self.set_error_message(format!("Failed to extract '{}': {}", running.name, e));
+                }
+            }
+        }
+    }
+
+    /// Zips the marked entries (or the one under the cursor) into the
+    /// current directory: `<name>.zip` for a single entry, `selection.zip`
+    /// for several, numbered when that name is taken.
+    pub fn compress_selected(&mut self) -> Result<String, String> {
+        self.ensure_writable()?;
+        if self.running_compression.is_some() {
+            return Err("An archive is already being created".to_string());
+        }
+        let targets = self.selected_targets()?;
+        let name = match targets.as_slice() {
+            [single] => format!("{}.zip", single.name),
+            _ => "selection.zip".to_string(),
+        };
+        let output = self.explorer.current_path().join(name);
+        let output = if output.symlink_metadata().is_ok() {
+            file_system::numbered_target(&output, false)
+        } else {
+            output
+        };
+
+        let sources: Vec<PathBuf> = targets.into_iter().map(|file| file.path).collect();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+        let task_output = output.clone();
+        let task_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut last_update = Instant::now();
+            let result = archive::create_zip(&task_output, &sources, &task_cancel, |done, total| {
+                if last_update.elapsed() >= Duration::from_millis(100) {
+                    last_update = Instant::now();
+                    let _ = sender.send(CompressEvent::Progress(done, total));
+                }
+            });
+            let _ = sender.send(CompressEvent::Finished(result));
+        });
+
+        let message = format!("Creating '{}'...", file_system::display_name(&output));
+        self.running_compression = Some(RunningCompression { output, cancel, events });
+        Ok(message)
+    }
+
+    /// Reports compression progress; when done, refreshes and selects the
+    /// new archive if it is in view.
+    pub fn poll_compression(&mut self) {
+        let Some(running) = self.running_compression.as_ref() else {
+            return;
+        };
+
+        let mut progress = None;
+        let result = loop {
+            match running.events.try_recv() {
+                Ok(CompressEvent::Progress(done, total)) => progress = Some((done, total)),
+                Ok(CompressEvent::Finished(result)) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    if let Some((done, total)) = progress {
+                        self.set_info_message(format!(
+                            "Creating '{}'... {} of {}",
+                            file_system::display_name(&running.output),
+                            format_size(done),
+                            format_size(total)
+                        ));
+                    }
+                    return;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(io::Error::other("compression stopped unexpectedly"));
+                }
+            }
+        };
+
+        let Some(running) = self.running_compression.take() else {
+            return;
+        };
+        let name = file_system::display_name(&running.output);
+        match result {
+            Ok(count) => {
+                let in_view = running.output.parent() == Some(self.explorer.current_path());
+                if in_view && !self.showing_search_results {
+                    let _ = self.refresh_listing();
+                    self.select_path(&running.output);
+                }
+                let files = if count == 1 { "1 file".to_string() } else { format!("{} files", count) };
+                self.set_info_message(format!("Created '{}' with {}", name, files));
+            }
+            Err(_) if running.cancel.load(Ordering::Relaxed) => {
+                self.set_info_message(format!("Creating '{}' cancelled", name));
+            }
+            Err(e) => self.set_error_message(format!("Failed to create '{}': {}", name, e)),
+        }
+    }
+
+    fn finish_paste(&mut self, mut job: PasteJob, cancelled: bool) -> Result<String, String> {
+        // Moved or vanished sources can't be pasted again; copies, skipped and
+        // cancelled entries can
+        if let Some(entry) = &mut self.clipboard {
+            entry.file_paths.retain(|path| {
+                let moved = matches!(entry.operation, ClipboardOperation::Cut)
+                    && job.pasted.iter().any(|(source, _)| source == path);
+                !moved && !job.gone.contains(path)
+            });
+            if entry.file_paths.is_empty() {
+                self.clipboard = None;
+            }
+        }
+
+        if !job.pasted.is_empty() && self.explorer.current_path() == job.destination_dir {
+            self.refresh_listing()?;
+        }
+
+        let verb = match job.operation {
+            ClipboardOperation::Copy => "Copied",
+            ClipboardOperation::Cut => "Moved",
+        };
+        if job.total == 1 {
+            return match (job.pasted.pop(), job.errors.pop()) {
+                (Some((_, name)), _) => Ok(format!("{} '{}' to current directory", verb, name)),
+                (None, Some(err)) => Err(err),
+                (None, None) if cancelled => Ok("Paste cancelled".to_string()),
+                (None, None) => Ok("Skipped - nothing was pasted".to_string()),
+            };
+        }
+
+        let mut done = format!("{} {} items to current directory", verb, job.pasted.len());
+        if job.skipped > 0 {
+            done.push_str(&format!(", {} skipped", job.skipped));
+        }
+        if cancelled {
+            done.push_str(&format!(", {} cancelled", job.queue.len()));
+        }
+        batch_result(done, job.errors)
+    }
+
+    /// Copies the selection next to itself under a free "(copy)" name and selects the copy.
+    pub fn duplicate_selected(&mut self) -> Result<String, String> {
+        self.ensure_writable()?;
+        let selected_file = self.get_selected_file()?.clone();
+        if selected_file.name == ".." {
+            return Err("Cannot duplicate the parent directory entry".to_string());
+        }
+
+        let parent = selected_file.path.parent()
+            .ok_or("Cannot duplicate the filesystem root")?;
+        let parent_writable = std::fs::metadata(parent)
+            .map(|metadata| !metadata.permissions().readonly())
+            .unwrap_or(false);
+        if !parent_writable {
+            return Err(format!("'{}' is not writable", parent.display()));
+        }
+
+        let destination = file_system::duplicate_target(&selected_file.path, selected_file.is_directory);
+        self.copy_file_operation(&selected_file.path, &destination)
+            .map_err(|e| format!("Failed to duplicate '{}': {}", selected_file.name, e))?;
+
+        let copy_name = file_system::display_name(&destination);
+        if !self.showing_search_results {
+            self.explorer.refresh().map_err(|e| format!("Failed to refresh: {}", e))?;
+            self.select_path(&destination);
+        }
+        self.refresh_disk_usage();
+        Ok(format!("Duplicated '{}' as '{}'", selected_file.name, copy_name))
+    }
+
+    /// Checks where one clipboard entry would be pasted. Without a
+    /// `conflict` resolution an existing destination is reported back as a
+    /// conflict; the flag says whether folder clashes are merged.
+    fn plan_paste(
+        &self,
+        job: &PasteJob,
+        source_path: &Path,
+        conflict: Option<(ConflictPolicy, bool)>,
+    ) -> Result<PastePlan, String> {
+        // Check if source file still exists
+        if !source_path.exists() {
+            return Err("Source file no longer exists".to_string());
+        }
+
+        let current_dir = job.destination_dir.as_path();
+        
+        // Get the filename from the source path
+        let file_name = source_path.file_name()
+            .ok_or("Invalid source file path")?;
+        
+        let destination = current_dir.join(file_name);
+
+        // Check if we're trying to move/copy to the same directory, also
+        // when one of them is reached through a symlink
+        if let Some(source_parent) = source_path.parent() {
+            if source_parent == current_dir || transfer::is_same_entry(source_parent, current_dir) {
+                return Err("Cannot paste file to the same directory".to_string());
+            }
+        }
+        if transfer::is_same_entry(source_path, &destination) {
+            return Err("Cannot paste a file onto itself".to_string());
+        }
+
+        let (policy, merge) = match conflict {
+            Some(resolution) => resolution,
+            None if destination.symlink_metadata().is_ok() => return Ok(PastePlan::Conflict),
+            // Nothing to clash with, so the policy is never consulted
+            None => (ConflictPolicy::Skip, false),
+        };
+        Ok(PastePlan::Transfer { destination, policy, merge })
+    }
+
+    /// Asks before removing the selection (or the marked entries).
+    /// `permanent` skips the trash even when `use_trash` is on.
+    pub fn request_delete_selected(&mut self, permanent: bool) -> Result<(), String> {
+        self.ensure_writable()?;
+        let targets = self.selected_targets()?;
+        let what = match targets.as_slice() {
+            [single] => {
+                let kind = if single.is_directory { "directory" } else { "file" };
+                format!("{} '{}'", kind, single.name)
+            }
+            many => format!("{} marked items", many.len()),
+        };
+
+        self.confirm_dialog = Some(if self.config.use_trash && !permanent {
+            ConfirmDialog {
+                title: "Move to Trash".to_string(),
+                message: format!("Move {} to the trash?", what),
+                action: PendingAction::Trash(targets),
+            }
+        } else {
+            ConfirmDialog {
+                title: "Confirm Delete".to_string(),
+                message: format!("Permanently delete {}?", what),
+                action: PendingAction::Delete(targets),
+            }
+        });
+        Ok(())
+    }
+
+    pub fn confirm_pending_action(&mut self) -> Result<String, String> {
+        let dialog = match self.confirm_dialog.take() {
+            Some(dialog) => dialog,
+            None => return Err("Nothing to confirm".to_string()),
+        };
+        // Read-only mode may have been switched on while the dialog was open
+        if !matches!(dialog.action, PendingAction::Search | PendingAction::MarkResults | PendingAction::RevokeShares | PendingAction::Quit) {
+            self.ensure_writable()?;
+        }
+
+        match dialog.action {
+            PendingAction::Delete(files) => self.delete_files(&files, false),
+            PendingAction::Trash(files) => self.delete_files(&files, true),
+            PendingAction::EmptyTrash => self.empty_trash(),
+            PendingAction::Extract { archive, destination } => Ok(self.start_extraction(archive, destination, false)),
+            PendingAction::Search => {
+                // The caller runs the search; this only records the answer
+                self.slow_search_confirmed = self.slow_search_root();
+                Ok(format!("Searching for '{}'...", self.search_input))
+            }
+            PendingAction::MarkResults => self.mark_all_results(),
+            // The caller revokes them, which needs the share server's lock
+            PendingAction::RevokeShares => Ok("Stopping all shares...".to_string()),
+            PendingAction::Quit => {
+                self.should_quit = true;
+                Ok("Shutting down...".to_string())
+            }
+        }
+    }
+
+    pub fn request_empty_trash(&mut self) -> Result<(), String> {
+        self.ensure_writable()?;
+        let size = file_system::trash::trash_size();
+        if size == Some(0) {
+            return Err("Trash is already empty".to_string());
+        }
+
+        let freed = match size {
+            Some(bytes) => format!("{} will be freed", format_size(bytes)),
+            None => "size unknown".to_string(),
+        };
+        self.confirm_dialog = Some(ConfirmDialog {
+            title: "Empty Trash".to_string(),
+            message: format!("Permanently delete everything in the trash? ({})", freed),
+            action: PendingAction::EmptyTrash,
+        });
+        Ok(())
+    }
+
+    fn empty_trash(&mut self) -> Result<String, String> {
+        file_system::trash::empty_trash().map_err(|e| format!("Failed to empty trash: {}", e))?;
+
+        // The trash may be the directory being browsed
+        if !self.showing_search_results {
+            let _ = self.refresh_listing();
+        }
+        Ok("Trash emptied".to_string())
+    }
+
+    pub async fn request_quit(&mut self) {
+        let shared_count = self.file_share_server.shared_file_count().await;
+        if shared_count == 0 {
+            self.should_quit = true;
+            return;
+        }
+
+        let kb = &self.config.key_bindings;
+        self.confirm_dialog = Some(ConfirmDialog {
+            title: "Confirm Quit".to_string(),
+            message: format!(
+                "Stop sharing {} file(s) and quit? ({} or double-tap {} to force quit)",
+                shared_count,
+                kb.get_key_display(&kb.actions.force_quit),
+                kb.get_key_display(&kb.actions.quit)
+            ),
+            action: PendingAction::Quit,
+        });
+    }
+
+    /// Returns true when the key should terminate the app immediately,
+    /// bypassing confirmations and the graceful server shutdown.
+    pub fn is_force_quit(&mut self, key: &KeyEvent) -> bool {
+        let kb = &self.config.key_bindings;
+        if kb.matches_chord(&kb.actions.force_quit, key) {
+            return true;
+        }
+
+        // Double-tapping quit also forces the exit, but not while typing
+        // (or in the share manager, where the key shows a QR code), and only
+        // when nothing else was pressed in between
+        let captures_quit_key = self.search_mode
+            || self.filter_mode
+            || self.input_prompt.is_some()
+            || self.share_manager.is_some();
+        if captures_quit_key || !kb.matches_chord(&kb.actions.quit, key) {
+            self.last_quit_press = None;
+            return false;
+        }
+
+        let now = Instant::now();
+        let double_tap = self.last_quit_press
+            .is_some_and(|last| now.duration_since(last) < FORCE_QUIT_WINDOW);
+        self.last_quit_press = Some(now);
+        double_tap
+    }
+
+    pub fn cancel_pending_action(&mut self) {
+        if self.confirm_dialog.take().is_some() {
+            self.set_info_message("Cancelled".to_string());
+        }
+    }
+
+    fn delete_files(&mut self, files: &[FileInfo], to_trash: bool) -> Result<String, String> {
+        let mut removed = Vec::new();
+        let mut errors = Vec::new();
+        for file_info in files {
+            let result = if to_trash {
+                self.explorer.move_to_trash(file_info)
+                    .map_err(|e| format!("Failed to move '{}' to the trash: {}", file_info.name, e))
+            } else {
+                self.explorer.delete(file_info)
+                    .map_err(|e| format!("Failed to delete '{}': {}", file_info.name, e))
+            };
+            match result {
+                Ok(()) => removed.push(file_info),
+                Err(e) => errors.push(e),
+            }
+        }
+        if removed.is_empty() {
+            return Err(errors.join("; "));
+        }
+        let is_removed = |path: &std::path::Path| removed.iter().any(|file| path.starts_with(&file.path));
+
+        // Forget a pending cut/copy of something that no longer exists
+        if let Some(entry) = &mut self.clipboard {
+            entry.file_paths.retain(|path| !is_removed(path));
+            if entry.file_paths.is_empty() {
+                self.clipboard = None;
+            }
+        }
+        self.marked.clear();
+
+        self.explorer.refresh().map_err(|e| format!("Failed to refresh: {}", e))?;
+        self.refresh_disk_usage();
+
+        if self.showing_search_results {
+            self.search_results.retain(|result| !is_removed(&result.file_info.path));
+            self.all_search_results.retain(|result| !is_removed(&result.file_info.path));
+            if self.all_search_results.is_empty() {
+                self.clear_search_results();
+            } else if self.search_results.is_empty() {
+                self.search_list_state.select(None);
+            } else {
+                let previous = self.search_list_state.selected().unwrap_or(0).saturating_sub(1);
+                self.search_list_state.select(Some(previous.min(self.search_results.len() - 1)));
+            }
+        } else if self.explorer.files().is_empty() {
+            self.list_state.select(None);
+        } else {
+            let previous = self.list_state.selected().unwrap_or(0).saturating_sub(1);
+            self.list_state.select(Some(previous.min(self.explorer.files().len() - 1)));
+        }
+
+        let done = match (removed.as_slice(), to_trash) {
+            ([single], true) => format!("Moved '{}' to the trash", single.name),
+            ([single], false) => format!("Deleted '{}'", single.name),
+            (many, true) => format!("Moved {} items to the trash", many.len()),
+            (many, false) => format!("Deleted {} items", many.len()),
+        };
+        batch_result(done, errors)
+    }
+
+    /// Entries a file operation applies to: the marked ones when anything
+    /// is marked, otherwise the entry under the cursor.
+    fn selected_targets(&self) -> Result<Vec<FileInfo>, String> {
+        if self.marked.is_empty() {
+            return Ok(vec![self.get_selected_file()?.clone()]);
+        }
+
+        let targets: Vec<FileInfo> = self.visible_entries()
+            .into_iter()
+            .filter(|file| self.marked.contains(&file.path))
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return Err("None of the marked entries are in this list".to_string());
+        }
+        Ok(targets)
+    }
+
+    /// The entries of whichever list is on screen
+    fn visible_entries(&self) -> Vec<&FileInfo> {
+        if self.showing_search_results {
+            self.search_results.iter().map(|result| &result.file_info).collect()
+        } else {
+            self.explorer.files().iter().collect()
+        }
+    }
+
+    /// Marks or unmarks the entry under the cursor and moves down.
+    pub fn toggle_mark_selected(&mut self) -> Result<(), String> {
+        let path = self.get_selected_file()?.path.clone();
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+        self.next_item();
+        Ok(())
+    }
+
+    pub fn mark_all(&mut self) -> String {
+        let paths: Vec<PathBuf> = self.visible_entries().into_iter().map(|file| file.path.clone()).collect();
+        self.marked.extend(paths);
+        format!("Marked {} items", self.marked.len())
+    }
+
+    /// Asks before marking every shown search result, so cut, copy,
+    /// delete and share apply to all of them.
+    pub fn request_select_all_results(&mut self) -> Result<(), String> {
+        if self.search_results.is_empty() {
+            return Err("No results to select".to_string());
+        }
+        let files: Vec<&FileInfo> = self.search_results.iter()
+            .map(|result| &result.file_info)
+            .filter(|file| !file.is_directory)
+            .collect();
+        let directories = self.search_results.len() - files.len();
+        let size = format_size(files.iter().map(|file| file.size).sum());
+        let total = if directories == 0 {
+            size
+        } else {
+            format!("{}, plus {} directories", size, directories)
+        };
+        self.confirm_dialog = Some(ConfirmDialog {
+            title: "Select All Results".to_string(),
+            message: format!("Mark all {} results ({})? Cut, copy, delete and share then apply to every one.",
+                self.search_results.len(), total),
+            action: PendingAction::MarkResults,
+        });
+        Ok(())
+    }
+
+    /// Marks the shown results that still exist, and drops those that
+    /// have disappeared since the search from the results.
+    fn mark_all_results(&mut self) -> Result<String, String> {
+        let (existing, gone): (Vec<PathBuf>, Vec<PathBuf>) = self.search_results.iter()
+            .map(|result| result.file_info.path.clone())
+            .partition(|path| path.symlink_metadata().is_ok());
+        if !gone.is_empty() {
+            self.search_results.retain(|result| !gone.contains(&result.file_info.path));
+            self.all_search_results.retain(|result| !gone.contains(&result.file_info.path));
+            let selected = self.search_list_state.selected().unwrap_or(0);
+            self.search_list_state.select(match self.search_results.len() {
+                0 => None,
+                len => Some(selected.min(len - 1)),
+            });
+        }
+        let marked = existing.len();
+        self.marked.extend(existing);
+        let errors = gone.iter().map(|path| format!("'{}' no longer exists", path.display())).collect();
+        batch_result(format!("Marked {} results", marked), errors)
+    }
+
+    pub fn clear_marks(&mut self) -> String {
+        let count = self.marked.len();
+        self.marked.clear();
+        format!("Cleared {} marks", count)
+    }
+
+    pub fn request_rename_selected(&mut self) -> Result<(), String> {
+        self.ensure_writable()?;
+        if !self.marked.is_empty() {
+            return self.request_batch_rename();
+        }
+        let selected_file = self.get_selected_file()?.clone();
+
+        self.input_prompt = Some(InputPrompt {
+            title: format!("Rename '{}'", selected_file.name),
+            value: selected_file.name.clone(),
+            purpose: InputPurpose::Rename(selected_file),
+            completions: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Applies the text typed into the input popup. On error the popup stays
+    /// open so the value can be corrected.
+    pub fn submit_input_prompt(&mut self) -> Result<String, String> {
+        let mut prompt = match self.input_prompt.take() {
+            Some(prompt) => prompt,
+            None => return Err("Nothing to submit".to_string()),
+        };
+
+        if !matches!(prompt.purpose, InputPurpose::Goto | InputPurpose::Jump) {
+            self.ensure_writable()?;
+        }
+        let result = match &mut prompt.purpose {
+            InputPurpose::Rename(file_info) => self.rename_file(file_info, &prompt.value),
+            InputPurpose::BatchRename(sources) => self.batch_rename(sources, &prompt.value),
+            InputPurpose::NewFile => self.create_entry(&prompt.value, false),
+            InputPurpose::NewDir => self.create_entry(&prompt.value, true),
+            InputPurpose::Goto => self.goto_path(&prompt.value),
+            InputPurpose::Jump => self.jump_to(&prompt.value),
+            InputPurpose::Export => self.export_results(&prompt.value),
+        };
+
+        if result.is_err() {
+            self.input_prompt = Some(prompt);
+        }
+        result
+    }
+
+    pub fn request_goto(&mut self) {
+        self.input_prompt = Some(InputPrompt {
+            title: "Go to path (Tab completes)".to_string(),
+            value: String::new(),
+            purpose: InputPurpose::Goto,
+            completions: Vec::new(),
+        });
+    }
+
+    /// Tab in the go-to popup: extends the typed path as far as the matching
+    /// entries agree and lists them when there is more than one. In the
+    /// export popup it switches the file to the next format.
+    pub fn complete_input_prompt(&mut self) {
+        let base = self.explorer.current_path().to_path_buf();
+        let Some(prompt) = self.input_prompt.as_mut() else {
+            return;
+        };
+        match prompt.purpose {
+            InputPurpose::Goto => {
+                let (value, completions) = completion::complete_path(&prompt.value, &base);
+                prompt.value = value;
+                prompt.completions = completions;
+            }
+            InputPurpose::Export => {
+                let path = Path::new(&prompt.value);
+                let next = ExportFormat::from_path(path).next();
+                prompt.value = path.with_extension(next.extension()).to_string_lossy().into_owned();
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens the export popup with a timestamped CSV file name in the
+    /// current directory.
+    pub fn request_export_results(&mut self) -> Result<(), String> {
+        self.ensure_writable()?;
+        if self.search_results.is_empty() {
+            return Err("No results to export".to_string());
+        }
+        self.input_prompt = Some(InputPrompt {
+            title: format!("Export {} results (.txt, .csv or .json - Tab switches)", self.search_results.len()),
+            value: export::default_file_name(ExportFormat::Csv, SystemTime::now()),
+            purpose: InputPurpose::Export,
+            completions: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Writes the shown results to `name`, relative to the current
+    /// directory, in the format its extension names. An existing file is
+    /// left alone.
+    fn export_results(&mut self, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Enter a file name".to_string());
+        }
+        let path = self.explorer.current_path().join(name);
+        let file = std::fs::OpenOptions::new().write(true).create_new(true).open(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let format = ExportFormat::from_path(&path);
+        export::write_results(&self.search_results, format, io::BufWriter::new(file))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(format!("Exported {} results to {}", self.search_results.len(), path.display()))
+    }
+
+    /// Enters the typed directory, or the parent of a typed file with the
+    /// file selected.
+    fn goto_path(&mut self, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err("Type a path to go to".to_string());
+        }
+        let path = completion::expand_path(input, self.explorer.current_path());
+        let (directory, file) = if path.is_dir() {
+            (path.clone(), None)
+        } else if path.symlink_metadata().is_ok() {
+            let parent = path.parent().map(Path::to_path_buf).ok_or("Path has no parent directory")?;
+            (parent, Some(path.clone()))
+        } else {
+            return Err(format!("No such file or directory: {}", path.display()));
+        };
+
+        self.remember_view_state();
+        self.explorer.navigate_to(directory.clone(), self.selected_list_path())
+            .map_err(|e| format!("Failed to open {}: {}", directory.display(), e))?;
+        if self.showing_search_results || self.search_mode {
+            self.clear_search_results();
+        }
+        self.marked.clear();
+        self.restore_view_state();
+        match file {
+            Some(file) => {
+                let file = self.explorer.current_path().join(file.file_name().unwrap_or_default());
+                self.select_path(&file);
+                Ok(format!("Selected {}", file.display()))
+            }
+            None => Ok(format!("Jumped to {}", self.explorer.current_path().display())),
+        }
+    }
+
+    /// Opens the jump popup, which lists the visited directories matching
+    /// what is typed, most frequently and recently visited first.
+    /// Directories deleted since are forgotten first.
+    pub fn request_jump(&mut self) -> Result<(), String> {
+        self.explorer.frecency_mut().prune_missing();
+        if self.explorer.frecency().is_empty() {
+            return Err("No visited directories to jump to yet".to_string());
+        }
+        self.input_prompt = Some(InputPrompt {
+            title: "Jump to a visited directory".to_string(),
+            value: String::new(),
+            purpose: InputPurpose::Jump,
+            completions: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// What the jump popup offers for `query`, best first, leaving out the
+    /// current directory.
+    pub fn jump_candidates(&self, query: &str) -> Vec<&Path> {
+        self.explorer.frecency().matches(query.trim(), SystemTime::now())
+            .into_iter()
+            .filter(|path| *path != self.explorer.current_path())
+            .take(JUMP_CANDIDATES)
+            .collect()
+    }
+
+    /// Enters the best candidate for `query`.
+    fn jump_to(&mut self, query: &str) -> Result<String, String> {
+        let target = self.jump_candidates(query).first().map(|path| path.to_path_buf())
+            .ok_or_else(|| format!("No visited directory matches '{}'", query.trim()))?;
+        if !target.is_dir() {
+            self.explorer.frecency_mut().prune_missing();
+            return Err(format!("{} no longer exists", target.display()));
+        }
+
+        self.remember_view_state();
+        self.explorer.navigate_to(target.clone(), self.selected_list_path())
+            .map_err(|e| format!("Failed to open {}: {}", target.display(), e))?;
+        if self.showing_search_results || self.search_mode {
+            self.clear_search_results();
+        }
+        self.marked.clear();
+        self.restore_view_state();
+        Ok(format!("Jumped to {}", target.display()))
+    }
+
+    pub fn cancel_input_prompt(&mut self) {
+        if self.input_prompt.take().is_some() {
+            self.set_info_message("Cancelled".to_string());
+        }
+    }
+
+    fn rename_file(&mut self, file_info: &FileInfo, new_name: &str) -> Result<String, String> {
+        let new_path = self.explorer.rename_entry(file_info, new_name)
+            .map_err(|e| format!("Failed to rename '{}': {}", file_info.name, e))?;
+
+        if new_path == file_info.path {
+            return Ok(format!("'{}' unchanged", file_info.name));
+        }
+
+        self.follow_rename(&file_info.path, &new_path);
+        self.explorer.refresh().map_err(|e| format!("Failed to refresh: {}", e))?;
+        if !self.showing_search_results {
+            self.select_path(&new_path);
+        }
+
+        Ok(format!("Renamed '{}' to '{}'", file_info.name, new_name.trim()))
+    }
+
+    /// Points the clipboard, marks and search results at an entry's new path.
+    fn follow_rename(&mut self, old_path: &Path, new_path: &Path) {
+        // Keep a pending cut/copy pointing at the right place
+        if let Some(entry) = &mut self.clipboard {
+            for path in &mut entry.file_paths {
+                if let Ok(suffix) = path.strip_prefix(old_path) {
+                    *path = new_path.join(suffix);
+                }
+            }
+        }
+        if self.marked.remove(old_path) {
+            self.marked.insert(new_path.to_path_buf());
+        }
+
+        if let Ok(renamed) = FileInfo::from_path(new_path) {
+            for result in self.search_results.iter_mut().chain(self.all_search_results.iter_mut()) {
+                if result.file_info.path == old_path {
+                    result.file_info = renamed.clone();
+                }
+            }
+        }
+    }
+
+    /// Opens the batch rename popup for the marked entries, in list order.
+    pub fn request_batch_rename(&mut self) -> Result<(), String> {
+        let sources: Vec<PathBuf> = self.selected_targets()?.into_iter().map(|file| file.path).collect();
+        self.input_prompt = Some(InputPrompt {
+            title: format!("Rename {} items ({{n}} {{n:03}} {{name}} {{ext}} or find->replace)", sources.len()),
+            value: String::new(),
+            purpose: InputPurpose::BatchRename(sources),
+            completions: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Renames `sources` from `pattern`, refusing while any names collide.
+    /// A failure partway lists the renames that did happen and leaves
+    /// `sources` at their new paths, so submitting again only retries the rest.
+    fn batch_rename(&mut self, sources: &mut [PathBuf], pattern: &str) -> Result<String, String> {
+        let plan = batch_rename::plan(sources, pattern)?;
+        if let Some(conflict) = plan.conflicts.first() {
+            return Err(match plan.conflicts.len() {
+                1 => conflict.clone(),
+                count => format!("{} (and {} more conflicts)", conflict, count - 1),
+            });
+        }
+        if plan.renames.is_empty() {
+            return Err("The pattern doesn't change any names".to_string());
+        }
+
+        let (renamed, failure) = match batch_rename::apply(&plan) {
+            Ok(renamed) => (renamed, None),
+            Err(err) => {
+                let message = err.to_string();
+                (err.renamed, Some(message))
+            }
+        };
+        for step in &renamed {
+            self.follow_rename(&step.from, &step.to);
+            if let Some(source) = sources.iter_mut().find(|source| **source == step.from) {
+                *source = step.to.clone();
+            }
+        }
+        self.refresh_listing()?;
+
+        match failure {
+            None => Ok(format!("Renamed {} items", renamed.len())),
+            // The popup stays open, so this has to say what already changed
+            Some(failure) if renamed.is_empty() => Err(format!("Nothing renamed: {}", failure)),
+            Some(failure) => {
+                let done: Vec<String> = renamed.iter()
+                    .map(|step| format!("'{}' -> '{}'", step.old_name(), step.new_name()))
+                    .collect();
+                Err(format!("Renamed {} of {} ({}), then failed on {}", renamed.len(), plan.renames.len(), done.join(", "), failure))
+            }
+        }
+    }
+
+    pub fn request_new_entry(&mut self, is_directory: bool) -> Result<(), String> {
+        self.ensure_writable()?;
+        let (title, purpose) = if is_directory {
+            ("New directory", InputPurpose::NewDir)
+        } else {
+            ("New file", InputPurpose::NewFile)
+        };
+
+        self.input_prompt = Some(InputPrompt {
+            title: title.to_string(),
+            value: String::new(),
+            purpose,
+            completions: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn create_entry(&mut self, name: &str, is_directory: bool) -> Result<String, String> {
+        let kind = if is_directory { "directory" } else { "file" };
+        let created = if is_directory {
+            self.explorer.create_dir(name)
+        } else {
+            self.explorer.create_file(name)
+        };
+        let created = created.map_err(|e| format!("Failed to create {} '{}': {}", kind, name.trim(), e))?;
+
+        self.explorer.refresh().map_err(|e| format!("Failed to refresh: {}", e))?;
+
+        // For nested names select the top-level entry that now holds the new item
+        let current_dir = self.explorer.current_path().to_path_buf();
+        let top_level = created.strip_prefix(&current_dir).ok()
+            .and_then(|relative| relative.components().next())
+            .map(|first| current_dir.join(first))
+            .unwrap_or(created);
+        self.select_path(&top_level);
+
+        Ok(format!("Created {} '{}'", kind, name.trim()))
+    }
+
+    pub fn toggle_hidden(&mut self) -> Result<String, String> {
+        let selected_path = self.selected_list_path();
+        self.explorer.toggle_hidden().map_err(|e| format!("Failed to refresh: {}", e))?;
+        self.restore_selection(selected_path.as_deref());
+
+        if self.explorer.show_hidden() {
+            Ok("Showing hidden files".to_string())
+        } else {
+            Ok("Hiding hidden files".to_string())
+        }
+    }
+
+    /// Switches between hiding and showing git-ignored entries. The rules
+    /// are cached per directory, so this doesn't re-read the listing.
+    pub fn toggle_ignored(&mut self) -> String {
+        let selected_path = self.selected_list_path();
+        self.explorer.set_hide_ignored(!self.explorer.hide_ignored());
+        self.restore_selection(selected_path.as_deref());
+
+        if !self.explorer.hide_ignored() {
+            "Showing git-ignored entries".to_string()
+        } else if self.explorer.in_git_repository() {
+            format!("Hiding {} git-ignored entries", self.explorer.ignored_count())
+        } else {
+            "Hiding git-ignored entries (not in a git repository here)".to_string()
+        }
+    }
+
+    /// Refreshes the listing if the current directory changed on disk,
+    /// keeping the selection on the same entry. Following the explorer to a
+    /// new directory re-targets the watch.
+    pub fn poll_directory_changes(&mut self) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+        if let Err(e) = watcher.watch(self.explorer.current_path()) {
+            self.set_warning_message(format!("Not watching this directory for changes: {}", e));
+            return;
+        }
+        if !watcher.poll_changed() {
+            return;
+        }
+
+        let _ = self.refresh_listing();
+    }
+
+    pub fn toggle_selected_executable(&mut self) -> Result<String, String> {
+        self.ensure_writable()?;
+        let selected_file = self.get_selected_file()?.clone();
+        if selected_file.is_directory {
+            return Err("The executable toggle only applies to files".to_string());
+        }
+
+        let executable = self.explorer.toggle_executable(&selected_file)
+            .map_err(|e| format!("Failed to change permissions of '{}': {}", selected_file.name, e))?;
+        if !self.showing_search_results {
+            self.refresh_listing()?;
+        }
+
+        if executable {
+            Ok(format!("'{}' is now executable", selected_file.name))
+        } else {
+            Ok(format!("'{}' is no longer executable", selected_file.name))
+        }
+    }
+
+    pub fn toggle_bookmarks_bar(&mut self) -> String {
+        self.show_bookmarks_bar = !self.show_bookmarks_bar;
+        if self.show_bookmarks_bar {
+            "Bookmarks bar shown".to_string()
+        } else {
+            "Bookmarks bar hidden".to_string()
+        }
+    }
+
+    /// Jumps to the bookmark shown as `number` (1-based) in the bookmarks bar.
+    pub fn jump_to_bookmark(&mut self, number: usize) -> Result<String, String> {
+        let bookmark = number.checked_sub(1)
+            .and_then(|index| self.config.bookmarks.get(index))
+            .cloned()
+            .ok_or_else(|| format!("No bookmark {}", number))?;
+        if !bookmark.is_dir() {
+            return Err(format!("Bookmark '{}' is not a directory", bookmark.display()));
+        }
+
+        self.remember_view_state();
+        self.explorer.navigate_to(bookmark.clone(), self.selected_list_path())
+            .map_err(|e| format!("Failed to open bookmark: {}", e))?;
+        self.marked.clear();
+        self.restore_view_state();
+        Ok(format!("Jumped to {}", bookmark.display()))
+    }
+
+    /// Bookmarks the current directory and saves the list to the config file.
+    pub fn add_bookmark(&mut self) -> Result<String, String> {
+        let path = self.explorer.current_path().to_path_buf();
+        if self.config.bookmarks.contains(&path) {
+            return Err(format!("{} is already bookmarked", path.display()));
+        }
+
+        self.config.bookmarks.push(path.clone());
+        let number = self.config.bookmarks.len();
+        self.config.save_bookmarks()
+            .map_err(|e| format!("Bookmarked {} but failed to save it: {}", path.display(), e))?;
+        Ok(format!("Bookmarked {} as {}", path.display(), number))
+    }
+
+    pub fn open_bookmarks(&mut self) -> Result<(), String> {
+        if self.config.bookmarks.is_empty() {
+            let kb = &self.config.key_bindings;
+            return Err(format!(
+                "No bookmarks yet - press {} to bookmark this directory",
+                kb.get_key_display(&kb.actions.add_bookmark)
+            ));
+        }
+        let mut state = ListState::default();
+        state.select(Some(0));
+        self.bookmark_picker = Some(state);
+        Ok(())
+    }
+
+    pub fn close_bookmarks(&mut self) {
+        self.bookmark_picker = None;
+    }
+
+    /// Moves the bookmarks popup selection by `offset`, wrapping around.
+    pub fn move_bookmark_selection(&mut self, offset: isize) {
+        let count = self.config.bookmarks.len() as isize;
+        if let Some(state) = self.bookmark_picker.as_mut() {
+            if count > 0 {
+                let current = state.selected().unwrap_or(0) as isize;
+                state.select(Some((current + offset).rem_euclid(count) as usize));
+            }
+        }
+    }
+
+    /// Jumps to the bookmark highlighted in the popup, closing it on success.
+    pub fn jump_to_picked_bookmark(&mut self) -> Result<String, String> {
+        let index = self.bookmark_picker.as_ref()
+            .and_then(|state| state.selected())
+            .ok_or("No bookmark selected")?;
+        let msg = self.jump_to_bookmark(index + 1)?;
+        self.bookmark_picker = None;
+        Ok(msg)
+    }
+
+    /// Removes the bookmark highlighted in the popup and saves the list.
+    pub fn remove_picked_bookmark(&mut self) -> Result<String, String> {
+        let index = self.bookmark_picker.as_ref()
+            .and_then(|state| state.selected())
+            .filter(|&index| index < self.config.bookmarks.len())
+            .ok_or("No bookmark selected")?;
+        let removed = self.config.bookmarks.remove(index);
+
+        let remaining = self.config.bookmarks.len();
+        if remaining == 0 {
+            self.bookmark_picker = None;
+        } else if let Some(state) = self.bookmark_picker.as_mut() {
+            state.select(Some(index.min(remaining - 1)));
+        }
+
+        self.config.save_bookmarks()
+            .map_err(|e| format!("Removed bookmark {} but failed to save: {}", removed.display(), e))?;
+        Ok(format!("Removed bookmark {}", removed.display()))
+    }
+
+    /// Opens the properties popup for the selection. A directory's item
+    /// count and total size are read on a blocking task.
+    pub fn show_properties(&mut self) -> Result<(), String> {
+        let path = self.get_selected_file()?.path.clone();
+        let file = FileInfo::from_path(&path)
+            .map_err(|e| format!("Failed to read '{}': {}", file_system::display_name(&path), e))?;
+        let mime_type = if file.is_directory { "inode/directory" } else { file_sharing::content_mime_type(&path) };
+        let rows = properties::describe(&file, mime_type)
+            .map_err(|e| format!("Failed to read '{}': {}", file.name, e))?;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let events = file.is_directory.then(|| {
+            let (sender, events) = mpsc::channel();
+            let task_cancel = cancel.clone();
+            let dir = path.clone();
+            tokio::task::spawn_blocking(move || properties::directory_details(&dir, &task_cancel, &sender));
+            events
+        });
+        self.properties = Some(PropertiesPopup { path, rows, cancel, events });
+        Ok(())
+    }
+
+    /// Fills in properties that were still loading.
+    pub fn poll_properties(&mut self) {
+        let Some(popup) = self.properties.as_mut() else {
+            return;
+        };
+        let Some(events) = popup.events.as_ref() else {
+            return;
+        };
+        loop {
+            match events.try_recv() {
+                Ok((label, value)) => {
+                    if let Some(row) = popup.rows.iter_mut().find(|(row_label, _)| *row_label == label) {
+                        row.1 = value;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    popup.events = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn close_properties(&mut self) {
+        if let Some(popup) = self.properties.take() {
+            popup.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Copies the properties as `Label: value` lines.
+    pub fn copy_properties(&self) -> Result<String, String> {
+        let popup = self.properties.as_ref().ok_or("No properties open")?;
+        match copy_to_clipboard(&properties::report(&popup.rows)) {
+            Ok(_) => Ok(format!("Copied properties of '{}'", file_system::display_name(&popup.path))),
+            Err(e) => Err(format!("Failed to copy properties to clipboard: {}", e)),
+        }
+    }
+
+    /// Starts comparing the two marked files on a blocking task; the result
+    /// arrives through `poll_compare`.
+    pub fn compare_marked(&mut self) -> Result<String, String> {
+        let mut paths: Vec<PathBuf> = self.marked.iter().cloned().collect();
+        let [left, right] = paths.as_mut_slice() else {
+            return Err("Mark exactly two files to compare".to_string());
+        };
+        if left > right {
+            std::mem::swap(left, right);
+        }
+        for path in [&*left, &*right] {
+            if path.is_dir() {
+                return Err(format!("'{}' is a directory - only files can be compared", file_system::display_name(path)));
+            }
+        }
+
+        if let Some(running) = self.running_compare.take() {
+            running.cancel.store(true, Ordering::Relaxed);
+        }
+
+        let (left, right) = (left.clone(), right.clone());
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+        let (task_left, task_right) = (left.clone(), right.clone());
+        let task_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut last_percent = None;
+            let result = compare::compare_files(&task_left, &task_right, &task_cancel, |done, total| {
+                let percent = (done * 100).checked_div(total).unwrap_or(100);
+                if last_percent != Some(percent) {
+                    last_percent = Some(percent);
+                    let _ = sender.send(CompareEvent::Progress(done, total));
+                }
+            });
+            let _ = sender.send(CompareEvent::Finished(result));
+        });
+
+        let message = format!("Comparing '{}' and '{}'...", file_system::display_name(&left), file_system::display_name(&right));
+        self.running_compare = Some(RunningCompare { left, right, cancel, events });
+        Ok(message)
+    }
+
+    /// Reports comparison progress; when done, opens a popup with the
+    /// unified diff, or sizes and checksums when either file isn't text.
+    pub fn poll_compare(&mut self) {
+        let Some(running) = self.running_compare.as_ref() else {
+            return;
+        };
+        let (left_name, right_name) = (file_system::display_name(&running.left), file_system::display_name(&running.right));
+
+        let mut progress = None;
+        let result = loop {
+            match running.events.try_recv() {
+                Ok(CompareEvent::Progress(done, total)) => progress = Some((done, total)),
+                Ok(CompareEvent::Finished(result)) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    if let Some((done, total)) = progress {
+                        let percent = (done * 100).checked_div(total).unwrap_or(100);
+                        self.set_info_message(format!("Comparing '{}' and '{}'... {}%", left_name, right_name, percent));
+                    }
+                    return;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(io::Error::other("comparison stopped unexpectedly"));
+                }
+            }
+        };
+
+        let Some(running) = self.running_compare.take() else {
+            return;
+        };
+        match result {
+            Ok(comparison) => {
+                self.diff_popup = Some(DiffPopup {
+                    title: format!("{} ↔ {}", left_name, right_name),
+                    lines: comparison.report.lines().map(str::to_string).collect(),
+                    scroll: 0,
+                });
+                self.set_info_message(if comparison.identical {
+                    format!("'{}' and '{}' are identical", left_name, right_name)
+                } else {
+                    format!("'{}' and '{}' differ", left_name, right_name)
+                });
+            }
+            Err(_) if running.cancel.load(Ordering::Relaxed) => {
+                self.set_info_message(format!("Comparing '{}' and '{}' cancelled", left_name, right_name));
+            }
+            Err(e) => self.set_error_message(format!("Failed to compare: {}", e)),
+        }
+    }
+
+    /// Scrolls the diff popup by `offset` lines, stopping at either end.
+    pub fn scroll_diff(&mut self, offset: isize) {
+        if let Some(popup) = self.diff_popup.as_mut() {
+            let last = popup.lines.len().saturating_sub(1);
+            popup.scroll = popup.scroll.saturating_add_signed(offset).min(last);
+        }
+    }
+
+    pub fn close_diff(&mut self) {
+        self.diff_popup = None;
+    }
+
+    pub fn copy_diff(&self) -> Result<String, String> {
+        let popup = self.diff_popup.as_ref().ok_or("No comparison open")?;
+        match copy_to_clipboard(&popup.lines.join("\n")) {
+            Ok(_) => Ok("Copied the comparison".to_string()),
+            Err(e) => Err(format!("Failed to copy the comparison to clipboard: {}", e)),
+        }
+    }
+
+    /// Swaps the file list for the mounted volumes, with the one holding
+    /// the current directory selected, or switches back.
+    pub fn toggle_drives(&mut self) -> Result<(), String> {
+        if self.drives_view.take().is_some() {
+            return Ok(());
+        }
+        let volumes = mounts::list_volumes(self.config.show_pseudo_filesystems);
+        if volumes.is_empty() {
+            return Err("No mounted volumes found".to_string());
+        }
+
+        let current_dir = self.explorer.current_path();
+        let current = volumes.iter()
+            .enumerate()
+            .filter(|(_, volume)| current_dir.starts_with(&volume.mount_point))
+            .max_by_key(|(_, volume)| volume.mount_point.components().count())
+            .map_or(0, |(index, _)| index);
+        let mut state = ListState::default();
+        state.select(Some(current));
+        self.drives_view = Some(DrivesView { volumes, state });
+        Ok(())
+    }
+
+    /// Moves the drives view selection by `offset`, wrapping around.
+    pub fn move_drive_selection(&mut self, offset: isize) {
+        if let Some(view) = self.drives_view.as_mut() {
+            let count = view.volumes.len() as isize;
+            let current = view.state.selected().unwrap_or(0) as isize;
+            view.state.select(Some((current + offset).rem_euclid(count) as usize));
+        }
+    }
+
+    /// Enters the volume highlighted in the drives view and goes back to the file list.
+    pub fn enter_picked_drive(&mut self) -> Result<String, String> {
+        let view = self.drives_view.as_ref().ok_or("No volume selected")?;
+        let mount_point = view.state.selected()
+            .and_then(|index| view.volumes.get(index))
+            .map(|volume| volume.mount_point.clone())
+            .ok_or("No volume selected")?;
+
+        self.remember_view_state();
+        self.explorer.navigate_to(mount_point.clone(), self.selected_list_path())
+            .map_err(|e| format!("Failed to open {}: {}", mount_point.display(), e))?;
+        self.drives_view = None;
+        self.marked.clear();
+        self.restore_view_state();
+        Ok(format!("Moved to {}", mount_point.display()))
+    }
+
+    /// Shows or hides the directory tree pane, focusing it when shown, and
+    /// saves the choice to the config file.
+    pub fn toggle_tree(&mut self) -> Result<String, String> {
+        self.config.show_tree_pane = !self.config.show_tree_pane;
+        self.tree_pane.focused = self.config.show_tree_pane;
+        let msg = if self.config.show_tree_pane {
+            if !self.tree_pane.tree.is_expanded(0) {
+                self.expand_tree_node_at(0)?;
+            }
+            "Tree pane shown"
+        } else {
+            "Tree pane hidden"
+        };
+        self.config.save_show_tree_pane()
+            .map_err(|e| format!("{} but the setting couldn't be saved: {}", msg, e))?;
+        Ok(msg.to_string())
+    }
+
+    /// Moves keyboard focus between the tree pane and the file list.
+    pub fn toggle_tree_focus(&mut self) {
+        self.tree_pane.focused = self.config.show_tree_pane && !self.tree_pane.focused;
+    }
+
+    pub fn tree_focused(&self) -> bool {
+        self.config.show_tree_pane && self.tree_pane.focused
+    }
+
+    /// Moves the tree cursor by `offset` and opens the directory it lands
+    /// on in the file list.
+    pub fn move_tree_selection(&mut self, offset: isize) -> Result<(), String> {
+        let last = self.tree_pane.tree.rows().len().saturating_sub(1) as isize;
+        let current = self.tree_pane.state.selected().unwrap_or(0) as isize;
+        let index = (current + offset).clamp(0, last) as usize;
+        self.tree_pane.state.select(Some(index));
+        self.follow_tree_selection()
+    }
+
+    /// Lists the subdirectories of the directory under the tree cursor.
+    pub fn expand_tree_node(&mut self) -> Result<(), String> {
+        let index = self.tree_pane.state.selected().unwrap_or(0);
+        self.expand_tree_node_at(index)
+    }
+
+    /// Folds the directory under the tree cursor, or moves the cursor to
+    /// its parent when it is already folded.
+    pub fn collapse_tree_node(&mut self) -> Result<(), String> {
+        let index = self.tree_pane.state.selected().unwrap_or(0);
+        if self.tree_pane.tree.is_expanded(index) {
+            self.tree_pane.tree.collapse(index);
+            return Ok(());
+        }
+        match self.tree_pane.tree.parent(index) {
+            Some(parent) => {
+                self.tree_pane.state.select(Some(parent));
+                self.follow_tree_selection()
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn expand_tree_node_at(&mut self, index: usize) -> Result<(), String> {
+        let show_hidden = self.explorer.show_hidden();
+        let tree = &mut self.tree_pane.tree;
+        let path = tree.rows().get(index).and_then(|row| row.path()).map(Path::to_path_buf);
+        tree.expand(index, show_hidden).map_err(|e| match path {
+            Some(path) => format!("Failed to read {}: {}", path.display(), e),
+            None => e.to_string(),
+        })
+    }
+
+    /// Points the file list at the directory under the tree cursor.
+    fn follow_tree_selection(&mut self) -> Result<(), String> {
+        let Some(path) = self.tree_pane.state.selected()
+            .and_then(|index| self.tree_pane.tree.rows().get(index))
+            .and_then(|row| row.path())
+            .map(Path::to_path_buf)
+        else {
+            return Ok(());
+        };
+        if path == self.explorer.current_path() && !self.showing_search_results {
+            return Ok(());
+        }
+
+        self.remember_view_state();
+        self.explorer.navigate_to(path.clone(), self.selected_list_path())
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        if self.showing_search_results {
+            self.clear_search_results();
+        }
+        self.marked.clear();
+        self.restore_view_state();
+        Ok(())
+    }
+
+    /// Navigates to the configured home base (or $HOME).
+    pub fn go_home(&mut self) -> Result<String, String> {
+        let home = self.config.home_base()
+            .ok_or_else(|| "No home directory is set".to_string())?;
+        if !home.is_dir() {
+            return Err(format!("Home '{}' is not a directory", home.display()));
+        }
+
+        self.remember_view_state();
+        self.explorer.navigate_to(home.clone(), self.selected_list_path())
+            .map_err(|e| format!("Failed to go home: {}", e))?;
+        self.marked.clear();
+        self.restore_view_state();
+        Ok(format!("Jumped to {}", home.display()))
+    }
+
+    pub fn cycle_sort(&mut self) -> String {
+        let selected_path = self.selected_list_path();
+        let mode = self.explorer.sort_mode().next();
+        self.explorer.set_sort(mode, self.explorer.sort_reverse());
+        self.restore_selection(selected_path.as_deref());
+        format!("Sorting {}", self.explorer.sort_description())
+    }
+
+    pub fn reverse_sort(&mut self) -> String {
+        let selected_path = self.selected_list_path();
+        self.explorer.set_sort(self.explorer.sort_mode(), !self.explorer.sort_reverse());
+        self.restore_selection(selected_path.as_deref());
+        format!("Sorting {}", self.explorer.sort_description())
+    }
+
+    /// Re-reads the current directory, keeping the selection on the same
+    /// entry (or the nearest one if it disappeared).
+    pub fn refresh_listing(&mut self) -> Result<(), String> {
+        let selected_path = self.selected_list_path();
+        self.explorer.refresh().map_err(|e| format!("Failed to refresh: {}", e))?;
+        self.restore_selection(selected_path.as_deref());
+        self.refresh_disk_usage();
+        self.git_status_dir = None;
+        self.directory_preview = None;
+        Ok(())
+    }
+
+    /// Reads the metadata a large directory was listed without, in batches
+    /// on a blocking task, and folds each batch into the listing as it
+    /// arrives. Leaving the directory cancels the read.
+    pub fn update_metadata(&mut self) {
+        let current_dir = self.explorer.current_path();
+        if self.running_metadata.as_ref().is_some_and(|running| running.dir != current_dir) {
+            if let Some(running) = self.running_metadata.take() {
+                running.cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        if self.running_metadata.is_none() {
+            let pending = self.explorer.pending_metadata();
+            if pending.is_empty() {
+                return;
+            }
+            let (sender, events) = mpsc::channel();
+            let cancel = Arc::new(AtomicBool::new(false));
+            let task_cancel = cancel.clone();
+            tokio::task::spawn_blocking(move || {
+                for chunk in pending.chunks(METADATA_BATCH) {
+                    if task_cancel.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let batch = chunk.iter().map(|path| (path.clone(), FileInfo::from_path(path).ok())).collect();
+                    if sender.send(batch).is_err() {
+                        return;
+                    }
+                }
+            });
+            self.running_metadata = Some(RunningMetadata { dir: current_dir.to_path_buf(), cancel, events });
+        }
+
+        let Some(running) = self.running_metadata.as_ref() else {
+            return;
+        };
+        let mut loaded = Vec::new();
+        loop {
+            match running.events.try_recv() {
+                Ok(batch) => loaded.extend(batch),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.running_metadata = None;
+                    break;
+                }
+            }
+        }
+        if !loaded.is_empty() {
+            let selected_path = self.selected_list_path();
+            self.explorer.fill_metadata(loaded);
+            self.restore_selection(selected_path.as_deref());
+        }
+    }
+
+    /// Lists the selected directory, or highlighted directory result, for
+    /// the preview pane on a blocking task, so selecting a huge directory
+    /// doesn't stall drawing. Only the selected directory's preview is kept.
+    pub fn update_directory_preview(&mut self) {
+        let selected = self.previewed_file()
+            .filter(|file| file.is_directory && !file.is_broken_link)
+            .cloned();
+        let Some(file) = selected else {
+            self.directory_preview = None;
+            return;
+        };
+        if self.directory_preview.as_ref().is_some_and(|(path, _)| *path != file.path) {
+            self.directory_preview = None;
+        }
+        if self.directory_preview.is_none() && self.running_directory_preview.as_ref().is_none_or(|running| running.path != file.path) {
+            // A preview still being listed for another entry is dropped
+            let (sender, events) = mpsc::channel();
+            let task_file = file.clone();
+            tokio::task::spawn_blocking(move || {
+                let _ = sender.send(preview::DirectoryPreview.preview(&task_file));
+            });
+            self.running_directory_preview = Some(RunningDirectoryPreview { path: file.path.clone(), events });
+        }
+
+        let Some(running) = self.running_directory_preview.as_ref() else {
+            return;
+        };
+        let lines = match running.events.try_recv() {
+            Ok(lines) => lines,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => vec![Line::from("Error reading directory")],
+        };
+        if let Some(running) = self.running_directory_preview.take() {
+            self.directory_preview = Some((running.path, lines));
+        }
+    }
+
+    /// Starts reading `git status` after a directory change or refresh, one
+    /// read at a time, and picks up the result once it is done.
+    pub fn update_git_status(&mut self) {
+        if !self.config.git_status {
+            return;
+        }
+        let current_dir = self.explorer.current_path();
+        if self.running_git_status.is_none() && self.git_status_dir.as_deref() != Some(current_dir) {
+            let dir = current_dir.to_path_buf();
+            let (sender, events) = mpsc::channel();
+            let task_dir = dir.clone();
+            tokio::task::spawn_blocking(move || {
+                let _ = sender.send(git_status::read_status(&task_dir));
+            });
+            self.git_status_dir = Some(dir);
+            self.running_git_status = Some(RunningGitStatus { events });
+        }
+
+        let Some(running) = self.running_git_status.as_ref() else {
+            return;
+        };
+        let result = match running.events.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(io::Error::other("git stopped unexpectedly")),
+        };
+        self.running_git_status = None;
+        // Statuses are keyed by absolute path, so a result for a directory
+        // that was left already matches nothing in the new listing
+        match result {
+            Ok(status) => self.git_status = status,
+            Err(e) => {
+                self.git_status = None;
+                self.set_warning_message(format!("Git status unavailable: {}", e));
+            }
+        }
+    }
+
+    /// Re-reads free space for the header once the directory has changed.
+    pub fn update_disk_usage(&mut self) {
+        if self.disk_usage_path != self.explorer.current_path() {
+            self.refresh_disk_usage();
+        }
+    }
+
+    fn refresh_disk_usage(&mut self) {
+        self.disk_usage_path = self.explorer.current_path().to_path_buf();
+        self.disk_usage = file_system::disk_usage(&self.disk_usage_path);
+    }
+
+    /// Records how the current directory is being viewed, before leaving it.
+    fn remember_view_state(&mut self) {
+        let state = ViewState {
+            sort_mode: self.explorer.sort_mode(),
+            sort_reverse: self.explorer.sort_reverse(),
+            show_hidden: self.explorer.show_hidden(),
+            filter: self.explorer.filter().to_string(),
+            selected: self.selected_list_path(),
+            offset: self.list_offset.get(),
+        };
+        self.view_states.insert(self.explorer.current_path().to_path_buf(), state);
+    }
+
+    /// After entering a directory, views it the way it was left, or starts
+    /// at the top with the current settings if it wasn't visited recently.
+    fn restore_view_state(&mut self) {
+        self.list_state.select(Some(0));
+        self.list_offset.set(0);
+        let Some(state) = self.view_states.get(self.explorer.current_path()).cloned() else {
+            return;
+        };
+        if state.show_hidden != self.explorer.show_hidden() {
+            let _ = self.explorer.set_show_hidden(state.show_hidden);
+        }
+        self.explorer.set_sort(state.sort_mode, state.sort_reverse);
+        self.explorer.set_filter(&state.filter);
+        self.list_offset.set(state.offset);
+        self.restore_selection(state.selected.as_deref());
+    }
+
+    /// Writes the remembered view states next to the config file when
+    /// `remember_view_state` is on, including the current directory's.
+    pub fn save_view_states(&mut self) -> Result<(), String> {
+        if !self.config.remember_view_state {
+            return Ok(());
+        }
+        self.remember_view_state();
+        let path = self.config.state_file_path(VIEW_STATE_FILE)
+            .map_err(|e| format!("Failed to save view state: {}", e))?;
+        self.view_states.save(&path)
+            .map_err(|e| format!("Failed to save view state to {}: {}", path.display(), e))
+    }
+
+    /// Writes the search history next to the config file when
+    /// `persist_search_history` is on.
+    pub fn save_search_history(&self) -> Result<(), String> {
+        if !self.config.persist_search_history {
+            return Ok(());
+        }
+        let path = self.config.state_file_path(SEARCH_HISTORY_FILE)
+            .map_err(|e| format!("Failed to save the search history: {}", e))?;
+        self.search_history.save(&path)
+            .map_err(|e| format!("Failed to save the search history to {}: {}", path.display(), e))
+    }
+
+    /// Writes the visited directories next to the config file when
+    /// `persist_frecency` is on.
+    pub fn save_frecency(&self) -> Result<(), String> {
+        if !self.config.persist_frecency {
+            return Ok(());
+        }
+        let path = self.config.state_file_path(FRECENCY_FILE)
+            .map_err(|e| format!("Failed to save the visited directories: {}", e))?;
+        self.explorer.frecency().save(&path)
+            .map_err(|e| format!("Failed to save the visited directories to {}: {}", path.display(), e))
+    }
+
+    /// Records the current directory, sort order and hidden-files setting
+    /// for the next launch when `restore_last_dir` is on.
+    pub fn save_session(&self) -> Result<(), String> {
+        if !self.config.restore_last_dir {
+            return Ok(());
+        }
+        let session = LastSession {
+            directory: self.explorer.current_path().to_path_buf(),
+            sort_mode: self.explorer.sort_mode(),
+            sort_reverse: self.explorer.sort_reverse(),
+            show_hidden: self.explorer.show_hidden(),
+        };
+        let path = self.config.state_file_path(SESSION_FILE)
+            .map_err(|e| format!("Failed to save the session: {}", e))?;
+        session.save(&path)
+            .map_err(|e| format!("Failed to save the session to {}: {}", path.display(), e))
+    }
+
+    fn selected_list_path(&self) -> Option<PathBuf> {
+        self.list_state.selected()
+            .and_then(|index| self.explorer.files().get(index))
+            .map(|file| file.path.clone())
+    }
+
+    /// Re-selects `path` after the listing changed, falling back to the
+    /// closest valid index when it is gone.
+    fn restore_selection(&mut self, path: Option<&std::path::Path>) {
+        let files_len = self.explorer.files().len();
+        let position = path.and_then(|path| self.explorer.files().iter().position(|file| file.path == path));
+        match position {
+            Some(index) => self.list_state.select(Some(index)),
+            None if files_len == 0 => self.list_state.select(None),
+            None => {
+                let index = self.list_state.selected().unwrap_or(0).min(files_len - 1);
+                self.list_state.select(Some(index));
+            }
+        }
+    }
+
+    /// Moves the file list cursor onto `path` if it is in the current listing.
+    fn select_path(&mut self, path: &std::path::Path) {
+        if let Some(index) = self.explorer.files().iter().position(|file| file.path == path) {
+            self.list_state.select(Some(index));
+        }
+    }
+
+    pub fn show_config_path(&mut self) {
+        match &self.config.source_path {
+            Some(path) => {
+                let text = format!("Config: {} - press 'e' to edit", path.display());
+                self.set_info_message(text);
+                self.config_edit_offered = true;
+            }
+            None => {
+                self.set_warning_message(
+                    "No config file loaded, using defaults. Run with --create-config to create one.".to_string(),
+                );
+            }
+        }
+    }
+
+    pub fn open_config_file(&self) -> Result<String, String> {
+        let path = self.config.source_path.as_ref()
+            .ok_or("No config file loaded")?;
+
+        match open::that(path) {
+            Ok(_) => Ok(format!("Opened {} - restart FilePilot to apply changes", path.display())),
+            Err(e) => Err(format!("Failed to open config file: {}", e)),
+        }
+    }
+
+    pub fn copy_selected_file_path(&self) -> Result<String, String> {
+        let file_info = if self.showing_search_results {
+            if let Some(selected) = self.search_list_state.selected() {
+                if selected < self.search_results.len() {
+                    &self.search_results[selected].file_info
+                } else {
+                    return Err("No file selected".to_string());
+                }
+            } else {
+                return Err("No file selected".to_string());
+            }
+        } else {
+            if let Some(selected) = self.list_state.selected() {
+                let files = self.explorer.files();
+                if selected < files.len() {
+                    &files[selected]
+                } else {
+                    return Err("No file selected".to_string());
+                }
+            } else {
+                return Err("No file selected".to_string());
+            }
+        };
+
+        let path_str = file_info.path.to_string_lossy().to_string();
+        
+        // Copy to system clipboard
+        match copy_to_clipboard(&path_str) {
+            Ok(_) => Ok(format!("Copied path to clipboard: {}", path_str)),
+            Err(e) => Err(format!("Failed to copy path to clipboard: {}", e)),
+        }
+    }
+
+    /// Starts hashing the selected file on a blocking task; the result
+    /// arrives through `poll_checksum`.
+    pub fn checksum_selected(&mut self) -> Result<String, String> {
+        let file = self.get_selected_file()?.clone();
+        if file.is_directory {
+            return Err("Checksums can only be computed for files".to_string());
+        }
+        if let Some(running) = self.running_checksum.take() {
+            running.cancel.store(true, Ordering::Relaxed);
+        }
+
+        let algorithm = self.config.checksum_algorithm;
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+        let task_path = file.path.clone();
+        let task_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut last_percent = None;
+            let result = checksum::checksum(&task_path, algorithm, &task_cancel, |done, total| {
+                // One update per percent is plenty for the status line
+                let percent = (done * 100).checked_div(total).unwrap_or(100);
+                if last_percent != Some(percent) {
+                    last_percent = Some(percent);
+                    let _ = sender.send(ChecksumEvent::Progress(done, total));
+                }
+            });
+            let _ = sender.send(ChecksumEvent::Finished(result));
+        });
+
+        self.running_checksum = Some(RunningChecksum {
+            path: file.path,
+            name: file.name.clone(),
+            algorithm,
+            cancel,
+            events,
+        });
+        Ok(format!("Hashing '{}' ({})...", file.name, algorithm.label()))
+    }
+
+    /// Reports checksum progress and the final digest, which is also copied
+    /// to the clipboard. Moving the selection off the file cancels the hash.
+    pub fn poll_checksum(&mut self) {
+        let Some(running) = self.running_checksum.as_ref() else {
+            return;
+        };
+
+        let still_selected = self.get_selected_file().is_ok_and(|file| file.path == running.path);
+        if !still_selected {
+            running.cancel.store(true, Ordering::Relaxed);
+            let name = running.name.clone();
+            self.running_checksum = None;
+            self.set_info_message(format!("Checksum of '{}' cancelled", name));
+            return;
+        }
+
+        let mut progress = None;
+        let result = loop {
+            match running.events.try_recv() {
+                Ok(ChecksumEvent::Progress(done, total)) => progress = Some((done, total)),
+                Ok(ChecksumEvent::Finished(result)) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    if let Some((done, total)) = progress {
+                        let percent = (done * 100).checked_div(total).unwrap_or(100);
+                        let msg = format!("Hashing '{}'... {}%", running.name, percent);
+                        self.set_info_message(msg);
+                    }
+                    return;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(io::Error::other("hashing stopped unexpectedly"));
+                }
+            }
+        };
+
+        let Some(running) = self.running_checksum.take() else {
+            return;
+        };
+        match result {
+            Ok(digest) => {
+                let copied = match copy_to_clipboard(&digest) {
+                    Ok(_) => "copied to clipboard",
+                    Err(_) => "clipboard unavailable",
+                };
+                self.set_info_message(format!("{} of '{}': {} ({})", running.algorithm.label(), running.name, digest, copied));
+            }
+            Err(e) => self.set_error_message(format!("Failed to hash '{}': {}", running.name, e)),
+        }
+    }
+
+    pub fn copy_selected_markdown_link(&self) -> Result<String, String> {
+        let file_info = self.get_selected_file()?;
+
+        let target = if self.config.markdown_links_absolute {
+            file_url(&file_info.path)
+        } else {
+            let relative = file_system::relative_path(self.explorer.current_path(), &file_info.path);
+            percent_encode_path(&relative.to_string_lossy().replace('\\', "/"))
+        };
+        let label = file_info.name.replace('[', "\\[").replace(']', "\\]");
+        let link = format!("[{}]({})", label, target);
+
+        match copy_to_clipboard(&link) {
+            Ok(_) => Ok(format!("Copied markdown link: {}", link)),
+            Err(e) => Err(format!("Failed to copy link to clipboard: {}", e)),
+        }
+    }
+
+    fn copy_file_operation(&self, source: &Path, destination: &Path) -> Result<(), std::io::Error> {
+        transfer::copy_path(source, destination, &mut Reporter::default())
+    }
+
+    /// Whether the main area lists search results rather than the directory.
+    fn results_shown(&self) -> bool {
+        (self.search_mode || self.showing_search_results) && !self.all_search_results.is_empty()
+    }
+
+    fn selected_result(&self) -> Option<&SearchResult> {
+        self.search_list_state.selected().and_then(|index| self.search_results.get(index))
+    }
+
+    /// The entry the preview pane shows: the highlighted search result while
+    /// results are listed, the selected file otherwise.
+    fn previewed_file(&self) -> Option<&FileInfo> {
+        if self.results_shown() {
+            self.selected_result().map(|result| &result.file_info)
+        } else {
+            self.list_state.selected().and_then(|index| self.explorer.files().get(index))
+        }
+    }
+
+    pub fn get_file_preview(&self) -> Vec<Line<'static>> {
+        let Some(selected_file) = self.previewed_file() else {
+            return vec![Line::from("No file selected")];
+        };
+
+        if selected_file.is_broken_link {
+            let target = selected_file.link_target.as_ref()
+                .map(|target| target.display().to_string())
+                .unwrap_or_default();
+            return vec![
+                Line::from(format!("🔗 Broken link: {}", selected_file.name)),
+                Line::from(""),
+                Line::from(format!("Target does not exist: {}", target)),
+            ];
+        }
+
+        // Large directories are listed before their entries' metadata is read
+        let loaded;
+        let selected_file = match selected_file.metadata_pending.then(|| FileInfo::from_path(&selected_file.path)) {
+            Some(Ok(info)) => {
+                loaded = info;
+                &loaded
+            }
+            _ => selected_file,
+        };
+
+        // Content search results show the lines around the match
+        let content_match = self.results_shown()
+            .then(|| self.selected_result())
+            .flatten()
+            .and_then(|result| result.content_match.as_ref());
+
+        // Directories are listed in the background, see `update_directory_preview`
+        let mut lines = if let Some(content_match) = content_match {
+            preview::match_context(selected_file, content_match.line_number)
+        } else if selected_file.is_directory {
+            match &self.directory_preview {
+                Some((path, lines)) if *path == selected_file.path => lines.clone(),
+                _ => vec![
+                    Line::from(format!("📁 Directory: {}", selected_file.name)),
+                    Line::from(""),
+                    Line::from("Loading..."),
+                ],
+            }
+        } else {
+            self.previews.preview(selected_file)
+        };
+        if !lines.is_empty() {
+            lines.insert(1, Line::from(permissions_line(selected_file)));
+        }
+        lines
+    }
+}
+
+pub async fn run_ui(
+    explorer: FileExplorer,
+    search_engine: SearchEngine,
+    config: Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Make sure a panic never leaves the shell in raw mode
+    let default_panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
+        default_panic_hook(info);
+    }));
+
+    // Setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // Create app
+    let mut app = App::new(explorer, search_engine, config);
+    if app.config.file_sharing.uploads_enabled {
+        match app.toggle_uploads().await {
+            Ok(msg) => app.set_info_message(msg),
+            Err(err) => app.set_error_message(err),
+        }
+    }
+    if app.config.search_index {
+        let root = app.explorer.current_path().to_path_buf();
+        if let Err(err) = app.start_indexing(root, false, false) {
+            app.set_error_message(err);
+        }
+    }
+
+    let res = run_app(&mut terminal, &mut app).await;
+
+    // Runtime teardown waits for blocking tasks, so stop a paste or hash in progress
+    app.cancel_transfer();
+    if let Some(running) = &app.running_checksum {
+        running.cancel.store(true, Ordering::Relaxed);
+    }
+    if let Some(running) = &app.running_compare {
+        running.cancel.store(true, Ordering::Relaxed);
+    }
+    app.cancel_search();
+    if let Some(running) = &app.running_duplicates {
+        running.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // Restore terminal
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    if let Err(err) = res {
+        println!("{:?}", err);
+    }
+    if let Err(err) = app.save_view_states() {
+        eprintln!("{}", err);
+    }
+    if let Err(err) = app.save_session() {
+        eprintln!("{}", err);
+    }
+    if let Err(err) = app.save_search_history() {
+        eprintln!("{}", err);
+    }
+    if let Err(err) = app.save_frecency() {
+        eprintln!("{}", err);
+    }
+
+    if app.force_quit {
+        // Don't let runtime teardown wait on in-flight searches or transfers
+        std::process::exit(0);
+    }
+
+    Ok(())
+}
+
+async fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+) -> io::Result<()> {
+    loop {
+        // Update message fade status
+        app.update_message_fade();
+        app.poll_directory_changes();
+        app.poll_transfer();
+        app.poll_checksum();
+        app.poll_extraction();
+        app.poll_compression();
+        app.poll_compare();
+        app.update_disk_usage();
+        app.update_git_status();
+        app.update_metadata();
+        app.update_directory_preview();
+        app.poll_properties();
+        app.poll_search_debounce();
+        app.poll_search();
+        app.poll_index();
+        app.poll_duplicates();
+        app.poll_uploads();
+        app.poll_share_access();
+        app.refresh_share_manager().await;
+        
+        terminal.draw(|f| ui(f, app))?;
+
+        // Wake up in time for a search waiting on a pause in typing
+        let timeout = app.search_debounce.remaining(Instant::now())
+            .map_or(Duration::from_millis(100), |remaining| remaining.min(Duration::from_millis(100)));
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    if app.is_force_quit(&key) {
+                        app.force_quit = true;
+                        return Ok(());
+                    }
+
+                    // The QR code closes on any key
+                    if app.qr_popup.is_some() {
+                        app.close_qr_popup();
+                        continue;
+                    }
+
+                    let kb = &app.config.key_bindings;
+                    let cancellable = app.running_transfer.is_some()
+                        || app.running_extraction.is_some()
+                        || app.running_compression.is_some()
+                        || app.running_compare.is_some()
+                        || app.running_index.is_some()
+                        || app.running_duplicates.is_some();
+                    if cancellable && kb.matches_chord(&kb.actions.cancel_transfer, &key) {
+                        app.cancel_transfer();
+                        continue;
+                    }
+
+                    // The "press e to edit" offer only lasts for a single key press
+                    let config_edit_offered = std::mem::take(&mut app.config_edit_offered);
+
+                    // A confirmation popup captures all input until answered
+                    if app.confirm_dialog.is_some() {
+                        match key.code {
+                            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+                                let starts_search = app.confirm_dialog.as_ref()
+                                    .is_some_and(|dialog| matches!(dialog.action, PendingAction::Search));
+                                let revokes_shares = app.confirm_dialog.as_ref()
+                                    .is_some_and(|dialog| matches!(dialog.action, PendingAction::RevokeShares));
+                                match app.confirm_pending_action() {
+                                    Ok(msg) => app.set_info_message(msg),
+                                    Err(err) => app.set_error_message(err),
+                                }
+                                if starts_search {
+                                    app.perform_search();
+                                }
+                                if revokes_shares {
+                                    let msg = app.revoke_all_shares().await;
+                                    app.set_info_message(msg);
+                                }
+                            }
+                            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                                app.cancel_pending_action();
+                            }
+                            _ => {}
+                        }
+                    } else if app.paste_conflict.is_some() {
+                        let choice = match key.code {
+                            KeyCode::Char('o') | KeyCode::Char('O') => Some(Some(ConflictPolicy::Overwrite)),
+                            KeyCode::Char('k') | KeyCode::Char('K') => Some(Some(ConflictPolicy::KeepBoth)),
+                            KeyCode::Char('s') | KeyCode::Char('S') => Some(Some(ConflictPolicy::Skip)),
+                            KeyCode::Char('c') | KeyCode::Char('C') | KeyCode::Esc => Some(None),
+                            KeyCode::Char('a') | KeyCode::Char('A') => {
+                                app.toggle_paste_conflict_apply_to_all();
+                                None
+                            }
+                            _ => None,
+                        };
+                        if let Some(choice) = choice {
+                            match app.resolve_paste_conflict(choice) {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        }
+                    } else if app.share_manager.is_some() {
+                        let kb = &app.config.key_bindings;
+                        if kb.matches_key(&kb.navigation.up, &key.code) {
+                            app.move_share_selection(-1);
+                        } else if kb.matches_key(&kb.navigation.down, &key.code) {
+                            app.move_share_selection(1);
+                        } else if key.code == KeyCode::Esc || kb.matches_key(&kb.actions.manage_shares, &key.code) {
+                            app.close_share_manager();
+                        } else if key.code == KeyCode::Char('X') {
+                            if let Err(err) = app.request_revoke_all_shares() {
+                                app.set_error_message(err);
+                            }
+                        } else if key.code == KeyCode::Char('q') {
+                            if let Err(err) = app.show_picked_share_qr() {
+                                app.set_error_message(err);
+                            }
+                        } else {
+                            let outcome = match key.code {
+                                KeyCode::Enter | KeyCode::Char('c') => Some(app.copy_picked_share_url()),
+                                KeyCode::Char('o') => Some(app.open_picked_share()),
+                                KeyCode::Char('d') | KeyCode::Delete => Some(app.revoke_picked_share().await),
+                                _ => None,
+                            };
+                            match outcome {
+                                Some(Ok(msg)) => app.set_info_message(msg),
+                                Some(Err(err)) => app.set_error_message(err),
+                                None => {}
+                            }
+                        }
+                    } else if app.bookmark_picker.is_some() {
+                        let kb = &app.config.key_bindings;
+                        if kb.matches_key(&kb.navigation.up, &key.code) {
+                            app.move_bookmark_selection(-1);
+                        } else if kb.matches_key(&kb.navigation.down, &key.code) {
+                            app.move_bookmark_selection(1);
+                        } else if key.code == KeyCode::Esc || kb.matches_key(&kb.actions.show_bookmarks, &key.code) {
+                            app.close_bookmarks();
+                        } else if matches!(key.code, KeyCode::Char('d') | KeyCode::Delete) {
+                            match app.remove_picked_bookmark() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else {
+                            let outcome = match key.code {
+                                KeyCode::Enter => Some(app.jump_to_picked_bookmark()),
+                                KeyCode::Char(digit @ '1'..='9') => {
+                                    let result = app.jump_to_bookmark(digit as usize - '0' as usize);
+                                    if result.is_ok() {
+                                        app.close_bookmarks();
+                                    }
+                                    Some(result)
+                                }
+                                _ => None,
+                            };
+                            match outcome {
+                                Some(Ok(msg)) => app.set_info_message(msg),
+                                Some(Err(err)) => app.set_error_message(err),
+                                None => {}
+                            }
+                        }
+                    } else if app.properties.is_some() {
+                        let kb = &app.config.key_bindings;
+                        if key.code == KeyCode::Esc || kb.matches_key(&kb.actions.properties, &key.code) {
+                            app.close_properties();
+                        } else if kb.matches_key(&kb.actions.copy, &key.code) {
+                            match app.copy_properties() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        }
+                    } else if app.diff_popup.is_some() {
+                        let kb = &app.config.key_bindings;
+                        if key.code == KeyCode::Esc || kb.matches_key(&kb.actions.diff, &key.code) {
+                            app.close_diff();
+                        } else if kb.matches_key(&kb.actions.copy, &key.code) {
+                            match app.copy_diff() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if kb.matches_key(&kb.navigation.up, &key.code) {
+                            app.scroll_diff(-1);
+                        } else if kb.matches_key(&kb.navigation.down, &key.code) {
+                            app.scroll_diff(1);
+                        } else {
+                            match key.code {
+                                KeyCode::PageUp => app.scroll_diff(-(DIFF_PAGE as isize)),
+                                KeyCode::PageDown => app.scroll_diff(DIFF_PAGE as isize),
+                                KeyCode::Home => app.scroll_diff(isize::MIN),
+                                KeyCode::End => app.scroll_diff(isize::MAX),
+                                _ => {}
+                            }
+                        }
+                    } else if app.drives_view.is_some() {
+                        let kb = &app.config.key_bindings;
+                        if kb.matches_key(&kb.navigation.up, &key.code) {
+                            app.move_drive_selection(-1);
+                        } else if kb.matches_key(&kb.navigation.down, &key.code) {
+                            app.move_drive_selection(1);
+                        } else if key.code == KeyCode::Esc || kb.matches_key(&kb.actions.drives, &key.code) {
+                            let _ = app.toggle_drives();
+                        } else if key.code == KeyCode::Enter || kb.matches_key(&kb.navigation.enter, &key.code) {
+                            match app.enter_picked_drive() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        }
+                    } else if app.open_with_menu.is_some() {
+                        let kb = &app.config.key_bindings;
+                        if kb.matches_key(&kb.navigation.up, &key.code) {
+                            app.move_open_with_selection(-1);
+                        } else if kb.matches_key(&kb.navigation.down, &key.code) {
+                            app.move_open_with_selection(1);
+                        } else if key.code == KeyCode::Esc || kb.matches_chord(&kb.actions.open_with, &key) {
+                            app.close_open_with();
+                        } else if key.code == KeyCode::Enter {
+                            match app.open_with_picked() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        }
+                    } else if let Some(prompt) = app.input_prompt.as_mut() {
+                        match key.code {
+                            KeyCode::Enter => {
+                                match app.submit_input_prompt() {
+                                    Ok(msg) => app.set_info_message(msg),
+                                    Err(err) => app.set_error_message(err),
+                                }
+                            }
+                            KeyCode::Esc => app.cancel_input_prompt(),
+                            KeyCode::Tab => app.complete_input_prompt(),
+                            KeyCode::Backspace => {
+                                prompt.value.pop();
+                                prompt.completions.clear();
+                            }
+                            KeyCode::Char(c) => {
+                                prompt.value.push(c);
+                                prompt.completions.clear();
+                            }
+                            _ => {}
+                        }
+                    } else if config_edit_offered && matches!(key.code, KeyCode::Char('e') | KeyCode::Char('E')) {
+                        match app.open_config_file() {
+                            Ok(msg) => app.set_info_message(msg),
+                            Err(err) => app.set_error_message(err),
+                        }
+                    } else if app.filter_mode {
+                        match key.code {
+                            KeyCode::Esc => app.clear_filter(),
+                            KeyCode::Enter => app.finish_filter(),
+                            KeyCode::Backspace => app.pop_filter_char(),
+                            KeyCode::Up => app.previous_item(),
+                            KeyCode::Down => app.next_item(),
+                            KeyCode::Char(c) => app.push_filter_char(c),
+                            _ => {}
+                        }
+                    } else if app.search_mode {
+                        let key_bindings = &app.config.key_bindings;
+                        if key_bindings.matches_key(&key_bindings.search_mode.exit_search, &key.code) {
+                            if app.pop_refinement() {
+                                app.search_mode = false;
+                                app.showing_search_results = true;
+                            } else {
+                                app.exit_search_mode();
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_mode.exit_to_results, &key.code) {
+                            app.submit_search();
+                        } else if key_bindings.matches_key(&key_bindings.search_mode.toggle_strategy, &key.code) {
+                            app.toggle_search_strategy();
+                            // Re-run search if we have input
+                            if !app.search_input.is_empty() {
+                                app.perform_search();
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.search_mode.toggle_case, &key) {
+                            app.cycle_case_mode();
+                            if !app.search_input.is_empty() {
+                                app.perform_search();
+                            }
+                        } else if let Some(toggle) = walk_toggle(key_bindings, &key) {
+                            app.toggle_walk_option(toggle);
+                            if !app.search_input.is_empty() {
+                                app.perform_search();
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_mode.toggle_pattern_mode, &key.code) {
+                            app.cycle_pattern_mode();
+                            if !app.search_input.is_empty() {
+                                app.perform_search();
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_results.cycle_type_filter, &key.code) {
+                            let msg = app.cycle_result_type_filter();
+                            app.set_info_message(msg);
+                        } else if let Some(strategy) = strategy_shortcut(key_bindings, &key.code) {
+                            app.set_search_strategy(strategy);
+                            if !app.search_input.is_empty() {
+                                app.perform_search();
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_mode.backspace, &key.code) {
+                            if app.search_input.is_empty() {
+                                // Backspace on an empty refinement goes back a level
+                                app.pop_refinement();
+                            } else {
+                                app.search_input.pop();
+                                if !app.search_input.is_empty() || !app.refinements.is_empty() {
+                                    app.schedule_search();
+                                } else {
+                                    app.cancel_search();
+                                    app.search_results.clear();
+                                    app.all_search_results.clear();
+                                }
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.navigation.up, &key.code) {
+                            if !app.recall_previous_search() {
+                                app.previous_item();
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.navigation.down, &key.code) {
+                            if !app.recall_next_search() {
+                                app.next_item();
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_mode.navigate_tab, &key.code) {
+                            app.navigate_to_selected().ok();
+                        } else {
+                            match key.code {
+                                KeyCode::Char(c) => {
+                                    app.search_input.push(c);
+                                    app.schedule_search();
+                                }
+                                _ => {}
+                            }
+                        }
+                    } else if app.tree_focused() {
+                        let kb = &app.config.key_bindings;
+                        let result = if kb.matches_key(&kb.navigation.up, &key.code) {
+                            app.move_tree_selection(-1)
+                        } else if kb.matches_key(&kb.navigation.down, &key.code) {
+                            app.move_tree_selection(1)
+                        } else if key.code == KeyCode::Enter || kb.matches_key(&kb.navigation.enter, &key.code) {
+                            app.expand_tree_node()
+                        } else if kb.matches_key(&kb.navigation.left, &key.code) {
+                            app.collapse_tree_node()
+                        } else if key.code == KeyCode::Esc || kb.matches_key(&kb.actions.focus_tree, &key.code) {
+                            app.toggle_tree_focus();
+                            Ok(())
+                        } else if kb.matches_key(&kb.actions.toggle_tree, &key.code) {
+                            app.toggle_tree().map(|msg| app.set_info_message(msg))
+                        } else if kb.matches_key(&kb.actions.quit, &key.code) {
+                            app.request_quit().await;
+                            Ok(())
+                        } else {
+                            Ok(())
+                        };
+                        if let Err(err) = result {
+                            app.set_error_message(err);
+                        }
+                    } else if app.showing_search_results {
+                        // Handle search results viewing mode keys
+                        let key_bindings = &app.config.key_bindings;
+                        if key_bindings.matches_chord(&key_bindings.actions.toggle_read_only, &key) {
+                            let msg = app.toggle_read_only();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_chord(&key_bindings.actions.open_with, &key) {
+                            if let Err(err) = app.request_open_with() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.share_protected, &key) {
+                            match app.share_selected_file(true).await {
+                                Ok(msg) if msg.contains("Warning:") => app.set_warning_message(msg),
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.toggle_uploads, &key) {
+                            match app.toggle_uploads().await {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.rebuild_index, &key) {
+                            match app.rebuild_index() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.toggle_search_root, &key) {
+                            match app.toggle_search_root() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.properties, &key.code) {
+                            if let Err(err) = app.show_properties() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.quit, &key.code) {
+                            app.request_quit().await;
+                        } else if key_bindings.matches_key(&key_bindings.actions.search, &key.code) {
+                            app.enter_search_mode();
+                        } else if key_bindings.matches_key(&key_bindings.actions.open, &key.code) {
+                            let result = if app.has_custom_open_command() {
+                                // Configured commands are often terminal programs, so hand them the screen
+                                suspend_tui(terminal, || app.open_selected_file())?
+                            } else {
+                                app.open_selected_file()
+                            };
+                            match result {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.edit, &key.code) {
+                            match suspend_tui(terminal, || app.edit_selected_file())? {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.reveal, &key.code) {
+                            match app.reveal_selected_in_file_manager() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.share, &key.code) {
+                            match app.share_selected_file(false).await {
+                                Ok(msg) => {
+                                    if msg.contains("Warning:") {
+                                        app.set_warning_message(msg);
+                                    } else {
+                                        app.set_info_message(msg);
+                                    }
+                                },
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.open_share_list, &key.code) {
+                            match app.open_share_list().await {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.manage_shares, &key.code) {
+                            if let Err(err) = app.open_share_manager().await {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.share_qr, &key.code) {
+                            if let Err(err) = app.show_share_qr().await {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.goto, &key.code) {
+                            app.request_goto();
+                        } else if key_bindings.matches_key(&key_bindings.actions.jump, &key.code) {
+                            if let Err(err) = app.request_jump() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.recent_files, &key.code) {
+                            app.start_recent_search();
+                        } else if key_bindings.matches_key(&key_bindings.actions.find_duplicates, &key.code) {
+                            match app.start_find_duplicates() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.toggle_mark, &key.code) {
+                            if let Err(err) = app.toggle_mark_selected() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.mark_all, &key.code) {
+                            let msg = app.mark_all();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.actions.duplicate, &key.code) {
+                            match app.duplicate_selected() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.checksum, &key.code) {
+                            match app.checksum_selected() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.extract, &key.code) {
+                            if let Err(err) = app.request_extract_selected() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.compress, &key.code) {
+                            match app.compress_selected() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.toggle_executable, &key) {
+                            match app.toggle_selected_executable() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.cut, &key.code) {
+                            match app.cut_selected_file() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.copy, &key.code) {
+                            match app.copy_selected_file() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.paste, &key.code) {
+                            match app.paste_file() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.delete_permanently, &key) {
+                            if let Err(err) = app.request_delete_selected(true) {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.delete, &key.code) {
+                            if let Err(err) = app.request_delete_selected(false) {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.rename, &key.code) {
+                            if let Err(err) = app.request_rename_selected() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.show_config, &key.code) {
+                            app.show_config_path();
+                        } else if key_bindings.matches_key(&key_bindings.actions.empty_trash, &key.code) {
+                            if let Err(err) = app.request_empty_trash() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.copy_path, &key.code) {
+                            match app.copy_selected_file_path() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.copy_markdown_link, &key.code) {
+                            match app.copy_selected_markdown_link() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_results.cycle_type_filter, &key.code) {
+                            let msg = app.cycle_result_type_filter();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.search_results.directories_first, &key.code) {
+                            let msg = app.toggle_directories_first();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.search_results.toggle_preview, &key.code) {
+                            let msg = app.toggle_search_preview();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.search_results.refine, &key.code) {
+                            app.start_refining();
+                        } else if key_bindings.matches_key(&key_bindings.search_results.export, &key.code) {
+                            if let Err(err) = app.request_export_results() {
+                                app.set_error_message(err);
+                            }
+                        } else if app.duplicates.is_some() && key_bindings.matches_key(&key_bindings.search_results.mark_copies, &key.code) {
+                            match app.mark_duplicate_copies() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_results.select_all, &key.code) {
+                            if let Err(err) = app.request_select_all_results() {
+                                app.set_error_message(err);
+                            }
+                        } else if !app.marked.is_empty() && key_bindings.matches_key(&key_bindings.actions.clear_marks, &key.code) {
+                            let msg = app.clear_marks();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.search_results.back, &key.code) {
+                            if !app.pop_refinement() {
+                                app.clear_search_results();
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_mode.toggle_strategy, &key.code) {
+                            app.toggle_search_strategy();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.enter, &key.code) {
+                            let _ = app.navigate_to_selected();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.up, &key.code) {
+                            app.previous_item();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.down, &key.code) {
+                            app.next_item();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.left, &key.code) {
+                            app.clear_search_results();
+                        }
+                    } else {
+                        // Handle normal navigation mode keys
+                        let key_bindings = &app.config.key_bindings;
+                        if key_bindings.matches_chord(&key_bindings.actions.toggle_read_only, &key) {
+                            let msg = app.toggle_read_only();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_chord(&key_bindings.actions.open_with, &key) {
+                            if let Err(err) = app.request_open_with() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.open_terminal, &key) {
+                            match app.open_terminal_here() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.share_protected, &key) {
+                            match app.share_selected_file(true).await {
+                                Ok(msg) if msg.contains("Warning:") => app.set_warning_message(msg),
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.toggle_uploads, &key) {
+                            match app.toggle_uploads().await {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.rebuild_index, &key) {
+                            match app.rebuild_index() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.toggle_search_root, &key) {
+                            match app.toggle_search_root() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.history_back, &key) {
+                            match app.go_back() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.history_forward, &key) {
+                            match app.go_forward() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.quit, &key.code) {
+                            app.request_quit().await;
+                        } else if key_bindings.matches_key(&key_bindings.actions.search, &key.code) {
+                            app.enter_search_mode();
+                        } else if key_bindings.matches_key(&key_bindings.actions.open, &key.code) {
+                            let result = if app.has_custom_open_command() {
+                                // Configured commands are often terminal programs, so hand them the screen
+                                suspend_tui(terminal, || app.open_selected_file())?
+                            } else {
+                                app.open_selected_file()
+                            };
+                            match result {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.edit, &key.code) {
+                            match suspend_tui(terminal, || app.edit_selected_file())? {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.reveal, &key.code) {
+                            match app.reveal_selected_in_file_manager() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.share, &key.code) {
+                            match app.share_selected_file(false).await {
+                                Ok(msg) => {
+                                    if msg.contains("Warning:") {
+                                        app.set_warning_message(msg);
+                                    } else {
+                                        app.set_info_message(msg);
+                                    }
+                                },
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.open_share_list, &key.code) {
+                            match app.open_share_list().await {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.manage_shares, &key.code) {
+                            if let Err(err) = app.open_share_manager().await {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.share_qr, &key.code) {
+                            if let Err(err) = app.show_share_qr().await {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.goto, &key.code) {
+                            app.request_goto();
+                        } else if key_bindings.matches_key(&key_bindings.actions.jump, &key.code) {
+                            if let Err(err) = app.request_jump() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.recent_files, &key.code) {
+                            app.start_recent_search();
+                        } else if key_bindings.matches_key(&key_bindings.actions.find_duplicates, &key.code) {
+                            match app.start_find_duplicates() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.toggle_mark, &key.code) {
+                            if let Err(err) = app.toggle_mark_selected() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.mark_all, &key.code) {
+                            let msg = app.mark_all();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.actions.duplicate, &key.code) {
+                            match app.duplicate_selected() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.checksum, &key.code) {
+                            match app.checksum_selected() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.extract, &key.code) {
+                            if let Err(err) = app.request_extract_selected() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.compress, &key.code) {
+                            match app.compress_selected() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.toggle_executable, &key) {
+                            match app.toggle_selected_executable() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.cut, &key.code) {
+                            match app.cut_selected_file() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.copy, &key.code) {
+                            match app.copy_selected_file() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.paste, &key.code) {
+                            match app.paste_file() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_chord(&key_bindings.actions.delete_permanently, &key) {
+                            if let Err(err) = app.request_delete_selected(true) {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.delete, &key.code) {
+                            if let Err(err) = app.request_delete_selected(false) {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.rename, &key.code) {
+                            if let Err(err) = app.request_rename_selected() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.show_config, &key.code) {
+                            app.show_config_path();
+                        } else if key_bindings.matches_key(&key_bindings.actions.empty_trash, &key.code) {
+                            if let Err(err) = app.request_empty_trash() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.new_file, &key.code) {
+                            if let Err(err) = app.request_new_entry(false) {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.new_dir, &key.code) {
+                            if let Err(err) = app.request_new_entry(true) {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.toggle_hidden, &key.code) {
+                            match app.toggle_hidden() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.toggle_ignored, &key.code) {
+                            let msg = app.toggle_ignored();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.actions.cycle_sort, &key.code) {
+                            let msg = app.cycle_sort();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.actions.reverse_sort, &key.code) {
+                            let msg = app.reverse_sort();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.actions.copy_path, &key.code) {
+                            match app.copy_selected_file_path() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.copy_markdown_link, &key.code) {
+                            match app.copy_selected_markdown_link() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.search_mode.toggle_strategy, &key.code) {
+                            app.toggle_search_strategy();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.enter, &key.code) {
+                            let _ = app.navigate_to_selected();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.up, &key.code) {
+                            app.previous_item();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.down, &key.code) {
+                            app.next_item();
+                        } else if key_bindings.matches_key(&key_bindings.navigation.left, &key.code) {
+                            let _ = app.go_up();
+                        } else if key_bindings.matches_key(&key_bindings.actions.filter, &key.code) {
+                            if let Err(err) = app.enter_filter_mode() {
+                                app.set_error_message(err);
+                            }
+                        } else if app.marked.is_empty()
+                            && !app.explorer.filter().is_empty()
+                            && key_bindings.matches_key(&key_bindings.actions.clear_marks, &key.code)
+                        {
+                            app.clear_filter();
+                        } else if key_bindings.matches_key(&key_bindings.actions.clear_marks, &key.code) {
+                            let msg = app.clear_marks();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.actions.toggle_bookmarks_bar, &key.code) {
+                            let msg = app.toggle_bookmarks_bar();
+                            app.set_info_message(msg);
+                        } else if key_bindings.matches_key(&key_bindings.actions.add_bookmark, &key.code) {
+                            match app.add_bookmark() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.show_bookmarks, &key.code) {
+                            if let Err(err) = app.open_bookmarks() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.properties, &key.code) {
+                            if let Err(err) = app.show_properties() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.drives, &key.code) {
+                            if let Err(err) = app.toggle_drives() {
+                                app.set_error_message(err);
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.diff, &key.code) {
+                            match app.compare_marked() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.toggle_tree, &key.code) {
+                            match app.toggle_tree() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if key_bindings.matches_key(&key_bindings.actions.focus_tree, &key.code) {
+                            app.toggle_tree_focus();
+                        } else if key_bindings.matches_key(&key_bindings.actions.go_home, &key.code) {
+                            match app.go_home() {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        } else if let KeyCode::Char(digit @ '1'..='9') = key.code {
+                            let number = digit as usize - '0' as usize;
+                            match app.jump_to_bookmark(number) {
+                                Ok(msg) => app.set_info_message(msg),
+                                Err(err) => app.set_error_message(err),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if app.should_quit {
+            // Properly shutdown the file sharing server
+            let _ = app.file_share_server.shutdown().await;
+            return Ok(());
+        }
+    }
+}
+
+fn ui(f: &mut Frame, app: &App) {
+    let selected_path_height = if app.config.show_selected_path { 1 } else { 0 };
+    let bookmarks_bar_height = if app.show_bookmarks_bar && !app.config.bookmarks.is_empty() { 1 } else { 0 };
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(selected_path_height),
+            Constraint::Length(bookmarks_bar_height),
+            Constraint::Min(0),
+            Constraint::Length(3),
+        ])
+        .split(f.size());
+    let chunks = [rows[0], rows[3], rows[4]];
+
+    // Header
+    let mut header_spans = vec![Span::raw(format!("FilePilot - {}", app.explorer.current_path().display()))];
+    if app.read_only {
+        header_spans.push(Span::styled("  [READ-ONLY]", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+    }
+    if let Some(usage) = app.disk_usage {
+        header_spans.push(Span::styled(
+            format!("  ({} free of {})", format_size(usage.available), format_size(usage.total)),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    let header = Paragraph::new(Line::from(header_spans))
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default().borders(Borders::NONE));
+    f.render_widget(header, chunks[0]);
+
+    if selected_path_height > 0 {
+        render_selected_path(f, app, rows[1]);
+    }
+
+    if bookmarks_bar_height > 0 {
+        render_bookmarks_bar(f, app, rows[2]);
+    }
+
+    // Main content, with the tree pane to its left when shown
+    let main_area = if app.config.show_tree_pane {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(25), Constraint::Percentage(75)])
+            .split(chunks[1]);
+        render_tree(f, app, columns[0]);
+        columns[1]
+    } else {
+        chunks[1]
+    };
+    if app.drives_view.is_some() {
+        render_drives(f, app, main_area);
+    } else if app.results_shown() {
+        render_search_results(f, app, main_area);
+    } else {
+        render_file_list(f, app, main_area);
+    }
+
+    // Footer
+    render_footer(f, app, chunks[2]);
+
+    // Search input overlay
+    if app.search_mode {
+        render_search_input(f, app);
+    }
+
+    if app.input_prompt.is_some() {
+        render_input_prompt(f, app);
+    }
+
+    if app.bookmark_picker.is_some() {
+        render_bookmark_picker(f, app);
+    }
+
+    if app.share_manager.is_some() {
+        render_share_manager(f, app);
+    }
+
+    if app.qr_popup.is_some() {
+        render_qr_popup(f, app);
+    }
+
+    if app.open_with_menu.is_some() {
+        render_open_with_menu(f, app);
+    }
+
+    if app.properties.is_some() {
+        render_properties(f, app);
+    }
+
+    if app.diff_popup.is_some() {
+        render_diff(f, app);
+    }
+
+    // Confirmation popup goes on top of everything else
+    if app.paste_conflict.is_some() {
+        render_paste_conflict(f, app);
+    }
+
+    if app.confirm_dialog.is_some() {
+        render_confirm_dialog(f, app);
+    }
+}
+
+fn render_selected_path(f: &mut Frame, app: &App, area: Rect) {
+    let Ok(file) = app.get_selected_file() else {
+        return;
+    };
+
+    let label = "Selected: ";
+    let available = (area.width as usize).saturating_sub(label.chars().count());
+    let path = truncate_middle(&file.path.to_string_lossy(), available);
+    let line = Line::from(vec![
+        Span::styled(label, Style::default().fg(Color::DarkGray)),
+        Span::styled(path, Style::default().fg(Color::White)),
+    ]);
+    f.render_widget(Paragraph::new(line), area);
+}
+
+/// Shortens `text` to `max_chars` by replacing its middle with an ellipsis,
+/// keeping both the start and the (usually more telling) end visible.
+fn truncate_middle(text: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= max_chars {
+        return text.to_string();
+    }
+    if max_chars == 0 {
+        return String::new();
+    }
+
+    let keep = max_chars - 1;
+    let head = keep / 2;
+    let tail = keep - head;
+    let mut truncated: String = chars[..head].iter().collect();
+    truncated.push('…');
+    truncated.extend(&chars[chars.len() - tail..]);
+    truncated
+}
+
+fn render_bookmarks_bar(f: &mut Frame, app: &App, area: Rect) {
+    let mut spans = Vec::new();
+    for (index, bookmark) in app.config.bookmarks.iter().take(9).enumerate() {
+        let name = bookmark.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| bookmark.display().to_string());
+        let name_style = if bookmark.as_path() == app.explorer.current_path() {
+            Style::default().fg(Color::Black).bg(Color::Cyan)
+        } else if !bookmark.is_dir() {
+            Style::default().fg(Color::DarkGray)
+        } else {
+            Style::default().fg(Color::Cyan)
+        };
+
+        spans.push(Span::styled(format!(" {} ", index + 1), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+        spans.push(Span::styled(name, name_style));
+        spans.push(Span::raw("  "));
+    }
+
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+fn render_tree(f: &mut Frame, app: &App, area: Rect) {
+    let pane = &app.tree_pane;
+    let items: Vec<ListItem> = pane.tree.rows()
+        .iter()
+        .map(|row| {
+            let indent = "  ".repeat(row.depth());
+            match row {
+                TreeRow::Directory { path, name, expanded, .. } => {
+                    let marker = if *expanded { "▾" } else { "▸" };
+                    let mut style = Style::default().fg(Color::Blue);
+                    if path == app.explorer.current_path() {
+                        style = style.add_modifier(Modifier::BOLD);
+                    }
+                    ListItem::new(Line::from(Span::styled(format!("{}{} {}", indent, marker, name), style)))
+                }
+                TreeRow::More { count, .. } => ListItem::new(Line::from(Span::styled(
+                    format!("{}  … {} more", indent, count),
+                    Style::default().fg(Color::DarkGray),
+                ))),
+            }
+        })
+        .collect();
+
+    let kb = &app.config.key_bindings;
+    let (title, border_style) = if pane.focused {
+        (format!("Tree - {}: Files", kb.get_key_display(&kb.actions.focus_tree)), Style::default().fg(Color::Yellow))
+    } else {
+        (format!("Tree - {}: Focus", kb.get_key_display(&kb.actions.focus_tree)), Style::default())
+    };
+    let highlight_style = if pane.focused {
+        Style::default().bg(Color::DarkGray)
+    } else {
+        Style::default()
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).border_style(border_style).title(title))
+        .highlight_style(highlight_style)
+        .highlight_symbol(if pane.focused { "► " } else { "  " });
+
+    let mut state = pane.state.clone();
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_drives(f: &mut Frame, app: &App, area: Rect) {
+    let Some(view) = &app.drives_view else {
+        return;
+    };
+
+    let width = view.volumes.iter()
+        .map(|volume| volume.mount_point.to_string_lossy().chars().count())
+        .max()
+        .unwrap_or(0);
+    let items: Vec<ListItem> = view.volumes
+        .iter()
+        .map(|volume| {
+            let space = match volume.usage {
+                Some(usage) => format!("{} free of {}", format_size(usage.available), format_size(usage.total)),
+                None => "size unknown".to_string(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("💽 {:<width$}", volume.mount_point.display(), width = width),
+                    Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(format!("  {:<8} ", volume.fs_type)),
+                Span::raw(format!("{:<22}", space)),
+                Span::styled(format!(" {}", volume.device), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+
+    let kb = &app.config.key_bindings;
+    let title = format!("Drives - Enter: Open | Esc/{}: Back to files", kb.get_key_display(&kb.actions.drives));
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().bg(Color::DarkGray))
+        .highlight_symbol("► ");
+
+    let mut state = view.state.clone();
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_file_list(f: &mut Frame, app: &App, area: Rect) {
+    // Split the area into two columns: file list (60%) and preview (40%)
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(60),
+            Constraint::Percentage(40),
+        ])
+        .split(area);
+
+    // Render file list in the left column
+    let two_line_rows = app.config.two_line_rows;
+    let items: Vec<ListItem> = app
+        .explorer
+        .files()
+        .iter()
+        .map(|file| {
+            let icon = if file.is_symlink { "🔗" } else if file.is_directory { "📁" } else { "📄" };
+            let style = if file.is_broken_link {
+                Style::default().fg(Color::Red)
+            } else if file.is_directory {
+                Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            
+            // Show file info as light gray text
+            let mut info_parts = Vec::new();
+            if !file.is_directory && !file.metadata_pending {
+                info_parts.push(format_size(file.size));
+            }
+            if let Some(modified) = file.modified {
+                if let Ok(duration) = modified.duration_since(std::time::UNIX_EPOCH) {
+                    let days_ago = duration.as_secs() / (24 * 60 * 60);
+                    if days_ago == 0 {
+                        info_parts.push("today".to_string());
+                    } else if days_ago < 7 {
+                        info_parts.push(format!("{}d ago", days_ago));
+                    } else {
+                        info_parts.push(format!("{}w ago", days_ago / 7));
+                    }
+                }
+            }
+            let info_style = Style::default().fg(Color::DarkGray);
+            let second_line = two_line_rows.then(|| {
+                info_parts.push(file.path.display().to_string());
+                Line::from(Span::styled(format!("     {}", info_parts.join(" · ")), info_style))
+            });
+            let info_str = if info_parts.is_empty() || two_line_rows {
+                String::new()
+            } else {
+                format!(" ({})", info_parts.join(", "))
+            };
+            
+            let marked = app.marked.contains(&file.path);
+            let style = if marked { style.fg(Color::Yellow).add_modifier(Modifier::BOLD) } else { style };
+            let (style, clipboard_tag) = clipboard_style(app, &file.path, style);
+            let mut spans = vec![Span::styled(if marked { "*" } else { " " }, Style::default().fg(Color::Yellow))];
+            if let Some(repo) = &app.git_status {
+                spans.push(git_status_glyph(repo.status_of(&file.path)));
+            }
+            spans.extend([
+                Span::raw(icon),
+                Span::raw(" "),
+                Span::styled(&file.name, style),
+            ]);
+            spans.extend(clipboard_tag);
+            if let Some(target) = &file.link_target {
+                let target_style = if file.is_broken_link {
+                    Style::default().fg(Color::Red)
+                } else {
+                    Style::default().fg(Color::Cyan)
+                };
+                spans.push(Span::styled(format!(" -> {}", target.display()), target_style));
+            }
+            spans.push(Span::styled(info_str, info_style));
+
+            let mut lines = vec![Line::from(spans)];
+            lines.extend(second_line);
+            ListItem::new(lines)
+        })
+        .collect();
+
+    let filter = app.explorer.filter();
+    let ignored = if app.explorer.hide_ignored() && app.explorer.ignored_count() > 0 {
+        format!(", {} ignored", app.explorer.ignored_count())
+    } else {
+        String::new()
+    };
+    let title = if app.filter_mode || !filter.is_empty() {
+        let cursor = if app.filter_mode { "▏" } else { "" };
+        format!(
+            "Files [{} | filter: {}{} | {}/{}{}]",
+            app.explorer.sort_description(),
+            filter,
+            cursor,
+            app.explorer.files().len(),
+            app.explorer.unfiltered_len(),
+            ignored
+        )
+    } else if !ignored.is_empty() {
+        format!("Files [{} | {}{}]", app.explorer.sort_description(), app.explorer.files().len(), ignored)
+    } else {
+        format!("Files [{}]", app.explorer.sort_description())
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().bg(Color::DarkGray))
+        .highlight_symbol("► ");
+
+    let mut state = app.list_state.clone().with_offset(app.list_offset.get());
+    f.render_stateful_widget(list, chunks[0], &mut state);
+    app.list_offset.set(state.offset());
+
+    // Render preview in the right column
+    render_preview(f, app, chunks[1]);
+}
+
+fn render_preview(f: &mut Frame, app: &App, area: Rect) {
+    let preview_lines = app.get_file_preview();
+    let preview_items: Vec<ListItem> = preview_lines
+        .into_iter()
+        .map(ListItem::new)
+        .collect();
+
+    let preview_block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Preview ")
+        .border_style(Style::default().fg(Color::Green));
+
+    let preview_list = List::new(preview_items).block(preview_block);
+    f.render_widget(preview_list, area);
+}
+
+fn render_search_results(f: &mut Frame, app: &App, area: Rect) {
+    // The highlighted result's preview on the right, unless collapsed
+    let preview_width = app.config.search_preview_width.min(90);
+    let area = if app.search_preview_hidden || preview_width == 0 {
+        area
+    } else {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(100 - preview_width),
+                Constraint::Percentage(preview_width),
+            ])
+            .split(area);
+        render_preview(f, app, chunks[1]);
+        chunks[0]
+    };
+
+    // The other paths of the highlighted result, under the list
+    let also_found_at = app.search_list_state.selected()
+        .and_then(|index| app.search_results.get(index))
+        .map(|result| result.also_found_at.as_slice())
+        .unwrap_or_default();
+    let area = if also_found_at.is_empty() {
+        area
+    } else {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(also_found_at.len().min(4) as u16 + 2)])
+            .split(area);
+        let paths: Vec<ListItem> = also_found_at.iter()
+            .map(|path| ListItem::new(path.to_string_lossy().into_owned()))
+            .collect();
+        let block = Block::default().borders(Borders::ALL).title("Also found at");
+        f.render_widget(List::new(paths).block(block), rows[1]);
+        rows[0]
+    };
+    let group_counts = duplicate_group_counts(app);
+    let group_of = |result: &SearchResult| {
+        app.duplicates.as_ref().and_then(|duplicates| duplicates.group_of.get(&result.file_info.path).copied())
+    };
+    let items: Vec<ListItem> = app
+        .search_results
+        .iter()
+        .enumerate()
+        .map(|(index, result)| {
+            let icon = if result.file_info.is_directory { "📁" } else { "📄" };
+            
+            // Show match type with different colors
+            let match_indicator = match result.match_type {
+                crate::search::MatchType::FileName => Span::styled("F", Style::default().fg(Color::Green)),
+                crate::search::MatchType::FilePath => Span::styled("P", Style::default().fg(Color::Yellow)),
+                crate::search::MatchType::Content => Span::styled("C", Style::default().fg(Color::Magenta)),
+                crate::search::MatchType::Duplicate => Span::styled("D", Style::default().fg(Color::Cyan)),
+                crate::search::MatchType::Modified => Span::styled("M", Style::default().fg(Color::Blue)),
+            };
+            
+            let marked = app.marked.contains(&result.file_info.path);
+            let path_style = if marked {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let (path_style, clipboard_tag) = clipboard_style(app, &result.file_info.path, path_style);
+
+            let mut spans = vec![
+                Span::styled(if marked { "*" } else { " " }, Style::default().fg(Color::Yellow)),
+                Span::raw(icon),
+                Span::raw(" "),
+                match_indicator,
+                Span::raw(" "),
+                Span::styled(result.file_info.path.to_string_lossy(), path_style),
+            ];
+            if !result.also_found_at.is_empty() {
+                let count = result.also_found_at.len();
+                let paths = if count == 1 { "path" } else { "paths" };
+                spans.push(Span::styled(format!(" +{} {}", count, paths), Style::default().fg(Color::Cyan)));
+            }
+            if let Some(content_match) = &result.content_match {
+                spans.push(Span::styled(format!(":{}: ", content_match.line_number), Style::default().fg(Color::DarkGray)));
+                spans.push(Span::raw(content_match.snippet.clone()));
+                spans.extend(clipboard_tag);
+            } else if let (search::MatchType::Modified, Some(modified)) = (result.match_type, result.file_info.modified) {
+                spans.extend(clipboard_tag);
+                let age = file_system::age_label(modified, SystemTime::now());
+                spans.push(Span::styled(format!(" ({})", age), Style::default().fg(Color::DarkGray)));
+            } else if result.match_type == search::MatchType::Duplicate {
+                spans.extend(clipboard_tag);
+                spans.push(Span::styled(format!(" ({})", format_size(result.file_info.size)), Style::default().fg(Color::DarkGray)));
+            } else {
+                spans.extend(clipboard_tag);
+                spans.push(Span::styled(format!(" ({})", result.score), Style::default().fg(Color::DarkGray)));
+            }
+
+            // Each group of duplicates starts with a header on its first file
+            let group = group_of(result);
+            let starts_group = group.is_some() && (index == 0 || group_of(&app.search_results[index - 1]) != group);
+            match (group, app.duplicates.as_ref()) {
+                (Some(group), Some(duplicates)) if starts_group => {
+                    let size = duplicates.sizes[group];
+                    let count = group_counts.get(&group).copied().unwrap_or(0);
+                    let header = format!("── {} {} of {} each, {} reclaimable", count, if count == 1 { "file" } else { "files" },
+                        format_size(size), format_size(size * (count as u64).saturating_sub(1)));
+                    let header = Line::from(Span::styled(header, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)));
+                    ListItem::new(vec![header, Line::from(spans)])
+                }
+                _ => ListItem::new(Line::from(spans)),
+            }
+        })
+        .collect();
+
+    let filters = search::SearchFilters::parse(&app.search_input).map(|(_, filters)| filters).unwrap_or_default();
+    let filter_terms = if filters.is_empty() { String::new() } else { format!(" {}", filters) };
+    let breadcrumb = if app.refinements.is_empty() { String::new() } else { format!(" {}", app.refinement_breadcrumb()) };
+    let grouping = if app.search_engine.settings.directories_first { ", dirs first" } else { "" };
+    let title = if let Some(duplicates) = &app.duplicates {
+        let wasted: u64 = group_counts.iter()
+            .map(|(&group, &count)| duplicates.sizes[group] * (count as u64).saturating_sub(1))
+            .sum();
+        format!("Duplicates in {} [{} groups, {} reclaimable]{}",
+            duplicates.root.display(), group_counts.len(), format_size(wasted), breadcrumb)
+    } else if let Some(window) = app.recent_window {
+        format!("Modified in the last {}h [{}]{}{} - newest first",
+            window.as_secs() / (60 * 60), app.result_type_filter.label(), breadcrumb, app.roots_note())
+    } else {
+        format!("Search Results [{}{}]{}{}{}{} - F:FileName P:Path C:Content",
+            app.result_type_filter.label(), grouping, breadcrumb, filter_terms, app.roots_note(), app.index_note())
+    };
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if app.search_timed_out {
+        block = block.title(Span::styled(" partial results - search timed out ", Style::default().fg(Color::Yellow)));
+    }
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(Color::DarkGray))
+        .highlight_symbol("► ");
+
+    f.render_stateful_widget(list, area, &mut app.search_list_state.clone());
+}
+
+/// Leaves the alternate screen and raw mode while `f` runs so a child
+/// process can use the terminal, then restores and redraws the TUI.
+fn suspend_tui<B: Backend, R>(terminal: &mut Terminal<B>, f: impl FnOnce() -> R) -> io::Result<R> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+
+    let result = f();
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear()?;
+    Ok(result)
+}
+
+/// The search strategy a direct-select key in search mode picks, if any
+fn strategy_shortcut(key_bindings: &crate::config::KeyBindings, key_code: &KeyCode) -> Option<SearchStrategy> {
+    let keys = &key_bindings.search_mode;
+    if key_bindings.matches_key(&keys.strategy_fast, key_code) {
+        Some(SearchStrategy::Fast)
+    } else if key_bindings.matches_key(&keys.strategy_comprehensive, key_code) {
+        Some(SearchStrategy::Comprehensive)
+    } else if key_bindings.matches_key(&keys.strategy_local, key_code) {
+        Some(SearchStrategy::LocalOnly)
+    } else {
+        None
+    }
+}
+
+/// The walk option a search mode key flips, if any
+fn walk_toggle(key_bindings: &crate::config::KeyBindings, key: &KeyEvent) -> Option<WalkToggle> {
+    let keys = &key_bindings.search_mode;
+    if key_bindings.matches_chord(&keys.toggle_hidden, key) {
+        Some(WalkToggle::Hidden)
+    } else if key_bindings.matches_chord(&keys.toggle_gitignore, key) {
+        Some(WalkToggle::Gitignore)
+    } else if key_bindings.matches_chord(&keys.toggle_follow_links, key) {
+        Some(WalkToggle::FollowLinks)
+    } else {
+        None
+    }
+}
+
+/// Lowercase extension `open_with_recent` is keyed by; empty for files without one.
+fn extension_key(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Message for an operation applied to several entries: `done` alone when
+/// everything worked, otherwise an error that also reports the failures.
+fn batch_result(done: String, errors: Vec<String>) -> Result<String, String> {
+    match errors.first() {
+        None => Ok(done),
+        Some(first) => Err(format!("{}, {} failed: {}", done, errors.len(), first)),
+    }
+}
+
+/// `-rw-r--r-- user group`, leaving out ownership where it isn't available
+fn permissions_line(file: &FileInfo) -> String {
+    let mut parts = vec![file.permissions.display(file.is_directory, file.is_symlink)];
+    parts.extend(file.owner.clone());
+    parts.extend(file.group.clone());
+    if !file.permissions.is_writable() && matches!(file.permissions, file_system::FilePermissions::Mode(_)) {
+        parts.push("(read-only)".to_string());
+    }
+    parts.join(" ")
+}
+
+fn copy_to_clipboard(text: &str) -> Result<(), arboard::Error> {
+    Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text))
+}
+
+fn file_url(path: &std::path::Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{}", percent_encode_path(&path))
+    } else {
+        // Windows drive paths need the extra slash: file:///C:/...
+        format!("file:///{}", percent_encode_path(&path))
+    }
+}
+
+// Escapes everything except unreserved characters and separators so links
+// with spaces or parentheses survive markdown renderers
+fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Helper function to format file sizes
+/// Shown files of each group of duplicates, by group
+fn duplicate_group_counts(app: &App) -> HashMap<usize, usize> {
+    let mut counts = HashMap::new();
+    if let Some(duplicates) = &app.duplicates {
+        for result in &app.search_results {
+            if let Some(&group) = duplicates.group_of.get(&result.file_info.path) {
+                *counts.entry(group).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// E.g. " (3 unreadable files skipped)", or nothing
+fn unreadable_note(unreadable: usize) -> String {
+    match unreadable {
+        0 => String::new(),
+        1 => " (1 unreadable file skipped)".to_string(),
+        count => format!(" ({} unreadable files skipped)", count),
+    }
+}
+
+/// Rough age such as "40s", "12m", "3h" or "2d"
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+fn render_footer(f: &mut Frame, app: &App, area: Rect) {
+    let kb = &app.config.key_bindings;
+    let text = if app.search_mode {
+        format!(
+            "{}: Exit search | {}: Exit to results | {}: Toggle strategy | {}: Match mode | {}: Case | {}/{}/{}: Hidden/Gitignore/Links | {}/{}/{}: Fast/Full/Local | {}: Navigate | {}: Browse",
+            kb.get_key_display(&kb.search_mode.exit_search),
+            kb.get_key_display(&kb.search_mode.exit_to_results),
+            kb.get_key_display(&kb.search_mode.toggle_strategy),
+            kb.get_key_display(&kb.search_mode.toggle_pattern_mode),
+            kb.get_key_display(&kb.search_mode.toggle_case),
+            kb.get_key_display(&kb.search_mode.toggle_hidden),
+            kb.get_key_display(&kb.search_mode.toggle_gitignore),
+            kb.get_key_display(&kb.search_mode.toggle_follow_links),
+            kb.get_key_display(&kb.search_mode.strategy_fast),
+            kb.get_key_display(&kb.search_mode.strategy_comprehensive),
+            kb.get_key_display(&kb.search_mode.strategy_local),
+            kb.get_key_display(&kb.search_mode.navigate_tab),
+            kb.get_key_display(&kb.navigation.up)
+        )
+    } else if app.showing_search_results {
+        let writable = !app.read_only;
+        let hints = footer_hints(kb, &[
+            (&kb.actions.quit, "Quit", true),
+            (&kb.actions.search, "New search", true),
+            (&kb.search_results.back, "Back", true),
+            (&kb.search_results.cycle_type_filter, "Files/dirs", true),
+            (&kb.search_results.directories_first, "Dirs first", true),
+            (&kb.search_results.toggle_preview, "Preview", true),
+            (&kb.search_results.refine, "Refine", true),
+            (&kb.search_results.export, "Export", writable),
+            (&kb.search_results.select_all, "Select all", true),
+            (&kb.search_results.mark_copies, "Mark copies", app.duplicates.is_some()),
+            (&kb.navigation.up, "Navigate", true),
+            (&kb.navigation.enter, "Open/Navigate", true),
+            (&kb.actions.open, "Open", true),
+            (&kb.actions.reveal, "Reveal", true),
+            (&kb.actions.share, "Share", true),
+            (&kb.actions.cut, "Cut", writable),
+            (&kb.actions.copy, "Copy", true),
+            (&kb.actions.delete, "Delete", writable),
+            (&kb.actions.rename, "Rename", writable),
+            (&kb.actions.copy_path, "Copy path", true),
+        ]);
+        format!("{}{}", hints, clipboard_status(app))
+    } else {
+        let writable = !app.read_only;
+        let hints = footer_hints(kb, &[
+            (&kb.actions.quit, "Quit", true),
+            (&kb.actions.search, "Search", true),
+            (&kb.navigation.up, "Navigate", true),
+            (&kb.navigation.enter, "Open/Navigate", true),
+            (&kb.navigation.left, "Go up", true),
+            (&kb.actions.open, "Open", true),
+            (&kb.actions.reveal, "Reveal", true),
+            (&kb.actions.share, "Share", true),
+            (&kb.actions.cut, "Cut", writable),
+            (&kb.actions.copy, "Copy", true),
+            (&kb.actions.delete, "Delete", writable),
+            (&kb.actions.rename, "Rename", writable),
+            (&kb.actions.new_file, "New file", writable),
+            (&kb.actions.new_dir, "New dir", writable),
+            (&kb.actions.toggle_hidden, "Toggle hidden", true),
+            (&kb.actions.copy_path, "Copy path", true),
+        ]);
+        format!("{}{}", hints, clipboard_status(app))
+    };
+    
+    let footer = Paragraph::new(vec![
+        Line::from(text),
+        Line::from(Span::styled(app.get_current_message(), app.get_message_style())),
+    ])
+    .block(Block::default().borders(Borders::ALL).title(footer_title(app)));
+    
+    f.render_widget(footer, area);
+
+    if let Some(running) = &app.running_transfer {
+        // Drawn over the key help line while a paste runs
+        let gauge_area = Rect { x: area.x + 1, y: area.y + 1, width: area.width.saturating_sub(2), height: 1 };
+        let (ratio, amount) = match &running.progress {
+            Some(progress) if progress.bytes_total > 0 => (
+                (progress.bytes_done as f64 / progress.bytes_total as f64).min(1.0),
+                format!("{} / {}", format_size(progress.bytes_done), format_size(progress.bytes_total)),
+            ),
+            Some(progress) => (0.0, format_size(progress.bytes_done)),
+            None => (0.0, "starting".to_string()),
+        };
+        let current = running.progress.as_ref()
+            .map(|progress| file_system::display_name(&progress.current_file))
+            .unwrap_or_else(|| running.name.clone());
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(Color::Green).bg(Color::Black))
+            .ratio(ratio)
+            .label(format!(
+                "{} ({}) - {}: Cancel",
+                current,
+                amount,
+                kb.get_key_display(&kb.actions.cancel_transfer)
+            ));
+        f.render_widget(gauge, gauge_area);
+    }
+}
+
+/// One-character column in the file list, like the gutter marks editors use
+fn git_status_glyph(status: Option<GitStatus>) -> Span<'static> {
+    match status {
+        Some(GitStatus::Modified) => Span::styled("M", Style::default().fg(Color::Yellow)),
+        Some(GitStatus::Staged) => Span::styled("+", Style::default().fg(Color::Green)),
+        Some(GitStatus::Untracked) => Span::styled("?", Style::default().fg(Color::Cyan)),
+        Some(GitStatus::Ignored) => Span::styled("!", Style::default().fg(Color::DarkGray)),
+        None => Span::raw(" "),
+    }
+}
+
+/// `key: label` pairs for the footer, leaving out those marked hidden
+fn footer_hints(kb: &KeyBindings, hints: &[(&Vec<String>, &str, bool)]) -> String {
+    hints.iter()
+        .filter(|(_, _, shown)| *shown)
+        .map(|(keys, label, _)| format!("{}: {}", kb.get_key_display(keys), label))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+// Persistent view state indicators shown in the footer border
+fn footer_title(app: &App) -> String {
+    let hidden = if app.explorer.show_hidden() { "shown" } else { "not shown" };
+    let mut parts = vec![format!("hidden files {}", hidden)];
+    if !app.marked.is_empty() {
+        parts.push(format!("{} marked", app.marked.len()));
+    }
+    let (back, forward) = app.explorer.history_depth();
+    if back > 0 || forward > 0 {
+        parts.push(format!("◀ {} | {} ▶", back, forward));
+    }
+    format!("Controls [{}]", parts.join(" | "))
+}
+
+/// Greys out entries waiting to be moved, the way file managers show cut
+/// files, and tags entries waiting to be copied.
+fn clipboard_style(app: &App, path: &Path, style: Style) -> (Style, Option<Span<'static>>) {
+    let Some(clipboard) = app.clipboard.as_ref().filter(|clipboard| clipboard.file_paths.iter().any(|p| p == path)) else {
+        return (style, None);
+    };
+    match clipboard.operation {
+        ClipboardOperation::Cut => (style.fg(Color::DarkGray).add_modifier(Modifier::ITALIC), None),
+        ClipboardOperation::Copy => (style, Some(Span::styled(" [copy]", Style::default().fg(Color::DarkGray)))),
+    }
+}
+
+fn clipboard_status(app: &App) -> String {
+    let Some(clipboard) = &app.clipboard else {
+        return String::new();
+    };
+    let kb = &app.config.key_bindings;
+    let operation = match clipboard.operation {
+        ClipboardOperation::Cut => "CUT",
+        ClipboardOperation::Copy => "COPIED",
+    };
+    let contents = match clipboard.file_paths.as_slice() {
+        [single] => file_system::display_name(single),
+        many => format!("{} items", many.len()),
+    };
+    format!(" | {}: {} [{}]", kb.get_key_display(&kb.actions.paste), operation, contents)
+}
+
+fn render_search_input(f: &mut Frame, app: &App) {
+    let area = centered_rect(60, 3, f.size());
+    
+    f.render_widget(Clear, area);
+    
+    let engine = &app.search_engine;
+    let title = if app.refinements.is_empty() {
+        format!("Search - {} - {}, {} {}", app.search_strategy.description(), engine.pattern_mode.label(),
+            engine.case_mode.label(), engine.settings.walk.flags())
+    } else {
+        let narrowed: Vec<&str> = app.refinements.iter().map(|level| level.parent_pattern.as_str()).collect();
+        format!("Refine {} - {}, {}", narrowed.join(" > "), engine.pattern_mode.label(), engine.case_mode.label())
+    };
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if let Some(note) = app.history_note() {
+        block = block.title(Span::styled(format!(" {} ", note), Style::default().fg(Color::DarkGray)));
+    }
+    let input = Paragraph::new(app.search_input.as_str())
+        .style(Style::default().fg(Color::Yellow))
+        .block(block);
+    
+    f.render_widget(input, area);
+}
+
+fn render_input_prompt(f: &mut Frame, app: &App) {
+    let prompt = match &app.input_prompt {
+        Some(prompt) => prompt,
+        None => return,
+    };
+
+    let mut text = vec![Line::from(prompt.value.as_str())];
+    if !prompt.completions.is_empty() {
+        text.push(Line::from(Span::styled(prompt.completions.join("  "), Style::default().fg(Color::DarkGray))));
+    }
+    if let InputPurpose::BatchRename(sources) = &prompt.purpose {
+        text.extend(batch_rename_preview(sources, &prompt.value));
+    }
+    if let InputPurpose::Jump = prompt.purpose {
+        text.extend(jump_candidate_lines(app, &prompt.value));
+    }
+
+    let height = match text.len() {
+        1 => 3,
+        lines => (lines as u16 + 4).min(f.size().height),
+    };
+    let area = centered_rect(60, height, f.size());
+
+    f.render_widget(Clear, area);
+
+    let input = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} - Enter to apply, Esc to cancel", prompt.title)))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(input, area);
+}
+
+/// The directories the jump popup would pick from, the one Enter enters
+/// highlighted.
+fn jump_candidate_lines(app: &App, query: &str) -> Vec<Line<'static>> {
+    let candidates = app.jump_candidates(query);
+    if candidates.is_empty() {
+        return vec![Line::from(Span::styled("No visited directory matches", Style::default().fg(Color::DarkGray)))];
+    }
+    candidates.iter().enumerate()
+        .map(|(index, path)| {
+            let style = if index == 0 {
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            Line::from(Span::styled(path.display().to_string(), style))
+        })
+        .collect()
+}
+
+/// The old -> new names a batch rename pattern gives, with any conflicts
+/// in red above them.
+fn batch_rename_preview(sources: &[PathBuf], pattern: &str) -> Vec<Line<'static>> {
+    let error = Style::default().fg(Color::Red);
+    let mut lines = vec![Line::from("")];
+    let plan = match batch_rename::plan(sources, pattern) {
+        Ok(plan) => plan,
+        Err(_) if pattern.is_empty() => return lines,
+        Err(err) => {
+            lines.push(Line::from(Span::styled(err, error)));
+            return lines;
+        }
+    };
+
+    for conflict in plan.conflicts.iter().take(BATCH_RENAME_PREVIEW_LINES) {
+        lines.push(Line::from(Span::styled(conflict.clone(), error)));
+    }
+    for step in plan.renames.iter().take(BATCH_RENAME_PREVIEW_LINES) {
+        lines.push(Line::from(format!("{} -> {}", step.old_name(), step.new_name())));
+    }
+    if plan.renames.len() > BATCH_RENAME_PREVIEW_LINES {
+        lines.push(Line::from(format!("... and {} more", plan.renames.len() - BATCH_RENAME_PREVIEW_LINES)));
+    }
+    let kept = sources.len() - plan.renames.len();
+    if kept > 0 {
+        lines.push(Line::from(Span::styled(format!("{} keep their names", kept), Style::default().fg(Color::DarkGray))));
+    }
+    lines
+}
+
+fn render_confirm_dialog(f: &mut Frame, app: &App) {
+    let dialog = match &app.confirm_dialog {
+        Some(dialog) => dialog,
+        None => return,
+    };
+
+    let area = centered_rect(60, 6, f.size());
+
+    f.render_widget(Clear, area);
+
+    let text = vec![
+        Line::from(dialog.message.as_str()),
+        Line::from(""),
+        Line::from(Span::styled("y/Enter: Confirm | n/Esc: Cancel", Style::default().fg(Color::DarkGray))),
+    ];
+    let popup = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(dialog.title.as_str())
+            .border_style(Style::default().fg(Color::Red)))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(popup, area);
+}
+
+fn render_paste_conflict(f: &mut Frame, app: &App) {
+    let conflict = match &app.paste_conflict {
+        Some(conflict) => conflict,
+        None => return,
+    };
+
+    let area = centered_rect(60, 8, f.size());
+
+    f.render_widget(Clear, area);
+
+    let kind = if conflict.is_directory { "A folder" } else { "A file" };
+    let overwrite_hint = if conflict.is_directory {
+        "Overwrite merges into the existing folder"
+    } else {
+        "Overwrite replaces the existing file"
+    };
+    let apply_to_all = if conflict.apply_to_all { "[x]" } else { "[ ]" };
+    let text = vec![
+        Line::from(format!("{} named '{}' already exists here.", kind, conflict.name)),
+        Line::from(Span::styled(overwrite_hint, Style::default().fg(Color::DarkGray))),
+        Line::from(format!("{} a: Apply to all conflicts, including inside folders", apply_to_all)),
+        Line::from(""),
+        Line::from(Span::styled(
+            "o: Overwrite | k: Keep both | s: Skip | c/Esc: Cancel",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+    let popup = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title("Paste Conflict")
+            .border_style(Style::default().fg(Color::Yellow)))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(popup, area);
+}
+
+fn render_bookmark_picker(f: &mut Frame, app: &App) {
+    let Some(state) = &app.bookmark_picker else {
+        return;
+    };
+
+    let bookmarks = &app.config.bookmarks;
+    let area = centered_rect(60, (bookmarks.len() as u16 + 3).min(20), f.size());
+    f.render_widget(Clear, area);
+
+    let items: Vec<ListItem> = bookmarks
+        .iter()
+        .enumerate()
+        .map(|(index, bookmark)| {
+            let number = if index < 9 { format!("{} ", index + 1) } else { "  ".to_string() };
+            let line = if bookmark.is_dir() {
+                Line::from(vec![
+                    Span::styled(number, Style::default().fg(Color::Yellow)),
+                    Span::styled(bookmark.display().to_string(), Style::default().fg(Color::Cyan)),
+                ])
+            } else {
+                let dimmed = Style::default().fg(Color::DarkGray);
+                Line::from(vec![
+                    Span::styled(number, dimmed),
+                    Span::styled(format!("{} (missing)", bookmark.display()), dimmed.add_modifier(Modifier::ITALIC)),
+                ])
+            };
+            ListItem::new(line)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title("Bookmarks - Enter: Jump | d: Remove | Esc: Close")
+            .border_style(Style::default().fg(Color::Cyan)))
+        .highlight_style(Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD));
+
+    let mut state = state.clone();
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_share_manager(f: &mut Frame, app: &App) {
+    let Some(manager) = &app.share_manager else {
+        return;
+    };
+
+    let area = centered_rect(80, (manager.shares.len() as u16 + 2).min(20), f.size());
+    f.render_widget(Clear, area);
+
+    let now = SystemTime::now();
+    let name_width = manager.shares.iter()
+        .map(|share| file_system::display_name(&share.path).chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(30);
+    let items: Vec<ListItem> = manager.shares
+        .iter()
+        .map(|share| {
+            let name = truncate_middle(&file_system::display_name(&share.path), name_width);
+            let mut notes = vec![
+                file_system::age_label(share.shared_at, now),
+                if share.hits == 1 { "1 hit".to_string() } else { format!("{} hits", share.hits) },
+            ];
+            if let Some(client) = share.last_client {
+                notes.push(format!("last from {}", client));
+            }
+            if let Some(expires_at) = share.expires_at {
+                let left = expires_at.duration_since(now).unwrap_or_default();
+                notes.push(format!("expires in {}", file_system::span_label(left)));
+            }
+            if let Some(remaining) = share.remaining_downloads {
+                notes.push(format!("{} downloads left", remaining));
+            }
+            match share.protection {
+                Some(ShareProtection::Token) => notes.push("token".to_string()),
+                Some(ShareProtection::Password(_)) => notes.push("password".to_string()),
+                None => {}
+            }
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<width$}  ", name, width = name_width), Style::default().fg(Color::Cyan)),
+                Span::raw(share.url.clone()),
+                Span::styled(format!("  {}", notes.join(", ")), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title("Shares - Enter/c: Copy URL | o: Open | q: QR code | d: Revoke | X: Revoke all | Esc: Close")
+            .border_style(Style::default().fg(Color::Cyan)))
+        .highlight_style(Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD));
+
+    let mut state = manager.state.clone();
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_qr_popup(f: &mut Frame, app: &App) {
+    let Some(popup) = &app.qr_popup else {
+        return;
+    };
+
+    let screen = f.size();
+    let code_width = popup.lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as u16;
+    // The code and the URL under it, inside the borders
+    let fits = code_width + 2 <= screen.width && popup.lines.len() as u16 + 3 <= screen.height;
+    let width = code_width.max(popup.url.chars().count() as u16).max(40) + 2;
+    let lines: Vec<Line> = if fits {
+        popup.lines.iter()
+            // Dark modules must come out dark to scan, whatever the theme
+            .map(|line| Line::from(Span::styled(line.clone(), Style::default().fg(Color::Black).bg(Color::White))))
+            .chain(std::iter::once(Line::from(Span::styled(popup.url.clone(), Style::default().fg(Color::DarkGray)))))
+            .collect()
+    } else {
+        vec![
+            Line::from("The terminal is too small for the QR code."),
+            Line::from(Span::styled(popup.url.clone(), Style::default().fg(Color::DarkGray))),
+        ]
+    };
+    let height = lines.len() as u16 + 2;
+    let area = Rect {
+        x: screen.width.saturating_sub(width) / 2,
+        y: screen.height.saturating_sub(height) / 2,
+        width: width.min(screen.width),
+        height: height.min(screen.height),
+    };
+    f.render_widget(Clear, area);
+
+    let paragraph = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} - any key to close", popup.name))
+            .border_style(Style::default().fg(Color::Cyan)));
+    f.render_widget(paragraph, area);
+}
+
+fn render_properties(f: &mut Frame, app: &App) {
+    let Some(popup) = &app.properties else {
+        return;
+    };
+
+    let area = centered_rect(70, popup.rows.len() as u16 + 2, f.size());
+    f.render_widget(Clear, area);
+
+    let width = popup.rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    let lines: Vec<Line> = popup.rows
+        .iter()
+        .map(|(label, value)| {
+            Line::from(vec![
+                Span::styled(format!("{:<width$}  ", label, width = width), Style::default().fg(Color::DarkGray)),
+                Span::raw(value.clone()),
+            ])
+        })
+        .collect();
+
+    let kb = &app.config.key_bindings;
+    let title = format!("Properties - {}: Copy | Esc: Close", kb.get_key_display(&kb.actions.copy));
+    let popup = Paragraph::new(lines)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan)));
+
+    f.render_widget(popup, area);
+}
+
+fn render_diff(f: &mut Frame, app: &App) {
+    let Some(popup) = &app.diff_popup else {
+        return;
+    };
+
+    let screen = f.size();
+    let area = screen.inner(&Margin { vertical: 2, horizontal: screen.width / 10 });
+    f.render_widget(Clear, area);
+
+    let visible = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = popup.lines
+        .iter()
+        .skip(popup.scroll)
+        .take(visible)
+        .map(|line| {
+            let style = if line.starts_with("---") || line.starts_with("+++") {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else if line.starts_with('+') {
+                Style::default().fg(Color::Green)
+            } else if line.starts_with('-') {
+                Style::default().fg(Color::Red)
+            } else if line.starts_with("@@") {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(line.clone(), style))
+        })
+        .collect();
+
+    let kb = &app.config.key_bindings;
+    let position = format!("{}/{}", (popup.scroll + 1).min(popup.lines.len()), popup.lines.len());
+    let title = format!(
+        "{} ({}) - {}: Copy | Esc: Close",
+        popup.title, position, kb.get_key_display(&kb.actions.copy),
+    );
+    let widget = Paragraph::new(lines)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Cyan)));
+
+    f.render_widget(widget, area);
+}
+
+fn render_open_with_menu(f: &mut Frame, app: &App) {
+    let Some(menu) = &app.open_with_menu else {
+        return;
+    };
+
+    let area = centered_rect(60, (menu.apps.len() as u16 + 2).min(20), f.size());
+    f.render_widget(Clear, area);
+
+    let items: Vec<ListItem> = menu.apps
+        .iter()
+        .map(|app| {
+            ListItem::new(Line::from(vec![
+                Span::styled(app.name.clone(), Style::default().fg(Color::Cyan)),
+                Span::styled(format!("  {}", app.command), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Open '{}' with - Enter: Open | Esc: Close", file_system::display_name(&menu.path)))
+            .border_style(Style::default().fg(Color::Cyan)))
+        .highlight_style(Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD));
+
+    let mut state = menu.state.clone();
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - height) / 2),
+            Constraint::Length(height),
+            Constraint::Percentage((100 - height) / 2),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use crate::test_support::TempDir;
+
+    fn app_in(dir: &Path) -> App {
+        let explorer = FileExplorer::new(dir.to_path_buf()).unwrap();
+        App::new(explorer, SearchEngine::new(), Config::default())
+    }
+
+    /// Lets a started paste run to completion and returns its final message.
+    async fn finish_paste(app: &mut App) -> String {
+        while app.running_transfer.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_transfer();
+        }
+        app.get_current_message().to_string()
+    }
+
+    fn selected_name(app: &App) -> String {
+        let index = app.list_state.selected().unwrap();
+        app.explorer.files()[index].name.clone()
+    }
+
+    #[test]
+    fn test_truncate_middle() {
+        assert_eq!(truncate_middle("/short/path", 20), "/short/path");
+        assert_eq!(truncate_middle("/home/user/projects/file.rs", 12), "/home…ile.rs");
+        assert_eq!(truncate_middle("abc", 0), "");
+    }
+
+    #[tokio::test]
+    async fn test_paste_keeps_selection() {
+        let dir = TempDir::new("paste");
+        let other = dir.join("other");
+        fs::create_dir(&other).unwrap();
+        fs::write(other.join("b.txt"), "").unwrap();
+        for name in ["a.txt", "c.txt", "d.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("c.txt"));
+        app.clipboard = Some(ClipboardEntry {
+            file_paths: vec![other.join("b.txt")],
+            operation: ClipboardOperation::Copy,
+        });
+        app.paste_file().unwrap();
+        finish_paste(&mut app).await;
+
+        assert!(dir.join("b.txt").exists());
+        assert_eq!(selected_name(&app), "c.txt");
+    }
+
+    #[tokio::test]
+    async fn test_marked_entries_are_copied_together() {
+        let dir = TempDir::new("marks");
+        let target = dir.join("target");
+        fs::create_dir(&target).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("a.txt"));
+        app.toggle_mark_selected().unwrap();
+        app.select_path(&dir.join("c.txt"));
+        app.toggle_mark_selected().unwrap();
+        app.copy_selected_file().unwrap();
+        assert!(app.marked.is_empty());
+
+        app.explorer.navigate_to(target.clone(), None).unwrap();
+        app.paste_file().unwrap();
+        assert_eq!(finish_paste(&mut app).await, "Copied 2 items to current directory");
+        assert!(target.join("a.txt").exists());
+        assert!(!target.join("b.txt").exists());
+        assert!(target.join("c.txt").exists());
+    }
+
+    #[test]
+    fn test_duplicate_picks_free_copy_name() {
+        let dir = TempDir::new("duplicate");
+        fs::write(dir.join("notes.txt"), "draft").unwrap();
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("notes.txt"));
+        app.duplicate_selected().unwrap();
+        assert_eq!(selected_name(&app), "notes (copy).txt");
+
+        app.select_path(&dir.join("notes.txt"));
+        app.duplicate_selected().unwrap();
+        assert_eq!(selected_name(&app), "notes (copy 2).txt");
+        assert_eq!(fs::read_to_string(dir.join("notes (copy 2).txt")).unwrap(), "draft");
+    }
+
+    #[test]
+    fn test_go_up_selects_previous_directory() {
+        let dir = TempDir::new("go-up");
+        for name in ["alpha", "beta", "gamma"] {
+            fs::create_dir(dir.join(name)).unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("gamma"));
+        app.navigate_to_selected().unwrap();
+        assert_eq!(app.explorer.current_path(), dir.join("gamma"));
+
+        app.go_up().unwrap();
+        assert_eq!(app.explorer.current_path(), dir.path());
+        assert_eq!(selected_name(&app), "gamma");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_directory_navigation_modes() {
+        let dir = TempDir::new("symlink-nav");
+        fs::create_dir_all(dir.join("real/inner")).unwrap();
+        fs::create_dir(dir.join("links")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("links/shortcut")).unwrap();
+
+        // By default links are resolved to where they point
+        let mut app = app_in(&dir.join("links"));
+        app.explorer.navigate_to(dir.join("links/shortcut"), None).unwrap();
+        assert_eq!(app.explorer.current_path(), dir.join("real"));
+        app.go_up().unwrap();
+        assert_eq!(app.explorer.current_path(), dir.path());
+
+        app.explorer.set_resolve_symlinks(false);
+        app.explorer.navigate_to(dir.join("links/shortcut"), None).unwrap();
+        assert_eq!(app.explorer.current_path(), dir.join("links/shortcut"));
+        app.go_up().unwrap();
+        assert_eq!(app.explorer.current_path(), dir.join("links"));
+    }
+
+    #[test]
+    fn test_refresh_falls_back_to_nearest_entry() {
+        let dir = TempDir::new("refresh");
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("b.txt"));
+        fs::write(dir.join("0.txt"), "").unwrap();
+        app.refresh_listing().unwrap();
+        assert_eq!(selected_name(&app), "b.txt");
+
+        // The selected entry vanishing leaves the cursor at the same position
+        fs::remove_file(dir.join("b.txt")).unwrap();
+        fs::remove_file(dir.join("c.txt")).unwrap();
+        app.refresh_listing().unwrap();
+        assert_eq!(selected_name(&app), "a.txt");
+    }
+
+    #[test]
+    fn test_slow_search_root_asks_once() {
+        let mut app = app_in(Path::new("/"));
+        app.enter_search_mode();
+        app.search_input = "notes".to_string();
+
+        app.search_strategy = SearchStrategy::Fast;
+        assert!(!app.request_slow_search_confirmation());
+
+        app.search_strategy = SearchStrategy::Comprehensive;
+        assert!(app.request_slow_search_confirmation());
+        assert!(app.confirm_pending_action().is_ok());
+        assert!(!app.request_slow_search_confirmation());
+
+        // A new search session asks again
+        app.enter_search_mode();
+        app.search_input = "notes".to_string();
+        assert!(app.request_slow_search_confirmation());
+    }
+
+    #[test]
+    fn test_refining_search_results() {
+        let dir = TempDir::new("refine-search");
+        for name in ["report-2024.pdf", "report-2024.txt", "report-2023.pdf", "notes.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let names = |app: &App| {
+            let mut names: Vec<String> = app.search_results.iter().map(|result| result.file_info.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        let mut app = app_in(&dir);
+        app.search_strategy = SearchStrategy::LocalOnly;
+        app.enter_search_mode();
+        app.search_input = "report".to_string();
+        app.perform_search();
+        app.exit_search_mode();
+        assert_eq!(app.search_results.len(), 3);
+
+        app.start_refining();
+        assert!(app.search_mode && app.search_input.is_empty());
+        app.search_input = "2024".to_string();
+        app.perform_search();
+        assert_eq!(names(&app), ["report-2024.pdf", "report-2024.txt"]);
+        app.exit_search_mode();
+        app.start_refining();
+        app.search_input = "pdf".to_string();
+        app.perform_search();
+        assert_eq!(names(&app), ["report-2024.pdf"]);
+        assert_eq!(app.refinement_breadcrumb(), "report > 2024 > pdf");
+
+        // Each level gives back what it narrowed, then the original search
+        app.search_input.clear();
+        app.perform_search();
+        assert_eq!(app.search_results.len(), 2);
+        assert!(app.pop_refinement());
+        assert_eq!(app.refinement_breadcrumb(), "report > 2024");
+        assert_eq!(names(&app), ["report-2024.pdf", "report-2024.txt"]);
+        assert!(app.pop_refinement());
+        assert_eq!((app.search_input.as_str(), app.search_results.len()), ("report", 3));
+        assert!(!app.pop_refinement());
+
+        // A refinement matching nothing is dropped on Enter
+        app.start_refining();
+        app.search_input = "zzz".to_string();
+        app.perform_search();
+        app.exit_search_mode();
+        assert!(app.showing_search_results && app.refinements.is_empty());
+        assert_eq!(app.search_results.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_search_results_stream_in_by_score() {
+        let dir = TempDir::new("stream-search");
+        fs::create_dir_all(dir.join("old")).unwrap();
+        fs::write(dir.join("report.txt"), "").unwrap();
+        fs::write(dir.join("quarterly-report.txt"), "").unwrap();
+        fs::write(dir.join("old/report.md"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut app = app_in(&dir);
+        app.search_strategy = SearchStrategy::Comprehensive;
+        app.enter_search_mode();
+        app.search_input = "report".to_string();
+        app.perform_search();
+        for _ in 0..500 {
+            if app.running_search.is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_search();
+        }
+        assert!(app.running_search.is_none());
+        assert_eq!(app.all_search_results.len(), 3);
+        assert!(app.search_results.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!(app.search_list_state.selected(), Some(0));
+        assert!(app.get_current_message().starts_with("Found 3 results"));
+
+        // A truncated search says how many matches it left out
+        app.search_engine.settings.max_results = 2;
+        app.perform_search();
+        while app.running_search.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_search();
+        }
+        assert_eq!(app.all_search_results.len(), 2);
+        assert!(app.get_current_message().ends_with(" - showing 2 of 3 matches"));
+
+        // Typing again replaces the running search
+        app.perform_search();
+        let replaced = app.running_search.as_ref().unwrap().cancel.clone();
+        app.search_input.push('s');
+        app.perform_search();
+        assert!(replaced.load(Ordering::Relaxed));
+        assert!(app.all_search_results.is_empty());
+
+        // Clearing the query or leaving search mode stops a running search
+        let cancel = app.running_search.as_ref().unwrap().cancel.clone();
+        app.exit_search_mode();
+        assert!(app.running_search.is_none());
+        assert!(cancel.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_search_roots() {
+        let dir = TempDir::new("search-roots");
+        for sub in ["work", "oss", "elsewhere"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join(format!("{}-notes.md", sub)), "").unwrap();
+        }
+
+        let mut app = app_in(&dir.join("work"));
+        assert!(app.toggle_search_root().unwrap().starts_with("Added"));
+        app.explorer.navigate_to(dir.join("oss"), None).unwrap();
+        assert!(app.toggle_search_root().unwrap().ends_with("(2 in all)"));
+        app.explorer.navigate_to(dir.join("elsewhere"), None).unwrap();
+
+        app.search_strategy = SearchStrategy::Comprehensive;
+        app.enter_search_mode();
+        app.search_input = "notes".to_string();
+        app.perform_search();
+        while app.running_search.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_search();
+        }
+        let mut names: Vec<&str> = app.all_search_results.iter().map(|result| result.file_info.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["oss-notes.md", "work-notes.md"]);
+        assert_eq!(app.roots_note(), format!(" - in {}, {}", dir.join("work").display(), dir.join("oss").display()));
+
+        // Removing the last root searches the current directory again
+        app.explorer.navigate_to(dir.join("oss"), None).unwrap();
+        assert!(app.toggle_search_root().unwrap().ends_with("(1 left)"));
+        app.explorer.navigate_to(dir.join("work"), None).unwrap();
+        assert!(app.toggle_search_root().unwrap().ends_with("searching the current directory again"));
+        assert_eq!(app.walk_roots(), [dir.join("work")]);
+        assert_eq!(app.roots_note(), "");
+    }
+
+    #[test]
+    fn test_search_history_recall() {
+        let dir = TempDir::new("search-history");
+        for name in ["report.txt", "notes.md", "todo.md"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let config_path = dir.join("config.json");
+        fs::write(&config_path, "{}").unwrap();
+        let config = Config::load_from_file(&config_path).unwrap();
+        let mut app = App::new(FileExplorer::new(dir.to_path_buf()).unwrap(), SearchEngine::new(), config);
+        assert!(app.search_history.is_empty());
+        app.search_strategy = SearchStrategy::LocalOnly;
+
+        for query in ["report", "notes", "notes", "todo"] {
+            app.enter_search_mode();
+            app.search_input = query.to_string();
+            app.perform_search();
+            app.submit_search();
+        }
+        assert_eq!(app.search_history.len(), 3);
+
+        // Up on an empty input walks back from the latest query, running each
+        app.enter_search_mode();
+        assert!(app.recall_previous_search());
+        assert_eq!((app.search_input.as_str(), app.history_note()), ("todo", Some("(3/3)".to_string())));
+        assert!(app.recall_previous_search());
+        assert!(app.recall_previous_search());
+        assert!(app.recall_previous_search());
+        assert_eq!(app.search_input, "report");
+        assert_eq!(app.all_search_results[0].file_info.name, "report.txt");
+        assert!(app.recall_next_search());
+        assert_eq!(app.search_input, "notes");
+        // Editing the recalled query hands Up and Down back to the results
+        app.search_input.push('s');
+        assert_eq!(app.history_note(), None);
+        assert!(!app.recall_previous_search() && !app.recall_next_search());
+
+        // Down past the latest query clears the input
+        app.search_input.clear();
+        assert!(app.recall_previous_search());
+        assert!(app.recall_next_search());
+        assert!(app.search_input.is_empty() && app.all_search_results.is_empty());
+
+        // Saved on exit unless persistence is off
+        app.save_search_history().unwrap();
+        let config = Config::load_from_file(&config_path).unwrap();
+        let app = App::new(FileExplorer::new(dir.to_path_buf()).unwrap(), SearchEngine::new(), config);
+        assert_eq!(app.search_history.get(2), Some("todo"));
+        let mut config = Config::load_from_file(&config_path).unwrap();
+        config.persist_search_history = false;
+        let app = App::new(FileExplorer::new(dir.to_path_buf()).unwrap(), SearchEngine::new(), config);
+        assert!(app.search_history.is_empty());
+    }
+
+    #[test]
+    fn test_export_search_results() {
+        let dir = TempDir::new("export-results");
+        for name in ["report.txt", "report.md", "notes.md"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let mut app = app_in(&dir);
+        assert_eq!(app.request_export_results().unwrap_err(), "No results to export");
+
+        app.search_strategy = SearchStrategy::LocalOnly;
+        app.enter_search_mode();
+        app.search_input = "report".to_string();
+        app.perform_search();
+        app.submit_search();
+        app.request_export_results().unwrap();
+        let prompt = app.input_prompt.as_ref().unwrap();
+        assert!(prompt.value.starts_with("filepilot-search-") && prompt.value.ends_with(".csv"));
+        // Tab switches the format by the extension
+        app.complete_input_prompt();
+        assert!(app.input_prompt.as_ref().unwrap().value.ends_with(".json"));
+
+        app.input_prompt.as_mut().unwrap().value = "out.json".to_string();
+        assert_eq!(app.submit_input_prompt().unwrap(), format!("Exported 2 results to {}", dir.join("out.json").display()));
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("out.json")).unwrap()).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+
+        // An existing file isn't overwritten, and the popup stays open
+        app.request_export_results().unwrap();
+        app.input_prompt.as_mut().unwrap().value = "out.json".to_string();
+        assert!(app.submit_input_prompt().unwrap_err().starts_with("Failed to create"));
+        assert!(app.input_prompt.is_some());
+    }
+
+    #[test]
+    fn test_select_all_results() {
+        let dir = TempDir::new("select-all-results");
+        fs::write(dir.join("app.log"), vec![b'x'; 1000]).unwrap();
+        fs::write(dir.join("db.log"), vec![b'x'; 24]).unwrap();
+        fs::write(dir.join("old.log"), "").unwrap();
+        fs::write(dir.join("notes.md"), "").unwrap();
+        let mut app = app_in(&dir);
+        app.search_strategy = SearchStrategy::LocalOnly;
+        app.enter_search_mode();
+        app.search_input = "log".to_string();
+        app.perform_search();
+        app.submit_search();
+
+        app.request_select_all_results().unwrap();
+        assert!(app.confirm_dialog.as_ref().unwrap().message.starts_with(&format!("Mark all 3 results ({})?", format_size(1024))));
+        // A result deleted since the search is reported and dropped, not marked
+        fs::remove_file(dir.join("old.log")).unwrap();
+        let err = app.confirm_pending_action().unwrap_err();
+        assert_eq!(err, format!("Marked 2 results, 1 failed: '{}' no longer exists", dir.join("old.log").display()));
+        assert_eq!(app.search_results.len(), 2);
+        assert_eq!(app.marked.len(), 2);
+
+        app.request_delete_selected(true).unwrap();
+        assert_eq!(app.confirm_pending_action().unwrap(), "Deleted 2 items");
+        assert!(!dir.join("app.log").exists() && dir.join("notes.md").exists());
+    }
+
+    #[test]
+    fn test_search_result_preview() {
+        let dir = TempDir::new("search-result-preview");
+        fs::write(dir.join("app.log"), "started\nlistening\nstopped").unwrap();
+        fs::write(dir.join("notes.md"), "").unwrap();
+        let mut app = app_in(&dir);
+        app.search_strategy = SearchStrategy::LocalOnly;
+        app.enter_search_mode();
+        app.search_input = "app.log".to_string();
+        app.perform_search();
+        app.submit_search();
+        let preview_text = |app: &App| -> Vec<String> {
+            app.get_file_preview().iter()
+                .map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect())
+                .collect()
+        };
+
+        // The highlighted result is previewed, not the file list's selection
+        assert_eq!(preview_text(&app)[0], "📄 File: app.log (0.0 KB)");
+        assert_eq!(preview_text(&app)[3], " 1: started");
+
+        // A content match is shown with the lines around it
+        let result = &mut app.search_results[0];
+        result.match_type = search::MatchType::Content;
+        result.content_match = Some(search::ContentMatch { line_number: 2, snippet: "listening".to_string() });
+        assert_eq!(&preview_text(&app)[3..], ["1: started", "2: listening", "3: stopped"]);
+
+        assert_eq!(app.toggle_search_preview(), "Preview hidden");
+        assert_eq!(app.toggle_search_preview(), "Preview shown");
+    }
+
+    #[tokio::test]
+    async fn test_find_and_delete_duplicates() {
+        let dir = TempDir::new("find-duplicates");
+        fs::create_dir(dir.join("backup")).unwrap();
+        fs::write(dir.join("report.pdf"), vec![b'r'; 2048]).unwrap();
+        fs::write(dir.join("backup/report.pdf"), vec![b'r'; 2048]).unwrap();
+        fs::write(dir.join("backup/report (2).pdf"), vec![b'r'; 2048]).unwrap();
+        fs::write(dir.join("notes.md"), "todo").unwrap();
+        fs::write(dir.join("backup/notes.md"), "todo").unwrap();
+        fs::write(dir.join("unique.md"), "only one").unwrap();
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options().write(true).open(dir.join("report.pdf")).unwrap().set_modified(old).unwrap();
+        let mut app = app_in(&dir);
+        assert!(app.mark_duplicate_copies().is_err());
+
+        app.start_find_duplicates().unwrap();
+        assert!(app.start_find_duplicates().is_err());
+        while app.running_duplicates.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_duplicates();
+        }
+        assert!(app.showing_search_results);
+        assert_eq!(app.search_results.len(), 5);
+        // The group wasting the most comes first, oldest copy on top
+        assert_eq!(app.search_results[0].file_info.path, dir.join("report.pdf"));
+        assert_eq!(duplicate_group_counts(&app).values().sum::<usize>(), 5);
+
+        assert_eq!(app.mark_duplicate_copies().unwrap(), format!("Marked 3 copies ({}), keeping the oldest of each group", format_size(4100)));
+        assert!(!app.marked.contains(&dir.join("report.pdf")));
+        app.request_delete_selected(true).unwrap();
+        assert_eq!(app.confirm_pending_action().unwrap(), "Deleted 3 items");
+        assert!(dir.join("report.pdf").exists() && !dir.join("backup/report.pdf").exists());
+        assert_eq!(app.search_results.len(), 2);
+        assert_eq!(app.mark_duplicate_copies().unwrap_err(), "No copies left to mark");
+
+        // A new search drops the groups
+        app.enter_search_mode();
+        app.search_input = "notes".to_string();
+        app.perform_search();
+        assert!(app.duplicates.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recently_modified_files() {
+        let dir = TempDir::new("recent-files");
+        fs::create_dir(dir.join("src")).unwrap();
+        let now = SystemTime::now();
+        for (name, hours_ago) in [("src/main.rs", 1), ("notes.md", 5), ("old.log", 30)] {
+            fs::write(dir.join(name), "x").unwrap();
+            let modified = now - Duration::from_secs(hours_ago * 60 * 60);
+            fs::File::options().write(true).open(dir.join(name)).unwrap().set_modified(modified).unwrap();
+        }
+        let mut app = app_in(&dir);
+        app.start_recent_search();
+        assert_eq!(app.search_subject(), "files modified in the last 24h");
+        while app.running_search.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_search();
+        }
+
+        // Newest first, directories and older files left out
+        let paths: Vec<&Path> = app.search_results.iter().map(|result| result.file_info.path.as_path()).collect();
+        assert_eq!(paths, [dir.join("src/main.rs"), dir.join("notes.md")]);
+        assert!(app.showing_search_results && app.recent_window.is_some());
+        let modified = app.search_results[1].file_info.modified.unwrap();
+        assert_eq!(file_system::age_label(modified, now), "5h ago");
+        assert_eq!(file_system::age_label(now - Duration::from_secs(90), now), "1m ago");
+        assert_eq!(file_system::age_label(now - Duration::from_secs(15 * 24 * 60 * 60), now), "2w ago");
+
+        // Going back leaves the view
+        app.clear_search_results();
+        assert!(app.recent_window.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_search_keeps_partial_results() {
+        let dir = TempDir::new("timed-out-search");
+        // Big enough that the walk is still going when the deadline passes
+        for i in 0..300 {
+            let sub = dir.join(format!("d{}", i));
+            fs::create_dir_all(&sub).unwrap();
+            for j in 0..30 {
+                fs::write(sub.join(format!("file{}.txt", j)), "needle").unwrap();
+            }
+        }
+
+        let mut app = app_in(&dir);
+        app.search_strategy = SearchStrategy::Content;
+        app.search_input = "needle".to_string();
+        // The deadline has passed before the first poll, so no race with
+        // the walker decides whether the search times out
+        app.search_engine.settings.timeout_secs = 0;
+        app.perform_search();
+        let running = app.running_search.as_ref().unwrap();
+        let (cancel, matched) = (running.cancel.clone(), running.matched.clone());
+        // Wait for matches without taking them, so the first poll has some
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while matched.load(Ordering::Relaxed) == 0 {
+            assert!(Instant::now() < deadline, "the search found nothing");
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        // A match is counted just before it is sent
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        app.poll_search();
+
+        assert!(app.running_search.is_none());
+        assert!(cancel.load(Ordering::Relaxed));
+        assert!(app.search_timed_out);
+        assert!(!app.search_results.is_empty());
+        assert!(app.get_current_message().starts_with("Partial results - search timed out after 0s"));
+        assert!(matches!(app.status_message.as_ref().unwrap().message_type, MessageType::Warning));
+
+        // The next search starts out complete again
+        app.search_strategy = SearchStrategy::LocalOnly;
+        app.perform_search();
+        assert!(!app.search_timed_out);
+    }
+
+    #[tokio::test]
+    async fn test_indexed_search_builds_and_reloads_index() {
+        let dir = TempDir::new("indexed-search");
+        let root = dir.join("tree");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/report.md"), "").unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+
+        let mut app = app_in(&root);
+        app.config.source_path = Some(dir.join("config.json"));
+        app.search_strategy = SearchStrategy::Indexed;
+        app.search_input = "report".to_string();
+        app.perform_search();
+        assert!(app.get_current_message().contains("no file index yet - press Ctrl+b"));
+
+        async fn finish_indexing(app: &mut App) {
+            while app.running_index.is_some() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                app.poll_index();
+            }
+        }
+        app.rebuild_index().unwrap();
+        assert_eq!(app.index_note(), " - index refreshing");
+        finish_indexing(&mut app).await;
+        assert_eq!(app.get_current_message(), format!("Indexed 3 entries under {}", root.display()));
+        assert_eq!(app.index_note(), " - index 0s old");
+        assert!(dir.join(INDEX_DIR).join(FileIndex::file_name(&root)).is_file());
+
+        app.perform_search();
+        let names: Vec<&str> = app.search_results.iter().map(|result| result.file_info.name.as_str()).collect();
+        assert_eq!(names, ["report.md"]);
+
+        // A later run reads the saved index and picks up what changed since
+        fs::write(root.join("report-2.txt"), "").unwrap();
+        let mut next = app_in(&root);
+        next.config.source_path = Some(dir.join("config.json"));
+        next.search_strategy = SearchStrategy::Indexed;
+        next.search_input = "report".to_string();
+        next.start_indexing(root.clone(), false, false).unwrap();
+        finish_indexing(&mut next).await;
+        assert_eq!(next.file_index.as_ref().unwrap().entries.len(), 4);
+        next.perform_search();
+        assert_eq!(next.search_results.len(), 2);
+
+        // Directories outside the index aren't searched
+        next.explorer.navigate_to(dir.to_path_buf(), None).unwrap();
+        next.perform_search();
+        assert!(next.get_current_message().contains("is outside the file index"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_runs_configured_editor() {
+        let dir = TempDir::new("edit");
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("notes.txt"));
+        // Templates split on whitespace, so use a script instead of quoting
+        let script = dir.join("editor.sh");
+        fs::write(&script, "#!/bin/sh\necho edited > \"$1\"\n").unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        app.config.editor_command = Some(format!("{} {{file}}", script.display()));
+
+        app.edit_selected_file().unwrap();
+        assert_eq!(fs::read_to_string(dir.join("notes.txt")).unwrap(), "edited\n");
+        assert_eq!(selected_name(&app), "notes.txt");
+
+        app.config.editor_command = Some("false".to_string());
+        assert!(app.edit_selected_file().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_usage_follows_current_directory() {
+        let dir = TempDir::new("disk-usage");
+        fs::create_dir(dir.join("sub")).unwrap();
+
+        let mut app = app_in(&dir);
+        assert!(app.disk_usage.is_none());
+        app.update_disk_usage();
+        let usage = app.disk_usage.expect("temp dir should report free space");
+        assert!(usage.total > 0 && usage.available <= usage.total);
+
+        app.select_path(&dir.join("sub"));
+        app.navigate_to_selected().unwrap();
+        app.update_disk_usage();
+        assert_eq!(app.disk_usage_path, dir.join("sub"));
+    }
+
+    #[test]
+    fn test_case_only_rename() {
+        let dir = TempDir::new("case-rename");
+        fs::write(dir.join("notes.txt"), "notes").unwrap();
+        // Only case-insensitive filesystems find the new name already taken
+        if !dir.join("NOTES.txt").exists() {
+            return;
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("notes.txt"));
+        let file_info = app.get_selected_file().unwrap().clone();
+        assert_eq!(app.rename_file(&file_info, "Notes.txt").unwrap(), "Renamed 'notes.txt' to 'Notes.txt'");
+        let names: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names, ["Notes.txt"]);
+    }
+
+    #[test]
+    fn test_batch_rename_marked_entries() {
+        let dir = TempDir::new("batch-rename");
+        for name in ["IMG_1.jpg", "IMG_2.jpg", "IMG_3.jpg", "photo_3.jpg"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        for name in ["IMG_1.jpg", "IMG_2.jpg", "IMG_3.jpg"] {
+            app.marked.insert(dir.join(name));
+        }
+        app.request_rename_selected().unwrap();
+        assert!(matches!(app.input_prompt.as_ref().unwrap().purpose, InputPurpose::BatchRename(_)));
+
+        app.input_prompt.as_mut().unwrap().value = "IMG_->photo_".to_string();
+        assert_eq!(app.submit_input_prompt().unwrap_err(), "'photo_3.jpg' already exists");
+        assert!(dir.join("IMG_1.jpg").exists());
+
+        app.input_prompt.as_mut().unwrap().value = "vacation_{n:03}{ext}".to_string();
+        assert_eq!(app.submit_input_prompt().unwrap(), "Renamed 3 items");
+        assert!(app.input_prompt.is_none());
+        assert_eq!(fs::read_to_string(dir.join("vacation_002.jpg")).unwrap(), "IMG_2.jpg");
+        assert!(app.marked.contains(&dir.join("vacation_003.jpg")));
+    }
+
+    #[test]
+    fn test_read_only_mode_blocks_changes() {
+        let dir = TempDir::new("read-only");
+        fs::write(dir.join("keep.txt"), "data").unwrap();
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("keep.txt"));
+        assert_eq!(app.toggle_read_only(), "Read-only mode on - changes to files are blocked");
+
+        let blocked = "Read-only mode - press Ctrl+r to allow changes";
+        assert_eq!(app.cut_selected_file().unwrap_err(), blocked);
+        assert_eq!(app.request_delete_selected(false).unwrap_err(), blocked);
+        assert_eq!(app.request_rename_selected().unwrap_err(), blocked);
+        assert_eq!(app.request_new_entry(true).unwrap_err(), blocked);
+        assert_eq!(app.duplicate_selected().unwrap_err(), blocked);
+
+        // Copying to the clipboard is fine, pasting it isn't
+        app.copy_selected_file().unwrap();
+        assert_eq!(app.paste_file().unwrap_err(), blocked);
+        assert!(app.confirm_dialog.is_none() && app.input_prompt.is_none());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        app.toggle_read_only();
+        app.request_rename_selected().unwrap();
+        app.input_prompt.as_mut().unwrap().value = "renamed.txt".to_string();
+        app.submit_input_prompt().unwrap();
+        assert!(dir.join("renamed.txt").exists());
+    }
+
+    #[test]
+    fn test_filter_narrows_listing_and_actions() {
+        let dir = TempDir::new("filter");
+        fs::create_dir(dir.join("Reports")).unwrap();
+        for name in ["report.txt", "notes.txt", "summary-REPORT.md"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("notes.txt"));
+        app.enter_filter_mode().unwrap();
+        for c in "rep".chars() {
+            app.push_filter_char(c);
+        }
+        let names: Vec<&str> = app.explorer.files().iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["Reports", "report.txt", "summary-REPORT.md"]);
+        assert_eq!(app.explorer.unfiltered_len(), 4);
+
+        app.finish_filter();
+        assert!(!app.filter_mode);
+        app.next_item();
+        assert_eq!(app.copy_selected_file().unwrap(), "Copied 'report.txt' - navigate to destination and press 'v' to paste");
+
+        app.clear_filter();
+        assert_eq!(app.explorer.files().len(), 4);
+        assert_eq!(selected_name(&app), "report.txt");
+    }
+
+    #[test]
+    fn test_jump_to_visited_directory() {
+        let dir = TempDir::new("jump");
+        for sub in ["projects/filepilot", "photos", "music"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let mut app = app_in(&dir);
+        *app.explorer.frecency_mut() = Frecency::default();
+        assert_eq!(app.request_jump().unwrap_err(), "No visited directories to jump to yet");
+
+        for sub in ["projects/filepilot", "photos", "projects/filepilot", "music"] {
+            app.explorer.navigate_to(dir.join(sub), None).unwrap();
+        }
+        // The directory we are in isn't offered
+        assert_eq!(app.jump_candidates(""), [dir.join("projects/filepilot").as_path(), dir.join("photos").as_path()]);
+        assert_eq!(app.jump_candidates("pht"), [dir.join("photos").as_path()]);
+
+        // Deleted directories are forgotten when the popup opens
+        fs::remove_dir(dir.join("photos")).unwrap();
+        app.request_jump().unwrap();
+        assert!(app.jump_candidates("pht").is_empty());
+        app.input_prompt.as_mut().unwrap().value = "pht".to_string();
+        assert_eq!(app.submit_input_prompt().unwrap_err(), "No visited directory matches 'pht'");
+
+        app.input_prompt.as_mut().unwrap().value = "fpilot".to_string();
+        assert_eq!(app.submit_input_prompt().unwrap(), format!("Jumped to {}", dir.join("projects/filepilot").display()));
+        assert_eq!(app.explorer.current_path(), dir.join("projects/filepilot"));
+    }
+
+    #[test]
+    fn test_goto_completes_and_selects_files() {
+        let dir = TempDir::new("goto");
+        fs::create_dir_all(dir.join("deep/nested")).unwrap();
+        fs::write(dir.join("deep/nested/target.txt"), "").unwrap();
+        fs::write(dir.join("deep/nested/other.txt"), "").unwrap();
+
+        let mut app = app_in(&dir);
+        app.request_goto();
+        app.input_prompt.as_mut().unwrap().value = format!("{}/de", dir.display());
+        app.complete_input_prompt();
+        app.complete_input_prompt();
+        assert_eq!(app.input_prompt.as_ref().unwrap().value, format!("{}/deep/nested/", dir.display()));
+
+        app.input_prompt.as_mut().unwrap().value.push_str("missing");
+        assert!(app.submit_input_prompt().is_err());
+        assert!(app.input_prompt.is_some());
+
+        app.input_prompt.as_mut().unwrap().value = "deep/nested/target.txt".to_string();
+        app.submit_input_prompt().unwrap();
+        assert!(app.input_prompt.is_none());
+        assert_eq!(app.explorer.current_path(), dir.join("deep/nested").as_path());
+        assert_eq!(selected_name(&app), "target.txt");
+    }
+
+    #[test]
+    fn test_history_restores_selection_and_skips_deleted() {
+        let dir = TempDir::new("history");
+        let one = dir.join("one");
+        let two = dir.join("two");
+        fs::create_dir_all(&two).unwrap();
+        fs::create_dir_all(&one).unwrap();
+        for name in ["x.txt", "y.txt"] {
+            fs::write(one.join(name), "").unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&one);
+        app.navigate_to_selected().unwrap();
+        app.select_path(&one.join("y.txt"));
+        app.explorer.navigate_to(two.clone(), app.selected_list_path()).unwrap();
+        assert_eq!(app.explorer.history_depth(), (2, 0));
+
+        app.go_back().unwrap();
+        assert_eq!(app.explorer.current_path(), one.as_path());
+        assert_eq!(selected_name(&app), "y.txt");
+        app.go_back().unwrap();
+        assert_eq!(selected_name(&app), "one");
+        assert_eq!(app.explorer.history_depth(), (0, 2));
+        assert!(app.go_back().is_err());
+
+        app.go_forward().unwrap();
+        fs::remove_dir(&two).unwrap();
+        assert!(app.go_forward().is_err());
+        assert_eq!(app.explorer.current_path(), one.as_path());
+    }
+
+    #[test]
+    fn test_open_with_remembers_last_app_per_extension() {
+        let dir = TempDir::new("open-with");
+        let config_path = dir.join("config.json");
+        fs::write(&config_path, "{}").unwrap();
+        fs::write(dir.join("photo.PNG"), "").unwrap();
+        fs::create_dir(dir.join("album")).unwrap();
+
+        let mut app = app_in(&dir);
+        app.config.source_path = Some(config_path.clone());
+        app.config.open_with = vec![
+            OpenWithApp { name: "First".to_string(), command: "true --first {path}".to_string() },
+            OpenWithApp { name: "Second".to_string(), command: "true".to_string() },
+        ];
+        app.select_path(&dir.join("album"));
+        assert!(app.request_open_with().is_err());
+
+        app.select_path(&dir.join("photo.PNG"));
+        app.request_open_with().unwrap();
+        assert_eq!(app.open_with_menu.as_ref().unwrap().apps[0].name, "First");
+        app.move_open_with_selection(1);
+        assert_eq!(app.open_with_picked().unwrap(), "Opened 'photo.PNG' with Second");
+        assert!(app.open_with_menu.is_none());
+
+        app.request_open_with().unwrap();
+        assert_eq!(app.open_with_menu.as_ref().unwrap().apps[0].name, "Second");
+        app.close_open_with();
+
+        let saved = Config::load_from_file(&config_path).unwrap();
+        assert_eq!(saved.open_with_recent.get("png").map(String::as_str), Some("Second"));
+    }
+
+    #[test]
+    fn test_drives_view_enters_volume() {
+        let dir = TempDir::new("drives");
+        let mut app = app_in(&dir);
+        app.toggle_drives().unwrap();
+        let view = app.drives_view.as_ref().unwrap();
+        // The volume holding the current directory starts out selected
+        let selected = &view.volumes[view.state.selected().unwrap()];
+        assert!(dir.starts_with(&selected.mount_point));
+        assert!(view.volumes.iter().all(|volume| !volume.is_pseudo()));
+
+        let mount_point = selected.mount_point.clone();
+        app.enter_picked_drive().unwrap();
+        assert!(app.drives_view.is_none());
+        assert_eq!(app.explorer.current_path(), mount_point.as_path());
+
+        app.toggle_drives().unwrap();
+        app.toggle_drives().unwrap();
+        assert!(app.drives_view.is_none());
+    }
+
+    #[test]
+    fn test_directory_view_state_is_restored() {
+        let dir = TempDir::new("view-state");
+        let downloads = dir.join("downloads");
+        fs::create_dir(&downloads).unwrap();
+        fs::create_dir(dir.join("other")).unwrap();
+        fs::write(downloads.join("a.txt"), "a").unwrap();
+        fs::write(downloads.join("b.txt"), "bbbb").unwrap();
+        fs::write(downloads.join("c.txt"), "cc").unwrap();
+        let config_path = dir.join("config.json");
+        fs::write(&config_path, "{}").unwrap();
+
+        let mut app = app_in(&dir);
+        app.config.source_path = Some(config_path.clone());
+        app.config.remember_view_state = true;
+        app.select_path(&downloads);
+        app.navigate_to_selected().unwrap();
+        app.cycle_sort();
+        assert_eq!(app.explorer.sort_mode(), file_system::SortMode::Size);
+        app.select_path(&downloads.join("c.txt"));
+
+        app.go_up().unwrap();
+        app.select_path(&dir.join("other"));
+        app.navigate_to_selected().unwrap();
+        // The parent kept its own order, which a new directory starts from
+        assert_eq!(app.explorer.sort_mode(), file_system::SortMode::Name);
+        app.cycle_sort();
+        app.cycle_sort();
+        app.go_up().unwrap();
+        assert_eq!(app.explorer.sort_mode(), file_system::SortMode::Name);
+        assert_eq!(selected_name(&app), "other");
+
+        app.select_path(&downloads);
+        app.navigate_to_selected().unwrap();
+        assert_eq!(app.explorer.sort_mode(), file_system::SortMode::Size);
+        assert_eq!(selected_name(&app), "c.txt");
+
+        // Saved on exit and picked up by the next run
+        app.save_view_states().unwrap();
+        let mut config = Config::load_from_file(&config_path).unwrap();
+        config.remember_view_state = true;
+        let mut app = App::new(FileExplorer::new(dir.to_path_buf()).unwrap(), SearchEngine::new(), config);
+        app.select_path(&downloads);
+        app.navigate_to_selected().unwrap();
+        assert_eq!(app.explorer.sort_mode(), file_system::SortMode::Size);
+        assert_eq!(selected_name(&app), "c.txt");
+    }
+
+    #[test]
+    fn test_tree_pane_drives_file_list() {
+        let dir = TempDir::new("tree");
+        fs::create_dir_all(dir.join("docs/drafts")).unwrap();
+        fs::create_dir_all(dir.join("music")).unwrap();
+        fs::write(dir.join("docs/drafts/plan.txt"), "").unwrap();
+        let config_path = dir.join("config.json");
+        fs::write(&config_path, "{}").unwrap();
+
+        let mut app = app_in(&dir);
+        app.config.source_path = Some(config_path.clone());
+        app.toggle_tree().unwrap();
+        assert!(app.tree_focused());
+        assert!(Config::load_from_file(&config_path).unwrap().show_tree_pane);
+        assert_eq!(app.tree_pane.tree.rows().len(), 3);
+
+        app.move_tree_selection(1).unwrap();
+        assert_eq!(app.explorer.current_path(), dir.join("docs"));
+        app.expand_tree_node().unwrap();
+        app.move_tree_selection(1).unwrap();
+        assert_eq!(app.explorer.current_path(), dir.join("docs/drafts"));
+        assert_eq!(selected_name(&app), "plan.txt");
+
+        // Left on a folded directory goes to its parent, then folds that
+        app.collapse_tree_node().unwrap();
+        assert_eq!(app.explorer.current_path(), dir.join("docs"));
+        app.collapse_tree_node().unwrap();
+        assert_eq!(app.tree_pane.tree.rows().len(), 3);
+
+        app.toggle_tree_focus();
+        assert!(!app.tree_focused());
+        app.toggle_tree().unwrap();
+        assert!(!Config::load_from_file(&config_path).unwrap().show_tree_pane);
+    }
+
+    #[tokio::test]
+    async fn test_compare_needs_exactly_two_marked_files() {
+        let dir = TempDir::new("compare");
+        fs::write(dir.join("new.txt"), "a\nb\n").unwrap();
+        fs::write(dir.join("old.txt"), "a\nc\n").unwrap();
+        fs::create_dir(dir.join("folder")).unwrap();
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("new.txt"));
+        app.toggle_mark_selected().unwrap();
+        assert_eq!(app.compare_marked().unwrap_err(), "Mark exactly two files to compare");
+
+        app.select_path(&dir.join("folder"));
+        app.toggle_mark_selected().unwrap();
+        assert!(app.compare_marked().unwrap_err().contains("'folder' is a directory"));
+        app.select_path(&dir.join("folder"));
+        app.toggle_mark_selected().unwrap();
+
+        app.select_path(&dir.join("old.txt"));
+        app.toggle_mark_selected().unwrap();
+        assert_eq!(app.compare_marked().unwrap(), "Comparing 'new.txt' and 'old.txt'...");
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while app.running_compare.is_some() {
+            assert!(Instant::now() < deadline, "the comparison never finished");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_compare();
         }
+        assert_eq!(app.get_current_message(), "'new.txt' and 'old.txt' differ");
+        let popup = app.diff_popup.as_ref().unwrap();
+        assert_eq!(popup.title, "new.txt ↔ old.txt");
+        assert_eq!(&popup.lines[3..], [" a", "-b", "+c"]);
+
+        app.scroll_diff(isize::MAX);
+        assert_eq!(app.diff_popup.as_ref().unwrap().scroll, 5);
+        app.scroll_diff(-2);
+        assert_eq!(app.diff_popup.as_ref().unwrap().scroll, 3);
+        app.close_diff();
+        assert!(app.diff_popup.is_none());
     }
-}
 
-fn ui(f: &mut Frame, app: &App) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(1)
-        .constraints([
-            Constraint::Length(1),
-            Constraint::Min(0),
-            Constraint::Length(3),
-        ])
-        .split(f.size());
+    #[test]
+    fn test_session_is_saved_only_when_enabled() {
+        let dir = TempDir::new("session");
+        let config_path = dir.join("config.json");
+        fs::write(&config_path, "{}").unwrap();
+        let session_path = dir.join(SESSION_FILE);
 
-    // Header
-    let header = Paragraph::new(format!("FilePilot - {}", app.explorer.current_path().display()))
-        .style(Style::default().fg(Color::Yellow))
-        .block(Block::default().borders(Borders::NONE));
-    f.render_widget(header, chunks[0]);
+        let mut app = app_in(&dir);
+        app.config.source_path = Some(config_path);
+        app.save_session().unwrap();
+        assert!(!session_path.exists());
 
-    // Main content
-    if (app.search_mode || app.showing_search_results) && !app.search_results.is_empty() {
-        render_search_results(f, app, chunks[1]);
-    } else {
-        render_file_list(f, app, chunks[1]);
+        app.config.restore_last_dir = true;
+        app.cycle_sort();
+        app.save_session().unwrap();
+        let session = LastSession::load(&session_path).unwrap().unwrap();
+        assert_eq!(session.directory, dir.path());
+        assert_eq!(session.sort_mode, app.explorer.sort_mode());
+        assert_eq!(session.show_hidden, app.explorer.show_hidden());
     }
 
-    // Footer
-    render_footer(f, app, chunks[2]);
+    #[tokio::test]
+    async fn test_properties_load_directory_details() {
+        let dir = TempDir::new("properties");
+        fs::create_dir(dir.join("photos")).unwrap();
+        fs::write(dir.join("photos/a.jpg"), "abc").unwrap();
 
-    // Search input overlay
-    if app.search_mode {
-        render_search_input(f, app);
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("photos"));
+        app.show_properties().unwrap();
+        let value = |app: &App, label: &str| {
+            app.properties.as_ref().unwrap().rows.iter()
+                .find(|(row_label, _)| *row_label == label)
+                .map(|(_, value)| value.clone())
+                .unwrap()
+        };
+        assert_eq!(value(&app, "Type"), "Directory");
+
+        while app.properties.as_ref().unwrap().events.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_properties();
+        }
+        assert_eq!(value(&app, "Items"), "1");
+        assert_eq!(value(&app, "Total size"), "3 bytes (3B) in 1 file");
+
+        app.close_properties();
+        assert!(app.properties.is_none());
     }
-}
 
-fn render_file_list(f: &mut Frame, app: &App, area: Rect) {
-    // Split the area into two columns: file list (60%) and preview (40%)
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(60),
-            Constraint::Percentage(40),
-        ])
-        .split(area);
+    #[tokio::test]
+    async fn test_share_manager_revokes_shares() {
+        let dir = TempDir::new("ui-share-manager");
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        let mut app = app_in(&dir);
+        assert!(app.open_share_manager().await.is_err());
 
-    // Render file list in the left column
-    let items: Vec<ListItem> = app
-        .explorer
-        .files()
-        .iter()
-        .map(|file| {
-            let icon = if file.is_directory { "📁" } else { "📄" };
-            let style = if file.is_directory {
-                Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD)
-            } else {
-                Style::default()
-            };
-            
-            // Show file info as light gray text
-            let mut info_parts = Vec::new();
-            if !file.is_directory {
-                info_parts.push(format_size(file.size));
-            }
-            if let Some(modified) = file.modified {
-                if let Ok(duration) = modified.duration_since(std::time::UNIX_EPOCH) {
-                    let days_ago = duration.as_secs() / (24 * 60 * 60);
-                    if days_ago == 0 {
-                        info_parts.push("today".to_string());
-                    } else if days_ago < 7 {
-                        info_parts.push(format!("{}d ago", days_ago));
-                    } else {
-                        info_parts.push(format!("{}w ago", days_ago / 7));
-                    }
-                }
-            }
-            let info_str = if info_parts.is_empty() {
-                String::new()
-            } else {
-                format!(" ({})", info_parts.join(", "))
-            };
-            
-            ListItem::new(Line::from(vec![
-                Span::raw(icon),
-                Span::raw(" "),
-                Span::styled(&file.name, style),
-                Span::styled(info_str, Style::default().fg(Color::DarkGray)),
-            ]))
-        })
-        .collect();
+        app.file_share_server.share_file(&dir.join("a.txt"), ShareOptions::from(&app.config.file_sharing)).await.unwrap();
+        app.file_share_server.share_file(&dir.join("b.txt"), ShareOptions::from(&app.config.file_sharing)).await.unwrap();
+        app.open_share_manager().await.unwrap();
+        assert_eq!(app.share_manager.as_ref().unwrap().shares.len(), 2);
 
-    let list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title("Files"))
-        .highlight_style(Style::default().bg(Color::DarkGray))
-        .highlight_symbol("► ");
+        // The newest share is listed first
+        app.move_share_selection(1);
+        assert_eq!(app.revoke_picked_share().await.unwrap(), "Stopped sharing 'a.txt'");
+        assert_eq!(app.picked_share().unwrap().path, dir.join("b.txt"));
 
-    f.render_stateful_widget(list, chunks[0], &mut app.list_state.clone());
+        app.request_revoke_all_shares().unwrap();
+        assert_eq!(app.confirm_pending_action().unwrap(), "Stopping all shares...");
+        assert_eq!(app.revoke_all_shares().await, "Stopped sharing 1 file(s)");
+        assert!(app.share_manager.is_none());
+        assert_eq!(app.file_share_server.shared_file_count().await, 0);
 
-    // Render preview in the right column
-    let preview_lines = app.get_file_preview();
-    let preview_items: Vec<ListItem> = preview_lines
-        .iter()
-        .map(|line| ListItem::new(line.as_str()))
-        .collect();
+        app.file_share_server.shutdown().await.unwrap();
+    }
 
-    let preview_block = Block::default()
-        .borders(Borders::ALL)
-        .title(" Preview ")
-        .border_style(Style::default().fg(Color::Green));
+    #[tokio::test]
+    async fn test_share_qr_popup() {
+        let dir = TempDir::new("ui-share-qr");
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        let mut app = app_in(&dir);
+        assert!(app.show_share_qr().await.is_err());
 
-    let preview_list = List::new(preview_items).block(preview_block);
-    f.render_widget(preview_list, chunks[1]);
-}
+        let plain = ShareOptions::from(&app.config.file_sharing);
+        app.file_share_server.share_file(&dir.join("a.txt"), plain.clone()).await.unwrap();
+        let protected = ShareOptions { protection: Some(ShareProtection::Token), ..plain };
+        app.file_share_server.share_file(&dir.join("b.txt"), protected).await.unwrap();
+        let url = app.file_share_server.list_shares().await[0].url.clone();
+        // The newest share, token and all
+        app.show_share_qr().await.unwrap();
+        let popup = app.qr_popup.as_ref().unwrap();
+        assert_eq!((popup.name.as_str(), popup.url.as_str()), ("b.txt", url.as_str()));
+        assert!(url.contains("?t="));
+        assert!(popup.lines.len() > 10 && popup.lines.iter().all(|line| line.chars().count() == popup.lines[0].chars().count()));
+        app.close_qr_popup();
 
-fn render_search_results(f: &mut Frame, app: &App, area: Rect) {
-    let items: Vec<ListItem> = app
-        .search_results
-        .iter()
-        .map(|result| {
-            let icon = if result.file_info.is_directory { "📁" } else { "📄" };
-            
-            // Show match type with different colors
-            let match_indicator = match result.match_type {
-                crate::search::MatchType::FileName => Span::styled("F", Style::default().fg(Color::Green)),
-                crate::search::MatchType::FilePath => Span::styled("P", Style::default().fg(Color::Yellow)),
-            };
-            
-            ListItem::new(Line::from(vec![
-                Span::raw(icon),
-                Span::raw(" "),
-                match_indicator,
-                Span::raw(" "),
-                Span::raw(result.file_info.path.to_string_lossy()),
-                Span::styled(format!(" ({})", result.score), Style::default().fg(Color::DarkGray)),
-            ]))
-        })
-        .collect();
+        app.open_share_manager().await.unwrap();
+        app.move_share_selection(1);
+        app.show_picked_share_qr().unwrap();
+        assert_eq!(app.qr_popup.as_ref().unwrap().name, "a.txt");
 
-    let title = format!("Search Results - F:FileName P:Path");
-    let list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title(title))
-        .highlight_style(Style::default().bg(Color::DarkGray))
-        .highlight_symbol("► ");
+        app.file_share_server.shutdown().await.unwrap();
+    }
 
-    f.render_stateful_widget(list, area, &mut app.search_list_state.clone());
-}
+    #[test]
+    fn test_bookmarks_are_added_jumped_to_and_removed() {
+        let dir = TempDir::new("bookmarks");
+        let project = dir.join("project");
+        fs::create_dir(&project).unwrap();
+        let config_path = dir.join("config.json");
+        fs::write(&config_path, "{}").unwrap();
 
-// Helper function to format file sizes
-fn format_size(size: u64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
-    let mut size = size as f64;
-    let mut unit_index = 0;
-    
-    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size /= 1024.0;
-        unit_index += 1;
+        let mut app = app_in(&project);
+        app.config.source_path = Some(config_path.clone());
+        app.add_bookmark().unwrap();
+        assert!(app.add_bookmark().is_err());
+        app.config.bookmarks.push(dir.join("gone"));
+
+        app.explorer.navigate_to(dir.to_path_buf(), None).unwrap();
+        app.open_bookmarks().unwrap();
+        app.move_bookmark_selection(1);
+        assert!(app.jump_to_picked_bookmark().is_err());
+        assert!(app.bookmark_picker.is_some());
+        app.remove_picked_bookmark().unwrap();
+        app.jump_to_picked_bookmark().unwrap();
+        assert_eq!(app.explorer.current_path(), project.as_path());
+        assert!(app.bookmark_picker.is_none());
+
+        let saved = Config::load_from_file(&config_path).unwrap();
+        assert_eq!(saved.bookmarks, vec![project]);
     }
-    
-    if unit_index == 0 {
-        format!("{:.0}{}", size, UNITS[unit_index])
-    } else {
-        format!("{:.1}{}", size, UNITS[unit_index])
+
+    #[tokio::test]
+    async fn test_compress_names_archive_after_selection() {
+        let dir = TempDir::new("compress");
+        for name in ["a.txt", "b.txt"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("a.txt"));
+        app.compress_selected().unwrap();
+        while app.running_compression.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_compression();
+        }
+        assert_eq!(app.get_current_message(), "Created 'a.txt.zip' with 1 file");
+        assert_eq!(selected_name(&app), "a.txt.zip");
+
+        app.mark_all();
+        app.marked.remove(&dir.join("a.txt.zip"));
+        app.compress_selected().unwrap();
+        while app.running_compression.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.poll_compression();
+        }
+        assert_eq!(selected_name(&app), "selection.zip");
     }
-}
 
-fn render_footer(f: &mut Frame, app: &App, area: Rect) {
-    let kb = &app.config.key_bindings;
-    let text = if app.search_mode {
-        format!(
-            "{}: Exit search | {}: Exit to results | {}: Toggle strategy | {}: Navigate | {}: Browse",
-            kb.get_key_display(&kb.search_mode.exit_search),
-            kb.get_key_display(&kb.search_mode.exit_to_results),
-            kb.get_key_display(&kb.search_mode.toggle_strategy),
-            kb.get_key_display(&kb.search_mode.navigate_tab),
-            kb.get_key_display(&kb.navigation.up)
-        )
-    } else if app.showing_search_results {
-        let clipboard_status = if let Some(clipboard) = &app.clipboard {
-            let operation = match clipboard.operation {
-                ClipboardOperation::Cut => "CUT",
-                ClipboardOperation::Copy => "COPIED",
-            };
-            let file_name = clipboard.file_path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("?");
-            format!(" | {}: {} [{}]", 
-                    kb.get_key_display(&kb.actions.paste), 
-                    operation, 
-                    file_name)
-        } else {
-            String::new()
+    #[tokio::test]
+    async fn test_git_status_is_read_once_per_directory() {
+        let dir = TempDir::new("git-status");
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut app = app_in(&dir);
+        app.config.git_status = false;
+        app.update_git_status();
+        assert!(app.running_git_status.is_none());
+
+        app.config.git_status = true;
+        app.update_git_status();
+        while app.running_git_status.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.update_git_status();
+        }
+        // Outside a repository there is nothing to show
+        assert!(app.git_status.is_none());
+        app.update_git_status();
+        assert!(app.running_git_status.is_none());
+
+        app.refresh_listing().unwrap();
+        app.update_git_status();
+        assert!(app.running_git_status.is_some());
+    }
+
+    #[test]
+    fn test_toggle_ignored_keeps_selection_and_counts() {
+        let dir = TempDir::new("toggle-ignored");
+        fs::create_dir(dir.join(".git")).unwrap();
+        fs::create_dir(dir.join("target")).unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(dir.join("build.log"), "").unwrap();
+        fs::write(dir.join("main.rs"), "").unwrap();
+
+        let mut app = app_in(&dir);
+        app.select_path(&dir.join("main.rs"));
+        assert_eq!(app.toggle_ignored(), "Hiding 2 git-ignored entries");
+        assert_eq!(selected_name(&app), "main.rs");
+        assert!(!app.explorer.files().iter().any(|file| file.name == "target" || file.name == "build.log"));
+        assert_eq!(app.explorer.unfiltered_len(), app.explorer.files().len());
+
+        // Still hidden after the listing is re-read
+        app.refresh_listing().unwrap();
+        assert_eq!(app.explorer.ignored_count(), 2);
+
+        assert_eq!(app.toggle_ignored(), "Showing git-ignored entries");
+        assert_eq!(selected_name(&app), "main.rs");
+        assert!(app.explorer.files().iter().any(|file| file.name == "build.log"));
+    }
+
+    #[tokio::test]
+    async fn test_huge_directory_lists_names_first() {
+        let dir = TempDir::new("huge");
+        fs::create_dir(dir.join("sub")).unwrap();
+        let count = file_system::LAZY_METADATA_THRESHOLD + 1;
+        for i in 0..count {
+            fs::write(dir.join(format!("file{:05}.txt", i)), "x").unwrap();
+        }
+
+        // Past the threshold, entries are listed before their metadata is read
+        let mut app = app_in(&dir);
+        app.refresh_listing().unwrap();
+        assert_eq!(app.explorer.files().len(), count + 1);
+        assert!(app.explorer.files()[0].is_directory);
+        assert!(app.explorer.files()[1..].iter().all(|file| file.metadata_pending));
+
+        // The directory preview is listed in the background too
+        let preview_text = |app: &App| -> Vec<String> {
+            app.get_file_preview().iter()
+                .map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect())
+                .collect()
         };
-        
-        format!(
-            "{}: Quit | {}: New search | {}: Back | {}: Navigate | {}: Open/Navigate | {}: Open | {}: Reveal | {}: Share | {}: Cut | {}: Copy | {}: Copy path{}",
-            kb.get_key_display(&kb.actions.quit),
-            kb.get_key_display(&kb.actions.search),
-            kb.get_key_display(&kb.search_results.back),
-            kb.get_key_display(&kb.navigation.up),
-            kb.get_key_display(&kb.navigation.enter),
-            kb.get_key_display(&kb.actions.open),
-            kb.get_key_display(&kb.actions.reveal),
-            kb.get_key_display(&kb.actions.share),
-            kb.get_key_display(&kb.actions.cut),
-            kb.get_key_display(&kb.actions.copy),
-            kb.get_key_display(&kb.actions.copy_path),
-            clipboard_status
-        )
-    } else {
-        let clipboard_status = if let Some(clipboard) = &app.clipboard {
-            let operation = match clipboard.operation {
-                ClipboardOperation::Cut => "CUT",
-                ClipboardOperation::Copy => "COPIED",
-            };
-            let file_name = clipboard.file_path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("?");
-            format!(" | {}: {} [{}]", 
-                    kb.get_key_display(&kb.actions.paste), 
-                    operation, 
-                    file_name)
+        app.list_state.select(Some(0));
+        assert_eq!(preview_text(&app).last().unwrap(), "Loading...");
+
+        fs::remove_file(dir.join("file00001.txt")).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while app.running_metadata.is_some() || !app.explorer.pending_metadata().is_empty() || app.directory_preview.is_none() {
+            assert!(Instant::now() < deadline, "metadata or the directory preview never arrived");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            app.update_metadata();
+            app.update_directory_preview();
+        }
+        let files = app.explorer.files();
+        assert_eq!(files.len(), count);
+        assert!(files.iter().all(|file| !file.metadata_pending && file.modified.is_some()));
+        assert_eq!(files[1].size, 1);
+        let preview = preview_text(&app);
+        assert_eq!(preview[0], "📁 Directory: sub");
+        assert_ne!(preview.last().unwrap(), "Loading...");
+    }
+
+    #[test]
+    fn test_fifty_thousand_entries_list_names_only() {
+        let dir = TempDir::new("fifty-thousand");
+        let count = 50_000;
+        for i in 0..count {
+            fs::File::create(dir.join(format!("entry{:05}", i))).unwrap();
+        }
+
+        // Nothing is stat-ed up front; all metadata is left for the background
+        let mut explorer = FileExplorer::new(dir.to_path_buf()).unwrap();
+        let files = explorer.refresh().unwrap();
+        assert_eq!(files.len(), count);
+        assert!(files.iter().all(|file| file.metadata_pending && file.modified.is_none()));
+        assert_eq!(files[0].name, "entry00000");
+        assert_eq!(files[count - 1].name, "entry49999");
+        assert_eq!(explorer.pending_metadata().len(), count);
+    }
+
+    #[tokio::test]
+    async fn test_paste_conflict_keep_both_and_skip() {
+        let source_dir = TempDir::new("conflict-src");
+        let dir = TempDir::new("conflict-dst");
+        fs::write(source_dir.join("a.txt"), "new").unwrap();
+        fs::write(source_dir.join("b.txt"), "new").unwrap();
+        fs::write(dir.join("a.txt"), "old").unwrap();
+        fs::write(dir.join("b.txt"), "old").unwrap();
+
+        let mut app = app_in(&dir);
+        app.clipboard = Some(ClipboardEntry {
+            file_paths: vec![source_dir.join("a.txt"), source_dir.join("b.txt")],
+            operation: ClipboardOperation::Copy,
+        });
+
+        app.paste_file().unwrap();
+        assert_eq!(app.paste_conflict.as_ref().unwrap().name, "a.txt");
+
+        app.resolve_paste_conflict(Some(ConflictPolicy::KeepBoth)).unwrap();
+        finish_paste(&mut app).await;
+        assert_eq!(fs::read_to_string(dir.join("a (1).txt")).unwrap(), "new");
+        assert_eq!(app.paste_conflict.as_ref().unwrap().name, "b.txt");
+
+        app.toggle_paste_conflict_apply_to_all();
+        app.resolve_paste_conflict(Some(ConflictPolicy::Skip)).unwrap();
+        let msg = finish_paste(&mut app).await;
+        assert_eq!(msg, "Copied 1 items to current directory, 1 skipped");
+        assert!(app.paste_conflict.is_none());
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "old");
+    }
+
+    #[tokio::test]
+    async fn test_double_tap_force_quit_ignores_typing() {
+        let dir = TempDir::new("force-quit");
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let mut app = app_in(&dir);
+        let q = KeyEvent::from(KeyCode::Char('q'));
+
+        assert!(!app.is_force_quit(&q));
+        assert!(app.is_force_quit(&q));
+
+        // Another key in between breaks the double tap
+        app.last_quit_press = None;
+        assert!(!app.is_force_quit(&q));
+        assert!(!app.is_force_quit(&KeyEvent::from(KeyCode::Down)));
+        assert!(!app.is_force_quit(&q));
+        app.last_quit_press = None;
+
+        // Typing "qq" into a prompt or the filter never quits
+        app.request_rename_selected().unwrap();
+        assert!(!app.is_force_quit(&q) && !app.is_force_quit(&q));
+        app.input_prompt = None;
+        app.filter_mode = true;
+        assert!(!app.is_force_quit(&q) && !app.is_force_quit(&q));
+        // Ctrl+q still quits from anywhere
+        assert!(app.is_force_quit(&KeyEvent::new(KeyCode::Char('q'), crossterm::event::KeyModifiers::CONTROL)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_paste_through_symlinked_directory_is_refused() {
+        let real = TempDir::new("paste-real");
+        let links = TempDir::new("paste-links");
+        fs::write(real.join("a.txt"), "data").unwrap();
+        std::os::unix::fs::symlink(&real, links.join("shortcut")).unwrap();
+
+        let mut app = app_in(&links.join("shortcut"));
+        app.clipboard = Some(ClipboardEntry {
+            file_paths: vec![real.join("a.txt")],
+            operation: ClipboardOperation::Copy,
+        });
+        let err = app.paste_file().unwrap_err();
+        assert!(err.contains("same directory"), "{}", err);
+        assert!(app.paste_conflict.is_none());
+        assert_eq!(fs::read_to_string(real.join("a.txt")).unwrap(), "data");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_paste_keeps_clipboard() {
+        let source_dir = TempDir::new("cancel-src");
+        let dir = TempDir::new("cancel-dst");
+        fs::write(source_dir.join("a.txt"), "data").unwrap();
+
+        let mut app = app_in(&dir);
+        app.clipboard = Some(ClipboardEntry {
+            file_paths: vec![source_dir.join("a.txt")],
+            operation: ClipboardOperation::Cut,
+        });
+        app.paste_file().unwrap();
+        assert!(app.paste_file().is_err(), "a second paste must wait for the first");
+
+        // The move may already be done by now; cancelling only affects what's left
+        app.cancel_transfer();
+        let msg = finish_paste(&mut app).await;
+        if msg == "Paste cancelled" {
+            assert!(app.clipboard.is_some());
         } else {
-            String::new()
-        };
-        
-        format!(
-            "{}: Quit | {}: Search | {}: Navigate | {}: Open/Navigate | {}: Go up | {}: Open | {}: Reveal | {}: Share | {}: Cut | {}: Copy | {}: Copy path{}",
-            kb.get_key_display(&kb.actions.quit),
-            kb.get_key_display(&kb.actions.search),
-            kb.get_key_display(&kb.navigation.up),
-            kb.get_key_display(&kb.navigation.enter),
-            kb.get_key_display(&kb.navigation.left),
-            kb.get_key_display(&kb.actions.open),
-            kb.get_key_display(&kb.actions.reveal),
-            kb.get_key_display(&kb.actions.share),
-            kb.get_key_display(&kb.actions.cut),
-            kb.get_key_display(&kb.actions.copy),
-            kb.get_key_display(&kb.actions.copy_path),
-            clipboard_status
-        )
-    };
-    
-    let footer = Paragraph::new(vec![
-        Line::from(text),
-        Line::from(Span::styled(app.get_current_message(), app.get_message_style())),
-    ])
-    .block(Block::default().borders(Borders::ALL).title("Controls"));
-    
-    f.render_widget(footer, area);
-}
+            assert_eq!(msg, "Moved 'a.txt' to current directory");
+            assert!(app.clipboard.is_none());
+        }
+    }
 
-fn render_search_input(f: &mut Frame, app: &App) {
-    let area = centered_rect(60, 3, f.size());
-    
-    f.render_widget(Clear, area);
-    
-    let title = format!("Search - {}", app.search_strategy.description());
-    let input = Paragraph::new(app.search_input.as_str())
-        .style(Style::default().fg(Color::Yellow))
-        .block(Block::default().borders(Borders::ALL).title(title));
-    
-    f.render_widget(input, area);
-}
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_non_utf8_name_is_shown_and_copied_intact() {
+        use std::os::unix::ffi::OsStrExt;
+        let source_dir = TempDir::new("latin1-src");
+        let dir = TempDir::new("latin1-dst");
+        let raw_name = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+        // Filesystems that insist on UTF-8 (like APFS) refuse the name outright
+        if fs::write(source_dir.join(raw_name), "menu").is_err() {
+            return;
+        }
 
-fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Percentage((100 - height) / 2),
-            Constraint::Length(height),
-            Constraint::Percentage((100 - height) / 2),
-        ])
-        .split(r);
+        let mut app = app_in(&source_dir);
+        app.select_path(&source_dir.join(raw_name));
+        assert_eq!(selected_name(&app), "caf\u{FFFD}.txt");
+        // Submitting the name as shown must not replace the undecodable byte
+        let selected = app.get_selected_file().unwrap().clone();
+        app.rename_file(&selected, "caf\u{FFFD}.txt").unwrap();
+        assert!(source_dir.join(raw_name).exists());
+        app.copy_selected_file().unwrap();
 
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage((100 - percent_x) / 2),
-            Constraint::Percentage(percent_x),
-            Constraint::Percentage((100 - percent_x) / 2),
-        ])
-        .split(popup_layout[1])[1]
+        app.explorer.navigate_to(dir.to_path_buf(), None).unwrap();
+        app.paste_file().unwrap();
+        finish_paste(&mut app).await;
+        assert_eq!(fs::read_to_string(dir.join(raw_name)).unwrap(), "menu");
+
+        // Keeping both builds the numbered name from the real bytes
+        app.paste_file().unwrap();
+        app.resolve_paste_conflict(Some(ConflictPolicy::KeepBoth)).unwrap();
+        finish_paste(&mut app).await;
+        assert!(dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9 (1).txt")).exists());
+    }
 }
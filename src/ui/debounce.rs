@@ -0,0 +1,78 @@
+//! Waits for a pause in typing before searching, so each keystroke in the
+//! search input doesn't start a walk that the next one cancels.
+
+use std::time::{Duration, Instant};
+
+/// Input is settled once this long has passed without another keystroke
+pub const SEARCH_DEBOUNCE: Duration = Duration::from_millis(150);
+
+#[derive(Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    /// The latest input not yet acted on
+    last_input: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Debouncer { delay, last_input: None }
+    }
+
+    /// Records input at `now`, pushing the deadline back.
+    pub fn input(&mut self, now: Instant) {
+        self.last_input = Some(now);
+    }
+
+    /// Forgets pending input, e.g. once it was acted on some other way.
+    pub fn cancel(&mut self) {
+        self.last_input = None;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.last_input.is_some()
+    }
+
+    /// True, once, when input is pending and none came for the delay.
+    pub fn ready(&mut self, now: Instant) -> bool {
+        match self.last_input {
+            Some(last) if now.saturating_duration_since(last) >= self.delay => {
+                self.last_input = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// How long until pending input is ready, so the event loop can wake
+    /// up for it; None when nothing is pending.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.last_input.map(|last| self.delay.saturating_sub(now.saturating_duration_since(last)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fires_once_after_a_pause() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut debouncer = Debouncer::new(ms(150));
+        assert!(!debouncer.ready(start + ms(500)));
+        assert_eq!(debouncer.remaining(start), None);
+
+        // Each keystroke pushes the deadline back
+        debouncer.input(start);
+        debouncer.input(start + ms(100));
+        assert!(!debouncer.ready(start + ms(200)));
+        assert_eq!(debouncer.remaining(start + ms(200)), Some(ms(50)));
+        assert!(debouncer.ready(start + ms(250)));
+        assert!(!debouncer.ready(start + ms(400)));
+        assert!(!debouncer.is_pending());
+
+        debouncer.input(start + ms(400));
+        debouncer.cancel();
+        assert!(!debouncer.ready(start + ms(1000)));
+    }
+}
@@ -0,0 +1,448 @@
+//! Preview pane content. Each kind of file gets a `PreviewHandler`, picked
+//! by `PreviewRegistry` from the entry type or its extension, so a new
+//! preview is a handler registration rather than another match arm.
+
+use super::format_size;
+use crate::file_system::archive::{self, ArchiveKind};
+use crate::file_system::{self, FileInfo};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::SystemTime;
+
+// Number of top-level archive entries listed in the preview pane
+const ARCHIVE_PREVIEW_ENTRIES: usize = 20;
+
+// Lines of a text file shown, and the width they are cut at
+const TEXT_PREVIEW_LINES: usize = 10;
+const TEXT_PREVIEW_WIDTH: usize = 60;
+
+// Lines shown above and below a content search match
+const MATCH_CONTEXT_LINES: usize = 4;
+
+// Entries of a directory shown
+const DIRECTORY_PREVIEW_ENTRIES: usize = 10;
+
+pub trait PreviewHandler {
+    fn preview(&self, file: &FileInfo) -> Vec<Line<'static>>;
+}
+
+/// Picks the handler for an entry: directories have their own, files are
+/// looked up by lowercase extension, and anything else gets the text
+/// handler. When a file's content contradicts its extension, say a PNG
+/// named `.txt`, the handler for what the content is wins.
+pub struct PreviewRegistry {
+    directory: Rc<dyn PreviewHandler>,
+    by_extension: HashMap<String, Rc<dyn PreviewHandler>>,
+    fallback: Rc<dyn PreviewHandler>,
+}
+
+impl PreviewRegistry {
+    /// The built-in handlers. `extract_key` is shown in archive previews.
+    pub fn new(extract_key: String) -> Self {
+        let mut registry = Self {
+            directory: Rc::new(DirectoryPreview),
+            by_extension: HashMap::new(),
+            fallback: Rc::new(TextPreview),
+        };
+
+        registry.register(&["zip", "tar", "tgz", "gz", "rar", "7z"], ArchivePreview::new(extract_key));
+        registry.register(&["jpg", "jpeg", "png", "gif", "bmp", "ico", "webp"], MediaPreview::new("Image", ""));
+        registry.register(&["mp4", "avi", "mov", "wmv", "flv", "webm", "mkv"], MediaPreview::new("Video", "🎥 "));
+        registry.register(&["mp3", "wav", "flac", "ogg", "m4a", "aac"], MediaPreview::new("Audio", "🎵 "));
+        registry.register(&["pdf"], MediaPreview::new("PDF", ""));
+        registry
+    }
+
+    /// Uses `handler` for files with any of `extensions`, replacing whatever
+    /// was registered for them before.
+    pub fn register(&mut self, extensions: &[&str], handler: impl PreviewHandler + 'static) {
+        let handler: Rc<dyn PreviewHandler> = Rc::new(handler);
+        for extension in extensions {
+            self.by_extension.insert(extension.to_lowercase(), handler.clone());
+        }
+    }
+
+    pub fn handler_for(&self, file: &FileInfo) -> &dyn PreviewHandler {
+        if file.is_directory {
+            return self.directory.as_ref();
+        }
+        let extension = file.path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let extension = match file_system::detect_type(&file.path) {
+            Ok(kind) if !kind.agrees_with_extension(&extension) => kind.extension().unwrap_or_default().to_string(),
+            _ => extension,
+        };
+        self.by_extension.get(&extension).unwrap_or(&self.fallback).as_ref()
+    }
+
+    pub fn preview(&self, file: &FileInfo) -> Vec<Line<'static>> {
+        self.handler_for(file).preview(file)
+    }
+}
+
+/// The first entries of a directory, folders first.
+pub struct DirectoryPreview;
+
+impl PreviewHandler for DirectoryPreview {
+    fn preview(&self, file: &FileInfo) -> Vec<Line<'static>> {
+        let entries = match std::fs::read_dir(&file.path) {
+            Ok(entries) => entries,
+            Err(_) => return vec![Line::from("Error reading directory")],
+        };
+
+        let mut entries: Vec<(bool, String)> = entries
+            .flatten()
+            .map(|entry| {
+                // The entry's type needs no extra stat, except to follow a symlink
+                let is_dir = match entry.file_type() {
+                    Ok(file_type) if !file_type.is_symlink() => file_type.is_dir(),
+                    _ => entry.path().is_dir(),
+                };
+                (is_dir, entry.file_name().to_string_lossy().to_string())
+            })
+            .collect();
+        entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        let mut lines = vec![Line::from(format!("📁 Directory: {}", file.name)), Line::from("")];
+        for (is_dir, name) in entries.iter().take(DIRECTORY_PREVIEW_ENTRIES) {
+            let icon = if *is_dir { "📁" } else { "📄" };
+            lines.push(Line::from(format!("{} {}", icon, name)));
+        }
+        if entries.len() > DIRECTORY_PREVIEW_ENTRIES {
+            lines.push(Line::from(format!("... and {} more items", entries.len() - DIRECTORY_PREVIEW_ENTRIES)));
+        }
+        lines
+    }
+}
+
+/// Numbered first lines of a text file; files that aren't UTF-8 get a
+/// short description instead.
+pub struct TextPreview;
+
+impl PreviewHandler for TextPreview {
+    fn preview(&self, file: &FileInfo) -> Vec<Line<'static>> {
+        let content = match std::fs::read_to_string(&file.path) {
+            Ok(content) => content,
+            Err(_) => {
+                return vec![
+                    Line::from(format!("Binary: {}", file.name)),
+                    Line::from(format!("Size: {:.1} KB", file.size as f64 / 1024.0)),
+                    Line::from(""),
+                    Line::from("Binary file - cannot preview"),
+                    Line::from("Use 'o' to open with default app"),
+                ];
+            }
+        };
+
+        let mut lines = vec![
+            Line::from(format!("📄 File: {} ({:.1} KB)", file.name, file.size as f64 / 1024.0)),
+            Line::from(""),
+        ];
+        let file_lines: Vec<&str> = content.lines().collect();
+        for (i, line) in file_lines.iter().take(TEXT_PREVIEW_LINES).enumerate() {
+            lines.push(Line::from(format!("{:2}: {}", i + 1, truncate_line(line))));
+        }
+        if file_lines.len() > TEXT_PREVIEW_LINES {
+            lines.push(Line::from(""));
+            lines.push(Line::from(format!("... ({} more lines)", file_lines.len() - TEXT_PREVIEW_LINES)));
+        }
+        lines
+    }
+}
+
+/// The lines of a text file around `line_number` (1-based), where a content
+/// search matched, with the matching line highlighted.
+pub fn match_context(file: &FileInfo, line_number: usize) -> Vec<Line<'static>> {
+    let content = match std::fs::read_to_string(&file.path) {
+        Ok(content) => content,
+        Err(e) => return vec![Line::from(format!("Cannot read {}: {}", file.name, e))],
+    };
+    let mut lines = vec![
+        Line::from(format!("📄 File: {} (match on line {})", file.name, line_number)),
+        Line::from(""),
+    ];
+    let first = line_number.saturating_sub(MATCH_CONTEXT_LINES).max(1);
+    let last = line_number + MATCH_CONTEXT_LINES;
+    let width = last.to_string().len();
+    for (i, line) in content.lines().enumerate().skip(first - 1).take(last + 1 - first) {
+        let number = i + 1;
+        let text = format!("{:>width$}: {}", number, truncate_line(line), width = width);
+        if number == line_number {
+            lines.push(Line::from(Span::styled(text, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))));
+        } else {
+            lines.push(Line::from(Span::styled(text, Style::default().fg(Color::DarkGray))));
+        }
+    }
+    lines
+}
+
+/// `line` cut to the preview width, with an ellipsis when it was longer.
+fn truncate_line(line: &str) -> String {
+    if line.chars().count() > TEXT_PREVIEW_WIDTH {
+        format!("{}...", line.chars().take(TEXT_PREVIEW_WIDTH - 3).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Name, size and a hint for files the pane can't show, like images or video.
+pub struct MediaPreview {
+    kind: &'static str,
+    icon: &'static str,
+}
+
+impl MediaPreview {
+    pub fn new(kind: &'static str, icon: &'static str) -> Self {
+        Self { kind, icon }
+    }
+}
+
+impl PreviewHandler for MediaPreview {
+    fn preview(&self, file: &FileInfo) -> Vec<Line<'static>> {
+        vec![
+            Line::from(format!("{}{}: {}", self.icon, self.kind, file.name)),
+            Line::from(format!("Size: {}", format_size(file.size))),
+            Line::from(""),
+            Line::from(format!("{} file - use 'o' to open", self.kind)),
+            Line::from("or 's' to share via web"),
+        ]
+    }
+}
+
+/// Preview lines for a file, valid while its path and mtime are unchanged
+type CachedPreview = (PathBuf, Option<SystemTime>, Vec<Line<'static>>);
+
+/// An archive being listed in the background, and where its preview
+/// lines arrive
+type RunningPreview = (PathBuf, Option<SystemTime>, mpsc::Receiver<Vec<Line<'static>>>);
+
+/// Entry count, size and top-level contents of a zip or tar archive. A
+/// compressed tar has no index, so the listing is read on a blocking task
+/// while "Loading..." shows, and cached for the last archive.
+pub struct ArchivePreview {
+    extract_key: String,
+    cache: RefCell<Option<CachedPreview>>,
+    running: RefCell<Option<RunningPreview>>,
+}
+
+impl ArchivePreview {
+    pub fn new(extract_key: String) -> Self {
+        Self { extract_key, cache: RefCell::new(None), running: RefCell::new(None) }
+    }
+
+    fn header(file: &FileInfo) -> Vec<Line<'static>> {
+        vec![
+            Line::from(format!("📦 Archive: {}", file.name)),
+            Line::from(format!("Size: {}", format_size(file.size))),
+            Line::from(""),
+        ]
+    }
+
+    /// The full preview, which may take a while to read.
+    fn summary(file: &FileInfo, extract_key: &str) -> Vec<Line<'static>> {
+        let mut lines = Self::header(file);
+        match archive::summarize(&file.path, ARCHIVE_PREVIEW_ENTRIES) {
+            Ok(summary) => {
+                let more = if summary.truncated { "+" } else { "" };
+                lines.push(Line::from(format!(
+                    "{}{} entries, {}{} uncompressed",
+                    summary.entry_count, more, format_size(summary.total_size), more
+                )));
+                lines.push(Line::from(""));
+                for entry in &summary.top_level {
+                    let icon = if entry.is_dir { "📁" } else { "📄" };
+                    let suffix = if entry.is_dir { "/" } else { "" };
+                    lines.push(Line::from(format!("{} {}{} ({})", icon, entry.name, suffix, format_size(entry.size))));
+                }
+                if summary.top_level_count > summary.top_level.len() {
+                    lines.push(Line::from(format!(
+                        "... and {} more items",
+                        summary.top_level_count - summary.top_level.len()
+                    )));
+                }
+                lines.push(Line::from(""));
+                lines.push(Line::from(format!("Press {} to extract here", extract_key)));
+            }
+            Err(e) => {
+                lines.push(Line::from(format!("Could not read archive: {}", e)));
+                lines.push(Line::from("Use 'o' to open with system default"));
+            }
+        }
+        lines
+    }
+}
+
+impl PreviewHandler for ArchivePreview {
+    fn preview(&self, file: &FileInfo) -> Vec<Line<'static>> {
+        if ArchiveKind::from_path(&file.path).is_none() {
+            // Formats FilePilot can't read, like .rar, .7z or a bare .gz
+            return vec![
+                Line::from(format!("Archive: {}", file.name)),
+                Line::from(format!("Size: {}", format_size(file.size))),
+                Line::from(""),
+                Line::from("Archive file - use 'o' to open"),
+                Line::from("with system default"),
+            ];
+        }
+        if let Some((path, modified, lines)) = self.cache.borrow().as_ref() {
+            if *path == file.path && *modified == file.modified {
+                return lines.clone();
+            }
+        }
+
+        let mut running = self.running.borrow_mut();
+        if !running.as_ref().is_some_and(|(path, modified, _)| *path == file.path && *modified == file.modified) {
+            // An archive still being read for another entry is dropped
+            let (sender, events) = mpsc::channel();
+            let task_file = file.clone();
+            let extract_key = self.extract_key.clone();
+            tokio::task::spawn_blocking(move || {
+                let _ = sender.send(Self::summary(&task_file, &extract_key));
+            });
+            *running = Some((file.path.clone(), file.modified, events));
+        }
+
+        let lines = match running.as_ref().map(|(_, _, events)| events.try_recv()) {
+            Some(Ok(lines)) => lines,
+            Some(Err(mpsc::TryRecvError::Disconnected)) => {
+                let mut lines = Self::header(file);
+                lines.push(Line::from("Could not read archive"));
+                lines
+            }
+            _ => {
+                let mut lines = Self::header(file);
+                lines.push(Line::from("Loading..."));
+                return lines;
+            }
+        };
+        *running = None;
+        *self.cache.borrow_mut() = Some((file.path.clone(), file.modified, lines.clone()));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use crate::test_support::TempDir;
+
+    fn file_info(path: &Path) -> FileInfo {
+        FileInfo::from_path(path).unwrap()
+    }
+
+    fn text(lines: &[Line]) -> Vec<String> {
+        lines.iter()
+            .map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_text_preview_numbers_and_truncates() {
+        let dir = TempDir::new("text");
+        let path = dir.join("notes.txt");
+        let long_line = "é".repeat(80);
+        let content: Vec<String> = (1..=12).map(|i| if i == 2 { long_line.clone() } else { format!("line {}", i) }).collect();
+        fs::write(&path, content.join("\n")).unwrap();
+
+        let lines = text(&TextPreview.preview(&file_info(&path)));
+        assert_eq!(lines[2], " 1: line 1");
+        assert_eq!(lines[3], format!(" 2: {}...", "é".repeat(57)));
+        assert_eq!(lines.last().unwrap(), "... (2 more lines)");
+    }
+
+    #[test]
+    fn test_match_context_centers_on_line() {
+        let dir = TempDir::new("match");
+        let path = dir.join("notes.txt");
+        let content: Vec<String> = (1..=20).map(|i| format!("line {}", i)).collect();
+        fs::write(&path, content.join("\n")).unwrap();
+
+        let preview = match_context(&file_info(&path), 10);
+        let lines = text(&preview);
+        assert_eq!(lines[0], "📄 File: notes.txt (match on line 10)");
+        assert_eq!(&lines[2..], [" 6: line 6", " 7: line 7", " 8: line 8", " 9: line 9", "10: line 10",
+            "11: line 11", "12: line 12", "13: line 13", "14: line 14"]);
+        assert_eq!(preview[6].spans[0].style.fg, Some(Color::Yellow));
+
+        // Near the start there is less context above
+        assert_eq!(text(&match_context(&file_info(&path), 2))[2], "1: line 1");
+    }
+
+    #[test]
+    fn test_directory_preview_lists_folders_first() {
+        let dir = TempDir::new("directory");
+        fs::write(dir.join("a.txt"), "").unwrap();
+        fs::create_dir(dir.join("z")).unwrap();
+
+        let lines = text(&DirectoryPreview.preview(&file_info(&dir)));
+        assert_eq!(lines[2..], ["📁 z".to_string(), "📄 a.txt".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_archive_preview_reads_in_background() {
+        use std::io::Write;
+        let dir = TempDir::new("archive");
+        let path = dir.join("bundle.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        zip.start_file("readme.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"hello").unwrap();
+        zip.finish().unwrap();
+
+        let handler = ArchivePreview::new("E".to_string());
+        let file = file_info(&path);
+        assert_eq!(text(&handler.preview(&file)).last().unwrap(), "Loading...");
+        let read = async {
+            loop {
+                let lines = text(&handler.preview(&file));
+                if lines.last().unwrap() != "Loading..." {
+                    break lines;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        };
+        let lines = tokio::time::timeout(std::time::Duration::from_secs(10), read).await.unwrap();
+        assert_eq!(lines[3], "1 entries, 5B uncompressed");
+        assert_eq!(lines.last().unwrap(), "Press E to extract here");
+        // Kept for the next draw
+        assert_eq!(text(&handler.preview(&file)), lines);
+    }
+
+    #[test]
+    fn test_registry_dispatch() {
+        struct Fixed;
+        impl PreviewHandler for Fixed {
+            fn preview(&self, _file: &FileInfo) -> Vec<Line<'static>> {
+                vec![Line::from("fixed")]
+            }
+        }
+
+        let dir = TempDir::new("registry");
+        let image = dir.join("photo.PNG");
+        let custom = dir.join("data.csv");
+        fs::write(&image, [0xff, 0xd8, 0xff]).unwrap();
+        fs::write(&custom, "a,b").unwrap();
+
+        let mut registry = PreviewRegistry::new("E".to_string());
+        assert_eq!(text(&registry.preview(&file_info(&image)))[0], "Image: photo.PNG");
+        assert!(text(&registry.preview(&file_info(&custom)))[0].starts_with("📄 File: data.csv"));
+
+        registry.register(&["csv"], Fixed);
+        assert_eq!(text(&registry.preview(&file_info(&custom))), ["fixed"]);
+
+        // Content that contradicts the name is previewed as what it is
+        let misnamed_image = dir.join("notes.txt");
+        let misnamed_text = dir.join("README.png");
+        fs::write(&misnamed_image, b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        fs::write(&misnamed_text, "# Read me").unwrap();
+        assert_eq!(text(&registry.preview(&file_info(&misnamed_image)))[0], "Image: notes.txt");
+        assert_eq!(text(&registry.preview(&file_info(&misnamed_text)))[2], " 1: # Read me");
+    }
+}
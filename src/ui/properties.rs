@@ -0,0 +1,156 @@
+//! Rows of the properties popup. Everything one `stat` can answer is read
+//! up front; a directory's item count and total size come from
+//! `directory_details` on a blocking task.
+
+use super::{format_size, permissions_line};
+use crate::file_system::{utc_date_time, FileInfo};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::SystemTime;
+
+pub const ITEMS: &str = "Items";
+pub const TOTAL_SIZE: &str = "Total size";
+
+// Shown until a lazily read value arrives
+pub const LOADING: &str = "…";
+
+/// Label and value rows for `file`, with `LOADING` for the directory
+/// values that `directory_details` fills in.
+pub fn describe(file: &FileInfo, mime_type: &str) -> io::Result<Vec<(&'static str, String)>> {
+    let link_metadata = fs::symlink_metadata(&file.path)?;
+    // Times and ids of what a symlink points to, like the listing's size
+    let metadata = if file.is_broken_link { link_metadata.clone() } else { fs::metadata(&file.path)? };
+
+    let kind = match (file.is_symlink, file.is_directory) {
+        (true, true) => "Symlink to directory",
+        (true, false) => "Symlink",
+        (false, true) => "Directory",
+        (false, false) => "File",
+    };
+    let mut rows = vec![
+        ("Name", file.name.clone()),
+        ("Path", file.path.display().to_string()),
+        ("Type", kind.to_string()),
+    ];
+    if let Some(target) = &file.link_target {
+        let broken = if file.is_broken_link { " (missing)" } else { "" };
+        rows.push(("Target", format!("{}{}", target.display(), broken)));
+    }
+    if file.is_directory {
+        rows.push((ITEMS, LOADING.to_string()));
+        rows.push((TOTAL_SIZE, LOADING.to_string()));
+    } else {
+        rows.push(("Size", exact_size(metadata.len())));
+        rows.push(("MIME type", mime_type.to_string()));
+    }
+    rows.push(("Modified", timestamp(metadata.modified())));
+    rows.push(("Accessed", timestamp(metadata.accessed())));
+    rows.push(("Created", timestamp(metadata.created())));
+    rows.push(("Permissions", permissions_line(file)));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        rows.push(("Inode", format!("{} on device {}", metadata.ino(), metadata.dev())));
+        rows.push(("Hard links", metadata.nlink().to_string()));
+    }
+    Ok(rows)
+}
+
+/// Sends the item count of `dir` and then the total size of everything
+/// below it, stopping early once `cancel` is set.
+pub fn directory_details(dir: &Path, cancel: &AtomicBool, sender: &Sender<(&'static str, String)>) {
+    let items = match fs::read_dir(dir) {
+        Ok(entries) => entries.count().to_string(),
+        Err(e) => format!("unreadable: {}", e),
+    };
+    if sender.send((ITEMS, items)).is_err() {
+        return;
+    }
+
+    let mut total = 0;
+    let mut files = 0usize;
+    for entry in walkdir::WalkDir::new(dir).into_iter().filter_map(|entry| entry.ok()) {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        if entry.file_type().is_file() {
+            total += entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            files += 1;
+        }
+    }
+    let plural = if files == 1 { "" } else { "s" };
+    let _ = sender.send((TOTAL_SIZE, format!("{} in {} file{}", exact_size(total), files, plural)));
+}
+
+/// The rows as plain `Label: value` lines, for the clipboard.
+pub fn report(rows: &[(&'static str, String)]) -> String {
+    rows.iter()
+        .map(|(label, value)| format!("{}: {}", label, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// "1,234,567 bytes (1.2MB)"
+fn exact_size(bytes: u64) -> String {
+    let digits = bytes.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!("{} bytes ({})", grouped, format_size(bytes))
+}
+
+fn timestamp(time: io::Result<SystemTime>) -> String {
+    match time {
+        Ok(time) => {
+            let (year, month, day, hour, minute, second) = utc_date_time(time);
+            format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, hour, minute, second)
+        }
+        Err(_) => "not available".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_exact_size() {
+        assert_eq!(exact_size(0), "0 bytes (0B)");
+        assert_eq!(exact_size(999), "999 bytes (999B)");
+        assert_eq!(exact_size(1_234_567), "1,234,567 bytes (1.2MB)");
+    }
+
+    #[test]
+    fn test_describe_and_directory_details() {
+        let dir = TempDir::new("properties");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("notes.txt"), "hello").unwrap();
+        fs::write(dir.join("sub/more.txt"), "12345678901").unwrap();
+
+        let rows = describe(&FileInfo::from_path(&dir.join("notes.txt")).unwrap(), "text/plain").unwrap();
+        let value = |rows: &[(&str, String)], label: &str| rows.iter().find(|(l, _)| *l == label).map(|(_, v)| v.clone());
+        assert_eq!(value(&rows, "Size").unwrap(), "5 bytes (5B)");
+        assert_eq!(value(&rows, "MIME type").unwrap(), "text/plain");
+        assert!(value(&rows, "Modified").unwrap().ends_with(" UTC"));
+        assert!(report(&rows).starts_with("Name: notes.txt\nPath: "));
+
+        let rows = describe(&FileInfo::from_path(&dir).unwrap(), "inode/directory").unwrap();
+        assert_eq!(value(&rows, ITEMS).unwrap(), LOADING);
+        assert!(value(&rows, "Size").is_none());
+
+        let (sender, events) = mpsc::channel();
+        directory_details(&dir, &AtomicBool::new(false), &sender);
+        assert_eq!(events.recv().unwrap(), (ITEMS, "2".to_string()));
+        assert_eq!(events.recv().unwrap(), (TOTAL_SIZE, "16 bytes (16B) in 2 files".to_string()));
+    }
+}
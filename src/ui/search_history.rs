@@ -0,0 +1,87 @@
+//! Queries submitted from the search input, so Up can bring them back the
+//! way a shell recalls earlier commands.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+
+// Queries remembered before the oldest one is dropped
+const SEARCH_HISTORY_LIMIT: usize = 100;
+
+/// Submitted queries, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SearchHistory {
+    entries: VecDeque<String>,
+}
+
+impl SearchHistory {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// Records `query` as the most recent entry, unless it is empty or
+    /// repeats the one before it.
+    pub fn push(&mut self, query: &str) {
+        if query.is_empty() || self.entries.back().is_some_and(|last| last == query) {
+            return;
+        }
+        self.entries.push_back(query.to_string());
+        while self.entries.len() > SEARCH_HISTORY_LIMIT {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Reads the history written by `save`; a missing file is an empty one.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_repeats_and_oldest_are_dropped() {
+        let mut history = SearchHistory::default();
+        for query in ["report", "report", "", "notes", "report"] {
+            history.push(query);
+        }
+        let entries: Vec<&str> = (0..history.len()).filter_map(|index| history.get(index)).collect();
+        assert_eq!(entries, ["report", "notes", "report"]);
+
+        for i in 0..SEARCH_HISTORY_LIMIT {
+            history.push(&format!("query {}", i));
+        }
+        assert_eq!(history.len(), SEARCH_HISTORY_LIMIT);
+        assert_eq!(history.get(0), Some("query 0"));
+
+        let dir = TempDir::new("search-history");
+        let path = dir.join("history.json");
+        history.save(&path).unwrap();
+        let loaded = SearchHistory::load(&path).unwrap();
+        assert_eq!(loaded.entries, history.entries);
+        std::fs::remove_file(&path).unwrap();
+        assert!(SearchHistory::load(&path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! The directory tree pane. Rows are kept flattened in display order;
+//! expanding a directory reads its subdirectories right then and inserts
+//! them below it, so nothing is walked before it is asked for.
+
+use crate::file_system;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Subdirectories listed under one node before a "… N more" row
+pub const CHILD_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeRow {
+    Directory {
+        path: PathBuf,
+        name: String,
+        depth: usize,
+        expanded: bool,
+    },
+    /// Stands in for subdirectories beyond `CHILD_LIMIT`
+    More { depth: usize, count: usize },
+}
+
+impl TreeRow {
+    pub fn depth(&self) -> usize {
+        match self {
+            TreeRow::Directory { depth, .. } | TreeRow::More { depth, .. } => *depth,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            TreeRow::Directory { path, .. } => Some(path),
+            TreeRow::More { .. } => None,
+        }
+    }
+}
+
+pub struct DirectoryTree {
+    rows: Vec<TreeRow>,
+}
+
+impl DirectoryTree {
+    /// A tree holding just `root`, collapsed.
+    pub fn new(root: PathBuf) -> Self {
+        let name = root.display().to_string();
+        DirectoryTree {
+            rows: vec![TreeRow::Directory { path: root, name, depth: 0, expanded: false }],
+        }
+    }
+
+    pub fn rows(&self) -> &[TreeRow] {
+        &self.rows
+    }
+
+    pub fn is_expanded(&self, index: usize) -> bool {
+        matches!(self.rows.get(index), Some(TreeRow::Directory { expanded: true, .. }))
+    }
+
+    /// Reads the subdirectories of the directory at `index` and shows them
+    /// below it. Hidden ones are left out unless `show_hidden` is set.
+    pub fn expand(&mut self, index: usize, show_hidden: bool) -> io::Result<()> {
+        let Some(TreeRow::Directory { path, depth, expanded: false, .. }) = self.rows.get(index) else {
+            return Ok(());
+        };
+        let depth = depth + 1;
+        let mut children = subdirectories(path, show_hidden)?;
+        children.sort_by_cached_key(|path| file_system::display_name(path).to_lowercase());
+
+        let more = children.len().saturating_sub(CHILD_LIMIT);
+        let mut rows: Vec<TreeRow> = children.into_iter()
+            .take(CHILD_LIMIT)
+            .map(|path| TreeRow::Directory { name: file_system::display_name(&path), path, depth, expanded: false })
+            .collect();
+        if more > 0 {
+            rows.push(TreeRow::More { depth, count: more });
+        }
+        self.rows.splice(index + 1..index + 1, rows);
+        if let Some(TreeRow::Directory { expanded, .. }) = self.rows.get_mut(index) {
+            *expanded = true;
+        }
+        Ok(())
+    }
+
+    /// Hides everything below the directory at `index`. Reopening it reads
+    /// the directory again.
+    pub fn collapse(&mut self, index: usize) {
+        let Some(TreeRow::Directory { depth, expanded, .. }) = self.rows.get_mut(index) else {
+            return;
+        };
+        *expanded = false;
+        let depth = *depth;
+        let end = self.rows[index + 1..].iter()
+            .position(|row| row.depth() <= depth)
+            .map_or(self.rows.len(), |offset| index + 1 + offset);
+        self.rows.drain(index + 1..end);
+    }
+
+    /// The row of the directory containing the one at `index`.
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        let depth = self.rows.get(index)?.depth();
+        self.rows[..index].iter().rposition(|row| row.depth() < depth)
+    }
+}
+
+/// Directories (and symlinks to them) directly inside `dir`.
+fn subdirectories(dir: &Path, show_hidden: bool) -> io::Result<Vec<PathBuf>> {
+    let mut children = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let is_dir = match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() => entry.path().is_dir(),
+            Ok(file_type) => file_type.is_dir(),
+            Err(_) => false,
+        };
+        if is_dir && (show_hidden || !entry.file_name().to_string_lossy().starts_with('.')) {
+            children.push(entry.path());
+        }
+    }
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn names(tree: &DirectoryTree) -> Vec<String> {
+        tree.rows().iter()
+            .map(|row| match row {
+                TreeRow::Directory { name, depth, .. } => format!("{}{}", "  ".repeat(*depth), name),
+                TreeRow::More { depth, count } => format!("{}… {} more", "  ".repeat(*depth), count),
+            })
+            .skip(1)
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_and_collapse() {
+        let dir = TempDir::new("tree");
+        fs::create_dir_all(dir.join("b/inner")).unwrap();
+        fs::create_dir_all(dir.join("A")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("file.txt"), "").unwrap();
+
+        let mut tree = DirectoryTree::new(dir.to_path_buf());
+        assert_eq!(tree.rows().len(), 1);
+        tree.expand(0, false).unwrap();
+        assert_eq!(names(&tree), ["  A", "  b"]);
+
+        tree.expand(2, false).unwrap();
+        assert_eq!(names(&tree), ["  A", "  b", "    inner"]);
+        assert_eq!(tree.parent(3), Some(2));
+        assert_eq!(tree.parent(1), Some(0));
+
+        tree.collapse(0);
+        assert!(names(&tree).is_empty());
+        tree.expand(0, true).unwrap();
+        assert_eq!(names(&tree), ["  .git", "  A", "  b"]);
+    }
+
+    #[test]
+    fn test_enormous_directory_is_capped() {
+        let dir = TempDir::new("tree-cap");
+        for i in 0..CHILD_LIMIT + 3 {
+            fs::create_dir_all(dir.join(format!("d{:04}", i))).unwrap();
+        }
+
+        let mut tree = DirectoryTree::new(dir.to_path_buf());
+        tree.expand(0, false).unwrap();
+        assert_eq!(tree.rows().len(), CHILD_LIMIT + 2);
+        assert_eq!(tree.rows().last(), Some(&TreeRow::More { depth: 1, count: 3 }));
+    }
+}
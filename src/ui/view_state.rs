@@ -0,0 +1,105 @@
+//! How each recently visited directory was being viewed, so going back to
+//! it brings back its sort order, hidden-files setting, filter and place
+//! in the list.
+
+use crate::file_system::SortMode;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Directories remembered before the least recently left one is dropped
+const VIEW_STATE_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewState {
+    pub sort_mode: SortMode,
+    pub sort_reverse: bool,
+    pub show_hidden: bool,
+    pub filter: String,
+    pub selected: Option<PathBuf>,
+    /// First visible row of the file list
+    pub offset: usize,
+}
+
+/// View states keyed by directory, least recently left first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ViewStates {
+    entries: VecDeque<(PathBuf, ViewState)>,
+}
+
+impl ViewStates {
+    pub fn get(&self, dir: &Path) -> Option<&ViewState> {
+        self.entries.iter().find(|(path, _)| path == dir).map(|(_, state)| state)
+    }
+
+    /// Records how `dir` was left, making it the most recent entry.
+    pub fn insert(&mut self, dir: PathBuf, state: ViewState) {
+        self.entries.retain(|(path, _)| *path != dir);
+        self.entries.push_back((dir, state));
+        while self.entries.len() > VIEW_STATE_LIMIT {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Reads states written by `save`; a missing file is an empty map.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn state(offset: usize) -> ViewState {
+        ViewState {
+            sort_mode: SortMode::Modified,
+            sort_reverse: false,
+            show_hidden: true,
+            filter: String::new(),
+            selected: None,
+            offset,
+        }
+    }
+
+    #[test]
+    fn test_least_recently_left_is_dropped() {
+        let mut states = ViewStates::default();
+        for i in 0..VIEW_STATE_LIMIT {
+            states.insert(PathBuf::from(format!("/dir{}", i)), state(i));
+        }
+        // Leaving /dir0 again makes it the newest
+        states.insert(PathBuf::from("/dir0"), state(100));
+        states.insert(PathBuf::from("/extra"), state(0));
+
+        assert_eq!(states.get(Path::new("/dir0")).map(|state| state.offset), Some(100));
+        assert!(states.get(Path::new("/dir1")).is_none());
+        assert!(states.get(Path::new("/extra")).is_some());
+        assert_eq!(states.entries.len(), VIEW_STATE_LIMIT);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("view-state");
+        let path = dir.join("view_state.json");
+        assert!(ViewStates::load(&path).unwrap().entries.is_empty());
+
+        let mut states = ViewStates::default();
+        states.insert(PathBuf::from("/downloads"), state(7));
+        states.save(&path).unwrap();
+        assert_eq!(ViewStates::load(&path).unwrap().get(Path::new("/downloads")), Some(&state(7)));
+
+    }
+}